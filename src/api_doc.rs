@@ -0,0 +1,296 @@
+//! Reference documentation for the builtins available to recipes, generated from their signatures
+
+use std::{
+	collections::HashMap,
+	fs,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+use starlark::{
+	docs::{DocMember, DocModule, DocParam},
+	environment::GlobalsBuilder,
+};
+
+use crate::{
+	project::Project,
+	starlark_api::{self, LATEST_API_VERSION},
+	starlark_global::PkgOpt,
+	starlark_probe::{self, Probes},
+	starlark_project::StarProject,
+	toolchain::Toolchain,
+	GlobalOptions,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiDocFormat {
+	Markdown,
+	Json,
+}
+
+impl ApiDocFormat {
+	pub fn parse(format: &str) -> Result<Self, String> {
+		match format {
+			"md" => Ok(ApiDocFormat::Markdown),
+			"json" => Ok(ApiDocFormat::Json),
+			_ => Err(format!("Unknown format \"{}\". Expected one of: md, json", format)),
+		}
+	}
+}
+
+/// The documentation of every builtin in version `api_version` of the recipe API
+fn documentation(api_version: u32) -> DocModule {
+	let project = Arc::new(Mutex::new(StarProject::new(
+		String::new(),
+		None,
+		Default::default(),
+		api_version,
+		PathBuf::new(),
+		Vec::new(),
+		HashMap::new(),
+	)));
+	let global_options = GlobalOptions {
+		c_standard: None,
+		cpp_standard: None,
+		position_independent_code: None,
+		reproducible: None,
+		hermetic: false,
+		strict_sources: true,
+		strict_include_dirs: false,
+		profile: None,
+		benchmarks: false,
+		artifact_cache: None,
+		windows_character_set: None,
+		output_layout: Default::default(),
+		root_package: String::new(),
+		regeneration: None,
+	};
+	// Only the signatures are documented, so no toolchain is needed
+	let toolchain = Toolchain::default();
+	let mut builder = GlobalsBuilder::new();
+	starlark_api::build_api(&project, None, api_version, &mut builder);
+	starlark_probe::build_probe_api(Arc::new(Probes::new(&global_options, &toolchain)), &project, &mut builder);
+	builder.build().documentation()
+}
+
+/// The recipe API reference, as Markdown with one section per builtin or as starlark's JSON documentation format
+pub fn render(format: ApiDocFormat, api_version: Option<u32>) -> Result<String, String> {
+	let api_version = api_version.unwrap_or(LATEST_API_VERSION);
+	if api_version == 0 || api_version > LATEST_API_VERSION {
+		return Err(format!(
+			"Unknown recipe API version {}. This version of catapult supports up to version {}",
+			api_version, LATEST_API_VERSION
+		));
+	}
+	let docs = documentation(api_version);
+	match format {
+		ApiDocFormat::Json => serde_json::to_string_pretty(&docs).map_err(|e| e.to_string()),
+		ApiDocFormat::Markdown => Ok(markdown(&docs, api_version)),
+	}
+}
+
+fn markdown(docs: &DocModule, api_version: u32) -> String {
+	let mut members = docs.members.iter().collect::<Vec<_>>();
+	members.sort_by_key(|(name, _)| name.as_str());
+	let mut ret = format!("# Recipe API reference (version {})\n", api_version);
+	for (name, member) in members {
+		ret += &format!("\n## {}\n\n", name);
+		match member {
+			DocMember::Function(func) => {
+				let params = func.params.iter().map(param_string).collect::<Vec<_>>();
+				ret += &format!("```python\n{}({}) -> {}\n```\n", name, params.join(", "), func.ret.typ);
+				if let Some(docs) = &func.docs {
+					ret += &format!("\n{}\n", docs.summary);
+					if let Some(details) = &docs.details {
+						ret += &format!("\n{}\n", details);
+					}
+				}
+			}
+			DocMember::Property(prop) => {
+				ret += &format!("`{}: {}`\n", name, prop.typ);
+			}
+		}
+	}
+	ret
+}
+
+/// A parameter as written in a signature, e.g. `link_private: list[str] = ...`
+fn param_string(param: &DocParam) -> String {
+	match param {
+		DocParam::Arg { name, typ, default_value, .. } => match default_value {
+			Some(_) => format!("{}: {} = ...", name, typ),
+			None => format!("{}: {}", name, typ),
+		},
+		DocParam::NoArgs => "*".to_owned(),
+		DocParam::OnlyPosBefore => "/".to_owned(),
+		DocParam::Args { name, typ, .. } | DocParam::Kwargs { name, typ, .. } => format!("{}: {}", name, typ),
+	}
+}
+
+/// The file editors are pointed at for completions in `build.catapult`
+pub const STUBS_FILE: &str = "catapult.pyi";
+
+/// The types of `GLOBAL` and of the values builtins take and return. `PackageOptions` depends on the package,
+/// so it's generated.
+const STUB_TYPES: &str = r#"class StaticLibrary:
+    include_dirs: list[str]
+    defines: list[str]
+    link_flags: list[str]
+    sources: list[str]
+    links: list[StaticLibrary | ObjectLibrary | InterfaceLibrary]
+
+class ObjectLibrary:
+    include_dirs: list[str]
+    defines: list[str]
+    link_flags: list[str]
+    sources: list[str]
+    links: list[StaticLibrary | ObjectLibrary | InterfaceLibrary]
+
+class InterfaceLibrary:
+    include_dirs: list[str]
+    defines: list[str]
+    compile_flags: list[str]
+    link_flags: list[str]
+    links: list[StaticLibrary | ObjectLibrary | InterfaceLibrary]
+
+class Executable:
+    include_dirs: list[str]
+    path: str
+
+class DisabledTarget: ...
+class GeneratorVars: ...
+
+class Version:
+    str: str
+    major: int
+    minor: int
+    patch: int
+    revision: str
+
+class Package:
+    name: str
+    version: Version | None
+    license: str | None
+    description: str | None
+    authors: list[str]
+    repository: str | None
+    output_dir: str
+
+class GlobalOptions:
+    c_standard: str | None
+    cpp_standard: str | None
+    position_independent_code: bool | None
+    reproducible: bool | None
+
+class Compiler:
+    id: str
+    version: Version
+
+class Assembler:
+    id: str
+    version: Version
+
+class Toolchain:
+    c_compiler: Compiler | None
+    cpp_compiler: Compiler | None
+    nasm_assembler: Assembler | None
+
+class Program:
+    path: str
+    version: Version | None
+
+class Profile:
+    name: str
+    c_compile_flags: list[str]
+    cpp_compile_flags: list[str]
+    nasm_assemble_flags: list[str]
+
+class Env:
+    def get(self, name: str, default: str | None = None) -> str | None: ...
+
+class ContextCompiler:
+    target_triple: str
+
+class Context:
+    c_compiler: ContextCompiler | None
+    cpp_compiler: ContextCompiler | None
+    global_options: GlobalOptions
+    package_options: PackageOptions
+    profile: str | None
+    source_dir: str
+    build_dir: str
+
+class Global:
+    package: Package
+    global_options: GlobalOptions
+    package_options: PackageOptions
+    toolchain: Toolchain
+    profile: Profile | None
+    env: Env
+
+GLOBAL: Global
+threads: InterfaceLibrary
+openmp: InterfaceLibrary
+"#;
+
+/// Python-style type stubs for the recipe of the root package of `project`: the builtins, `GLOBAL` and the values of
+/// its dependencies, for editors with a Starlark or Python language server
+pub fn stubs(project: &Project) -> String {
+	let mut ret = "# Generated by catapult. Stubs of the builtins and values available in build.catapult.\n".to_owned();
+	ret += "import typing\n\n";
+
+	let mut options = project.info.package_options.iter().collect::<Vec<_>>();
+	options.sort_by_key(|(name, _)| name.as_str());
+	ret += "class PackageOptions:\n";
+	if options.is_empty() {
+		ret += "    ...\n";
+	}
+	for (name, value) in options {
+		let ty = match value {
+			PkgOpt::Bool(_) => "bool",
+			PkgOpt::Int(_) => "int",
+			PkgOpt::Float(_) => "float",
+			PkgOpt::String(_) => "str",
+		};
+		ret += &format!("    {}: {}\n", name, ty);
+	}
+	ret += "\n";
+	ret += STUB_TYPES;
+
+	for dep in &project.dependencies {
+		let class_name = format!("_{}_Package", dep.info.name);
+		ret += &format!("\nclass {}:\n", class_name);
+		if dep.info.exports.is_empty() {
+			ret += "    ...\n";
+		}
+		for (name, ty) in &dep.info.exports {
+			ret += &format!("    {}: {}\n", name, ty);
+		}
+		ret += &format!("\n{}: {}\n", dep.info.name, class_name);
+	}
+
+	let docs = documentation(project.info.api_version);
+	let mut members = docs.members.iter().collect::<Vec<_>>();
+	members.sort_by_key(|(name, _)| name.as_str());
+	for (name, member) in members {
+		if let DocMember::Function(func) = member {
+			let params = func.params.iter().map(param_string).collect::<Vec<_>>();
+			ret += &format!("\ndef {}({}) -> {}: ...\n", name, params.join(", "), func.ret.typ);
+		}
+	}
+	ret
+}
+
+/// Writes `stubs()` to the build directory, so editors can be pointed at a stable path
+pub fn write_stubs(project: &Project, build_dir: &Path) -> Result<(), String> {
+	let path = build_dir.join(STUBS_FILE);
+	fs::write(&path, stubs(project)).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+#[test]
+fn test_api_doc() {
+	let md = render(ApiDocFormat::Markdown, None).unwrap();
+	assert!(md.contains("## add_static_library\n"));
+	assert!(md.contains("name: str, sources: list[str], link_private: list[str] = ..."));
+	assert!(render(ApiDocFormat::Json, Some(LATEST_API_VERSION + 1)).is_err());
+}
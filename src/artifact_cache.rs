@@ -0,0 +1,88 @@
+//! The artifact cache: static libraries of packages fetched from a registry, stored in
+//! `<cache dir>/<key>/<file name>`. The key hashes the registry's hash of the package's sources along with the
+//! compilers, flags and files that go into building the library, so an entry doesn't need to be invalidated while the
+//! registry's record of the package is unchanged.
+
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+/// Bumped whenever what goes into a key changes, so that old entries aren't reused
+const KEY_VERSION: &str = "catapult-artifact-2";
+
+pub(crate) struct ArtifactKey(Sha256);
+
+impl ArtifactKey {
+	pub fn new() -> Self {
+		let mut key = ArtifactKey(Sha256::new());
+		key.add("version", KEY_VERSION);
+		key
+	}
+
+	/// Each value is labelled and length-prefixed so that different inputs can't hash the same
+	pub fn add(&mut self, label: &str, value: &str) {
+		for x in [label, value] {
+			self.0.update((x.len() as u64).to_le_bytes());
+			self.0.update(x.as_bytes());
+		}
+	}
+
+	pub fn add_file(&mut self, name: &str, path: &Path) -> io::Result<()> {
+		let content = fs::read(path)?;
+		self.add("file", name);
+		self.0.update((content.len() as u64).to_le_bytes());
+		self.0.update(content);
+		Ok(())
+	}
+
+	/// Adds the name and content of every file in `dir`, recursively.
+	/// Names are relative to `dir`, so the key doesn't depend on where the directory is.
+	pub fn add_dir(&mut self, dir: &Path) -> io::Result<()> {
+		let mut files = Vec::new();
+		let mut stack = vec![PathBuf::new()];
+		while let Some(rel) = stack.pop() {
+			for entry in fs::read_dir(dir.join(&rel))? {
+				let entry = entry?;
+				let path = rel.join(entry.file_name());
+				if entry.file_type()?.is_dir() {
+					stack.push(path);
+				} else {
+					files.push(path);
+				}
+			}
+		}
+		files.sort();
+		self.add("dir", &files.len().to_string());
+		for rel in files {
+			self.add_file(&rel.to_string_lossy().replace('\\', "/"), &dir.join(&rel))?;
+		}
+		Ok(())
+	}
+
+	pub fn finish(self) -> String {
+		self.0.finalize().iter().map(|x| format!("{:02x}", x)).collect()
+	}
+}
+
+/// Where the artifact named `file_name` with key `key` is stored
+pub(crate) fn entry_path(cache_dir: &Path, key: &str, file_name: &str) -> PathBuf {
+	cache_dir.join(key).join(file_name)
+}
+
+#[test]
+fn test_artifact_key() {
+	let key = |values: &[(&str, &str)]| {
+		let mut key = ArtifactKey::new();
+		for (label, value) in values {
+			key.add(label, value);
+		}
+		key.finish()
+	};
+	assert_eq!(key(&[("flag", "-O2")]), key(&[("flag", "-O2")]));
+	assert_ne!(key(&[("flag", "-O2")]), key(&[("flag", "-O3")]));
+	assert_ne!(key(&[("ab", "c")]), key(&[("a", "bc")]));
+	assert_eq!(key(&[]).len(), 64);
+}
@@ -0,0 +1,451 @@
+//! The package cache: downloaded packages are stored in `<cache dir>/catapult/cache/<name>/<channel>`
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	process,
+	time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::lockfile::Lockfile;
+
+pub(crate) const HASH_FILE: &str = "catapult.hash";
+/// SHA-256 digests of the files of an entry that come from the registry record, one `<digest>  <file>` per line
+pub(crate) const DIGESTS_FILE: &str = "catapult.digests";
+const LOCKFILE_INDEX: &str = "lockfiles.toml";
+
+pub struct CacheEntry {
+	pub name: String,
+	pub channel: String,
+	pub hash: String,
+	pub path: PathBuf,
+	/// Size on disk in bytes
+	pub size: u64,
+	/// The most recent time a known lockfile referencing this entry was used, if any
+	pub last_used: Option<SystemTime>,
+}
+
+#[derive(Default)]
+pub struct GcReport {
+	pub removed: Vec<CacheEntry>,
+	pub kept: Vec<CacheEntry>,
+}
+
+/// Lockfiles that have been written by catapult, so that cache entries still in use can be identified
+#[derive(Default, Deserialize, Serialize)]
+struct LockfileIndex {
+	#[serde(default)]
+	lockfile: Vec<IndexedLockfile>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct IndexedLockfile {
+	path: PathBuf,
+	/// Seconds since the unix epoch
+	last_used: u64,
+}
+
+fn catapult_cache_dir() -> Result<PathBuf, String> {
+	match dirs::cache_dir() {
+		Some(x) => Ok(x.join("catapult")),
+		None => Err("Could not find a HOME directory".to_owned()),
+	}
+}
+
+pub(crate) fn package_dir(name: &str, channel: &str) -> Result<PathBuf, String> {
+	Ok(catapult_cache_dir()?.join("cache").join(name).join(channel))
+}
+
+/// Takes an exclusive lock on `<path>.lock`, blocking until it is available. The lock is released when the returned file is dropped.
+pub(crate) fn lock_entry(path: &Path) -> Result<fs::File, String> {
+	let lock_path = path_with_suffix(path, ".lock");
+	if let Some(parent) = lock_path.parent() {
+		if let Err(e) = fs::create_dir_all(parent) {
+			return Err(format!("Error creating directory {}: {}", parent.display(), e));
+		}
+	}
+	let lock_file = match fs::File::create(&lock_path) {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error opening lock file {}: {}", lock_path.display(), e)),
+	};
+	match lock_file.try_lock() {
+		Ok(()) => return Ok(lock_file),
+		Err(fs::TryLockError::WouldBlock) => {
+			println!("Waiting for another catapult process to release {} ...", lock_path.display());
+		}
+		Err(fs::TryLockError::Error(e)) => {
+			return Err(format!("Error locking {}: {}", lock_path.display(), e));
+		}
+	}
+	match lock_file.lock() {
+		Ok(()) => Ok(lock_file),
+		Err(e) => Err(format!("Error locking {}: {}", lock_path.display(), e)),
+	}
+}
+
+pub(crate) fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut path = path.as_os_str().to_owned();
+	path.push(suffix);
+	PathBuf::from(path)
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+	Sha256::digest(bytes).iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// Writes the digests of `files`, given as (file name, contents), to the digests file of the entry at `dir`
+pub(crate) fn write_digests(dir: &Path, files: &[(&str, &[u8])]) -> Result<(), String> {
+	let digests = files
+		.iter()
+		.map(|(name, contents)| format!("{}  {}\n", sha256_hex(contents), name))
+		.collect::<String>();
+	let path = dir.join(DIGESTS_FILE);
+	fs::write(&path, digests).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// Checks that each of `files` has the digest of the contents given, both as recorded when the entry at `dir` was
+/// unpacked and as it is on disk now
+pub(crate) fn verify_digests(dir: &Path, files: &[(&str, &[u8])]) -> Result<(), String> {
+	let path = dir.join(DIGESTS_FILE);
+	let recorded = match fs::read_to_string(&path) {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error reading {}: {}", path.display(), e)),
+	};
+	for (name, contents) in files {
+		let expected = sha256_hex(contents);
+		match recorded.lines().find_map(|x| x.strip_suffix(name)?.strip_suffix("  ")) {
+			Some(x) if x == expected => {}
+			Some(_) => return Err(format!("The recorded digest of {} does not match the registry", name)),
+			None => return Err(format!("No digest of {} is recorded in {}", name, path.display())),
+		}
+		let file_path = dir.join(name);
+		match fs::read(&file_path) {
+			Ok(x) if sha256_hex(&x) == expected => {}
+			Ok(_) => return Err(format!("{} has been modified or truncated", file_path.display())),
+			Err(e) => return Err(format!("Error reading {}: {}", file_path.display(), e)),
+		}
+	}
+	Ok(())
+}
+
+/// Runs git with `args`, returning its standard output
+fn run_git(args: &[&str]) -> Result<String, String> {
+	let output = match process::Command::new("git").args(args).output() {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error executing git: {}", e)),
+	};
+	if !output.status.success() {
+		return Err(format!(
+			"Command \"git {}\" failed:\n{}",
+			args.join(" "),
+			String::from_utf8_lossy(&output.stderr).trim_end()
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// `name` with every character that isn't safe in a single path component replaced by `_`
+fn cache_dir_name(name: &str) -> String {
+	name.replace(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-', "_")
+}
+
+/// Whether `rev` is a full commit hash, which always names the same tree, unlike a branch, tag or abbreviated hash
+fn is_full_commit_hash(rev: &str) -> bool {
+	matches!(rev.len(), 40 | 64) && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Clones `url` into the cache and checks out `rev`, or the default branch if no rev is given.
+/// Checkouts are stored by the commit they resolved to. A rev that is a full commit hash reuses its checkout, while
+/// branches, tags and the default branch are cloned again each time so they pick up new commits.
+pub(crate) fn git_checkout(url: &str, rev: Option<&str>) -> Result<PathBuf, String> {
+	let repo_dir = catapult_cache_dir()?.join("git").join(cache_dir_name(url));
+	if let Some(rev) = rev.filter(|x| is_full_commit_hash(x)) {
+		let checkout_path = repo_dir.join(cache_dir_name(&rev.to_ascii_lowercase()));
+		let _lock = lock_entry(&checkout_path)?;
+		if checkout_path.exists() {
+			log::debug!("Git checkout found in cache: {}", checkout_path.display());
+			return Ok(checkout_path);
+		}
+	}
+
+	println!("Cloning {} ...", url);
+	if let Err(e) = fs::create_dir_all(&repo_dir) {
+		return Err(format!("Error creating directory {}: {}", repo_dir.display(), e));
+	}
+	let tmp_path = repo_dir.join(format!(".tmp-{}", uuid::Uuid::new_v4()));
+	let tmp_str = tmp_path.to_string_lossy();
+	let mut result = run_git(&["clone", "--quiet", url, &tmp_str]);
+	if let (Ok(_), Some(rev)) = (&result, rev) {
+		result = run_git(&["-C", &tmp_str, "checkout", "--quiet", rev]);
+	}
+	let commit = match result.and_then(|_| run_git(&["-C", &tmp_str, "rev-parse", "HEAD"])) {
+		Ok(x) => x,
+		Err(e) => {
+			let _ = fs::remove_dir_all(&tmp_path);
+			return Err(e);
+		}
+	};
+	let checkout_path = repo_dir.join(cache_dir_name(&commit));
+	let _lock = lock_entry(&checkout_path)?;
+	if checkout_path.exists() {
+		// The rev still points to a commit that's already checked out
+		let _ = fs::remove_dir_all(&tmp_path);
+		return Ok(checkout_path);
+	}
+	if let Err(e) = fs::rename(&tmp_path, &checkout_path) {
+		let _ = fs::remove_dir_all(&tmp_path);
+		return Err(format!("Error moving {} to {}: {}", tmp_path.display(), checkout_path.display(), e));
+	}
+	Ok(checkout_path)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+	time.duration_since(SystemTime::UNIX_EPOCH)
+		.map(|x| x.as_secs())
+		.unwrap_or(0)
+}
+
+fn read_index(index_path: &Path) -> Result<LockfileIndex, String> {
+	if !index_path.exists() {
+		return Ok(LockfileIndex::default());
+	}
+	let index_toml = match fs::read_to_string(index_path) {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error opening {}: {}", index_path.display(), e)),
+	};
+	match toml::from_str::<LockfileIndex>(&index_toml) {
+		Ok(x) => Ok(x),
+		Err(e) => Err(format!("Error reading {}: {}", index_path.display(), e)),
+	}
+}
+
+fn write_index(index_path: &Path, index: &LockfileIndex) -> Result<(), String> {
+	let index_toml = match toml::to_string(index) {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error serializing {}: {}", index_path.display(), e)),
+	};
+	match fs::write(index_path, index_toml) {
+		Ok(()) => Ok(()),
+		Err(e) => Err(format!("Error writing {}: {}", index_path.display(), e)),
+	}
+}
+
+/// Record that the lockfile at `lock_path` was just used, so that `gc` keeps the packages it references
+pub(crate) fn register_lockfile(lock_path: &Path) -> Result<(), String> {
+	let lock_path = match fs::canonicalize(lock_path) {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error resolving {}: {}", lock_path.display(), e)),
+	};
+	let index_path = catapult_cache_dir()?.join(LOCKFILE_INDEX);
+	let _lock = lock_entry(&index_path)?;
+	let mut index = read_index(&index_path)?;
+	let now = unix_secs(SystemTime::now());
+	match index.lockfile.iter_mut().find(|x| x.path == lock_path) {
+		Some(x) => x.last_used = now,
+		None => index.lockfile.push(IndexedLockfile { path: lock_path, last_used: now }),
+	}
+	write_index(&index_path, &index)
+}
+
+fn dir_size(path: &Path) -> u64 {
+	let mut size = 0;
+	if let Ok(read_dir) = fs::read_dir(path) {
+		for entry in read_dir.flatten() {
+			match entry.file_type() {
+				Ok(x) if x.is_dir() => size += dir_size(&entry.path()),
+				Ok(_) => size += entry.metadata().map(|x| x.len()).unwrap_or(0),
+				Err(_) => {}
+			}
+		}
+	}
+	size
+}
+
+/// A cache entry is any directory containing a hash file. Channels may contain slashes, so entries can be nested.
+fn find_entries(dir: &Path, name: &str, channel: &Path, entries: &mut Vec<CacheEntry>) {
+	if let Ok(hash) = fs::read_to_string(dir.join(HASH_FILE)) {
+		entries.push(CacheEntry {
+			name: name.to_owned(),
+			channel: channel.to_string_lossy().replace('\\', "/"),
+			hash: hash.trim().to_owned(),
+			path: dir.to_owned(),
+			size: dir_size(dir),
+			last_used: None,
+		});
+		return;
+	}
+	let read_dir = match fs::read_dir(dir) {
+		Ok(x) => x,
+		Err(_) => return,
+	};
+	for entry in read_dir.flatten() {
+		if !entry.file_type().map(|x| x.is_dir()).unwrap_or(false) {
+			continue;
+		}
+		let file_name = entry.file_name();
+		if file_name.to_string_lossy().contains(".tmp-") {
+			// An unpack in progress (or an abandoned one)
+			continue;
+		}
+		find_entries(&entry.path(), name, &channel.join(&file_name), entries);
+	}
+}
+
+/// All packages in the cache, annotated with the last time a known lockfile referenced them
+pub fn list() -> Result<Vec<CacheEntry>, String> {
+	let cache_root = catapult_cache_dir()?;
+	let mut entries = Vec::new();
+	if let Ok(read_dir) = fs::read_dir(cache_root.join("cache")) {
+		for entry in read_dir.flatten() {
+			if entry.file_type().map(|x| x.is_dir()).unwrap_or(false) {
+				let name = entry.file_name().to_string_lossy().to_string();
+				find_entries(&entry.path(), &name, Path::new(""), &mut entries);
+			}
+		}
+	}
+	entries.sort_by(|a, b| (&a.name, &a.channel).cmp(&(&b.name, &b.channel)));
+
+	let index = read_index(&cache_root.join(LOCKFILE_INDEX))?;
+	for indexed in &index.lockfile {
+		let lock_dir = match indexed.path.parent() {
+			Some(x) => x,
+			None => continue,
+		};
+		let lockfile = match Lockfile::read(lock_dir) {
+			Ok(x) => x,
+			Err(e) => {
+				log::debug!("{}", e);
+				continue;
+			}
+		};
+		let last_used = SystemTime::UNIX_EPOCH + Duration::from_secs(indexed.last_used);
+		for pkg in &lockfile.package {
+			for entry in entries.iter_mut() {
+				if entry.name == pkg.name && entry.channel == pkg.channel && entry.hash == pkg.hash {
+					entry.last_used = Some(entry.last_used.map_or(last_used, |x| x.max(last_used)));
+				}
+			}
+		}
+	}
+	Ok(entries)
+}
+
+/// Removes cache entries not referenced by any lockfile used within `max_age`.
+/// If the remaining entries exceed `max_size` bytes, the least recently used are removed until they fit.
+pub fn gc(max_size: Option<u64>, max_age: Option<Duration>) -> Result<GcReport, String> {
+	let now = SystemTime::now();
+	let is_recent = |last_used: Option<SystemTime>| match (last_used, max_age) {
+		(None, _) => false,
+		(Some(_), None) => true,
+		(Some(x), Some(age)) => now.duration_since(x).map(|x| x <= age).unwrap_or(true),
+	};
+	let (mut kept, mut stale): (Vec<_>, Vec<_>) = list()?.into_iter().partition(|x| is_recent(x.last_used));
+	if let Some(max_size) = max_size {
+		// Most recently used first
+		kept.sort_by_key(|x| std::cmp::Reverse(x.last_used));
+		let mut total = 0;
+		let mut idx = 0;
+		while idx < kept.len() {
+			total += kept[idx].size;
+			if total > max_size {
+				break;
+			}
+			idx += 1;
+		}
+		stale.extend(kept.split_off(idx));
+	}
+
+	let mut report = GcReport { removed: Vec::new(), kept };
+	for entry in stale {
+		let _lock = lock_entry(&entry.path)?;
+		// Re-check under the lock in case another process replaced the entry in the meantime
+		match fs::read_to_string(entry.path.join(HASH_FILE)) {
+			Ok(hash) if hash.trim() == entry.hash => {}
+			_ => continue,
+		}
+		if let Err(e) = fs::remove_dir_all(&entry.path) {
+			return Err(format!("Error removing {}: {}", entry.path.display(), e));
+		}
+		report.removed.push(entry);
+	}
+	Ok(report)
+}
+
+/// Parses a size such as `500M` or `2G`. Suffixes are binary multiples.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+	let size = size.trim();
+	let (num, mult) = match size.char_indices().last() {
+		Some((i, 'K' | 'k')) => (&size[..i], 1 << 10),
+		Some((i, 'M' | 'm')) => (&size[..i], 1 << 20),
+		Some((i, 'G' | 'g')) => (&size[..i], 1 << 30),
+		Some((i, 'B' | 'b')) => (&size[..i], 1),
+		_ => (size, 1),
+	};
+	match num.trim().parse::<u64>() {
+		Ok(x) => x
+			.checked_mul(mult)
+			.ok_or_else(|| format!("Size \"{}\" is too large", size)),
+		Err(_) => Err(format!("Invalid size \"{}\". Expected a number with an optional K, M or G suffix", size)),
+	}
+}
+
+/// Parses an age such as `30d` or `12h`. A number without a suffix is a number of days.
+pub fn parse_age(age: &str) -> Result<Duration, String> {
+	let age = age.trim();
+	let (num, mult) = match age.char_indices().last() {
+		Some((i, 's')) => (&age[..i], 1),
+		Some((i, 'm')) => (&age[..i], 60),
+		Some((i, 'h')) => (&age[..i], 60 * 60),
+		Some((i, 'd')) => (&age[..i], 24 * 60 * 60),
+		Some((i, 'w')) => (&age[..i], 7 * 24 * 60 * 60),
+		_ => (age, 24 * 60 * 60),
+	};
+	match num.trim().parse::<u64>() {
+		Ok(x) => match x.checked_mul(mult) {
+			Some(secs) => Ok(Duration::from_secs(secs)),
+			None => Err(format!("Age \"{}\" is too large", age)),
+		},
+		Err(_) => Err(format!("Invalid age \"{}\". Expected a number with an optional s, m, h, d or w suffix", age)),
+	}
+}
+
+#[test]
+fn test_parse_size_and_age() {
+	assert_eq!(parse_size("1024").unwrap(), 1024);
+	assert_eq!(parse_size("500M").unwrap(), 500 * 1024 * 1024);
+	assert_eq!(parse_size("2g").unwrap(), 2 * 1024 * 1024 * 1024);
+	assert!(parse_size("M").is_err());
+	assert_eq!(parse_age("30").unwrap(), Duration::from_secs(30 * 24 * 60 * 60));
+	assert_eq!(parse_age("12h").unwrap(), Duration::from_secs(12 * 60 * 60));
+	assert!(parse_age("1y").is_err());
+	assert!(parse_size("18446744073709551615G").is_err());
+	assert!(parse_age("18446744073709551615w").is_err());
+}
+
+#[test]
+fn test_git_cache_names() {
+	assert_eq!(cache_dir_name("../x"), ".._x");
+	assert_eq!(cache_dir_name("feature/x"), "feature_x");
+	assert!(is_full_commit_hash("0123456789abcdef0123456789ABCDEF01234567"));
+	assert!(!is_full_commit_hash("0123456"));
+	assert!(!is_full_commit_hash("main"));
+}
+
+#[test]
+fn test_verify_digests() {
+	let dir = std::env::temp_dir().join(format!("catapult-test-digests-{}", uuid::Uuid::new_v4()));
+	fs::create_dir_all(&dir).unwrap();
+	let files: [(&str, &[u8]); 2] = [("catapult.toml", b"[package]\n"), ("build.catapult", b"")];
+	assert!(verify_digests(&dir, &files).is_err());
+	for (name, contents) in files {
+		fs::write(dir.join(name), contents).unwrap();
+	}
+	write_digests(&dir, &files).unwrap();
+	assert!(verify_digests(&dir, &files).is_ok());
+	fs::write(dir.join("catapult.toml"), b"[pack").unwrap();
+	assert!(verify_digests(&dir, &files).is_err());
+	let _ = fs::remove_dir_all(&dir);
+}
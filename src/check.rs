@@ -0,0 +1,161 @@
+//! `catapult check`: problems in recipes that evaluate fine but are likely mistakes
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::{
+	link_type::LinkPtr,
+	project::Project,
+	starlark_api::LATEST_API_VERSION,
+	target::Target, //
+};
+
+/// A problem found by `catapult check`
+#[derive(Clone, Debug, Serialize)]
+pub struct Issue {
+	/// What kind of problem it is: `unused-dependency`, `unused-target`, `missing-source` or `deprecated-api`
+	pub code: &'static str,
+	pub package: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub target: Option<String>,
+	pub message: String,
+}
+
+/// The issues in every package of the project. Dependencies are checked before their dependents.
+pub(crate) fn check(project: &Project) -> Vec<Issue> {
+	let mut packages = Vec::new();
+	collect_packages(project, &mut BTreeSet::new(), &mut packages);
+
+	// Every (package, target) that some target links
+	let mut linked = BTreeSet::new();
+	for package in &packages {
+		for link in package_links(package) {
+			linked.insert((link.project().info.name.clone(), link.name().to_owned()));
+		}
+	}
+
+	let mut issues = Vec::new();
+	for package in packages {
+		check_package(package, &linked, &mut issues);
+	}
+	issues
+}
+
+fn collect_packages<'a>(project: &'a Project, visited: &mut BTreeSet<String>, out: &mut Vec<&'a Project>) {
+	if !visited.insert(project.info.name.clone()) {
+		return;
+	}
+	for dep in &project.dependencies {
+		collect_packages(dep, visited, out);
+	}
+	out.push(project);
+}
+
+/// The targets linked by the targets of `project`
+fn package_links(project: &Project) -> Vec<&LinkPtr> {
+	let mut links = Vec::new();
+	for exe in &project.executables {
+		links.extend(&exe.links);
+	}
+	for lib in &project.static_libraries {
+		links.extend(lib.link_private.iter().chain(&lib.link_public));
+	}
+	for lib in &project.object_libraries {
+		links.extend(lib.link_private.iter().chain(&lib.link_public));
+	}
+	for lib in &project.interface_libraries {
+		links.extend(&lib.links);
+	}
+	links
+}
+
+fn check_package(project: &Project, linked: &BTreeSet<(String, String)>, issues: &mut Vec<Issue>) {
+	let package = &project.info.name;
+	let mut issue = |code, target: Option<&str>, message: String| {
+		issues.push(Issue {
+			code,
+			package: package.clone(),
+			target: target.map(str::to_owned),
+			message,
+		})
+	};
+
+	if project.info.api_version < LATEST_API_VERSION {
+		issue(
+			"deprecated-api",
+			None,
+			format!(
+				"Package \"{}\" uses version {} of the recipe API. The latest is version {}.",
+				package, project.info.api_version, LATEST_API_VERSION
+			),
+		);
+	}
+
+	let linked_packages = package_links(project)
+		.into_iter()
+		.map(|x| x.project().info.name.clone())
+		.collect::<BTreeSet<_>>();
+	for dep in &project.dependencies {
+		if !linked_packages.contains(&dep.info.name) {
+			issue(
+				"unused-dependency",
+				None,
+				format!(
+					"Package \"{}\" depends on \"{}\", but none of its targets link a library of it",
+					package, dep.info.name
+				),
+			);
+		}
+	}
+
+	// Public libraries may be linked by packages that depend on this one, but nothing else can link a private one
+	let libraries = project
+		.static_libraries
+		.iter()
+		.map(|x| x.name.as_str())
+		.chain(project.object_libraries.iter().map(|x| x.name.as_str()))
+		.chain(project.interface_libraries.iter().map(|x| x.name.as_str()));
+	for name in libraries {
+		let exported = project.info.exports.iter().any(|(x, _)| x == name);
+		if !exported && !linked.contains(&(package.clone(), name.to_owned())) {
+			issue(
+				"unused-target",
+				Some(name),
+				format!("Library \"{}::{}\" is private to its package, but no target links it", package, name),
+			);
+		}
+	}
+
+	let sources = project
+		.executables
+		.iter()
+		.map(|x| (x.name.as_str(), &x.sources))
+		.chain(project.static_libraries.iter().map(|x| (x.name.as_str(), &x.sources)))
+		.chain(project.object_libraries.iter().map(|x| (x.name.as_str(), &x.sources)));
+	for (name, sources) in sources {
+		for src in sources.iter() {
+			if !src.full.exists() {
+				issue(
+					"missing-source",
+					Some(name),
+					format!("Source \"{}\" of \"{}::{}\" does not exist", src.name, package, name),
+				);
+			}
+		}
+	}
+}
+
+/// One line per issue, e.g. `warning[unused-target]: Library "mypkg::helpers" is private to its package, ...`
+pub fn render_issues(issues: &[Issue]) -> String {
+	let mut out = String::new();
+	for issue in issues {
+		out += &format!("warning[{}]: {}\n", issue.code, issue.message);
+	}
+	match issues.len() {
+		0 => out += "No issues found\n",
+		1 => out += "1 issue found\n",
+		n => out += &format!("{} issues found\n", n),
+	}
+	out
+}
@@ -0,0 +1,344 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+const CONFIG_DIR: &str = "catapult";
+const REPO_CONFIG_DIR: &str = ".catapult";
+const CONFIG_TOML: &str = "config.toml";
+/// Written to the build directory when it's configured
+pub const BUILD_DIR_SETTINGS: &str = "CatapultCache.toml";
+
+/// Default CLI settings, read from the user's `~/.config/catapult/config.toml` and the nearest
+/// `.catapult/config.toml` in the source directory or its parents. Settings in the repo config take precedence
+/// over the user config, and command line options take precedence over both.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+	pub generator: Option<String>,
+	/// Relative paths are resolved against the directory containing `.catapult`, or the user config directory
+	pub toolchain: Option<PathBuf>,
+	pub profile: Option<String>,
+	/// Package name -> option name -> value, like `--package-option <package>:<option>=<value>`
+	#[serde(default)]
+	package_options: BTreeMap<String, BTreeMap<String, toml::Value>>,
+	/// Registry URL -> mirrors to try, in order, if the registry can't provide a package
+	#[serde(default)]
+	pub mirrors: BTreeMap<String, Vec<String>>,
+	/// Like `--artifact-cache`. Relative paths are resolved like `toolchain`.
+	pub artifact_cache: Option<PathBuf>,
+	/// Like `--layout`
+	pub layout: Option<String>,
+	/// The config files that were read, in the order they were merged
+	#[serde(skip)]
+	pub files: Vec<PathBuf>,
+}
+
+impl Config {
+	fn read(path: &Path, base_dir: &Path) -> Result<Option<Config>, String> {
+		let content = match fs::read_to_string(path) {
+			Ok(x) => x,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(format!("Error reading {}: {}", path.display(), e)),
+		};
+		let mut config = match toml::from_str::<Config>(&content) {
+			Ok(x) => x,
+			Err(e) => return Err(format!("Error reading {}: {}", path.display(), e)),
+		};
+		log::info!("Using config {}", path.display());
+		config.toolchain = config.toolchain.map(|x| base_dir.join(x));
+		config.artifact_cache = config.artifact_cache.map(|x| base_dir.join(x));
+		config.files = vec![path.to_owned()];
+		Ok(Some(config))
+	}
+
+	/// Settings in `other` replace those in `self`. Package options and mirrors are replaced individually.
+	fn merge(mut self, other: Config) -> Config {
+		for (package, options) in other.package_options {
+			self.package_options.entry(package).or_default().extend(options);
+		}
+		self.mirrors.extend(other.mirrors);
+		self.files.extend(other.files);
+		Config {
+			generator: other.generator.or(self.generator),
+			toolchain: other.toolchain.or(self.toolchain),
+			profile: other.profile.or(self.profile),
+			package_options: self.package_options,
+			mirrors: self.mirrors,
+			artifact_cache: other.artifact_cache.or(self.artifact_cache),
+			layout: other.layout.or(self.layout),
+			files: self.files,
+		}
+	}
+
+	/// Package options in the form they're given on the command line
+	pub fn package_options(&self) -> BTreeMap<String, BTreeMap<String, String>> {
+		self.package_options
+			.iter()
+			.map(|(package, options)| {
+				let options = options
+					.iter()
+					.map(|(name, value)| {
+						let value = match value {
+							toml::Value::String(x) => x.clone(),
+							x => x.to_string(),
+						};
+						(name.clone(), value)
+					})
+					.collect();
+				(package.clone(), options)
+			})
+			.collect()
+	}
+
+	/// `registries` with each registry followed by its mirrors
+	pub(crate) fn with_mirrors(&self, registries: Vec<String>) -> Vec<String> {
+		let mut ret = Vec::new();
+		for registry in registries {
+			let mirrors = self.mirrors.get(&registry).cloned().unwrap_or_default();
+			ret.push(registry);
+			ret.extend(mirrors);
+		}
+		ret
+	}
+}
+
+/// `$XDG_CONFIG_HOME`, or `~/.config`, on every Unix including macOS, where `dirs::config_dir()` is
+/// `~/Library/Application Support`. `%APPDATA%` on Windows.
+fn user_config_dir() -> Option<PathBuf> {
+	if cfg!(windows) {
+		return dirs::config_dir();
+	}
+	match std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+		Some(x) if x.is_absolute() => Some(x),
+		_ => dirs::home_dir().map(|x| x.join(".config")),
+	}
+}
+
+/// Reads the user config and the repo config for the project in `src_dir`
+pub fn load(src_dir: &Path) -> Result<Config, String> {
+	let mut config = Config::default();
+	if let Some(config_dir) = user_config_dir() {
+		let user_dir = config_dir.join(CONFIG_DIR);
+		if let Some(user_config) = Config::read(&user_dir.join(CONFIG_TOML), &user_dir)? {
+			config = config.merge(user_config);
+		}
+	}
+	let src_dir = match fs::canonicalize(src_dir) {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error resolving path: {} (path: {})", e, src_dir.display())),
+	};
+	for dir in src_dir.ancestors() {
+		if let Some(repo_config) = Config::read(&dir.join(REPO_CONFIG_DIR).join(CONFIG_TOML), dir)? {
+			config = config.merge(repo_config);
+			break;
+		}
+	}
+	Ok(config)
+}
+
+/// The settings a build directory was configured with, so that later commands on the build directory only need
+/// `--build-dir`
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildDirSettings {
+	pub source_dir: PathBuf,
+	pub generator: String,
+	pub toolchain: PathBuf,
+	/// Not set for MSVC, which generates every profile
+	pub profile: Option<String>,
+	#[serde(default)]
+	pub package_options: BTreeMap<String, BTreeMap<String, String>>,
+	/// The output layout, if one was given
+	#[serde(default)]
+	pub layout: Option<String>,
+	#[serde(default)]
+	pub relative_paths: bool,
+	#[serde(default)]
+	pub artifact_cache: Option<PathBuf>,
+	#[serde(default)]
+	pub allow_unknown_options: bool,
+}
+
+/// The settings given on the command line, which mustn't differ from those the build directory was configured with
+#[derive(Default)]
+pub struct GivenSettings<'a> {
+	pub source_dir: Option<&'a Path>,
+	pub generator: Option<&'a str>,
+	pub toolchain: Option<&'a Path>,
+	pub profile: Option<&'a str>,
+	pub layout: Option<&'a str>,
+	pub artifact_cache: Option<&'a Path>,
+	pub package_options: Option<&'a BTreeMap<String, BTreeMap<String, String>>>,
+}
+
+impl BuildDirSettings {
+	/// `None` if the build directory hasn't been configured
+	pub fn read(build_dir: &Path) -> Result<Option<BuildDirSettings>, String> {
+		let path = build_dir.join(BUILD_DIR_SETTINGS);
+		let content = match fs::read_to_string(&path) {
+			Ok(x) => x,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(format!("Error reading {}: {}", path.display(), e)),
+		};
+		match toml::from_str::<BuildDirSettings>(&content) {
+			Ok(x) => Ok(Some(x)),
+			Err(e) => Err(format!("Error reading {}: {}", path.display(), e)),
+		}
+	}
+
+	pub fn write(&self, build_dir: &Path) -> Result<(), String> {
+		let path = build_dir.join(BUILD_DIR_SETTINGS);
+		let content = toml::to_string(self).map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+		fs::write(&path, content).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+	}
+
+	/// Errors if a setting given on the command line differs from the one the build directory was configured with.
+	/// Settings the build directory wasn't configured with, such as a new package option, don't conflict.
+	/// Paths must be absolute.
+	pub fn check_conflicts(&self, given: &GivenSettings) -> Result<(), String> {
+		let conflict = |opt: &str, given: &str, configured: &str| {
+			Err(format!(
+				"--{} {} conflicts with the {} the build directory was configured with ({}). Use a new build \
+				 directory, or delete {} from this one to configure it again.",
+				opt, given, opt, configured, BUILD_DIR_SETTINGS
+			))
+		};
+		if let Some(x) = given.source_dir {
+			if x != self.source_dir {
+				return conflict("source-dir", &x.display().to_string(), &self.source_dir.display().to_string());
+			}
+		}
+		if let Some(x) = given.generator {
+			if x != self.generator {
+				return conflict("generator", x, &self.generator);
+			}
+		}
+		if let Some(x) = given.toolchain {
+			if x != self.toolchain {
+				return conflict("toolchain", &x.display().to_string(), &self.toolchain.display().to_string());
+			}
+		}
+		if let (Some(x), Some(configured)) = (given.profile, &self.profile) {
+			if x != configured {
+				return conflict("profile", x, configured);
+			}
+		}
+		if let (Some(x), Some(configured)) = (given.layout, &self.layout) {
+			if x != configured {
+				return conflict("layout", x, configured);
+			}
+		}
+		if let (Some(x), Some(configured)) = (given.artifact_cache, &self.artifact_cache) {
+			if x != configured {
+				return conflict("artifact-cache", &x.display().to_string(), &configured.display().to_string());
+			}
+		}
+		for (pkg_name, opts) in given.package_options.into_iter().flatten() {
+			for (opt_name, opt_val) in opts {
+				match self.package_options.get(pkg_name).and_then(|x| x.get(opt_name)) {
+					Some(configured) if configured != opt_val => {
+						return conflict(
+							"package-option",
+							&format!("{}:{}={}", pkg_name, opt_name, opt_val),
+							&format!("{}:{}={}", pkg_name, opt_name, configured),
+						)
+					}
+					_ => (),
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+#[test]
+fn test_merge() {
+	let user = toml::from_str::<Config>(
+		r#"
+generator = "Ninja"
+profile = "Debug"
+mirrors = { "https://a" = ["https://a-mirror"] }
+[package_options]
+zstd = { legacy = false, level = 3 }
+"#,
+	)
+	.unwrap();
+	let repo = toml::from_str::<Config>(
+		r#"
+profile = "Release"
+[package_options]
+zstd = { legacy = true }
+"#,
+	)
+	.unwrap();
+	let config = user.merge(repo);
+	assert_eq!(config.generator.as_deref(), Some("Ninja"));
+	assert_eq!(config.profile.as_deref(), Some("Release"));
+	let zstd = &config.package_options()["zstd"];
+	assert_eq!(zstd["legacy"], "true");
+	assert_eq!(zstd["level"], "3");
+	assert_eq!(
+		config.with_mirrors(vec!["https://a".to_owned(), "https://b".to_owned()]),
+		["https://a", "https://a-mirror", "https://b"]
+	);
+}
+
+#[test]
+fn test_build_dir_settings_conflicts() {
+	let settings = BuildDirSettings {
+		source_dir: PathBuf::from("/src"),
+		generator: "Ninja".to_owned(),
+		toolchain: PathBuf::from("/tc.toml"),
+		profile: Some("Debug".to_owned()),
+		package_options: BTreeMap::from([("zstd".to_owned(), BTreeMap::from([("level".to_owned(), "3".to_owned())]))]),
+		layout: Some("isolated".to_owned()),
+		relative_paths: true,
+		artifact_cache: None,
+		allow_unknown_options: false,
+	};
+	let settings = toml::from_str::<BuildDirSettings>(&toml::to_string(&settings).unwrap()).unwrap();
+	assert_eq!(settings.layout.as_deref(), Some("isolated"));
+	assert!(settings.relative_paths);
+	let options =
+		|opt: &str, x: &str| BTreeMap::from([("zstd".to_owned(), BTreeMap::from([(opt.to_owned(), x.to_owned())]))]);
+	let level_3 = options("level", "3");
+	assert!(settings
+		.check_conflicts(&GivenSettings {
+			source_dir: Some(Path::new("/src")),
+			generator: Some("Ninja"),
+			profile: Some("Debug"),
+			layout: Some("isolated"),
+			package_options: Some(&level_3),
+			..Default::default()
+		})
+		.is_ok());
+	assert!(settings
+		.check_conflicts(&GivenSettings { generator: Some("MSVC"), ..Default::default() })
+		.is_err());
+	assert!(settings
+		.check_conflicts(&GivenSettings { profile: Some("Release"), ..Default::default() })
+		.is_err());
+	assert!(settings
+		.check_conflicts(&GivenSettings { layout: Some("flat"), ..Default::default() })
+		.is_err());
+	// The artifact cache wasn't configured, so one can be added
+	assert!(settings
+		.check_conflicts(&GivenSettings {
+			artifact_cache: Some(Path::new("/cache")),
+			..Default::default()
+		})
+		.is_ok());
+	let level_4 = options("level", "4");
+	assert!(settings
+		.check_conflicts(&GivenSettings { package_options: Some(&level_4), ..Default::default() })
+		.is_err());
+	// Options the build directory wasn't configured with are merged in
+	let legacy = options("legacy", "true");
+	assert!(settings
+		.check_conflicts(&GivenSettings { package_options: Some(&legacy), ..Default::default() })
+		.is_ok());
+}
@@ -0,0 +1,83 @@
+use std::{fs, path::Path};
+
+use allocative::Allocative;
+
+use crate::misc::macro_prefix;
+
+/// A header of `#define`s from `generate_config_header()`, usually the results of compiler checks
+#[derive(Clone, Debug, Allocative)]
+pub struct ConfigHeader {
+	/// The header is `<name>.h`
+	pub name: String,
+	pub defines: Vec<(String, ConfigValue)>,
+}
+
+/// The value of a define in a config header
+#[derive(Clone, Debug, PartialEq, Allocative)]
+pub enum ConfigValue {
+	/// `True` defines the macro as `1`, `False` leaves it undefined
+	Bool(bool),
+	Int(i32),
+	/// Written as it is, so a string literal needs its own quotes
+	Str(String),
+}
+
+impl ConfigHeader {
+	pub(crate) fn content(&self) -> String {
+		let guard = macro_prefix(&self.name) + "_H";
+		let mut content = format!("#ifndef {guard}\n#define {guard}\n\n");
+		for (name, value) in &self.defines {
+			content += &match value {
+				ConfigValue::Bool(true) => format!("#define {name} 1\n"),
+				ConfigValue::Bool(false) => format!("/* #undef {name} */\n"),
+				ConfigValue::Int(x) => format!("#define {name} {x}\n"),
+				ConfigValue::Str(x) => format!("#define {name} {x}\n"),
+			};
+		}
+		content += &format!("\n#endif /* {guard} */\n");
+		content
+	}
+
+	/// Writes `<name>.h` to `<build_dir>/<project_name>/include`, returning the include dir.
+	/// The file is left untouched if its content hasn't changed, so dependent objects aren't rebuilt.
+	pub(crate) fn write(&self, build_dir: &Path, project_name: &str) -> Result<String, String> {
+		let include_dir = build_dir.join(project_name).join("include");
+		if let Err(e) = fs::create_dir_all(&include_dir) {
+			return Err(format!("Error creating directory {}: {}", include_dir.display(), e));
+		}
+		let header_path = include_dir.join(self.name.clone() + ".h");
+		let content = self.content();
+		if fs::read_to_string(&header_path).ok().as_deref() != Some(content.as_str()) {
+			if let Err(e) = fs::write(&header_path, content) {
+				return Err(format!("Error writing {}: {}", header_path.display(), e));
+			}
+		}
+		Ok(include_dir.to_string_lossy().to_string())
+	}
+}
+
+#[test]
+fn test_content() {
+	let header = ConfigHeader {
+		name: "my-lib_config".to_owned(),
+		defines: vec![
+			("HAVE_EPOLL".to_owned(), ConfigValue::Bool(true)),
+			("HAVE_KQUEUE".to_owned(), ConfigValue::Bool(false)),
+			("MAX_EVENTS".to_owned(), ConfigValue::Int(64)),
+			("VERSION".to_owned(), ConfigValue::Str("\"1.2\"".to_owned())),
+		],
+	};
+	assert_eq!(
+		header.content(),
+		r#"#ifndef MY_LIB_CONFIG_H
+#define MY_LIB_CONFIG_H
+
+#define HAVE_EPOLL 1
+/* #undef HAVE_KQUEUE */
+#define MAX_EVENTS 64
+#define VERSION "1.2"
+
+#endif /* MY_LIB_CONFIG_H */
+"#
+	);
+}
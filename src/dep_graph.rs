@@ -0,0 +1,113 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::Serialize;
+
+/// Where a resolved dependency came from
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "source", rename_all = "lowercase")]
+pub enum DepSource {
+	Registry {
+		registry: String,
+		version: String,
+		channel: String,
+	},
+	Git {
+		url: String,
+		rev: Option<String>,
+	},
+	Path {
+		path: String,
+	},
+}
+
+impl fmt::Display for DepSource {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			DepSource::Registry { registry, version, channel } => write!(f, "v{version} ({channel} @ {registry})"),
+			DepSource::Git { url, rev: Some(rev) } => write!(f, "(git: {url} @ {rev})"),
+			DepSource::Git { url, rev: None } => write!(f, "(git: {url})"),
+			DepSource::Path { path } => write!(f, "(path: {path})"),
+		}
+	}
+}
+
+/// The resolved dependency graph. Each edge is recorded against the package that requested it.
+#[derive(Debug, Default)]
+pub struct DepGraph {
+	pub root: String,
+	pub sources: BTreeMap<String, DepSource>,
+	/// Package name -> names of the dependencies it requested, in manifest order
+	pub edges: BTreeMap<String, Vec<String>>,
+}
+
+impl DepGraph {
+	pub(crate) fn add_edge(&mut self, requester: &str, dependency: &str) {
+		self.edges
+			.entry(requester.to_owned())
+			.or_default()
+			.push(dependency.to_owned());
+	}
+
+	/// Renders the graph like `cargo tree`. Packages already printed elsewhere are marked with `(*)` and not expanded again.
+	pub fn render(&self) -> String {
+		let mut out = self.root.clone() + "\n";
+		let mut printed = Vec::new();
+		self.render_children(&self.root, "", &mut printed, &mut out);
+		out
+	}
+
+	fn render_children(&self, name: &str, prefix: &str, printed: &mut Vec<String>, out: &mut String) {
+		let children = match self.edges.get(name) {
+			Some(x) => x,
+			None => return,
+		};
+		for (i, child) in children.iter().enumerate() {
+			let last = i + 1 == children.len();
+			*out += prefix;
+			*out += if last { "└── " } else { "├── " };
+			*out += child;
+			if let Some(source) = self.sources.get(child) {
+				*out += &format!(" {source}");
+			}
+			let seen = printed.contains(child);
+			if seen && self.edges.contains_key(child) {
+				*out += " (*)";
+			}
+			*out += "\n";
+			if !seen {
+				printed.push(child.clone());
+				let child_prefix = prefix.to_owned() + if last { "    " } else { "│   " };
+				self.render_children(child, &child_prefix, printed, out);
+			}
+		}
+	}
+}
+
+#[test]
+fn test_render() {
+	let mut graph = DepGraph { root: "app".to_owned(), ..Default::default() };
+	graph.sources.insert(
+		"zstd".to_owned(),
+		DepSource::Registry {
+			registry: "https://reg".to_owned(),
+			version: "1.5.5".to_owned(),
+			channel: "main".to_owned(),
+		},
+	);
+	graph
+		.sources
+		.insert("mylib".to_owned(), DepSource::Path { path: "../mylib".to_owned() });
+	graph.add_edge("app", "mylib");
+	graph.add_edge("app", "zstd");
+	graph.add_edge("mylib", "zstd");
+	graph.add_edge("zstd", "xxhash");
+	assert_eq!(
+		graph.render(),
+		"app
+├── mylib (path: ../mylib)
+│   └── zstd v1.5.5 (main @ https://reg)
+│       └── xxhash
+└── zstd v1.5.5 (main @ https://reg) (*)
+"
+	);
+}
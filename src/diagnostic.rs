@@ -0,0 +1,147 @@
+use std::{
+	env, fmt,
+	io::{self, IsTerminal},
+	path::Path,
+};
+
+use starlark::codemap::FileSpan;
+
+const RED_BOLD: &str = "\x1b[1;31m";
+const BLUE_BOLD: &str = "\x1b[1;34m";
+const CYAN_BOLD: &str = "\x1b[1;36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// A line and column in a recipe or manifest. Both are 1-based.
+#[derive(Clone, Debug)]
+pub struct Location {
+	pub file: String,
+	pub line: usize,
+	pub column: usize,
+	pub source_line: String,
+}
+
+impl Location {
+	pub(crate) fn from_file_span(span: &FileSpan) -> Self {
+		let begin = span.resolve_span().begin;
+		Location {
+			file: span.filename().to_owned(),
+			line: begin.line + 1,
+			column: begin.column + 1,
+			source_line: span.file.source_line(begin.line).to_owned(),
+		}
+	}
+
+	/// The location of a byte offset into `source`
+	pub(crate) fn from_offset(file: &Path, source: &str, offset: usize) -> Self {
+		let offset = offset.min(source.len());
+		let line_start = source[..offset].rfind('\n').map_or(0, |x| x + 1);
+		let line_end = source[offset..].find('\n').map_or(source.len(), |x| offset + x);
+		Location {
+			file: file.to_string_lossy().to_string(),
+			line: source[..offset].matches('\n').count() + 1,
+			column: source[line_start..offset].chars().count() + 1,
+			source_line: source[line_start..line_end].trim_end_matches('\r').to_owned(),
+		}
+	}
+}
+
+/// An error reported to the user, with the offending location when it's known and hints on how to fix it
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+	pub message: String,
+	pub location: Option<Location>,
+	pub hints: Vec<String>,
+}
+
+impl Diagnostic {
+	pub(crate) fn new(message: impl Into<String>) -> Self {
+		Diagnostic { message: message.into(), location: None, hints: Vec::new() }
+	}
+
+	pub(crate) fn with_location(mut self, location: Location) -> Self {
+		self.location = Some(location);
+		self
+	}
+
+	pub(crate) fn with_hint(mut self, hint: impl Into<String>) -> Self {
+		self.hints.push(hint.into());
+		self
+	}
+
+	/// A diagnostic for a starlark error, pointing at the span starlark recorded
+	pub(crate) fn from_starlark(e: &starlark::Error) -> Self {
+		let diagnostic = Diagnostic::new(e.without_diagnostic().to_string());
+		match e.span() {
+			Some(span) => diagnostic.with_location(Location::from_file_span(span)),
+			None => diagnostic,
+		}
+	}
+
+	/// A diagnostic for an error deserializing the TOML file at `path`
+	pub(crate) fn from_toml(path: &Path, source: &str, e: &toml::de::Error) -> Self {
+		let diagnostic = Diagnostic::new(format!("Error reading {}: {}", path.display(), e.message()));
+		match e.span() {
+			Some(span) => diagnostic.with_location(Location::from_offset(path, source, span.start)),
+			None => diagnostic,
+		}
+	}
+
+	/// Renders the diagnostic like rustc: a header, the offending source line with a caret under the column, then hints
+	pub fn render(&self, color: bool) -> String {
+		let style = |code: &'static str| if color { code } else { "" };
+		let (red, blue, cyan, bold, reset) =
+			(style(RED_BOLD), style(BLUE_BOLD), style(CYAN_BOLD), style(BOLD), style(RESET));
+		let mut out = format!("{red}error{reset}{bold}: {}{reset}\n", self.message);
+		if let Some(loc) = &self.location {
+			let gutter = " ".repeat(loc.line.to_string().len());
+			out += &format!("{gutter}{blue}-->{reset} {}:{}:{}\n", loc.file, loc.line, loc.column);
+			out += &format!("{gutter} {blue}|{reset}\n");
+			out += &format!("{blue}{} |{reset} {}\n", loc.line, loc.source_line);
+			let caret_indent: String = loc
+				.source_line
+				.chars()
+				.take(loc.column - 1)
+				.map(|c| if c == '\t' { '\t' } else { ' ' })
+				.collect();
+			out += &format!("{gutter} {blue}|{reset} {caret_indent}{red}^{reset}\n");
+		}
+		for hint in &self.hints {
+			out += &format!("{cyan}hint{reset}: {}\n", hint);
+		}
+		out
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.render(false).trim_end())
+	}
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// The diagnostic carried by `e`, or a diagnostic with just its message
+pub fn from_error(e: &anyhow::Error) -> Diagnostic {
+	match e.downcast_ref::<Diagnostic>() {
+		Some(x) => x.clone(),
+		None => Diagnostic::new(e.to_string()),
+	}
+}
+
+/// Colors are used when stdout is a terminal, unless `NO_COLOR` is set
+pub fn use_color() -> bool {
+	io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|x| x.is_empty())
+}
+
+#[test]
+fn test_render() {
+	let source = "[package]\nname = 3\n";
+	let diagnostic = Diagnostic::new("invalid type")
+		.with_location(Location::from_offset(Path::new("catapult.toml"), source, 17))
+		.with_hint("did you mean a string?");
+	assert_eq!(
+		diagnostic.render(false),
+		"error: invalid type\n --> catapult.toml:2:8\n  |\n2 | name = 3\n  |        ^\nhint: did you mean a string?\n"
+	);
+}
@@ -0,0 +1,72 @@
+use std::{
+	path::Path,
+	sync::{Arc, Weak},
+};
+
+use crate::{misc::SourcePath, project::Project, target::Target};
+
+/// Documentation built with Doxygen, added with `add_docs()`
+#[derive(Debug)]
+pub struct Docs {
+	pub parent_project: Weak<Project>,
+	pub name: String,
+	/// Doxyfile template. `@VAR@` placeholders are expanded when the build is generated.
+	pub doxyfile: Option<SourcePath>,
+	/// Files and directories to document
+	pub sources: Vec<SourcePath>,
+}
+
+impl Target for Docs {
+	fn name(&self) -> &str {
+		&self.name
+	}
+	fn output_name(&self) -> &str {
+		&self.name
+	}
+	fn project(&self) -> Arc<Project> {
+		self.parent_project.upgrade().unwrap()
+	}
+}
+
+fn quote(path: &Path) -> String {
+	format!("\"{}\"", path.to_string_lossy())
+}
+
+impl Docs {
+	/// The Doxyfile to run doxygen with. Without a template, a Doxyfile documenting `sources` is generated.
+	///
+	/// Templates can use `@PROJECT_NAME@`, `@PROJECT_VERSION@`, `@SOURCE_DIR@`, `@INPUT@` and `@OUTPUT_DIRECTORY@`.
+	pub(crate) fn doxyfile_content(&self, output_dir: &Path) -> Result<String, String> {
+		let info = &self.project().info;
+		let input = self
+			.sources
+			.iter()
+			.map(|x| quote(&x.full))
+			.collect::<Vec<_>>()
+			.join(" ");
+		let version = info.version.clone().unwrap_or_default();
+		let source_dir = std::path::absolute(&info.path).unwrap_or_else(|_| info.path.clone());
+		let template = match &self.doxyfile {
+			Some(doxyfile) => match std::fs::read_to_string(&doxyfile.full) {
+				Ok(x) => x,
+				Err(e) => return Err(format!("Error reading Doxyfile \"{}\": {}", doxyfile.full.display(), e)),
+			},
+			None => "PROJECT_NAME = \"@PROJECT_NAME@\"\n\
+				PROJECT_NUMBER = \"@PROJECT_VERSION@\"\n\
+				INPUT = @INPUT@\n\
+				RECURSIVE = YES\n\
+				OUTPUT_DIRECTORY = @OUTPUT_DIRECTORY@\n\
+				GENERATE_LATEX = NO\n"
+				.to_owned(),
+		};
+		Ok(template
+			.replace("@PROJECT_NAME@", &info.name)
+			.replace("@PROJECT_VERSION@", &version)
+			.replace("@SOURCE_DIR@", &source_dir.to_string_lossy())
+			.replace("@INPUT@", &input)
+			.replace("@OUTPUT_DIRECTORY@", &quote(output_dir)))
+	}
+	pub(crate) fn set_parent(&mut self, parent: Weak<Project>) {
+		self.parent_project = parent;
+	}
+}
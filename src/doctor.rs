@@ -0,0 +1,258 @@
+//! `catapult doctor`: checks that the tools in a toolchain file work before a project is configured with it
+
+use std::{collections::BTreeMap, fs, path::Path, process};
+
+use crate::{
+	generator::Generator,
+	toolchain::{self, Profile, Toolchain},
+};
+
+const C_SOURCE: &str = "int main(void) { return 0; }\n";
+const CPP_SOURCE: &str = "#include <vector>\nint main() { std::vector<int> v{1}; return v.size() == 1 ? 0 : 1; }\n";
+const NASM_SOURCE: &str = "section .text\n";
+
+pub enum Outcome {
+	Ok(String),
+	/// The tool isn't in the toolchain file, so sources that need it can't be built
+	Skipped(String),
+	Failed(String),
+}
+
+/// A check of one tool or of building one kind of output with a profile
+pub struct Check {
+	pub name: String,
+	pub outcome: Outcome,
+}
+
+impl Check {
+	fn new(name: impl Into<String>, outcome: Outcome) -> Self {
+		Check { name: name.into(), outcome }
+	}
+}
+
+/// Identifies the tools in the toolchain file at `toolchain_path`, then builds a trivial C and C++ executable, a static
+/// library and a NASM object with each profile. The host toolchain, if any, is checked the same way.
+pub fn diagnose(toolchain_path: &Path) -> Vec<Check> {
+	let toolchain = match toolchain::get_toolchain(toolchain_path, false) {
+		Ok(x) => x,
+		Err(e) => {
+			return vec![Check::new(
+				"toolchain file",
+				Outcome::Failed(format!("{}\nCheck that every command in the toolchain file exists and runs", e)),
+			)]
+		}
+	};
+	let work_dir = std::env::temp_dir().join(format!("catapult-doctor-{}", uuid::Uuid::new_v4()));
+	if let Err(e) = fs::create_dir_all(&work_dir) {
+		return vec![Check::new(
+			"toolchain file",
+			Outcome::Failed(format!("Error creating directory {}: {}", work_dir.display(), e)),
+		)];
+	}
+	let mut checks = vec![Check::new(
+		"toolchain file",
+		Outcome::Ok(toolchain_path.display().to_string()),
+	)];
+	check_toolchain(&toolchain, &toolchain.profile, "", &work_dir, &mut checks);
+	if let Some(host) = &toolchain.host {
+		check_toolchain(host, &toolchain.profile, "host ", &work_dir, &mut checks);
+	}
+	let _ = fs::remove_dir_all(&work_dir);
+	checks
+}
+
+/// `profiles` are the profiles of the toolchain file, which the host toolchain uses unless it has its own of the same
+/// name
+fn check_toolchain(
+	toolchain: &Toolchain,
+	profiles: &BTreeMap<String, Profile>,
+	prefix: &str,
+	work_dir: &Path,
+	checks: &mut Vec<Check>,
+) {
+	for (name, compiler, key) in [
+		("C compiler", &toolchain.c_compiler, "c_compiler"),
+		("C++ compiler", &toolchain.cpp_compiler, "cpp_compiler"),
+	] {
+		checks.push(Check::new(
+			format!("{}{}", prefix, name),
+			match compiler {
+				Some(x) => Outcome::Ok(format!("{} {}, targeting {}", x.id(), x.version(), x.target())),
+				None => Outcome::Skipped(format!("No {} in the toolchain file", key)),
+			},
+		));
+	}
+	checks.push(Check::new(
+		format!("{}NASM assembler", prefix),
+		match &toolchain.nasm_assembler {
+			Some(x) => Outcome::Ok(format!("{} {}", x.id(), x.version())),
+			None => Outcome::Skipped("No nasm_assembler in the toolchain file".to_owned()),
+		},
+	));
+	checks.push(Check::new(
+		format!("{}static linker", prefix),
+		match &toolchain.static_linker {
+			Some(x) => Outcome::Ok(x.join(" ")),
+			None => Outcome::Skipped("No static_linker in the toolchain file".to_owned()),
+		},
+	));
+	checks.push(Check::new(
+		format!("{}executable linker", prefix),
+		match &toolchain.exe_linker {
+			Some(x) => Outcome::Ok(x.cmd().join(" ")),
+			None => Outcome::Skipped("No exe_linker in the toolchain file".to_owned()),
+		},
+	));
+
+	let platform = Generator::Ninja.target_platform(toolchain);
+	let mut profiles = profiles.clone();
+	profiles.extend(
+		toolchain
+			.profile
+			.iter()
+			.map(|(name, profile)| (name.clone(), profile.clone())),
+	);
+	if profiles.is_empty() {
+		profiles.insert("(no profile)".to_owned(), Profile::default());
+	}
+	for (profile_name, profile) in &profiles {
+		let name = |what: &str| format!("{}{}: {}", prefix, profile_name, what);
+		let dir = work_dir.join(format!("{}{}", prefix.trim(), profile_name));
+		if let Err(e) = fs::create_dir_all(&dir) {
+			checks.push(Check::new(
+				name("build"),
+				Outcome::Failed(format!("Error creating directory {}: {}", dir.display(), e)),
+			));
+			continue;
+		}
+		let mut objects = Vec::new();
+		for (lang, key, compiler, source, ext, flags) in [
+			("C", "c", &toolchain.c_compiler, C_SOURCE, ".c", &profile.c_compile_flags),
+			("C++", "cpp", &toolchain.cpp_compiler, CPP_SOURCE, ".cpp", &profile.cpp_compile_flags),
+		] {
+			let Some(compiler) = compiler else {
+				continue;
+			};
+			let src = format!("main_{}{}", objects.len(), ext);
+			let obj = format!("main_{}{}", objects.len(), platform.obj_ext);
+			let mut cmd = compiler.cmd();
+			cmd.extend(flags.iter().cloned());
+			cmd.extend([compiler.out_flag(), obj.clone(), "-c".to_owned(), src.clone()]);
+			let compiled = write_source(&dir, &src, source).and_then(|_| run(&cmd, &dir));
+			checks.push(Check::new(
+				name(&format!("compile {}", lang)),
+				outcome(compiled, &format!("{key}_compiler and the {key}_compile_flags of the profile")),
+			));
+			if checks.last().is_some_and(|x| matches!(x.outcome, Outcome::Failed(_))) {
+				continue;
+			}
+			if let Some(exe_linker) = &toolchain.exe_linker {
+				let exe = format!("main_{}{}", objects.len(), platform.exe_ext);
+				let mut cmd = exe_linker.cmd();
+				cmd.extend([obj.clone(), "-o".to_owned(), exe]);
+				checks.push(Check::new(
+					name(&format!("link {} executable", lang)),
+					outcome(run(&cmd, &dir), "exe_linker and that it can find the standard libraries"),
+				));
+			}
+			objects.push(obj);
+		}
+		if let (Some(static_linker), Some(obj)) = (&toolchain.static_linker, objects.first()) {
+			let mut cmd = static_linker.clone();
+			cmd.extend([format!("main{}", platform.static_lib_ext), obj.clone()]);
+			checks.push(Check::new(name("static library"), outcome(run(&cmd, &dir), "static_linker")));
+		}
+		if let Some(assembler) = &toolchain.nasm_assembler {
+			let mut cmd = assembler.cmd();
+			cmd.extend(profile.nasm_assemble_flags.iter().cloned());
+			cmd.extend([
+				assembler.out_flag(),
+				format!("nasm{}", platform.obj_ext),
+				"nasm.asm".to_owned(),
+			]);
+			let assembled = write_source(&dir, "nasm.asm", NASM_SOURCE).and_then(|_| run(&cmd, &dir));
+			checks.push(Check::new(
+				name("assemble NASM"),
+				outcome(assembled, "nasm_assembler and the nasm_assemble_flags of the profile"),
+			));
+		}
+	}
+}
+
+fn write_source(dir: &Path, name: &str, source: &str) -> Result<(), String> {
+	let path = dir.join(name);
+	fs::write(&path, source).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// Runs `cmd` in `dir`. The error has the command and what it printed.
+fn run(cmd: &[String], dir: &Path) -> Result<(), String> {
+	let Some((exe, args)) = cmd.split_first() else {
+		return Err("The command is empty".to_owned());
+	};
+	let output = match process::Command::new(exe).args(args).current_dir(dir).output() {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error running \"{}\": {}", exe, e)),
+	};
+	if output.status.success() {
+		return Ok(());
+	}
+	let printed = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+	Err(format!("`{}` failed ({}):\n{}", cmd.join(" "), output.status, printed.trim_end()))
+}
+
+fn outcome(result: Result<(), String>, what_to_check: &str) -> Outcome {
+	match result {
+		Ok(()) => Outcome::Ok(String::new()),
+		Err(e) => Outcome::Failed(format!("{}\nCheck {}", e, what_to_check)),
+	}
+}
+
+/// Whether every check passed or was skipped
+pub fn passed(checks: &[Check]) -> bool {
+	!checks.iter().any(|x| matches!(x.outcome, Outcome::Failed(_)))
+}
+
+/// A table with a line per check, followed by the details of the failures
+pub fn render_checks(checks: &[Check]) -> String {
+	let width = checks.iter().map(|x| x.name.len()).max().unwrap_or(0);
+	let mut out = String::new();
+	let mut failures = String::new();
+	for check in checks {
+		let (status, detail) = match &check.outcome {
+			Outcome::Ok(x) => ("ok", x.as_str()),
+			Outcome::Skipped(x) => ("skipped", x.as_str()),
+			Outcome::Failed(x) => {
+				failures += &format!("\n{}:\n{}\n", check.name, x);
+				("FAILED", x.lines().next().unwrap_or_default())
+			}
+		};
+		out += format!("{:<7}  {:<width$}  {}", status, check.name, detail).trim_end();
+		out += "\n";
+	}
+	out += &failures;
+	match checks
+		.iter()
+		.filter(|x| matches!(x.outcome, Outcome::Failed(_)))
+		.count()
+	{
+		0 => out += "\nThe toolchain works\n",
+		1 => out += "\n1 check failed\n",
+		n => out += &format!("\n{} checks failed\n", n),
+	}
+	out
+}
+
+#[test]
+fn test_render_checks() {
+	let checks = [
+		Check::new("C compiler", Outcome::Ok("GCC 13.2.0, targeting x86_64-linux-gnu".to_owned())),
+		Check::new("NASM assembler", Outcome::Skipped("No nasm_assembler in the toolchain file".to_owned())),
+		Check::new("Debug: compile C", Outcome::Failed("`gcc -c main_0.c` failed\nCheck c_compiler".to_owned())),
+	];
+	let out = render_checks(&checks);
+	assert!(out.starts_with("ok       C compiler        GCC 13.2.0, targeting x86_64-linux-gnu\n"));
+	assert!(out.contains("FAILED   Debug: compile C  `gcc -c main_0.c` failed\n"));
+	assert!(out.contains("\nDebug: compile C:\n`gcc -c main_0.c` failed\nCheck c_compiler\n"));
+	assert!(out.ends_with("\n1 check failed\n"));
+	assert!(!passed(&checks));
+}
@@ -23,10 +23,47 @@ pub struct Executable {
 	pub include_dirs: Vec<SourcePath>,
 	pub defines: Vec<String>,
 	pub link_flags: Vec<String>,
+	/// Apple frameworks to link, e.g. "CoreFoundation". Ignored when not targeting an Apple platform.
+	pub frameworks: Vec<String>,
+	/// Run-time search paths for shared libraries, used while running from the build directory
+	pub build_rpath: Vec<String>,
+	/// Run-time search paths for shared libraries once installed, e.g. "$ORIGIN/../lib"
+	pub install_rpath: Vec<String>,
+	/// Passed to the linker with `-T` to lay out the executable, e.g. for firmware
+	pub linker_script: Option<SourcePath>,
+	/// Passed to the linker with `--version-script` to set the versions and visibility of exported symbols
+	pub version_script: Option<SourcePath>,
+	/// Write a linker map file, `<name>.map`, next to the executable
+	pub map_file: bool,
+	/// Files objcopy converts the executable to, next to it, e.g. "bin" or "hex" images to flash
+	pub binary_formats: Vec<String>,
 
 	pub generator_vars: Option<OwnedFrozenValue>,
 
 	pub output_name: Option<String>,
+
+	/// Executables and static libraries, as (package, name), built before this target's pre-build steps and sources
+	pub depends: Vec<(String, String)>,
+	/// Commands run before the target's sources are compiled
+	pub pre_build: Vec<String>,
+	/// Commands run after the target is built
+	pub post_build: Vec<String>,
+	/// Files copied next to the executable, e.g. DLLs and data files
+	pub runtime_files: Vec<SourcePath>,
+	/// Added with `add_benchmark()`. Only generated when benchmarks are enabled.
+	pub benchmark: bool,
+	/// Added with `add_test()`, so `catapult test` runs it
+	pub test: bool,
+	/// Built with the host toolchain, if there is one, because it runs during the build
+	pub host: bool,
+	/// The Windows subsystem, "console" or "windows", if set with `win32_subsystem`
+	pub win32_subsystem: Option<String>,
+	/// The function the executable starts at on Windows, instead of the C runtime's default
+	pub entry_point: Option<String>,
+	/// An application manifest embedded in the Windows executable, e.g. to declare DPI awareness
+	pub win32_manifest: Option<SourcePath>,
+	/// The `requestedExecutionLevel` of the manifest, e.g. "requireAdministrator"
+	pub win32_execution_level: Option<String>,
 }
 
 impl fmt::Display for Executable {
@@ -116,6 +153,15 @@ impl Executable {
 					flags.push(flag);
 				}
 			}
+			// Private link flags reach the executable through the libraries that link them privately too, since those
+			// libraries are linked into it
+			for link in std::iter::once(link.clone()).chain(link.public_links_recursive()) {
+				for flag in link.private_link_flags() {
+					if !flags.contains(&flag) {
+						flags.push(flag);
+					}
+				}
+			}
 		}
 		for flag in &self.link_flags {
 			if !flags.contains(flag) {
@@ -124,6 +170,25 @@ impl Executable {
 		}
 		flags
 	}
+	/// The frameworks of this executable and of every library it links
+	pub(crate) fn frameworks_recursive(&self) -> Vec<String> {
+		let mut frameworks = Vec::new();
+		for link in &self.links {
+			for link in std::iter::once(link.clone()).chain(link.public_links_recursive()) {
+				for framework in link.frameworks() {
+					if !frameworks.contains(framework) {
+						frameworks.push(framework.clone());
+					}
+				}
+			}
+		}
+		for framework in &self.frameworks {
+			if !frameworks.contains(framework) {
+				frameworks.push(framework.clone());
+			}
+		}
+		frameworks
+	}
 	pub(crate) fn set_parent(&mut self, parent: Weak<Project>) {
 		self.parent_project = parent;
 	}
@@ -0,0 +1,68 @@
+use std::{fs, path::Path};
+
+use crate::misc::macro_prefix;
+
+/// Catapult only builds static libraries, so consumers and the library itself are compiled with this defined
+pub(crate) fn static_define(base_name: &str) -> String {
+	macro_prefix(base_name) + "_STATIC_DEFINE"
+}
+
+pub(crate) fn header_content(base_name: &str) -> String {
+	let prefix = macro_prefix(base_name);
+	format!(
+		r#"#ifndef {prefix}_EXPORT_H
+#define {prefix}_EXPORT_H
+
+#ifdef {prefix}_STATIC_DEFINE
+#  define {prefix}_EXPORT
+#  define {prefix}_NO_EXPORT
+#else
+#  if defined(_WIN32) || defined(__CYGWIN__)
+#    ifdef {prefix}_EXPORTS
+#      define {prefix}_EXPORT __declspec(dllexport)
+#    else
+#      define {prefix}_EXPORT __declspec(dllimport)
+#    endif
+#    define {prefix}_NO_EXPORT
+#  else
+#    define {prefix}_EXPORT __attribute__((visibility("default")))
+#    define {prefix}_NO_EXPORT __attribute__((visibility("hidden")))
+#  endif
+#endif
+
+#if defined(_MSC_VER)
+#  define {prefix}_DEPRECATED __declspec(deprecated)
+#else
+#  define {prefix}_DEPRECATED __attribute__((__deprecated__))
+#endif
+
+#endif /* {prefix}_EXPORT_H */
+"#
+	)
+}
+
+/// Writes `<base_name>_export.h` to `<build_dir>/<project_name>/include`, returning the include dir.
+/// The file is left untouched if its content hasn't changed, so dependent objects aren't rebuilt.
+pub(crate) fn write(build_dir: &Path, project_name: &str, base_name: &str) -> Result<String, String> {
+	let include_dir = build_dir.join(project_name).join("include");
+	if let Err(e) = fs::create_dir_all(&include_dir) {
+		return Err(format!("Error creating directory {}: {}", include_dir.display(), e));
+	}
+	let header_path = include_dir.join(base_name.to_owned() + "_export.h");
+	let content = header_content(base_name);
+	if fs::read_to_string(&header_path).ok().as_deref() != Some(content.as_str()) {
+		if let Err(e) = fs::write(&header_path, content) {
+			return Err(format!("Error writing {}: {}", header_path.display(), e));
+		}
+	}
+	Ok(include_dir.to_string_lossy().to_string())
+}
+
+#[test]
+fn test_header_content() {
+	assert_eq!(static_define("my-lib"), "MY_LIB_STATIC_DEFINE");
+	let content = header_content("my-lib");
+	assert!(content.starts_with("#ifndef MY_LIB_EXPORT_H\n"));
+	assert!(content.contains("#    ifdef MY_LIB_EXPORTS\n"));
+	assert!(content.contains("#      define MY_LIB_EXPORT __declspec(dllexport)\n"));
+}
@@ -1,23 +1,118 @@
+pub mod layout;
 mod msvc;
 mod ninja;
 
+pub use layout::OutputLayout;
+
 use std::{
-	path::Path, //
+	collections::BTreeSet,
+	path::{Path, PathBuf},
+	process::Command,
 	sync::Arc,
 };
 
 use crate::{
+	api_doc,
+	link_type::LinkPtr,
+	paths,
 	project::Project,
-	toolchain::{Profile, Toolchain},
+	target::LinkTarget,
+	toolchain::{
+		compiler::{is_msvc_target, is_windows_target, Compiler},
+		Profile, Toolchain,
+	},
 	GlobalOptions,
 };
 
+#[derive(Clone, Copy)]
 pub enum Generator {
 	Msvc,
 	Ninja,
 }
 
+/// How generated build files refer to sources and build outputs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathStyle {
+	#[default]
+	Absolute,
+	/// Relative to the build directory, so the build files don't depend on where the checkout is.
+	/// Paths that share nothing but the filesystem root with the build directory stay absolute.
+	Relative,
+}
+
+/// The character set Windows targets are built with, set with the `windows_character_set` option
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharacterSet {
+	Unicode,
+	MultiByte,
+}
+
+impl CharacterSet {
+	pub fn parse(character_set: &str) -> Result<Self, String> {
+		match character_set {
+			"unicode" => Ok(CharacterSet::Unicode),
+			"multibyte" => Ok(CharacterSet::MultiByte),
+			_ => {
+				Err(format!("Unknown windows_character_set \"{}\". Expected one of: unicode, multibyte", character_set))
+			}
+		}
+	}
+
+	/// The definitions Visual Studio adds for the character set, which the Ninja generator adds itself
+	pub(crate) fn defines(&self) -> Vec<String> {
+		match self {
+			CharacterSet::Unicode => vec!["UNICODE".to_owned(), "_UNICODE".to_owned()],
+			CharacterSet::MultiByte => vec!["_MBCS".to_owned()],
+		}
+	}
+
+	/// The value of a `.vcxproj`'s `<CharacterSet>`
+	pub(crate) fn vcxproj_value(&self) -> &'static str {
+		match self {
+			CharacterSet::Unicode => "Unicode",
+			CharacterSet::MultiByte => "MultiByte",
+		}
+	}
+}
+
+/// How generated build files configure the build directory again when a file read while configuring changes
+#[derive(Clone, Debug)]
+pub struct Regeneration {
+	/// The catapult command line that configures the build directory with the same settings
+	pub command: Vec<String>,
+	/// Files read while configuring that aren't part of a package, e.g. the toolchain and config files
+	pub inputs: Vec<PathBuf>,
+}
+
+/// The manifests, recipes and loaded modules of `project` and its dependencies, followed by `extra`, without
+/// duplicates
+pub fn configure_inputs(project: &Project, extra: &[PathBuf]) -> Vec<PathBuf> {
+	fn collect(project: &Project, visited: &mut BTreeSet<String>, files: &mut Vec<PathBuf>) {
+		if !visited.insert(project.info.name.clone()) {
+			return;
+		}
+		for dep in &project.dependencies {
+			collect(dep, visited, files);
+		}
+		files.extend(project.info.recipe_files.iter().cloned());
+	}
+	let mut files = Vec::new();
+	collect(project, &mut BTreeSet::new(), &mut files);
+	files.extend(extra.iter().cloned());
+	let mut seen = BTreeSet::new();
+	files.retain(|x| seen.insert(x.clone()));
+	files
+}
+
 impl Generator {
+	/// The name `--generator` takes
+	pub fn name(&self) -> &'static str {
+		match self {
+			Generator::Msvc => "MSVC",
+			Generator::Ninja => "Ninja",
+		}
+	}
+
 	pub fn generate(
 		&self,
 		project: Arc<Project>,
@@ -25,34 +120,171 @@ impl Generator {
 		build_dir: &Path,
 		toolchain: Toolchain,
 		profile: Profile,
+		path_style: PathStyle,
 	) -> Result<(), String> {
+		let stubs_project = project.clone();
+		match self {
+			Generator::Msvc => msvc::Msvc::generate(project, build_dir, toolchain, global_opts, path_style)?,
+			Generator::Ninja => {
+				let target_platform = target_platform(&toolchain);
+				ninja::Ninja::generate(
+					project,
+					build_dir,
+					toolchain,
+					profile,
+					global_opts,
+					target_platform,
+					path_style,
+				)?
+			}
+		}
+		api_doc::write_stubs(&stubs_project, build_dir)
+	}
+
+	/// The file `generate()` writes to the build directory, used to tell whether the build directory is configured
+	pub fn build_file(&self, build_dir: &Path, project_name: &str) -> PathBuf {
 		match self {
-			Generator::Msvc => msvc::Msvc::generate(project, build_dir, toolchain, global_opts),
+			Generator::Msvc => build_dir.join(project_name.to_owned() + ".sln"),
+			Generator::Ninja => build_dir.join("build.ninja"),
+		}
+	}
+
+	/// The command that builds `target`, or everything if `None`, in a configured build directory
+	pub fn build_command(
+		&self,
+		build_dir: &Path,
+		root_project_name: &str,
+		target: Option<&str>,
+		toolchain: &Toolchain,
+		profile: Option<&str>,
+	) -> Result<Command, String> {
+		match self {
+			Generator::Msvc => {
+				let (configuration, platform) = msvc_configuration(toolchain, profile)?;
+				let mut cmd = Command::new("msbuild");
+				cmd.arg(self.build_file(build_dir, root_project_name))
+					.arg(format!("-p:Configuration={configuration}"))
+					.arg(format!("-p:Platform={platform}"));
+				if let Some(target) = target {
+					cmd.arg(format!("-t:{target}"));
+				}
+				Ok(cmd)
+			}
 			Generator::Ninja => {
-				let target_triple = if let Some(compiler) = &toolchain.c_compiler {
-					compiler.target()
-				} else if let Some(compiler) = &toolchain.cpp_compiler {
-					compiler.target()
-				} else {
-					String::new()
-				};
-				let target_platform = if target_triple.contains("-windows-") || target_triple.ends_with("-windows") {
-					TargetPlatform {
-						obj_ext: ".obj".to_owned(),
-						static_lib_ext: ".lib".to_owned(),
-						exe_ext: ".exe".to_owned(),
-					}
-				} else {
-					TargetPlatform {
-						obj_ext: ".o".to_owned(),
-						static_lib_ext: ".a".to_owned(),
-						exe_ext: "".to_owned(),
-					}
-				};
-				ninja::Ninja::generate(project, build_dir, toolchain, profile, global_opts, target_platform)
+				let mut cmd = Command::new("ninja");
+				cmd.arg("-C").arg(build_dir);
+				if let Some(target) = target {
+					cmd.arg(target);
+				}
+				Ok(cmd)
 			}
 		}
 	}
+
+	/// The command that removes the build outputs from a configured build directory
+	pub fn clean_command(
+		&self,
+		build_dir: &Path,
+		root_project_name: &str,
+		toolchain: &Toolchain,
+		profile: Option<&str>,
+	) -> Result<Command, String> {
+		match self {
+			Generator::Msvc => {
+				let mut cmd = self.build_command(build_dir, root_project_name, None, toolchain, profile)?;
+				cmd.arg("-t:Clean");
+				Ok(cmd)
+			}
+			Generator::Ninja => {
+				let mut cmd = Command::new("ninja");
+				cmd.arg("-C").arg(build_dir).arg("-t").arg("clean");
+				Ok(cmd)
+			}
+		}
+	}
+
+	/// The command that builds executable `exe_name` in a configured build directory, and the path the executable is
+	/// built to. `package_dir` is where its package's outputs are, from `OutputLayout::package_dir()`.
+	pub fn build_executable(
+		&self,
+		build_dir: &Path,
+		root_project_name: &str,
+		package_dir: &Path,
+		exe_name: &str,
+		toolchain: &Toolchain,
+		profile: Option<&str>,
+	) -> Result<(Command, PathBuf), String> {
+		let cmd = self.build_command(build_dir, root_project_name, Some(exe_name), toolchain, profile)?;
+		let exe_ext = self.target_platform(toolchain).exe_ext;
+		let exe_path =
+			layout::output_dir(self, build_dir, package_dir, toolchain, profile)?.join(exe_name.to_owned() + &exe_ext);
+		Ok((cmd, exe_path))
+	}
+
+	/// The file extensions of build outputs
+	pub(crate) fn target_platform(&self, toolchain: &Toolchain) -> TargetPlatform {
+		match self {
+			Generator::Msvc => TargetPlatform {
+				obj_ext: ".obj".to_owned(),
+				static_lib_ext: ".lib".to_owned(),
+				exe_ext: ".exe".to_owned(),
+			},
+			Generator::Ninja => target_platform(toolchain),
+		}
+	}
+}
+
+/// The configuration (profile) and platform msbuild builds: the selected profile, or the first one with a `vcxproj` section,
+/// and the first platform
+fn msvc_configuration<'a>(toolchain: &'a Toolchain, profile: Option<&'a str>) -> Result<(&'a str, &'a str), String> {
+	let platform = match toolchain.msvc_platforms.first() {
+		Some(x) => x,
+		None => return Err("Toolchain doesn't contain any msvc_platforms, required for MSVC generator".to_owned()),
+	};
+	let configuration = match profile {
+		// Only profiles with a "vcxproj" section are generated
+		Some(x) => match toolchain.get_profile(x)?.vcxproj {
+			Some(_) => x,
+			None => {
+				return Err(format!("Profile \"{}\" has no \"vcxproj\" section, so it isn't generated for MSVC", x))
+			}
+		},
+		None => match toolchain.profile.iter().find(|(_, prof)| prof.vcxproj.is_some()) {
+			Some((name, _)) => name.as_str(),
+			None => return Err("Toolchain doesn't contain any profiles with a \"vcxproj\" section".to_owned()),
+		},
+	};
+	Ok((configuration, platform))
+}
+
+fn target_platform(toolchain: &Toolchain) -> TargetPlatform {
+	let target_triple = if let Some(compiler) = &toolchain.c_compiler {
+		compiler.target()
+	} else if let Some(compiler) = &toolchain.cpp_compiler {
+		compiler.target()
+	} else {
+		String::new()
+	};
+	if is_msvc_target(&target_triple) {
+		TargetPlatform {
+			obj_ext: ".obj".to_owned(),
+			static_lib_ext: ".lib".to_owned(),
+			exe_ext: ".exe".to_owned(),
+		}
+	} else if is_windows_target(&target_triple) {
+		// MinGW uses GNU-style objects and archives
+		TargetPlatform {
+			obj_ext: ".o".to_owned(),
+			static_lib_ext: ".a".to_owned(),
+			exe_ext: ".exe".to_owned(),
+		}
+	} else {
+		TargetPlatform {
+			obj_ext: ".o".to_owned(),
+			static_lib_ext: ".a".to_owned(),
+			exe_ext: "".to_owned(),
+		}
+	}
 }
 
 pub struct TargetPlatform {
@@ -60,3 +292,548 @@ pub struct TargetPlatform {
 	pub static_lib_ext: String,
 	pub exe_ext: String,
 }
+
+/// Path prefixes replaced in the outputs of reproducible builds: the root package's directory becomes ".",
+/// each dependency package's directory its name, and the build directory "build".
+/// Longer paths come last because compilers apply the last map that matches.
+pub(crate) fn reproducible_path_maps(project: &Project, build_dir: &Path) -> Result<Vec<(String, String)>, String> {
+	fn add_packages(project: &Project, maps: &mut Vec<(PathBuf, String)>, is_root: bool) {
+		if !maps.iter().any(|(path, _)| path == &project.info.path) {
+			let to = if is_root {
+				".".to_owned()
+			} else {
+				project.info.name.clone()
+			};
+			maps.push((project.info.path.clone(), to));
+		}
+		for dep in &project.dependencies {
+			add_packages(dep, maps, false);
+		}
+	}
+	let mut maps = Vec::new();
+	add_packages(project, &mut maps, true);
+	maps.push((build_dir.to_owned(), "build".to_owned()));
+	let mut ret = Vec::new();
+	for (path, to) in maps {
+		let path = match paths::canonicalize(&path) {
+			Ok(x) => x,
+			Err(e) => return Err(format!("Error resolving directory {}: {}", path.display(), e)),
+		};
+		ret.push((path.to_string_lossy().to_string(), to));
+	}
+	ret.sort_by_key(|(from, _)| from.len());
+	Ok(ret)
+}
+
+/// The `compile_flags` of the interface libraries in `links` and those they link, for the sources of the target that
+/// links them
+pub(crate) fn interface_compile_flags(links: &[LinkPtr]) -> Vec<String> {
+	let mut ret = Vec::new();
+	for link in links
+		.iter()
+		.cloned()
+		.chain(links.iter().flat_map(|x| x.public_links_recursive()))
+	{
+		if let LinkPtr::Interface(lib) = link {
+			for flag in &lib.compile_flags {
+				if !ret.contains(flag) {
+					ret.push(flag.clone());
+				}
+			}
+		}
+	}
+	ret
+}
+
+/// Path prefixes and definitions that keep paths and timestamps out of outputs, if the `reproducible` option is enabled
+#[derive(Clone)]
+pub(crate) struct Reproducible {
+	/// Path prefixes replaced in outputs, from `reproducible_path_maps()`
+	pub path_maps: Vec<(String, String)>,
+	/// Definitions of `__DATE__`, `__TIME__` and `__TIMESTAMP__`
+	pub defines: Vec<String>,
+}
+
+impl Reproducible {
+	/// None unless `global_opts` enables the `reproducible` option
+	pub(crate) fn new(
+		project: &Project,
+		global_opts: &GlobalOptions,
+		build_dir: &Path,
+	) -> Result<Option<Reproducible>, String> {
+		match global_opts.reproducible {
+			Some(true) => Ok(Some(Reproducible {
+				path_maps: reproducible_path_maps(project, build_dir)?,
+				defines: reproducible_defines()?,
+			})),
+			_ => Ok(None),
+		}
+	}
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Language {
+	C,
+	Cpp,
+}
+
+/// What the Ninja generator builds every target with besides the target's own settings, which
+/// `query::resolve_targets()` reports as well
+pub(crate) struct TargetFlags<'a> {
+	pub toolchain: &'a Toolchain,
+	pub profile: &'a Profile,
+	pub global_opts: &'a GlobalOptions,
+	pub reproducible: Option<&'a Reproducible>,
+}
+
+impl TargetFlags<'_> {
+	/// The flags `compiler` compiles the `language` sources of target `name` with. `interface_flags` are those of the
+	/// interface libraries it links. Executables are position independent executables rather than position
+	/// independent code.
+	pub(crate) fn compile_flags(
+		&self,
+		compiler: &dyn Compiler,
+		language: Language,
+		name: &str,
+		interface_flags: &[String],
+		executable: bool,
+	) -> Result<Vec<String>, String> {
+		let mut flags = match language {
+			Language::C => self.profile.c_compile_flags.clone(),
+			Language::Cpp => self.profile.cpp_compile_flags.clone(),
+		};
+		if self.profile.coverage {
+			match compiler.coverage_flags() {
+				Some(x) => flags.extend(x),
+				None => return Err(format!("Coverage is not supported by compiler \"{}\"", compiler.id())),
+			}
+		}
+		if let Some(reproducible) = self.reproducible {
+			flags.extend(compiler.reproducible_flags(&reproducible.path_maps));
+		}
+		flags.extend_from_slice(interface_flags);
+		if let Some(bare_metal) = &self.toolchain.bare_metal {
+			flags.extend(bare_metal.compile_flags());
+		}
+		match (language, &self.global_opts.c_standard, &self.global_opts.cpp_standard) {
+			(Language::C, Some(std), _) => flags.push(compiler.c_std_flag(std)?),
+			(Language::Cpp, _, Some(std)) => flags.push(compiler.cpp_std_flag(std)?),
+			_ => (),
+		}
+		if let Some(true) = self.global_opts.position_independent_code {
+			flags.extend(match executable {
+				true => compiler.position_independent_executable_flag(),
+				false => compiler.position_independent_code_flag(),
+			});
+		}
+		Ok(normalize_flags(name, flags))
+	}
+
+	/// Definitions every source is compiled with
+	pub(crate) fn defines(&self) -> Vec<String> {
+		let mut defines = Vec::new();
+		if let Some(reproducible) = self.reproducible {
+			defines.extend_from_slice(&reproducible.defines);
+		}
+		if let Some(character_set) = self.global_opts.windows_character_set {
+			if self.toolchain.platform() == Some("windows") {
+				defines.extend(character_set.defines());
+			}
+		}
+		defines
+	}
+
+	/// The flags executable `name` is linked with before those for its platform and its own. Instrumented
+	/// executables write coverage data to `coverage_dir`.
+	pub(crate) fn exe_link_flags(&self, name: &str, coverage_dir: &str) -> Result<Vec<String>, String> {
+		let Some(exe_linker) = &self.toolchain.exe_linker else {
+			return Ok(Vec::new());
+		};
+		let mut flags = Vec::new();
+		if self.reproducible.is_some() {
+			flags.extend(exe_linker.reproducible_flags());
+		}
+		if self.profile.coverage {
+			match exe_linker.coverage_flags(coverage_dir) {
+				Some(x) => flags.extend(x),
+				None => return Err(format!("Coverage is not supported by the linker of \"{}\"", name)),
+			}
+		}
+		if let Some(true) = self.global_opts.position_independent_code {
+			flags.extend(exe_linker.position_independent_executable_flag());
+		}
+		Ok(flags)
+	}
+
+	/// Linker arguments for the `link_libraries` of the profile, which follow an executable's inputs. Names are
+	/// passed as `-l<name>`, while flags, paths and file names such as `ws2_32.lib` are passed as they are.
+	pub(crate) fn link_libraries(&self) -> Vec<String> {
+		self.profile
+			.link_libraries(self.toolchain.platform())
+			.into_iter()
+			.map(|x| {
+				let verbatim = x.starts_with('-')
+					|| x.contains(['/', '\\'])
+					|| [".lib", ".a", ".so", ".dylib", ".tbd"]
+						.iter()
+						.any(|ext| x.ends_with(ext));
+				match verbatim {
+					true => x,
+					false => format!("-l{}", x),
+				}
+			})
+			.collect()
+	}
+}
+
+/// Flags whose value may be the next argument, e.g. `-framework Cocoa`, which are compared together with it. Other
+/// arguments that don't start with `-` or `/` are kept with the flag before them too.
+const FLAGS_WITH_VALUE: [&str; 33] = [
+	"-I",
+	"-D",
+	"-U",
+	"-L",
+	"-l",
+	"-o",
+	"-u",
+	"-z",
+	"/I",
+	"/D",
+	"/U",
+	"-include",
+	"-imacros",
+	"-isystem",
+	"-iquote",
+	"-idirafter",
+	"-isysroot",
+	"-iprefix",
+	"-iwithprefix",
+	"-iwithprefixbefore",
+	"-framework",
+	"-arch",
+	"-target",
+	"-x",
+	"-MF",
+	"-MT",
+	"-MQ",
+	"-Xclang",
+	"-Xlinker",
+	"-Xpreprocessor",
+	"-Xassembler",
+	"-mllvm",
+	"-T",
+];
+
+/// `flags` with repeated flags removed, keeping the first of each in place. Contradictory flags, such as two
+/// optimization levels, are kept and logged, since the compiler uses whichever comes last.
+pub(crate) fn normalize_flags(target_name: &str, flags: Vec<String>) -> Vec<String> {
+	let mut units: Vec<Vec<String>> = Vec::new();
+	for unit in flag_units(flags) {
+		if !units.contains(&unit) {
+			units.push(unit);
+		}
+	}
+	let mut seen: Vec<(String, &str)> = Vec::new();
+	for unit in &units {
+		let Some(group) = flag_group(&unit[0]) else {
+			continue;
+		};
+		if let Some((_, other)) = seen.iter().find(|(x, _)| *x == group) {
+			log::warn!(
+				"Target \"{}\" has the conflicting flags \"{}\" and \"{}\". The last one takes effect.",
+				target_name,
+				other,
+				unit[0]
+			);
+		} else {
+			seen.push((group, &unit[0]));
+		}
+	}
+	units.into_iter().flatten().collect()
+}
+
+/// Link `flags` with immediate repeats removed. Others are kept, since what a link flag does can depend on its
+/// position, e.g. a library between `-Wl,--whole-archive` and `-Wl,--no-whole-archive` or after `-Bstatic`, or a
+/// library repeated to resolve a circular dependency.
+pub(crate) fn normalize_link_flags(flags: Vec<String>) -> Vec<String> {
+	let mut units: Vec<Vec<String>> = Vec::new();
+	for unit in flag_units(flags) {
+		if units.last() != Some(&unit) {
+			units.push(unit);
+		}
+	}
+	units.into_iter().flatten().collect()
+}
+
+/// Splits `flags` into flags and their values. An argument that isn't a flag, e.g. the `a` of `-I a`, is the value of
+/// the flag before it, so it's never compared on its own.
+fn flag_units(flags: Vec<String>) -> Vec<Vec<String>> {
+	let mut units: Vec<Vec<String>> = Vec::new();
+	let mut flags = flags.into_iter();
+	while let Some(flag) = flags.next() {
+		if !is_flag(&flag) {
+			if let Some(unit) = units.last_mut() {
+				unit.push(flag);
+				continue;
+			}
+		}
+		let mut unit = vec![flag];
+		if FLAGS_WITH_VALUE.contains(&unit[0].as_str()) {
+			unit.extend(flags.next());
+		}
+		units.push(unit);
+	}
+	units
+}
+
+/// Whether `arg` is a flag rather than a value. MSVC flags start with `/`.
+fn is_flag(arg: &str) -> bool {
+	arg.starts_with('-') || arg.starts_with('/')
+}
+
+/// A name shared by the flags that contradict each other, like `-O0` and `-O2` or `-fexceptions` and
+/// `-fno-exceptions`, or None for flags that can be combined
+fn flag_group(flag: &str) -> Option<String> {
+	if flag.starts_with("-O") || (flag.starts_with("/O") && flag.len() == 3) {
+		return Some("optimization".to_owned());
+	}
+	if ["/MD", "/MDd", "/MT", "/MTd"].contains(&flag) {
+		return Some("runtime".to_owned());
+	}
+	if let Some((name, _)) = flag.split_once(['=', ':']) {
+		return ["-std", "/std", "-march", "-mtune", "-mcpu", "-fvisibility"]
+			.contains(&name)
+			.then(|| name.to_owned());
+	}
+	if let Some(name) = flag.strip_prefix("-fno-") {
+		return Some(format!("-f{}", name));
+	}
+	if flag.starts_with("-f") {
+		return Some(flag.to_owned());
+	}
+	None
+}
+
+/// Definitions that replace `__DATE__`, `__TIME__` and `__TIMESTAMP__` with the time in the `SOURCE_DATE_EPOCH`
+/// environment variable, or with the Unix epoch if it isn't set
+pub(crate) fn reproducible_defines() -> Result<Vec<String>, String> {
+	let epoch = match std::env::var("SOURCE_DATE_EPOCH") {
+		Ok(x) => match x.trim().parse::<u64>() {
+			Ok(y) => y,
+			Err(e) => return Err(format!("Invalid SOURCE_DATE_EPOCH \"{}\": {}", x, e)),
+		},
+		Err(_) => 0,
+	};
+	let (date, time, timestamp) = format_timestamp(epoch);
+	Ok(vec![
+		format!("__DATE__=\"{}\"", date),
+		format!("__TIME__=\"{}\"", time),
+		format!("__TIMESTAMP__=\"{}\"", timestamp),
+	])
+}
+
+/// The UTC time `secs` after the Unix epoch in the formats of `__DATE__` ("Jan  1 1970"), `__TIME__` ("00:00:00")
+/// and `__TIMESTAMP__` ("Thu Jan  1 00:00:00 1970")
+const EXE_PLACEHOLDER: &str = "@EXE:";
+
+/// What `Executable.path` is in recipes: a placeholder for the path of executable `name` of package `package`, which
+/// generators replace in build steps once the path is known
+pub(crate) fn executable_placeholder(package: &str, name: &str) -> String {
+	format!("{}{}::{}@", EXE_PLACEHOLDER, package, name)
+}
+
+/// `command` with each executable placeholder replaced by `path(package, name)`
+pub(crate) fn expand_executable_paths(
+	command: &str,
+	mut path: impl FnMut(&str, &str) -> Result<String, String>,
+) -> Result<String, String> {
+	let mut ret = String::new();
+	let mut rest = command;
+	while let Some(start) = rest.find(EXE_PLACEHOLDER) {
+		ret += &rest[..start];
+		let after = &rest[start + EXE_PLACEHOLDER.len()..];
+		let target = after
+			.find('@')
+			.and_then(|end| Some((after[..end].split_once("::")?, end)));
+		match target {
+			Some(((package, name), end)) => {
+				ret += &path(package, name)?;
+				rest = &after[end + 1..];
+			}
+			None => {
+				ret += EXE_PLACEHOLDER;
+				rest = after;
+			}
+		}
+	}
+	ret += rest;
+	Ok(ret)
+}
+
+/// The package named `package` in `project` or its dependencies, if it has an executable named `name`
+pub(crate) fn find_executable_package<'a>(project: &'a Project, package: &str, name: &str) -> Option<&'a Project> {
+	if project.info.name == package {
+		return project.executables.iter().any(|x| x.name == name).then_some(project);
+	}
+	project
+		.dependencies
+		.iter()
+		.find_map(|dep| find_executable_package(dep, package, name))
+}
+
+/// The executables, as (package, name), that run during the build: those added with `host = True` and those used in
+/// build steps. They're built with the host toolchain when cross-compiling.
+pub(crate) fn host_executables(project: &Project) -> BTreeSet<(String, String)> {
+	fn add_package(project: &Project, ret: &mut BTreeSet<(String, String)>) {
+		for dep in &project.dependencies {
+			add_package(dep, ret);
+		}
+		let mut steps = Vec::new();
+		for exe in &project.executables {
+			if exe.host {
+				ret.insert((project.info.name.clone(), exe.name.clone()));
+			}
+			steps.extend(exe.pre_build.iter().chain(&exe.post_build));
+		}
+		for lib in &project.static_libraries {
+			steps.extend(lib.pre_build.iter().chain(&lib.post_build));
+		}
+		for lib in &project.object_libraries {
+			steps.extend(lib.pre_build.iter().chain(&lib.post_build));
+		}
+		for step in steps {
+			let _ = expand_executable_paths(step, |package, name| {
+				ret.insert((package.to_owned(), name.to_owned()));
+				Ok(String::new())
+			});
+		}
+	}
+	let mut ret = BTreeSet::new();
+	add_package(project, &mut ret);
+	ret
+}
+
+fn format_timestamp(secs: u64) -> (String, String, String) {
+	const MONTHS: [&str; 12] = [
+		"Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+	];
+	const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+	let days = secs / 86400;
+	let rem = secs % 86400;
+	// Converts days since the epoch to a civil date. See http://howardhinnant.github.io/date_algorithms.html
+	let z = days + 719468;
+	let era = z / 146097;
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + u64::from(month <= 2);
+
+	let month = MONTHS[month as usize - 1];
+	let time = format!("{:02}:{:02}:{:02}", rem / 3600, rem % 3600 / 60, rem % 60);
+	(
+		format!("{} {:2} {}", month, day, year),
+		time.clone(),
+		format!("{} {} {:2} {} {}", WEEKDAYS[(days % 7) as usize], month, day, time, year),
+	)
+}
+
+#[test]
+fn test_format_timestamp() {
+	assert_eq!(
+		format_timestamp(0),
+		("Jan  1 1970".to_owned(), "00:00:00".to_owned(), "Thu Jan  1 00:00:00 1970".to_owned())
+	);
+	assert_eq!(
+		format_timestamp(1_700_000_000),
+		("Nov 14 2023".to_owned(), "22:13:20".to_owned(), "Tue Nov 14 22:13:20 2023".to_owned())
+	);
+	assert_eq!(format_timestamp(951_782_400).0, "Feb 29 2000");
+}
+
+#[test]
+fn test_expand_executable_paths() {
+	let command = format!("{} -o gen.h @EXE:broken", executable_placeholder("protobuf", "protoc"));
+	let expanded = expand_executable_paths(&command, |package, name| Ok(format!("build/{}/{}", package, name)));
+	assert_eq!(expanded.unwrap(), "build/protobuf/protoc -o gen.h @EXE:broken");
+}
+
+#[test]
+fn test_normalize_flags() {
+	let flags = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+	assert_eq!(
+		normalize_flags(
+			"app",
+			flags(&[
+				"-O2",
+				"-g",
+				"-O2",
+				"-framework",
+				"A",
+				"-framework",
+				"B",
+				"-framework",
+				"A"
+			])
+		),
+		["-O2", "-g", "-framework", "A", "-framework", "B"]
+	);
+	// Conflicting flags are kept in order
+	assert_eq!(normalize_flags("app", flags(&["-O2", "-O0"])), ["-O2", "-O0"]);
+	// Values are never dropped apart from their flag
+	assert_eq!(
+		normalize_flags("app", flags(&["-I", "a", "-I", "b", "-I", "a", "-I", "/usr/include"])),
+		["-I", "a", "-I", "b", "-I", "/usr/include"]
+	);
+	assert_eq!(
+		normalize_flags(
+			"app",
+			flags(&[
+				"-Xpreprocessor",
+				"-fopenmp",
+				"-Xpreprocessor",
+				"-fopenmp",
+				"-Xpreprocessor",
+				"-DX"
+			])
+		),
+		["-Xpreprocessor", "-fopenmp", "-Xpreprocessor", "-DX"]
+	);
+	assert_eq!(
+		normalize_flags("app", flags(&["-Xassembler", "--noexecstack", "-Xassembler", "--fatal-warnings"])),
+		["-Xassembler", "--noexecstack", "-Xassembler", "--fatal-warnings"]
+	);
+	assert_eq!(
+		normalize_flags("app", flags(&["--param", "x=1", "--param", "y=2"])),
+		["--param", "x=1", "--param", "y=2"]
+	);
+	assert_eq!(flag_group("-fno-exceptions"), flag_group("-fexceptions"));
+	assert_eq!(flag_group("-std=c11"), flag_group("-std=c17"));
+	assert_eq!(flag_group("/Od"), flag_group("-O3"));
+	assert_eq!(flag_group("-fsanitize=address"), None);
+	assert_eq!(flag_group("-Wall"), None);
+}
+
+#[test]
+fn test_normalize_link_flags() {
+	let flags = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+	let whole_archive = flags(&[
+		"-Wl,--whole-archive",
+		"-la",
+		"-Wl,--no-whole-archive",
+		"-lb",
+		"-Wl,--whole-archive",
+		"-lc",
+		"-Wl,--no-whole-archive",
+	]);
+	assert_eq!(normalize_link_flags(whole_archive.clone()), whole_archive);
+	let static_dynamic = flags(&["-Bstatic", "-la", "-Bdynamic", "-lb", "-Bstatic", "-lc", "-Bdynamic"]);
+	assert_eq!(normalize_link_flags(static_dynamic.clone()), static_dynamic);
+	// A library repeated after the one that depends on it stays, while an immediate repeat is dropped
+	assert_eq!(
+		normalize_link_flags(flags(&["-la", "-lb", "-la", "-la", "-Xlinker", "-z", "-Xlinker", "-z"])),
+		["-la", "-lb", "-la", "-Xlinker", "-z"]
+	);
+}
@@ -0,0 +1,170 @@
+//! Where generators write outputs in the build directory. Every generator, `catapult install`, the query API and
+//! recipes (through `GLOBAL.package.output_dir`) take paths from here instead of rebuilding them.
+//!
+//! Each package gets a directory relative to the build directory from `OutputLayout`. Ninja builds one profile per
+//! build directory and writes outputs directly into the package directory. MSVC builds every configuration from one
+//! solution, so its outputs are under `<Platform>\<Configuration>\` (no platform for Win32) unless the profile sets
+//! `out_dir`.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+	paths,
+	project::ProjectInfo,
+	toolchain::{Toolchain, VcxprojProfile},
+};
+
+use super::{msvc_configuration, Generator};
+
+/// Where generators write the outputs of each package in the build directory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputLayout {
+	/// `<build>/<package>` for every package
+	#[default]
+	Flat,
+	/// `<build>/_deps/<package>-<version>` for dependencies, so their outputs don't mix with the root package's and
+	/// one package can be cleaned by deleting its directory
+	Isolated,
+}
+
+impl OutputLayout {
+	pub fn parse(layout: &str) -> Result<Self, String> {
+		match layout {
+			"flat" => Ok(OutputLayout::Flat),
+			"isolated" => Ok(OutputLayout::Isolated),
+			_ => Err(format!("Unknown layout \"{}\". Expected one of: flat, isolated", layout)),
+		}
+	}
+
+	/// The name `--layout` takes
+	pub fn name(&self) -> &'static str {
+		match self {
+			OutputLayout::Flat => "flat",
+			OutputLayout::Isolated => "isolated",
+		}
+	}
+
+	/// The directory, relative to the build directory, that the outputs of package `info` are written to
+	pub fn package_dir(&self, info: &ProjectInfo, root_package: &str) -> PathBuf {
+		self.dir_of(&info.name, info.version.as_deref(), root_package)
+	}
+
+	/// `package_dir()` for a package that may not be parsed yet
+	pub fn dir_of(&self, name: &str, version: Option<&str>, root_package: &str) -> PathBuf {
+		if *self == OutputLayout::Flat || name == root_package {
+			return PathBuf::from(name);
+		}
+		match version {
+			Some(version) => Path::new("_deps").join(format!("{}-{}", name, version)),
+			None => Path::new("_deps").join(name),
+		}
+	}
+}
+
+/// The directory executables and static libraries of the package with outputs in `package_dir` are built to
+pub fn output_dir(
+	generator: &Generator,
+	build_dir: &Path,
+	package_dir: &Path,
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+) -> Result<PathBuf, String> {
+	match generator {
+		Generator::Msvc => {
+			let (configuration, platform) = msvc_configuration(toolchain, profile)?;
+			let vcxproj = toolchain
+				.profile
+				.get(configuration)
+				.and_then(|x| x.vcxproj.clone())
+				.unwrap_or_default();
+			let out_dir = out_dir(&vcxproj, platform, package_dir);
+			Ok(expand_msbuild_dir(&out_dir, build_dir, configuration, platform))
+		}
+		Generator::Ninja => Ok(build_dir.join(package_dir)),
+	}
+}
+
+/// Output `src` + `ext` of the package with outputs in `package_dir`, as written to build.ninja
+pub(crate) fn output_path(build_dir: &Path, package_dir: &Path, src: &str, ext: &str) -> String {
+	paths::to_build_string(&build_dir.join(package_dir).join(src.to_owned() + ext))
+}
+
+/// Like `output_path()`, in the `<subfolder>.dir` directory of the package, which is usually named after a target
+pub(crate) fn output_subfolder_path(
+	build_dir: &Path,
+	package_dir: &Path,
+	subfolder: &str,
+	src: &str,
+	ext: &str,
+) -> String {
+	paths::to_build_string(
+		&build_dir
+			.join(package_dir)
+			.join(subfolder.to_owned() + ".dir")
+			.join(src.to_owned() + ext),
+	)
+}
+
+/// `package_dir` as part of an MSBuild directory
+fn msbuild_dir(package_dir: &Path) -> String {
+	package_dir
+		.iter()
+		.map(|x| x.to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("\\")
+}
+
+/// The `OutDir` of targets of the package with outputs in `package_dir`: Visual Studio's default,
+/// `$(SolutionDir)$(Platform)\$(Configuration)\` without the platform for Win32, followed by the package directory so
+/// targets of different packages can have the same name
+pub(crate) fn out_dir(profile: &VcxprojProfile, platform: &str, package_dir: &Path) -> String {
+	let package = msbuild_dir(package_dir);
+	match &profile.out_dir {
+		Some(x) => x.replace("{package}", &package),
+		None if platform == "Win32" => format!("$(SolutionDir)$(Configuration)\\{package}\\"),
+		None => format!("$(SolutionDir)$(Platform)\\$(Configuration)\\{package}\\"),
+	}
+}
+
+/// The `IntDir` of target `target_name`, which is unique to the target, platform and configuration
+pub(crate) fn int_dir(profile: &VcxprojProfile, package_dir: &Path, target_name: &str) -> String {
+	let package = msbuild_dir(package_dir);
+	match &profile.int_dir {
+		Some(x) => x.replace("{package}", &package).replace("{target}", target_name),
+		None => format!("$(SolutionDir){package}\\{target_name}.dir\\$(Platform)\\$(Configuration)\\"),
+	}
+}
+
+/// `dir` from `out_dir()` as a path, with the MSBuild macros whose values are known outside MSBuild expanded
+pub(crate) fn expand_msbuild_dir(dir: &str, build_dir: &Path, configuration: &str, platform: &str) -> PathBuf {
+	let dir = dir
+		.replace("$(SolutionDir)", &format!("{}/", build_dir.display()))
+		.replace("$(Configuration)", configuration)
+		.replace("$(Platform)", platform);
+	PathBuf::from(dir.replace('\\', "/"))
+}
+
+#[test]
+fn test_output_dirs() {
+	let package_dir = Path::new("_deps").join("zlib-1.3.0");
+	let mut profile = VcxprojProfile::default();
+	let out_dir = out_dir(&profile, "x64", &package_dir);
+	assert_eq!(out_dir, r"$(SolutionDir)$(Platform)\$(Configuration)\_deps\zlib-1.3.0\");
+	assert_eq!(
+		expand_msbuild_dir(&out_dir, Path::new("build"), "Debug", "x64"),
+		Path::new("build/x64/Debug/_deps/zlib-1.3.0")
+	);
+	assert_eq!(
+		int_dir(&profile, &package_dir, "z"),
+		r"$(SolutionDir)_deps\zlib-1.3.0\z.dir\$(Platform)\$(Configuration)\"
+	);
+	profile.int_dir = Some(r"C:\obj\{package}\{target}\$(Configuration)\".to_owned());
+	assert_eq!(int_dir(&profile, &package_dir, "z"), r"C:\obj\_deps\zlib-1.3.0\z\$(Configuration)\");
+}
+
+#[test]
+fn test_package_dir() {
+	assert_eq!(OutputLayout::Flat.dir_of("zlib", Some("1.3.0"), "app"), Path::new("zlib"));
+	assert_eq!(OutputLayout::Isolated.dir_of("zlib", Some("1.3.0"), "app"), Path::new("_deps").join("zlib-1.3.0"));
+	assert_eq!(OutputLayout::Isolated.dir_of("app", Some("1.0"), "app"), Path::new("app"));
+}
@@ -1,10 +1,10 @@
 mod index_map;
 
 use std::{
-	collections::BTreeMap,
+	collections::{BTreeMap, BTreeSet},
 	fs,
 	io::Write,
-	path::{Path, PathBuf},
+	path::{Component, Path, PathBuf},
 	sync::Arc,
 };
 
@@ -13,11 +13,13 @@ use uuid::Uuid;
 
 use crate::{
 	link_type::LinkPtr, //
-	misc::{join_parent, Sources},
+	misc::{join_parent, relative_path, SourceExtensions, Sources},
 	object_library::ObjectLibrary,
+	paths,
 	project::{Project, ProjectInfo},
 	starlark_context::{StarContext, StarContextCompiler},
-	starlark_generator::eval_vars,
+	starlark_generator::{eval_interface_vars, eval_vars},
+	starlark_global::{StarGlobalOptions, StarPackageOptions},
 	starlark_object_library::StarGeneratorVars,
 	static_library::StaticLibrary,
 	target::{LinkTarget, Target},
@@ -25,9 +27,25 @@ use crate::{
 	GlobalOptions,
 };
 
+use super::{
+	self as generator,
+	layout::{int_dir, out_dir},
+	CharacterSet, OutputLayout, PathStyle,
+};
 use index_map::IndexMap;
 
 const VS_CPP_GUID: &str = "8BC9CEB8-8B4A-11D0-8D11-00A0C91BC942";
+/// The project type of solution folders, which hold files that aren't part of a project
+const VS_SOLUTION_FOLDER_GUID: &str = "2150E333-8FDC-42A3-9474-1A3956D46DE8";
+/// The namespace of the GUIDs derived from the names of projects, solutions and filters
+const GUID_NAMESPACE: Uuid = Uuid::from_u128(0x190ca781_1e70_4582_b8cb_5503dac20bf1);
+
+/// A GUID derived from `name`, so that regenerating doesn't change it and invalidate Visual Studio's state for it
+fn stable_guid(name: &str) -> String {
+	Uuid::new_v5(&GUID_NAMESPACE, name.as_bytes())
+		.to_string()
+		.to_ascii_uppercase()
+}
 
 #[derive(Clone)]
 struct VsProject {
@@ -38,53 +56,25 @@ struct VsProject {
 	has_nasm: bool,
 }
 
-fn input_path(src: &Path, project_path: &Path) -> String {
+fn input_path(src: &Path, project_path: &Path) -> PathBuf {
 	if src.is_relative() {
 		project_path.join(src)
 	} else {
 		src.to_owned()
 	}
-	.to_str()
-	.unwrap()
-	.trim_start_matches(r"\\?\")
-	.to_owned()
 }
 
-enum CStd {
-	C11,
-	C17,
-}
-
-impl CStd {
-	fn as_str(&self) -> &str {
-		match self {
-			CStd::C11 => "stdc11",
-			CStd::C17 => "stdc17",
-		}
-	}
-}
-
-enum CppStd {
-	Cpp11,
-	Cpp14,
-	Cpp17,
-	Cpp20,
-}
-
-impl CppStd {
-	fn as_str(&self) -> &str {
-		match self {
-			CppStd::Cpp11 => "stdcpp11",
-			CppStd::Cpp14 => "stdcpp14",
-			CppStd::Cpp17 => "stdcpp17",
-			CppStd::Cpp20 => "stdcpp20",
-		}
-	}
+/// The `LanguageStandard` value for a compiler's `/std:` flag, e.g. `/std:c++17` -> `stdcpp17`
+fn language_standard(std_flag: &str) -> String {
+	"std".to_owned() + &std_flag.trim_start_matches("/std:").replace("c++", "cpp")
 }
 
 struct Options {
-	c_standard: Option<CStd>,
-	cpp_standard: Option<CppStd>,
+	c_standard: Option<String>,
+	cpp_standard: Option<String>,
+	/// Compiler options that keep paths and timestamps out of objects, if the `reproducible` option is enabled
+	reproducible_flags: Option<Vec<String>>,
+	character_set: Option<CharacterSet>,
 }
 
 impl VsProject {
@@ -111,9 +101,19 @@ impl VsProject {
 	}
 }
 
+#[derive(Clone)]
 struct ProfileFragment {
 	vcxproj: VcxprojProfile,
 	nasm_assemble_flags: Vec<String>,
+	/// The profile's `link_libraries` and `link_flags` for Windows
+	link_libraries: Vec<String>,
+	link_flags: Vec<String>,
+	/// C and C++ compile flags of the interface libraries the target links
+	compile_flags: Vec<String>,
+	/// The application manifest embedded in the target
+	manifest: Option<String>,
+	/// The profile's `map_files`
+	map_files: bool,
 }
 
 fn item_definition_group(
@@ -131,24 +131,159 @@ fn item_definition_group(
 	);
 
 	if !sources.c.is_empty() || !sources.cpp.is_empty() {
-		ret += &cl_compile(&profile.vcxproj, include_dirs, defines, opts, sources.cpp.is_empty());
+		ret += &cl_compile(profile, include_dirs, defines, opts, sources.cpp.is_empty());
 	}
 	if !sources.nasm.is_empty() {
 		ret += &nasm_compile(profile, platform, include_dirs, defines)?;
 	}
-	if !profile.vcxproj.link.is_empty() {
+	let has_link_settings = !profile.link_libraries.is_empty() || !profile.link_flags.is_empty();
+	if !profile.vcxproj.link.is_empty() || opts.reproducible_flags.is_some() || has_link_settings {
 		ret += "    <Link>\n";
 		for (key, val) in &profile.vcxproj.link {
 			ret += &format!("      <{key}>{val}</{key}>\n")
 		}
+		if !profile.link_libraries.is_empty() {
+			let libs = profile
+				.link_libraries
+				.iter()
+				.map(|x| match x.ends_with(".lib") || x.contains(['/', '\\']) {
+					true => xml_escape(x),
+					false => xml_escape(&format!("{}.lib", x)),
+				})
+				.collect::<Vec<_>>();
+			ret += &format!(
+				"      <AdditionalDependencies>{};%(AdditionalDependencies)</AdditionalDependencies>\n",
+				libs.join(";")
+			);
+		}
+		if !profile.link_flags.is_empty() {
+			ret += &format!(
+				"      <AdditionalOptions>{} %(AdditionalOptions)</AdditionalOptions>\n",
+				xml_escape(&profile.link_flags.join(" "))
+			);
+		}
+		if opts.reproducible_flags.is_some() {
+			ret += "      <AdditionalOptions>/Brepro %(AdditionalOptions)</AdditionalOptions>\n";
+		}
 		ret += "    </Link>\n";
 	}
+	if let Some(manifest) = &profile.manifest {
+		ret += &format!(
+			"    <Manifest>\n      <AdditionalManifestFiles>{};%(AdditionalManifestFiles)</AdditionalManifestFiles>\n    </Manifest>\n",
+			xml_escape(manifest)
+		);
+	}
+	if opts.reproducible_flags.is_some() {
+		ret += "    <Lib>\n      <AdditionalOptions>/Brepro %(AdditionalOptions)</AdditionalOptions>\n    </Lib>\n";
+	}
 	ret += "  </ItemDefinitionGroup>\n";
 
 	Ok(ret)
 }
 
-fn item_group_conditional(sources: &Sources, project_info: &ProjectInfo, platform: &str) -> String {
+/// The toolchain's `sign_command` as a build event command, with `$out` replaced by the target's path
+fn sign_event(sign_command: &[String]) -> String {
+	sign_command
+		.iter()
+		.map(|x| match x.contains(' ') {
+			true => format!("\"{}\"", x.replace("$out", "$(TargetPath)")),
+			false => x.replace("$out", "\"$(TargetPath)\""),
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// The `<UACExecutionLevel>` of a manifest's `requestedExecutionLevel`
+fn uac_execution_level(level: &str) -> &'static str {
+	match level {
+		"highestAvailable" => "HighestAvailable",
+		"requireAdministrator" => "RequireAdministrator",
+		_ => "AsInvoker",
+	}
+}
+
+fn xml_escape(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// `<PreBuildEvent>` and `<PostBuildEvent>`, shared by all configurations unless the paths of the executables they
+/// use differ between them, and the `.vcxproj` paths of those executables
+fn build_events(proj_opts: &VcxprojOpts, target_data: &TargetData) -> Result<(String, BTreeSet<String>), String> {
+	let mut tools = BTreeSet::new();
+	if target_data.pre_build.is_empty() && target_data.post_build.is_empty() {
+		return Ok((String::new(), tools));
+	}
+	let mut configurations = Vec::new();
+	for platform in &proj_opts.msvc_platforms {
+		for (profile_name, profile_cfg) in &proj_opts.profiles {
+			let mut expand_path = |package: &str, name: &str| {
+				let Some(tool_package) = generator::find_executable_package(&target_data.project, package, name) else {
+					return Err(format!(
+						"A build step of \"{}\" uses executable \"{}::{}\", which is not in its package or dependencies",
+						target_data.name, package, name
+					));
+				};
+				let package_dir = proj_opts
+					.output_layout
+					.package_dir(&tool_package.info, &proj_opts.root_package);
+				let vcxproj_path = PathBuf::from(&tool_package.info.name)
+					.join(name)
+					.join(name.to_owned() + ".vcxproj");
+				tools.insert(vcxproj_path.to_string_lossy().into_owned());
+				Ok(out_dir(&profile_cfg.vcxproj, platform, &package_dir) + name + ".exe")
+			};
+			let mut expand = |commands: &[String]| {
+				commands
+					.iter()
+					.map(|x| generator::expand_executable_paths(x, &mut expand_path))
+					.collect::<Result<Vec<_>, _>>()
+			};
+			let pre_build = expand(&target_data.pre_build)?;
+			let post_build = expand(&target_data.post_build)?;
+			configurations.push((format!("{profile_name}|{platform}"), pre_build, post_build));
+		}
+	}
+	let shared = configurations.windows(2).all(|x| x[0].1 == x[1].1 && x[0].2 == x[1].2);
+	if shared {
+		configurations.truncate(1);
+	}
+	let mut ret = String::new();
+	for (configuration, pre_build, post_build) in &configurations {
+		ret += &match shared {
+			true => "  <ItemDefinitionGroup>\n".to_owned(),
+			false => {
+				format!("  <ItemDefinitionGroup Condition=\"'$(Configuration)|$(Platform)'=='{configuration}'\">\n")
+			}
+		};
+		ret += &item_build_events(pre_build, post_build);
+		ret += "  </ItemDefinitionGroup>\n";
+	}
+	Ok((ret, tools))
+}
+
+fn item_build_events(pre_build: &[String], post_build: &[String]) -> String {
+	let mut ret = String::new();
+	for (element, commands) in [("PreBuildEvent", pre_build), ("PostBuildEvent", post_build)] {
+		if !commands.is_empty() {
+			// Stop at the first failing command, like Ninja's `&&`
+			let command = commands
+				.iter()
+				.map(|x| xml_escape(x))
+				.collect::<Vec<_>>()
+				.join("\nif %errorlevel% neq 0 exit /b %errorlevel%\n");
+			ret += &format!("    <{element}>\n      <Command>{command}</Command>\n    </{element}>\n");
+		}
+	}
+	ret
+}
+
+fn item_group_conditional(
+	sources: &Sources,
+	project_info: &ProjectInfo,
+	platform: &str,
+	proj_opts: &VcxprojOpts,
+	vcxproj_dir: &Path,
+) -> String {
 	let item_group_tag = format!(
 		r#"  <ItemGroup Condition="'$(Platform)'=='{platform}'">
 "#
@@ -157,7 +292,7 @@ fn item_group_conditional(sources: &Sources, project_info: &ProjectInfo, platfor
 	if !sources.c.is_empty() {
 		ret += &item_group_tag;
 		for src in &sources.c {
-			let input = input_path(&src.full, &project_info.path);
+			let input = proj_opts.msbuild_path(&input_path(&src.full, &project_info.path), vcxproj_dir);
 			ret += &format!("    <ClCompile Include=\"{input}\" />\n");
 		}
 		ret += "  </ItemGroup>\n";
@@ -165,7 +300,7 @@ fn item_group_conditional(sources: &Sources, project_info: &ProjectInfo, platfor
 	if !sources.cpp.is_empty() {
 		ret += &item_group_tag;
 		for src in &sources.cpp {
-			let input = input_path(&src.full, &project_info.path);
+			let input = proj_opts.msbuild_path(&input_path(&src.full, &project_info.path), vcxproj_dir);
 			ret += &format!("    <ClCompile Include=\"{input}\" />\n");
 		}
 		ret += "  </ItemGroup>\n";
@@ -173,7 +308,7 @@ fn item_group_conditional(sources: &Sources, project_info: &ProjectInfo, platfor
 	if !sources.nasm.is_empty() {
 		ret += &item_group_tag;
 		for src in &sources.nasm {
-			let input = input_path(&src.full, &project_info.path);
+			let input = proj_opts.msbuild_path(&input_path(&src.full, &project_info.path), vcxproj_dir);
 			ret += &format!("    <NASM Include=\"{input}\" />\n");
 		}
 		ret += "  </ItemGroup>\n";
@@ -182,7 +317,7 @@ fn item_group_conditional(sources: &Sources, project_info: &ProjectInfo, platfor
 }
 
 fn cl_compile(
-	profile: &VcxprojProfile,
+	profile: &ProfileFragment,
 	include_dirs: &[String],
 	defines: &[String],
 	opts: &Options,
@@ -190,7 +325,7 @@ fn cl_compile(
 ) -> String {
 	let mut ret = "    <ClCompile>\n".to_owned();
 
-	for (key, val) in &profile.cl_compile {
+	for (key, val) in &profile.vcxproj.cl_compile {
 		ret += &format!("      <{key}>{val}</{key}>\n");
 	}
 
@@ -216,12 +351,24 @@ fn cl_compile(
 
 	ret += "      <ConformanceMode>true</ConformanceMode>\n";
 
+	if let Some(flags) = &opts.reproducible_flags {
+		ret += "      <AdditionalOptions>";
+		ret += &xml_escape(&flags.join(" "));
+		ret += " %(AdditionalOptions)</AdditionalOptions>\n";
+	}
+	if !profile.compile_flags.is_empty() {
+		ret += "      <AdditionalOptions>";
+		ret += &xml_escape(&profile.compile_flags.join(" "));
+		ret += " %(AdditionalOptions)</AdditionalOptions>\n";
+	}
+
 	// TODO(Travers): Add global options for warnings
 	// <WarningLevel>Level4</WarningLevel>
 	// <TreatWarningAsError>false</TreatWarningAsError>
 	// TODO(Travers): Add other definitions and compile flags
 	ret += "      <PreprocessorDefinitions>";
 	ret += &profile
+		.vcxproj
 		.preprocessor_definitions
 		.iter()
 		.chain(defines)
@@ -268,18 +415,115 @@ fn nasm_compile(
 
 struct TargetData {
 	name: String,
+	project: Arc<Project>,
 	sources: Sources,
-	includes: Vec<String>,
+	includes: Vec<PathBuf>,
 	defines: Vec<String>,
 	links: Vec<LinkPtr>,
 	generator_vars: Option<OwnedFrozenValue>,
+	pre_build: Vec<String>,
+	post_build: Vec<String>,
+	runtime_files: Vec<PathBuf>,
+	/// The `link_files` of the interface libraries the target links, e.g. those found with `find_library()`
+	link_files: Vec<PathBuf>,
+	/// The `compile_flags` of the interface libraries the target links, e.g. `/openmp`
+	compile_flags: Vec<String>,
+	/// The `win32_subsystem` and `entry_point` of an executable
+	win32_subsystem: Option<String>,
+	entry_point: Option<String>,
+	/// The `win32_manifest` and `win32_execution_level` of an executable
+	win32_manifest: Option<PathBuf>,
+	win32_execution_level: Option<String>,
+	/// The `map_file` of an executable
+	map_file: bool,
+	/// The link flags of an executable and of the libraries it links
+	link_flags: Vec<String>,
+	/// The executables and static libraries, as (package, name), built before the target
+	depends: Vec<(String, String)>,
+}
+
+/// The `.lib` files of the static libraries `links` link, recursively, in the output directories of `profile` and
+/// `platform`
+fn static_lib_files(
+	links: &[LinkPtr],
+	proj_opts: &VcxprojOpts,
+	profile: &VcxprojProfile,
+	platform: &str,
+) -> Vec<String> {
+	let mut ret = Vec::new();
+	for link in links
+		.iter()
+		.cloned()
+		.chain(links.iter().flat_map(|x| x.public_links_recursive()))
+	{
+		if let LinkPtr::Static(lib) = link {
+			let package_dir = proj_opts
+				.output_layout
+				.package_dir(&lib.project().info, &proj_opts.root_package);
+			let file = out_dir(profile, platform, &package_dir) + lib.output_name() + ".lib";
+			if !ret.contains(&file) {
+				ret.push(file);
+			}
+		}
+	}
+	ret
+}
+
+fn interface_link_files(links: &[LinkPtr]) -> Vec<PathBuf> {
+	let mut ret = Vec::new();
+	for link in links
+		.iter()
+		.cloned()
+		.chain(links.iter().flat_map(|x| x.public_links_recursive()))
+	{
+		if let LinkPtr::Interface(lib) = link {
+			for file in &lib.link_files {
+				if !ret.contains(file) {
+					ret.push(file.clone());
+				}
+			}
+		}
+	}
+	ret
+}
+
+/// `file`, a path relative to the build directory, relative to the directory of a vcxproj in `vcxproj_dir`, which is
+/// also relative to the build directory
+fn build_dir_relative(vcxproj_dir: &Path, file: &str) -> String {
+	let mut ret = "..\\".repeat(vcxproj_dir.components().count());
+	ret += file;
+	ret
 }
 
 struct VcxprojOpts {
 	build_dir: PathBuf,
+	/// Decides the directories of each package's outputs, like it does for Ninja
+	output_layout: OutputLayout,
+	root_package: String,
+	/// The canonical build directory, if paths are written relative to the vcxproj
+	relative_to: Option<PathBuf>,
 	profiles: BTreeMap<String, ProfileFragment>,
 	msvc_platforms: Vec<String>,
 	opts: Options,
+	/// Passed to generator functions
+	global_options: StarGlobalOptions,
+	/// Whether targets added with `add_benchmark()` are generated
+	benchmarks: bool,
+	source_extensions: SourceExtensions,
+	/// The toolchain's `sign_command`, run before an executable's post-build commands
+	sign_command: Option<Vec<String>>,
+}
+
+impl VcxprojOpts {
+	/// `path` as written in the vcxproj in `vcxproj_dir`, which is relative to the build directory
+	fn msbuild_path(&self, path: &Path, vcxproj_dir: &Path) -> String {
+		if let Some(base) = &self.relative_to {
+			if let Some(rel) = relative_path(path, &base.join(vcxproj_dir)) {
+				return format!("$(ProjectDir){}", rel.display());
+			}
+		}
+		paths::to_build_string(path)
+	}
 }
 
 pub struct Msvc {}
@@ -290,37 +534,42 @@ impl Msvc {
 		build_dir: &Path,
 		toolchain: Toolchain,
 		global_opts: GlobalOptions,
+		path_style: PathStyle,
 	) -> Result<(), String> {
 		if toolchain.msvc_platforms.is_empty() {
 			return Err("Toolchain doesn't contain any msvc_platforms, required for MSVC generator".to_owned());
 		}
 		let mut guid_map = IndexMap::new();
-		let c_standard = match global_opts.c_standard {
-			None => None,
-			Some(x) => match x.as_str() {
-				"11" => Some(CStd::C11),
-				"17" => Some(CStd::C17),
-				_ => {
-					return Err(format!(
-						"Unrecognized value for option for \"c_standard\": \"{x}\". Accepted values are \"17\", \"11\"",
-					))
-				}
-			},
+		let star_global_options = StarGlobalOptions::new(&global_opts);
+		// The toolchain's compiler decides which standards are supported
+		let c_standard = match (&global_opts.c_standard, &toolchain.c_compiler) {
+			(Some(x), Some(c_compiler)) => Some(language_standard(&c_compiler.c_std_flag(x)?)),
+			_ => None,
 		};
-		let cpp_standard = match global_opts.cpp_standard {
-			None => None,
-			Some(x) => match x.as_str() {
-				"11" => Some(CppStd::Cpp11),
-				"14" => Some(CppStd::Cpp14),
-				"17" => Some(CppStd::Cpp17),
-				"20" => Some(CppStd::Cpp20),
-				_ => {
-					return Err(format!(
-						"Unrecognized value for option for \"cpp_standard\": \"{x}\". Accepted values are \"20\", \"17\", \"14\", \"11\"",
-					))
-				}
-			},
+		let cpp_standard = match (&global_opts.cpp_standard, &toolchain.cpp_compiler) {
+			(Some(x), Some(cpp_compiler)) => Some(language_standard(&cpp_compiler.cpp_std_flag(x)?)),
+			_ => None,
 		};
+		if toolchain.command_wrapper.is_some() {
+			log::warn!("Ignoring command_wrapper. It is only used by the Ninja generator");
+		}
+		let reproducible_flags = match (global_opts.reproducible, &toolchain.c_compiler) {
+			(Some(true), Some(c_compiler)) => {
+				Some(c_compiler.reproducible_flags(&generator::reproducible_path_maps(&project, build_dir)?))
+			}
+			_ => None,
+		};
+		if global_opts.artifact_cache.is_some() {
+			log::warn!("Ignoring the artifact cache. It is only used by the Ninja generator");
+		}
+		for (name, profile) in &toolchain.profile {
+			if profile.coverage {
+				log::warn!(
+					"Ignoring \"coverage\" of profile \"{}\". Coverage is only supported by the Ninja generator",
+					name
+				);
+			}
+		}
 		let profiles = toolchain
 			.profile
 			.iter()
@@ -331,6 +580,11 @@ impl Msvc {
 						ProfileFragment {
 							vcxproj: prof.clone(),
 							nasm_assemble_flags: x.1.nasm_assemble_flags.clone(),
+							link_libraries: x.1.link_libraries(Some("windows")),
+							link_flags: x.1.link_flags(Some("windows")),
+							compile_flags: Vec::new(),
+							manifest: None,
+							map_files: x.1.map_files,
 						},
 					)
 				})
@@ -342,11 +596,32 @@ impl Msvc {
 					.to_owned(),
 			);
 		}
+		let relative_to = match path_style {
+			PathStyle::Absolute => None,
+			PathStyle::Relative => match paths::canonicalize(build_dir) {
+				Ok(x) => Some(x),
+				Err(e) => return Err(format!("Error resolving build directory {}: {}", build_dir.display(), e)),
+			},
+		};
+		// The solution contains all profiles, so benchmarks are generated if any profile enables them
+		let benchmarks = global_opts.benchmarks || toolchain.profile.values().any(|x| x.benchmarks);
 		let proj_opts = VcxprojOpts {
 			build_dir: build_dir.to_owned(),
+			output_layout: global_opts.output_layout,
+			root_package: project.info.name.clone(),
+			relative_to,
 			profiles,
 			msvc_platforms: toolchain.msvc_platforms,
-			opts: Options { c_standard, cpp_standard },
+			opts: Options {
+				c_standard,
+				cpp_standard,
+				reproducible_flags,
+				character_set: global_opts.windows_character_set,
+			},
+			global_options: star_global_options,
+			benchmarks,
+			source_extensions: toolchain.source_extensions,
+			sign_command: toolchain.sign_command,
 		};
 		Self::generate_inner(&project, &proj_opts, &mut guid_map)?;
 
@@ -358,6 +633,28 @@ impl Msvc {
 		for proj in guid_map.iter().rev() {
 			sln_content += &proj.to_sln_project_section();
 		}
+		// The files read while configuring, listed in a solution folder so they're at hand in the Solution Explorer
+		let extra_inputs = global_opts.regeneration.as_ref().map(|x| x.inputs.as_slice());
+		let inputs = generator::configure_inputs(&project, extra_inputs.unwrap_or_default());
+		if !inputs.is_empty() {
+			let folder_guid = stable_guid(&format!("{}/Configure Inputs", project.info.name));
+			sln_content += &format!(
+				"Project(\"{{{VS_SOLUTION_FOLDER_GUID}}}\") = \"Configure Inputs\", \"Configure Inputs\", \"{{{folder_guid}}}\"\n"
+			);
+			sln_content += "	ProjectSection(SolutionItems) = preProject\n";
+			for input in &inputs {
+				let item = match proj_opts
+					.relative_to
+					.as_deref()
+					.and_then(|base| relative_path(input, base))
+				{
+					Some(x) => paths::to_build_string(&x),
+					None => paths::to_build_string(input),
+				};
+				sln_content += &format!("		{item} = {item}\n");
+			}
+			sln_content += "	EndProjectSection\nEndProject\n";
+		}
 		sln_content += r#"Global
 	GlobalSection(SolutionConfigurationPlatforms) = preSolution
 "#;
@@ -382,7 +679,7 @@ impl Msvc {
 		}
 		sln_content += "	EndGlobalSection\n";
 
-		let sln_guid = Uuid::new_v4().to_string().to_ascii_uppercase();
+		let sln_guid = stable_guid(&format!("{}.sln", project.info.name));
 		sln_content += &format!(
 			r#"	GlobalSection(SolutionProperties) = preSolution
 		HideSolutionNode = FALSE
@@ -413,6 +710,8 @@ impl Msvc {
 	}
 
 	fn generate_inner(project: &Arc<Project>, proj_opts: &VcxprojOpts, guid_map: &mut IndexMap) -> Result<(), String> {
+		// As with Ninja, dependencies come first and targets in the order they're declared. `guid_map` keeps that
+		// order, and GUIDs are derived from names, so the solution and projects are the same on every run.
 		for subproject in &project.dependencies {
 			Self::generate_inner(subproject, proj_opts, guid_map)?;
 		}
@@ -428,24 +727,55 @@ impl Msvc {
 			}
 		}
 		for exe in &project.executables {
+			if exe.benchmark && !proj_opts.benchmarks {
+				continue;
+			}
+			if !exe.binary_formats.is_empty() {
+				log::warn!(
+					"Ignoring the binary_formats of \"{}\". They're only created by the Ninja generator",
+					exe.name
+				);
+			}
+			if exe.linker_script.is_some() || exe.version_script.is_some() {
+				log::warn!(
+					"Ignoring the linker and version scripts of \"{}\". MSVC's linker doesn't take them",
+					exe.name
+				);
+			}
 			let configuration_type = "Application";
 			let project_info = &exe.project().info;
 			let target_data = TargetData {
 				name: exe.name.clone(),
+				project: exe.project(),
 				sources: exe.sources.clone(),
-				// Visual Studio doesn't seem to support extended-length name syntax
-				includes: exe
-					.public_includes_recursive()
-					.into_iter()
-					.map(|x| x.to_string_lossy().trim_start_matches(r"\\?\").to_owned())
-					.collect::<Vec<String>>(),
+				includes: exe.public_includes_recursive(),
 				defines: exe.public_defines_recursive(),
 				links: exe.links.clone(),
 				generator_vars: exe.generator_vars.clone(),
+				pre_build: exe.pre_build.clone(),
+				post_build: proj_opts
+					.sign_command
+					.iter()
+					.map(|x| sign_event(x))
+					.chain(exe.post_build.iter().cloned())
+					.collect(),
+				runtime_files: exe.runtime_files.iter().map(|x| x.full.clone()).collect(),
+				link_files: interface_link_files(&exe.links),
+				compile_flags: generator::interface_compile_flags(&exe.links),
+				win32_subsystem: exe.win32_subsystem.clone(),
+				entry_point: exe.entry_point.clone(),
+				win32_manifest: exe.win32_manifest.as_ref().map(|x| x.full.clone()),
+				win32_execution_level: exe.win32_execution_level.clone(),
+				map_file: exe.map_file,
+				link_flags: exe.link_flags_recursive(),
+				depends: exe.depends.clone(),
 			};
 			let vsproj = make_vcxproj(proj_opts, guid_map, configuration_type, project_info, &target_data)?;
 			guid_map.insert_exe(vsproj);
 		}
+		for docs in &project.docs {
+			log::warn!("Skipping docs target \"{}\". Docs targets are only generated for Ninja", docs.name);
+		}
 		Ok(())
 	}
 }
@@ -459,11 +789,6 @@ fn add_static_lib(
 	let project_info = &lib.project().info;
 	let mut includes = lib.public_includes_recursive();
 	includes.extend_from_slice(&lib.private_includes());
-	let includes = includes
-		.into_iter()
-		// Visual Studio doesn't seem to support extended-length name syntax
-		.map(|x| x.to_string_lossy().trim_start_matches(r"\\?\").to_owned())
-		.collect::<Vec<String>>();
 	let mut defines = lib.public_defines_recursive();
 	defines.extend_from_slice(lib.private_defines());
 	let links = lib
@@ -471,14 +796,28 @@ fn add_static_lib(
 		.iter()
 		.cloned()
 		.chain(lib.link_public.iter().cloned())
-		.collect();
+		.collect::<Vec<_>>();
+	let compile_flags = generator::interface_compile_flags(&links);
 	let target_data = TargetData {
 		name: lib.name.clone(),
+		project: lib.project(),
 		sources: lib.sources.clone(),
 		includes,
 		defines,
 		links,
 		generator_vars: lib.generator_vars.clone(),
+		pre_build: lib.pre_build.clone(),
+		post_build: lib.post_build.clone(),
+		runtime_files: Vec::new(),
+		link_files: Vec::new(),
+		compile_flags,
+		win32_subsystem: None,
+		entry_point: None,
+		win32_manifest: None,
+		win32_execution_level: None,
+		map_file: false,
+		link_flags: Vec::new(),
+		depends: lib.depends.clone(),
 	};
 	let vsproj = make_vcxproj(proj_opts, guid_map, "StaticLibrary", project_info, &target_data)?;
 	let link_ptr = LinkPtr::Static(lib.clone());
@@ -495,11 +834,6 @@ fn add_object_lib(
 	let project_info = &lib.project().info;
 	let mut includes = lib.public_includes_recursive();
 	includes.extend_from_slice(&lib.private_includes());
-	let includes = includes
-		.into_iter()
-		// Visual Studio doesn't seem to support extended-length name syntax
-		.map(|x| x.to_string_lossy().trim_start_matches(r"\\?\").to_owned())
-		.collect::<Vec<String>>();
 	let mut defines = lib.public_defines_recursive();
 	defines.extend_from_slice(lib.private_defines());
 	let links = lib
@@ -507,14 +841,28 @@ fn add_object_lib(
 		.iter()
 		.cloned()
 		.chain(lib.link_public.iter().cloned())
-		.collect();
+		.collect::<Vec<_>>();
+	let compile_flags = generator::interface_compile_flags(&links);
 	let target_data = TargetData {
 		name: lib.name.clone(),
+		project: lib.project(),
 		sources: lib.sources.clone(),
 		includes,
 		defines,
 		links,
 		generator_vars: lib.generator_vars.clone(),
+		pre_build: lib.pre_build.clone(),
+		post_build: lib.post_build.clone(),
+		runtime_files: Vec::new(),
+		link_files: Vec::new(),
+		compile_flags,
+		win32_subsystem: None,
+		entry_point: None,
+		win32_manifest: None,
+		win32_execution_level: None,
+		map_file: false,
+		link_flags: Vec::new(),
+		depends: lib.depends.clone(),
 	};
 	let vsproj = make_vcxproj(proj_opts, guid_map, "StaticLibrary", project_info, &target_data)?;
 	guid_map.insert(LinkPtr::Object(lib.clone()), vsproj.clone());
@@ -536,7 +884,11 @@ fn make_vcxproj(
 		return Err(format!("This generator does not support mixing C and C++ sources. Consider splitting them into separate libraries. Target: {target_name}"));
 	}
 	const PLATFORM_TOOLSET: &str = "v143";
-	let target_guid = Uuid::new_v4().to_string().to_ascii_uppercase();
+	let vcxproj_dir = PathBuf::from(&project_info.name).join(target_name);
+	let package_dir = proj_opts
+		.output_layout
+		.package_dir(project_info, &proj_opts.root_package);
+	let target_guid = stable_guid(&paths::to_build_string(&vcxproj_dir));
 	let mut out_str = r#"<?xml version="1.0" encoding="utf-8"?>
 <Project DefaultTargets="Build" xmlns="http://schemas.microsoft.com/developer/msbuild/2003">
   <ItemGroup Label="ProjectConfigurations">
@@ -573,9 +925,15 @@ fn make_vcxproj(
     <PlatformToolset>{PLATFORM_TOOLSET}</PlatformToolset>
 "#
 			);
+			// Before the profile's properties, which may still set them directly
+			let out_dir = out_dir(&profile_cfg.vcxproj, platform, &package_dir);
+			let int_dir = int_dir(&profile_cfg.vcxproj, &package_dir, target_name);
+			out_str += &format!("    <OutDir>{out_dir}</OutDir>\n    <IntDir>{int_dir}</IntDir>\n");
 			// <UseDebugLibraries>true</UseDebugLibraries>
-			// <CharacterSet>MultiByte</CharacterSet>
 			// <WholeProgramOptimization>true</WholeProgramOptimization>
+			if let Some(character_set) = proj_opts.opts.character_set {
+				out_str += &format!("    <CharacterSet>{}</CharacterSet>\n", character_set.vcxproj_value());
+			}
 			for (prop_name, prop_val) in &profile_cfg.vcxproj.property_group {
 				out_str += &format!("    <{prop_name}>{prop_val}</{prop_name}>\n");
 			}
@@ -590,48 +948,113 @@ fn make_vcxproj(
 	let mut item_groups = Vec::new();
 	let mut has_nasm = !sources.nasm.is_empty();
 	for platform in &proj_opts.msvc_platforms {
-		let generator_vars = if let Some(gen_func) = &target_data.generator_vars {
+		let star_context = || -> Result<StarContext, String> {
 			let target_triple = map_platform_to_target_triple(platform)?;
-			let star_context = StarContext {
+			Ok(StarContext {
 				c_compiler: Some(StarContextCompiler { target_triple: target_triple.to_owned() }),
 				cpp_compiler: Some(StarContextCompiler { target_triple: target_triple.to_owned() }),
-			};
-			eval_vars(gen_func, star_context.clone(), "generator_vars")?
+				global_options: proj_opts.global_options.clone(),
+				package_options: StarPackageOptions(Default::default()),
+				profile: None,
+				source_dir: String::new(),
+				build_dir: proj_opts.build_dir.to_string_lossy().to_string(),
+			})
+		};
+		let generator_vars = if let Some(gen_func) = &target_data.generator_vars {
+			eval_vars(gen_func, star_context()?.for_package(project_info), "generator_vars")?
 		} else {
 			StarGeneratorVars::default()
 		};
-		let generator_sources = Sources::from_slice(&generator_vars.sources, &project_info.path)?;
+		let interface_vars = eval_interface_vars(&target_data.links, star_context)?;
+		let generator_sources =
+			Sources::from_slice(&generator_vars.sources, &project_info.path, &proj_opts.source_extensions)?;
 		has_nasm |= !generator_sources.nasm.is_empty();
 		let sources_gen = sources.extended_with(&generator_sources);
 		let includes_gen = target_data
 			.includes
-			.clone()
-			.into_iter()
+			.iter()
+			.cloned()
 			.chain(
 				generator_vars
 					.include_dirs
 					.iter()
-					.map(|x| join_parent(&project_info.path, x).full.to_string_lossy().to_string()),
+					.map(|x| join_parent(&project_info.path, x).full),
 			)
+			.chain(interface_vars.include_dirs.iter().map(PathBuf::from))
+			.map(|x| proj_opts.msbuild_path(&x, &vcxproj_dir))
 			.collect::<Vec<_>>();
 		let defines_gen = target_data
 			.defines
 			.clone()
 			.into_iter()
 			.chain(generator_vars.defines.clone())
+			.chain(interface_vars.defines.clone())
 			.collect::<Vec<_>>();
 		for (profile_name, profile) in &proj_opts.profiles {
+			// Library files are linked like the profile's link libraries, which are passed as they are if they're paths
+			let mut profile = profile.clone();
+			profile
+				.link_libraries
+				.extend(target_data.link_files.iter().map(|x| x.to_string_lossy().into_owned()));
+			// Project references link static libraries in most setups, but not all, so they're listed explicitly too
+			if configuration_type == "Application" {
+				profile.link_libraries.extend(static_lib_files(
+					&target_data.links,
+					proj_opts,
+					&profile.vcxproj,
+					platform,
+				));
+			}
+			profile.compile_flags = generator::normalize_flags(target_name, target_data.compile_flags.clone());
+			if configuration_type == "Application" {
+				profile
+					.link_flags
+					.extend(target_data.link_flags.iter().chain(&interface_vars.link_flags).cloned());
+				profile.link_flags = generator::normalize_link_flags(profile.link_flags);
+			}
+			// The target's settings override the profile's
+			if let Some(subsystem) = &target_data.win32_subsystem {
+				let value = if subsystem == "windows" { "Windows" } else { "Console" };
+				profile.vcxproj.link.insert("SubSystem".to_owned(), value.to_owned());
+			}
+			if let Some(entry_point) = &target_data.entry_point {
+				profile
+					.vcxproj
+					.link
+					.insert("EntryPointSymbol".to_owned(), entry_point.clone());
+			}
+			if let Some(level) = &target_data.win32_execution_level {
+				profile.vcxproj.link.insert("EnableUAC".to_owned(), "true".to_owned());
+				profile
+					.vcxproj
+					.link
+					.insert("UACExecutionLevel".to_owned(), uac_execution_level(level).to_owned());
+			}
+			if configuration_type == "Application" && (target_data.map_file || profile.map_files) {
+				profile
+					.vcxproj
+					.link
+					.insert("GenerateMapFile".to_owned(), "true".to_owned());
+				profile
+					.vcxproj
+					.link
+					.insert("MapFileName".to_owned(), "$(OutDir)$(TargetName).map".to_owned());
+			}
+			profile.manifest = target_data
+				.win32_manifest
+				.as_ref()
+				.map(|x| proj_opts.msbuild_path(x, &vcxproj_dir));
 			item_definition_groups.push(item_definition_group(
 				platform,
 				profile_name,
-				profile,
+				&profile,
 				&sources_gen,
 				&includes_gen,
 				&defines_gen,
 				&proj_opts.opts,
 			)?);
 		}
-		item_groups.push(item_group_conditional(&generator_sources, project_info, platform));
+		item_groups.push(item_group_conditional(&generator_sources, project_info, platform, proj_opts, &vcxproj_dir));
 	}
 	// Make these variables immutable
 	let item_definition_groups = item_definition_groups;
@@ -639,8 +1062,7 @@ fn make_vcxproj(
 	let has_nasm = has_nasm;
 
 	if has_nasm {
-		out_str += r#"    <Import Project="..\..\nasm.props" />
-"#;
+		out_str += &format!("    <Import Project=\"{}\" />\n", build_dir_relative(&vcxproj_dir, "nasm.props"));
 	}
 	out_str += r#"  </ImportGroup>
   <ImportGroup Label="Shared">
@@ -662,13 +1084,15 @@ fn make_vcxproj(
 	for item in item_definition_groups {
 		out_str += &item;
 	}
+	let (build_events, tools) = build_events(proj_opts, target_data)?;
+	out_str += &build_events;
 	for item in item_groups {
 		out_str += &item;
 	}
 	if !sources.c.is_empty() {
 		out_str += "  <ItemGroup>\n";
 		for src in &sources.c {
-			let input = input_path(&src.full, &project_info.path);
+			let input = proj_opts.msbuild_path(&input_path(&src.full, &project_info.path), &vcxproj_dir);
 			out_str += &format!("    <ClCompile Include=\"{input}\" />\n");
 		}
 		out_str += "  </ItemGroup>\n";
@@ -676,7 +1100,7 @@ fn make_vcxproj(
 	if !sources.cpp.is_empty() {
 		out_str += "  <ItemGroup>\n";
 		for src in &sources.cpp {
-			let input = input_path(&src.full, &project_info.path);
+			let input = proj_opts.msbuild_path(&input_path(&src.full, &project_info.path), &vcxproj_dir);
 			out_str += &format!("    <ClCompile Include=\"{input}\" />\n");
 		}
 		out_str += "  </ItemGroup>\n";
@@ -684,30 +1108,55 @@ fn make_vcxproj(
 	if !sources.nasm.is_empty() {
 		out_str += "  <ItemGroup>\n";
 		for src in &sources.nasm {
-			let input = input_path(&src.full, &project_info.path);
+			let input = proj_opts.msbuild_path(&input_path(&src.full, &project_info.path), &vcxproj_dir);
 			out_str += &format!("    <NASM Include=\"{input}\" />\n");
 		}
 		out_str += "  </ItemGroup>\n";
 	}
 
+	if !target_data.runtime_files.is_empty() {
+		out_str += "  <ItemGroup>\n";
+		for file in &target_data.runtime_files {
+			let input = proj_opts.msbuild_path(&input_path(file, &project_info.path), &vcxproj_dir);
+			out_str += &format!(
+				"    <CopyFileToFolders Include=\"{input}\">\n      <DestinationFolders>$(OutDir)</DestinationFolders>\n    </CopyFileToFolders>\n"
+			);
+		}
+		out_str += "  </ItemGroup>\n";
+	}
+
 	let mut dependencies = Vec::new();
+	for tool in tools {
+		// Only for the solution's build order, as linking an executable makes no sense
+		match guid_map.iter().find(|x| x.vcxproj_path == tool) {
+			Some(x) => dependencies.push(x.clone()),
+			None => log::warn!(
+				"\"{}\" uses {} in a build step before it's generated, so the solution may build them in either order",
+				target_name,
+				tool
+			),
+		}
+	}
 	if !target_data.links.is_empty() {
 		out_str += "  <ItemGroup>\n";
-		out_str += &add_project_references(&target_data.links, proj_opts, guid_map, &mut dependencies)?;
+		out_str += &add_project_references(&target_data.links, &vcxproj_dir, proj_opts, guid_map, &mut dependencies)?;
+		out_str += "  </ItemGroup>\n";
+	}
+	let depends_references = add_depends_references(target_data, &vcxproj_dir, proj_opts, guid_map, &mut dependencies);
+	if !depends_references.is_empty() {
+		out_str += "  <ItemGroup>\n";
+		out_str += &depends_references;
 		out_str += "  </ItemGroup>\n";
 	}
 	out_str += r#"  <Import Project="$(VCTargetsPath)\Microsoft.Cpp.targets" />
   <ImportGroup Label="ExtensionTargets">
 "#;
 	if has_nasm {
-		out_str += r#"    <Import Project="..\..\nasm.targets" />
-"#;
+		out_str += &format!("    <Import Project=\"{}\" />\n", build_dir_relative(&vcxproj_dir, "nasm.targets"));
 	}
 	out_str += "  </ImportGroup>\n";
 	out_str += "</Project>\n";
-	let vcxproj_pathbuf = PathBuf::from(&project_info.name)
-		.join(target_name)
-		.join(target_name.to_owned() + ".vcxproj");
+	let vcxproj_pathbuf = vcxproj_dir.join(target_name.to_owned() + ".vcxproj");
 	let vcxproj_pathbuf_abs = proj_opts.build_dir.join(&vcxproj_pathbuf);
 	let vcxproj_path = vcxproj_pathbuf.to_string_lossy().into_owned();
 	let vsproj = VsProject {
@@ -722,11 +1171,134 @@ fn make_vcxproj(
 		return Err(format!("Error creating directory for \"{}\": {}", vcxproj_pathbuf.to_string_lossy(), e));
 	};
 	write_file(&vcxproj_pathbuf_abs, &out_str)?;
+	let filters_path = vcxproj_dir.join(target_name.to_owned() + ".vcxproj.filters");
+	write_file(
+		&proj_opts.build_dir.join(filters_path),
+		&filters_content(sources, project_info, proj_opts, &vcxproj_dir),
+	)?;
 	Ok(vsproj)
 }
 
+/// The `.vcxproj.filters` file, which lays out a target's sources in folders in Visual Studio.
+/// Sources in a `source_group()` are shown in the group's folder, others in a folder for their directory in the package.
+fn filters_content(
+	sources: &Sources,
+	project_info: &ProjectInfo,
+	proj_opts: &VcxprojOpts,
+	vcxproj_dir: &Path,
+) -> String {
+	let mut filters = BTreeSet::new();
+	let mut items = String::new();
+	for (element, group_sources) in [
+		("ClCompile", &sources.c),
+		("ClCompile", &sources.cpp),
+		("NASM", &sources.nasm),
+	] {
+		for src in group_sources {
+			let full = input_path(&src.full, &project_info.path);
+			let filter = match project_info.source_groups.iter().find(|x| x.sources.contains(&full)) {
+				Some(group) => group.name.replace('/', "\\"),
+				None => match Path::new(&src.name).parent() {
+					Some(dir) if dir.components().all(|x| matches!(x, Component::Normal(_))) => {
+						dir.to_string_lossy().replace('/', "\\")
+					}
+					_ => String::new(),
+				},
+			};
+			let input = proj_opts.msbuild_path(&full, vcxproj_dir);
+			if filter.is_empty() {
+				items += &format!("    <{element} Include=\"{input}\" />\n");
+				continue;
+			}
+			// Visual Studio needs each parent folder declared too
+			let mut parent = String::new();
+			for part in filter.split('\\') {
+				if !parent.is_empty() {
+					parent += "\\";
+				}
+				parent += part;
+				filters.insert(parent.clone());
+			}
+			let filter = xml_escape(&filter);
+			items +=
+				&format!("    <{element} Include=\"{input}\">\n      <Filter>{filter}</Filter>\n    </{element}>\n");
+		}
+	}
+	let mut ret = r#"<?xml version="1.0" encoding="utf-8"?>
+<Project ToolsVersion="4.0" xmlns="http://schemas.microsoft.com/developer/msbuild/2003">
+"#
+	.to_owned();
+	if !filters.is_empty() {
+		ret += "  <ItemGroup>\n";
+		for filter in filters {
+			let guid = stable_guid(&format!("{}/{}", paths::to_build_string(vcxproj_dir), filter));
+			let filter = xml_escape(&filter);
+			ret += &format!(
+				"    <Filter Include=\"{filter}\">\n      <UniqueIdentifier>{{{guid}}}</UniqueIdentifier>\n    </Filter>\n"
+			);
+		}
+		ret += "  </ItemGroup>\n";
+	}
+	if !items.is_empty() {
+		ret += "  <ItemGroup>\n";
+		ret += &items;
+		ret += "  </ItemGroup>\n";
+	}
+	ret += "</Project>\n";
+	ret
+}
+
+/// References to the projects of the targets `target_data` `depends` on, which only order the build.
+/// Like the projects of build step tools, they have to be generated before the target's.
+fn add_depends_references(
+	target_data: &TargetData,
+	vcxproj_dir: &Path,
+	proj_opts: &VcxprojOpts,
+	guid_map: &IndexMap,
+	dependencies: &mut Vec<VsProject>,
+) -> String {
+	let mut out_str = String::new();
+	for (package, name) in &target_data.depends {
+		let vcxproj_path = PathBuf::from(package)
+			.join(name)
+			.join(name.to_owned() + ".vcxproj")
+			.to_string_lossy()
+			.into_owned();
+		let Some(proj_ref) = guid_map.iter().find(|x| x.vcxproj_path == vcxproj_path) else {
+			log::warn!(
+				"\"{}\" depends on {} before it's generated, so the solution may build them in either order",
+				target_data.name,
+				vcxproj_path
+			);
+			continue;
+		};
+		dependencies.push(proj_ref.clone());
+		let proj_ref_include = match proj_opts.relative_to {
+			Some(_) => format!("$(ProjectDir){}", build_dir_relative(vcxproj_dir, &proj_ref.vcxproj_path)),
+			None => proj_opts
+				.build_dir
+				.join(&proj_ref.vcxproj_path)
+				.to_string_lossy()
+				.to_string(),
+		};
+		out_str += &format!(
+			r#"    <ProjectReference Include="{}">
+      <Project>{{{}}}</Project>
+      <Name>{}</Name>
+      <ReferenceOutputAssembly>false</ReferenceOutputAssembly>
+      <LinkLibraryDependencies>false</LinkLibraryDependencies>
+      <CopyToOutputDirectory>Never</CopyToOutputDirectory>
+    </ProjectReference>
+"#,
+			proj_ref_include, proj_ref.guid, name
+		);
+	}
+	out_str
+}
+
 fn add_project_references(
 	project_links: &Vec<LinkPtr>,
+	vcxproj_dir: &Path,
 	proj_opts: &VcxprojOpts,
 	guid_map: &mut IndexMap,
 	dependencies: &mut Vec<VsProject>,
@@ -735,21 +1307,36 @@ fn add_project_references(
 	let mut out_str = String::new();
 	for link in project_links {
 		log::debug!("   link: {}", link.name());
-		let mut add_dependency = |proj_ref: &VsProject| {
+		let mut add_dependency = |proj_ref: &VsProject, object_lib: bool| {
 			log::debug!("   add_dependency() {}", proj_ref.name);
 			dependencies.push(proj_ref.clone());
-			let proj_ref_include = proj_opts.build_dir.join(&proj_ref.vcxproj_path);
+			// vcxproj files are two directories below the build directory
+			let proj_ref_include = match proj_opts.relative_to {
+				Some(_) => format!("$(ProjectDir){}", build_dir_relative(vcxproj_dir, &proj_ref.vcxproj_path)),
+				None => proj_opts
+					.build_dir
+					.join(&proj_ref.vcxproj_path)
+					.to_string_lossy()
+					.to_string(),
+			};
+			// Object libraries are built as static libraries, so link their objects instead of the .lib to match Ninja
+			let link_objects = if object_lib {
+				"      <LinkLibraryDependencies>true</LinkLibraryDependencies>\n      <UseLibraryDependencyInputs>true</UseLibraryDependencyInputs>\n"
+			} else {
+				""
+			};
 			out_str += &format!(
 				r#"    <ProjectReference Include="{}">
       <Project>{{{}}}</Project>
       <Name>{}</Name>
       <ReferenceOutputAssembly>false</ReferenceOutputAssembly>
-      <CopyToOutputDirectory>Never</CopyToOutputDirectory>
+{}      <CopyToOutputDirectory>Never</CopyToOutputDirectory>
     </ProjectReference>
 "#,
-				proj_ref_include.to_string_lossy(),
+				proj_ref_include,
 				proj_ref.guid,
-				link.name()
+				link.name(),
+				link_objects
 			);
 		};
 		log::debug!("   match link: {}", link.name());
@@ -762,7 +1349,7 @@ fn add_project_references(
 						guid_map.get(link).unwrap()
 					}
 				};
-				add_dependency(proj_ref);
+				add_dependency(proj_ref, false);
 			}
 			LinkPtr::Object(obj_lib) => {
 				let proj_ref = match guid_map.get(link) {
@@ -772,10 +1359,11 @@ fn add_project_references(
 						guid_map.get(link).unwrap()
 					}
 				};
-				add_dependency(proj_ref);
+				add_dependency(proj_ref, true);
 			}
 			LinkPtr::Interface(_) => {
-				out_str += &add_project_references(&link.public_links(), proj_opts, guid_map, dependencies)?;
+				out_str +=
+					&add_project_references(&link.public_links(), vcxproj_dir, proj_opts, guid_map, dependencies)?;
 			}
 		}
 	}
@@ -827,3 +1415,18 @@ fn map_platform_to_nasm_format(platform: &str) -> Result<&'static str, String> {
 		)),
 	}
 }
+
+#[test]
+fn test_stable_guid() {
+	assert_eq!(stable_guid("pkg/app"), stable_guid("pkg/app"));
+	assert_ne!(stable_guid("pkg/app"), stable_guid("pkg/lib"));
+	assert_eq!(stable_guid("pkg/app").len(), 36);
+}
+
+#[test]
+fn test_build_dir_relative() {
+	let vcxproj_dir = PathBuf::from("pkg").join("app");
+	assert_eq!(build_dir_relative(&vcxproj_dir, "nasm.props"), r"..\..\nasm.props");
+	let vcxproj_dir = PathBuf::from("vendor").join("pkg").join("app");
+	assert_eq!(build_dir_relative(&vcxproj_dir, "nasm.targets"), r"..\..\..\nasm.targets");
+}
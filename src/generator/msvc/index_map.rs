@@ -29,7 +29,7 @@ impl IndexMap {
 	pub(super) fn insert_exe(&mut self, val: VsProject) {
 		self.vec.push(val);
 	}
-	pub(super) fn iter(&self) -> IndexMapIter {
+	pub(super) fn iter(&self) -> IndexMapIter<'_> {
 		IndexMapIter { index: 0, map: self }
 	}
 }
@@ -1,6 +1,6 @@
 use core::default::Default;
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 	hash::Hash,
 	io::Write,
 	path::{Path, PathBuf}, //
@@ -9,58 +9,35 @@ use std::{
 
 use log;
 
-use super::{TargetPlatform, Toolchain};
+use super::{
+	self as generator,
+	layout::{output_path, output_subfolder_path},
+	Language, PathStyle, Reproducible, TargetFlags, TargetPlatform, Toolchain,
+};
 use crate::{
+	artifact_cache::{self, ArtifactKey},
+	docs::Docs,
 	executable::Executable,
+	interface_library::InterfaceLibrary,
 	link_type::LinkPtr,
-	misc::{join_parent, Sources},
+	misc::{join_parent, relative_path, Sources},
 	object_library::ObjectLibrary,
-	project::Project,
+	paths,
+	project::{Project, ProjectInfo},
 	starlark_context::{StarContext, StarContextCompiler},
-	starlark_generator::eval_vars,
+	starlark_generator::{eval_interface_vars, eval_vars},
+	starlark_global::{StarGlobalOptions, StarPackageOptions},
 	starlark_object_library::StarGeneratorVars,
 	static_library::StaticLibrary,
 	target::{LinkTarget, Target},
 	toolchain::{
+		self,
 		compiler::{Assembler, Compiler, ExeLinker},
-		Profile,
+		CommandWrapper, Profile,
 	},
 	GlobalOptions,
 };
 
-fn input_path(src: &Path, project_path: &Path) -> String {
-	if src.is_relative() {
-		project_path.join(src)
-	} else {
-		src.to_owned()
-	}
-	.to_str()
-	.unwrap()
-	.trim_start_matches(r"\\?\")
-	.to_owned()
-}
-
-fn output_path(build_dir: &Path, project_name: &str, src: &str, ext: &str) -> String {
-	build_dir
-		.join(project_name)
-		.join(src.to_owned() + ext)
-		.to_str()
-		.unwrap()
-		.trim_start_matches(r"\\?\")
-		.to_owned()
-}
-
-fn output_subfolder_path(build_dir: &Path, project_name: &str, subfolder: &str, src: &str, ext: &str) -> String {
-	build_dir
-		.join(project_name)
-		.join(subfolder.to_owned() + ".dir")
-		.join(src.to_owned() + ext)
-		.to_str()
-		.unwrap()
-		.trim_start_matches(r"\\?\")
-		.to_owned()
-}
-
 fn transform_defines(defines: &[String]) -> Vec<String> {
 	defines
 		.iter()
@@ -84,17 +61,14 @@ fn transform_defines(defines: &[String]) -> Vec<String> {
 }
 
 #[derive(Clone)]
-#[allow(dead_code)]
 enum NinjaDeps {
 	Gcc,
-	Msvc, // `deps = msvc` is unused until catapult supports using cl.exe with Ninja
 }
 
 impl NinjaDeps {
 	fn as_str(&self) -> &'static str {
 		match self {
 			Self::Gcc => "gcc",
-			Self::Msvc => "msvc",
 		}
 	}
 }
@@ -167,24 +141,66 @@ struct NinjaRules {
 	assemble_nasm_object: Option<NinjaRule>,
 	link_static_lib: Option<NinjaRule>,
 	link_exe: Option<NinjaRule>,
+	sign_exe: Option<NinjaRule>,
+	objcopy: Option<NinjaRule>,
+	build_event: Option<NinjaRule>,
+	copy_file: Option<NinjaRule>,
+	artifact_store: Option<NinjaRule>,
+	build_docs: Option<NinjaRule>,
+	lint: Option<NinjaRule>,
+	compdb: Option<NinjaRule>,
+	coverage_report: Option<NinjaRule>,
+	size_report: Option<NinjaRule>,
+	regenerate: Option<NinjaRule>,
+}
+
+/// The files that targets linking each library depend on. Keys hash by pointer, so the interior mutability of the
+/// targets doesn't affect them.
+#[derive(Default)]
+struct LinkTargets {
+	map: HashMap<LinkPtr, Vec<String>>,
+}
+
+impl LinkTargets {
+	fn contains(&self, link: &LinkPtr) -> bool {
+		self.map.contains_key(link)
+	}
+
+	fn get(&self, link: &LinkPtr) -> Option<&Vec<String>> {
+		self.map.get(link)
+	}
+
+	fn insert(&mut self, link: LinkPtr, files: Vec<String>) {
+		self.map.insert(link, files);
+	}
 }
 
 struct NinjaBuild {
 	inputs: Vec<String>,
+	/// Inputs that aren't passed to the command but retrigger it when they change
+	implicit: Vec<String>,
+	order_only: Vec<String>,
 	output_targets: Vec<String>,
 	rule_name: String,
-	keyval_set: HashMap<String, Vec<String>>,
+	keyval_set: BTreeMap<String, Vec<String>>,
 }
 
 impl NinjaBuild {
 	fn as_string(&self) -> String {
 		let mut ret = String::new();
 		ret += &format!(
-			"build {}: {} {}\n",
+			"build {}: {} {}",
 			self.output_targets.join(" ").replace(':', "$:"),
 			self.rule_name,
 			self.inputs.join(" ").replace(':', "$:"),
 		);
+		if !self.implicit.is_empty() {
+			ret += &format!(" | {}", self.implicit.join(" ").replace(':', "$:"));
+		}
+		if !self.order_only.is_empty() {
+			ret += &format!(" || {}", self.order_only.join(" ").replace(':', "$:"));
+		}
+		ret += "\n";
 		for (key, values) in &self.keyval_set {
 			if !values.is_empty() {
 				ret += &format!("  {key} = {}\n", values.join(" ").replace(':', "$:"));
@@ -259,6 +275,7 @@ fn link_exe(exe_linker: &dyn ExeLinker) -> NinjaRule {
 		"-o".to_string(),
 		"$TARGET_FILE".to_string(),
 		"$LINK_PATH".to_string(),
+		"$LINK_LIBRARIES".to_string(),
 	]);
 	NinjaRule {
 		name: String::from("link_exe"),
@@ -268,54 +285,517 @@ fn link_exe(exe_linker: &dyn ExeLinker) -> NinjaRule {
 	}
 }
 
+/// Runs the toolchain's `sign_command` on the executable `$in`, then writes the stamp file `$out`
+fn sign_exe(sign_command: &[String]) -> NinjaRule {
+	let mut command = sign_command
+		.iter()
+		.map(|x| match x.contains(' ') {
+			true => format!("\"{}\"", x),
+			false => x.clone(),
+		})
+		.map(|x| x.replace('$', "$$").replace("$$out", "$in"))
+		.collect::<Vec<_>>();
+	command.extend(["&&", "cd", ".", ">", "\"$out\""].map(str::to_owned));
+	NinjaRule {
+		name: String::from("sign_exe"),
+		command,
+		description: Some("Signing $in".to_owned()),
+		..Default::default()
+	}
+}
+
+/// Converts the executable `$in` to `$out`, in the objcopy output format `$FORMAT`
+fn objcopy(objcopy: Vec<String>) -> NinjaRule {
+	let mut command = objcopy;
+	command.extend(["-O", "$FORMAT", "$in", "$out"].map(str::to_owned));
+	NinjaRule {
+		name: String::from("objcopy"),
+		command,
+		description: Some("Creating $out".to_owned()),
+		..Default::default()
+	}
+}
+
+/// The library files an interface library links, which are inputs of the executables that link it
+fn interface_link_files(lib: &InterfaceLibrary) -> Vec<String> {
+	lib.link_files.iter().map(|x| paths::to_build_string(x)).collect()
+}
+
+fn build_event() -> NinjaRule {
+	NinjaRule {
+		name: String::from("build_event"),
+		command: vec!["$COMMAND".to_owned()],
+		description: Some("$DESC".to_owned()),
+		..Default::default()
+	}
+}
+
+/// Copies a file with the host's shell, which Ninja runs commands with
+fn copy_file() -> NinjaRule {
+	let command = if cfg!(windows) {
+		vec!["cmd", "/c", "copy", "/Y", "$in", "$out", ">", "NUL"]
+	} else {
+		vec!["cp", "-f", "$in", "$out"]
+	};
+	NinjaRule {
+		name: String::from("copy_file"),
+		command: command.into_iter().map(str::to_owned).collect(),
+		description: Some("Copying $out".to_owned()),
+		..Default::default()
+	}
+}
+
+/// Copies a static library into the artifact cache, through a temporary file so that an interrupted copy isn't reused
+fn artifact_store() -> NinjaRule {
+	let command = if cfg!(windows) {
+		"cmd /c copy /Y $in $ENTRY.tmp > NUL && move /Y $ENTRY.tmp $ENTRY > NUL && cd . > $out"
+	} else {
+		"cp -f $in $ENTRY.tmp && mv -f $ENTRY.tmp $ENTRY && cd . > $out"
+	};
+	NinjaRule {
+		name: String::from("artifact_store"),
+		command: vec![command.to_owned()],
+		description: Some("Caching $in".to_owned()),
+		..Default::default()
+	}
+}
+
+fn build_docs() -> NinjaRule {
+	NinjaRule {
+		name: String::from("build_docs"),
+		command: ["doxygen", "$DOXYFILE", "&&", "cd", ".", ">", "\"$out\""]
+			.into_iter()
+			.map(str::to_owned)
+			.collect(),
+		description: Some("Generating documentation $DOXYFILE".to_owned()),
+		..Default::default()
+	}
+}
+
+fn lint() -> NinjaRule {
+	NinjaRule {
+		name: String::from("lint"),
+		command: vec!["$COMMAND".to_owned()],
+		description: Some("$DESC".to_owned()),
+		..Default::default()
+	}
+}
+
+/// Writes the compilation database clang-tidy reads, regenerated whenever build.ninja changes
+fn compdb() -> NinjaRule {
+	NinjaRule {
+		name: String::from("compdb"),
+		command: [
+			"ninja",
+			"-t",
+			"compdb",
+			"compile_c_object",
+			"compile_cpp_object",
+			">",
+			"$out",
+		]
+		.into_iter()
+		.map(str::to_owned)
+		.collect(),
+		description: Some("Writing $out".to_owned()),
+		..Default::default()
+	}
+}
+
+/// Quotes a command argument containing whitespace for the shell
+fn quote_arg(arg: &str) -> String {
+	match arg.contains(char::is_whitespace) {
+		true => format!("\"{}\"", arg),
+		false => arg.to_owned(),
+	}
+}
+
+/// Configures the build directory again. Ninja reloads build.ninja after running it.
+fn regenerate(command: &[String]) -> NinjaRule {
+	NinjaRule {
+		name: String::from("regenerate"),
+		command: command.iter().map(|x| quote_arg(x)).collect(),
+		description: Some("Regenerating build.ninja".to_owned()),
+		generator: true,
+		..Default::default()
+	}
+}
+
+fn coverage_report() -> NinjaRule {
+	NinjaRule {
+		name: String::from("coverage_report"),
+		command: vec!["$COMMAND".to_owned()],
+		description: Some("Reporting coverage".to_owned()),
+		..Default::default()
+	}
+}
+
+fn size_report(size_tool: String) -> NinjaRule {
+	NinjaRule {
+		name: String::from("size_report"),
+		command: vec![size_tool, "$in".to_owned()],
+		description: Some("Reporting binary sizes".to_owned()),
+		..Default::default()
+	}
+}
+
+/// Joins a target's pre/post-build commands into one. `&&` works in both sh and cmd.exe.
+fn event_command(commands: &[String]) -> String {
+	commands
+		.iter()
+		.map(|x| x.replace('$', "$$"))
+		.collect::<Vec<_>>()
+		.join(" && ")
+}
+
+/// Adds an edge running the target's pre-build commands, which the target's objects (`build_lines[first_object..]`) wait for.
+/// Its output is never created so the commands run on every build.
+/// `commands` with the paths of the executables recipes refer to with `Executable.path`, and those paths, which the
+/// build step depends on
+fn expand_build_step(
+	target: &dyn Target,
+	commands: &[String],
+	generator_opts: &GeneratorOpts,
+) -> Result<(Vec<String>, Vec<String>), String> {
+	let project = target.project();
+	let mut tools = Vec::new();
+	let mut expand_path = |package: &str, name: &str| {
+		let Some(tool_package) = generator::find_executable_package(&project, package, name) else {
+			return Err(format!(
+				"A build step of \"{}\" uses executable \"{}::{}\", which is not in its package or dependencies",
+				target.name(),
+				package,
+				name
+			));
+		};
+		// Tools are built in the host graph if there is one
+		let tool_opts = generator_opts.host.as_deref().unwrap_or(generator_opts);
+		let path = output_path(
+			&tool_opts.build_dir,
+			&tool_opts.package_dir(&tool_package.info),
+			name,
+			&tool_opts.target_platform.exe_ext,
+		);
+		if !tools.contains(&path) {
+			tools.push(path.clone());
+		}
+		Ok(path)
+	};
+	let commands = commands
+		.iter()
+		.map(|x| generator::expand_executable_paths(x, &mut expand_path))
+		.collect::<Result<Vec<_>, _>>()?;
+	Ok((commands, tools))
+}
+
+fn add_pre_build(
+	target: &dyn Target,
+	commands: &[String],
+	first_object: usize,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> Result<(), String> {
+	if commands.is_empty() {
+		return Ok(());
+	}
+	let (commands, tools) = expand_build_step(target, commands, generator_opts)?;
+	let out_name = output_subfolder_path(
+		&generator_opts.build_dir,
+		&generator_opts.package_dir(&target.project().info),
+		target.name(),
+		"pre_build",
+		"",
+	);
+	for obj in &mut build_lines[first_object..] {
+		obj.order_only.push(out_name.clone());
+	}
+	let rule = rules.build_event.get_or_insert_with(build_event);
+	build_lines.push(NinjaBuild {
+		inputs: tools,
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![out_name],
+		rule_name: rule.name.clone(),
+		keyval_set: BTreeMap::from([
+			("COMMAND".to_owned(), vec![event_command(&commands)]),
+			("DESC".to_owned(), vec![format!("Pre-build step for {}", target.name())]),
+		]),
+	});
+	Ok(())
+}
+
+/// Makes the target's objects and pre-build step (`build_lines[first_object..]`) wait for the phony targets of the
+/// executables and static libraries it `depends` on
+fn add_depends(
+	target: &dyn Target,
+	depends: &[(String, String)],
+	first_object: usize,
+	generator_opts: &GeneratorOpts,
+	build_lines: &mut [NinjaBuild],
+) {
+	let project = target.project();
+	for (package, name) in depends {
+		// Executables are built by the host graph if they run during the build, but keep their name there
+		let phony = match generator::find_executable_package(&project, package, name) {
+			Some(_) => name.clone(),
+			None => generator_opts.library_phony(name),
+		};
+		for line in &mut build_lines[first_object..] {
+			if !line.order_only.contains(&phony) {
+				line.order_only.push(phony.clone());
+			}
+		}
+	}
+}
+
+/// Adds an edge running the target's post-build commands after `inputs` are built, and returns its stamp file
+fn add_post_build(
+	target: &dyn Target,
+	commands: &[String],
+	mut inputs: Vec<String>,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> Result<Option<String>, String> {
+	if commands.is_empty() {
+		return Ok(None);
+	}
+	let (commands, tools) = expand_build_step(target, commands, generator_opts)?;
+	inputs.extend(tools);
+	let out_name = output_subfolder_path(
+		&generator_opts.build_dir,
+		&generator_opts.package_dir(&target.project().info),
+		target.name(),
+		"post_build",
+		".stamp",
+	);
+	let rule = rules.build_event.get_or_insert_with(build_event);
+	build_lines.push(NinjaBuild {
+		inputs,
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![out_name.clone()],
+		rule_name: rule.name.clone(),
+		keyval_set: BTreeMap::from([
+			("COMMAND".to_owned(), vec![event_command(&commands) + " && cd . > \"$out\""]),
+			("DESC".to_owned(), vec![format!("Post-build step for {}", target.name())]),
+		]),
+	});
+	Ok(Some(out_name))
+}
+
 pub struct Ninja {}
 
 struct GeneratorOpts {
+	/// Where outputs are written. `$builddir` when generating relative paths.
 	build_dir: PathBuf,
+	/// The canonical build directory that sources and include directories are made relative to, if any
+	relative_to: Option<PathBuf>,
 	toolchain: Toolchain,
 	profile: Profile,
 	global_opts: GlobalOptions,
 	target_platform: TargetPlatform,
 	star_context: StarContext,
+	/// The name of the root package. Only dependency packages use the artifact cache.
+	root_package: String,
+	/// Set when the `reproducible` option is enabled
+	reproducible: Option<Reproducible>,
+	/// The executables that run during the build, as (package, name)
+	host_executables: BTreeSet<(String, String)>,
+	/// Options of the graph built with `[host_toolchain]`, which builds `host_executables` and the libraries they
+	/// link, if the toolchain has one
+	host: Option<Box<GeneratorOpts>>,
+	/// Set in the host graph, whose outputs go in `host/`
+	is_host: bool,
 }
 
 struct SourceData {
 	includes: Vec<PathBuf>,
 	defines: Vec<String>,
+	/// C and C++ compile flags of the interface libraries linked
+	compile_flags: Vec<String>,
+}
+
+impl GeneratorOpts {
+	/// `path`, relative to the build directory if generating relative paths
+	fn source_path(&self, path: &Path) -> PathBuf {
+		match &self.relative_to {
+			Some(base) => relative_path(path, base).unwrap_or_else(|| path.to_owned()),
+			None => path.to_owned(),
+		}
+	}
+
+	fn input_path(&self, src: &Path, project_path: &Path) -> String {
+		let src = if src.is_relative() {
+			project_path.join(src)
+		} else {
+			src.to_owned()
+		};
+		paths::to_build_string(&self.source_path(&src))
+	}
+
+	/// Where the outputs of package `info` go, relative to the build directory
+	fn package_dir(&self, info: &ProjectInfo) -> PathBuf {
+		let package_dir = self.global_opts.output_layout.package_dir(info, &self.root_package);
+		match self.is_host {
+			true => Path::new("host").join(package_dir),
+			false => package_dir,
+		}
+	}
+
+	/// The phony target of library `name`, prefixed with `host/` in the host graph so it doesn't clash with the same
+	/// library built for the target
+	fn library_phony(&self, name: &str) -> String {
+		match self.is_host {
+			true => format!("host/{}", name),
+			false => name.to_owned(),
+		}
+	}
+
+	/// Whether `exe` is left to the host graph
+	fn is_host_executable(&self, exe: &Executable) -> bool {
+		self.host.is_some()
+			&& self
+				.host_executables
+				.contains(&(exe.project().info.name.clone(), exe.name.clone()))
+	}
+
+	/// The build directory on disk, for files written while generating
+	fn real_build_dir(&self) -> &Path {
+		self.relative_to.as_deref().unwrap_or(&self.build_dir)
+	}
+
+	/// Where instrumented executables write coverage data, if the linker needs to be told
+	fn coverage_dir(&self) -> PathBuf {
+		self.real_build_dir().join("coverage")
+	}
+
+	/// What every target is built with besides its own settings
+	fn flags(&self) -> TargetFlags<'_> {
+		TargetFlags {
+			toolchain: &self.toolchain,
+			profile: &self.profile,
+			global_opts: &self.global_opts,
+			reproducible: self.reproducible.as_ref(),
+		}
+	}
+
+	fn source_data(&self, includes: Vec<PathBuf>, mut defines: Vec<String>, compile_flags: Vec<String>) -> SourceData {
+		defines.extend(self.flags().defines());
+		SourceData {
+			includes: includes.iter().map(|x| self.source_path(x)).collect(),
+			defines,
+			compile_flags,
+		}
+	}
 }
 
 impl Ninja {
 	pub fn generate(
 		project: Arc<Project>,
 		build_dir: &Path,
-		toolchain: Toolchain,
+		mut toolchain: Toolchain,
 		profile: Profile,
 		global_opts: GlobalOptions,
 		target_platform: TargetPlatform,
+		path_style: PathStyle,
 	) -> Result<(), String> {
+		let relative_to = match path_style {
+			PathStyle::Absolute => None,
+			PathStyle::Relative => match paths::canonicalize(build_dir) {
+				Ok(x) => Some(x),
+				Err(e) => return Err(format!("Error resolving build directory {}: {}", build_dir.display(), e)),
+			},
+		};
+		let reproducible = Reproducible::new(&project, &global_opts, build_dir)?;
 		let mut rules = NinjaRules::default();
 		let mut build_lines = Vec::new();
-		let star_context = StarContext {
-			c_compiler: toolchain
-				.c_compiler
-				.as_ref()
-				.map(|compiler| StarContextCompiler { target_triple: compiler.target() }),
-			cpp_compiler: toolchain
-				.cpp_compiler
+		let host_toolchain = toolchain.host.take();
+		let host = host_toolchain.map(|host_toolchain| {
+			// The host toolchain's profile of the same name, if it has one
+			let mut host_profile = global_opts
+				.profile
 				.as_ref()
-				.map(|compiler| StarContextCompiler { target_triple: compiler.target() }),
-		};
+				.and_then(|name| host_toolchain.profile.get(name))
+				.cloned()
+				.unwrap_or_else(|| profile.clone());
+			host_profile.coverage = false;
+			Box::new(GeneratorOpts {
+				build_dir: match relative_to {
+					Some(_) => PathBuf::from("$builddir"),
+					None => build_dir.to_owned(),
+				},
+				relative_to: relative_to.clone(),
+				target_platform: generator::target_platform(&host_toolchain),
+				star_context: star_context(&host_toolchain, &global_opts, build_dir),
+				toolchain: *host_toolchain,
+				profile: host_profile,
+				global_opts: global_opts.clone(),
+				root_package: project.info.name.clone(),
+				reproducible: reproducible.clone(),
+				host_executables: BTreeSet::new(),
+				host: None,
+				is_host: true,
+			})
+		});
 		let generator_opts = GeneratorOpts {
-			build_dir: build_dir.to_owned(),
+			build_dir: match relative_to {
+				Some(_) => PathBuf::from("$builddir"),
+				None => build_dir.to_owned(),
+			},
+			relative_to,
+			star_context: star_context(&toolchain, &global_opts, build_dir),
 			toolchain,
 			profile,
 			global_opts,
 			target_platform,
-			star_context,
+			root_package: project.info.name.clone(),
+			reproducible,
+			host_executables: generator::host_executables(&project),
+			host,
+			is_host: false,
 		};
-		let mut link_targets = HashMap::new();
+		let mut link_targets = LinkTargets::default();
 		Ninja::generate_inner(&project, &generator_opts, &mut rules, &mut build_lines, &mut link_targets)?;
+		add_lint_targets(&project, &generator_opts, &mut rules, &mut build_lines);
+		if generator_opts.profile.coverage {
+			add_coverage_target(&project, &generator_opts, &mut rules, &mut build_lines)?;
+		}
+		add_size_report_target(&generator_opts, &mut rules, &mut build_lines);
+		if let Some(wrapper) = &generator_opts.toolchain.command_wrapper {
+			wrap_rules(&mut rules, wrapper);
+		}
+		if let Some(regeneration) = &generator_opts.global_opts.regeneration {
+			let project_path = project.info.path.clone();
+			rules.regenerate = Some(regenerate(&regeneration.command));
+			build_lines.push(NinjaBuild {
+				inputs: generator::configure_inputs(&project, &regeneration.inputs)
+					.iter()
+					.map(|x| generator_opts.input_path(x, &project_path))
+					.collect(),
+				implicit: Vec::new(),
+				order_only: Vec::new(),
+				// As ninja loaded it, so it knows the manifest is out of date
+				output_targets: vec!["build.ninja".to_owned()],
+				rule_name: "regenerate".to_owned(),
+				keyval_set: BTreeMap::new(),
+			});
+		}
+		let host_rules = match &generator_opts.host {
+			Some(host_opts) => Some(add_host_graph(
+				&project,
+				host_opts,
+				&generator_opts.host_executables,
+				&mut rules,
+				&mut build_lines,
+			)?),
+			None => None,
+		};
 		let mut rules_str = String::new();
+		if generator_opts.relative_to.is_some() {
+			rules_str += "builddir = .\n\n";
+		}
 		if let Some(c) = rules.compile_c_object {
 			rules_str += &c.as_string();
 		}
@@ -331,6 +811,48 @@ impl Ninja {
 		if let Some(c) = rules.link_exe {
 			rules_str += &c.as_string();
 		}
+		if let Some(c) = rules.sign_exe {
+			rules_str += &c.as_string();
+		}
+		if let Some(c) = rules.objcopy {
+			rules_str += &c.as_string();
+		}
+		if let Some(mut host_rules) = host_rules {
+			for c in toolchain_rules(&mut host_rules).into_iter().flatten() {
+				rules_str += &c.as_string();
+			}
+		}
+		if let Some(c) = rules.build_event {
+			rules_str += &c.as_string();
+		}
+		if let Some(c) = rules.copy_file {
+			rules_str += &c.as_string();
+		}
+		if let Some(c) = rules.artifact_store {
+			rules_str += &c.as_string();
+		}
+		let has_optional_targets = rules.build_docs.is_some()
+			|| rules.lint.is_some()
+			|| rules.coverage_report.is_some()
+			|| rules.size_report.is_some();
+		if let Some(c) = rules.build_docs {
+			rules_str += &c.as_string();
+		}
+		if let Some(c) = rules.lint {
+			rules_str += &c.as_string();
+		}
+		if let Some(c) = rules.compdb {
+			rules_str += &c.as_string();
+		}
+		if let Some(c) = rules.coverage_report {
+			rules_str += &c.as_string();
+		}
+		if let Some(c) = rules.size_report {
+			rules_str += &c.as_string();
+		}
+		if let Some(c) = rules.regenerate {
+			rules_str += &c.as_string();
+		}
 		let build_ninja_path = build_dir.join("build.ninja");
 		let mut f = match std::fs::File::create(build_ninja_path) {
 			Ok(x) => x,
@@ -339,11 +861,16 @@ impl Ninja {
 		if let Err(e) = f.write_all(rules_str.as_bytes()) {
 			return Err(format!("Error writing to build.ninja: {}", e));
 		}
-		for line in build_lines {
+		for line in &build_lines {
 			if let Err(e) = f.write_all(line.as_string().as_bytes()) {
 				return Err(format!("Error writing to build.ninja: {}", e));
 			}
 		}
+		if has_optional_targets {
+			if let Err(e) = f.write_all(default_targets(&build_lines).as_bytes()) {
+				return Err(format!("Error writing to build.ninja: {}", e));
+			}
+		}
 		Ok(())
 	}
 
@@ -352,53 +879,215 @@ impl Ninja {
 		generator_opts: &GeneratorOpts,
 		rules: &mut NinjaRules,
 		build_lines: &mut Vec<NinjaBuild>,
-		link_targets: &mut HashMap<LinkPtr, Vec<String>>,
+		link_targets: &mut LinkTargets,
 	) -> Result<(), String> {
 		log::debug!("Ninja::generate_inner() build_dir: {}", generator_opts.build_dir.display());
 
+		// Dependencies come before the packages that use them, and targets in the order they're declared, so the build
+		// lines are in the same order on every run
 		for subproject in &project.dependencies {
 			Ninja::generate_inner(subproject, generator_opts, rules, build_lines, link_targets)?;
 		}
 
 		for lib in &project.static_libraries {
-			if !link_targets.contains_key(&LinkPtr::Static(lib.clone())) {
+			if !link_targets.contains(&LinkPtr::Static(lib.clone())) {
 				add_static_lib_target(lib, generator_opts, rules, build_lines, link_targets)?;
 			}
 		}
 
 		for lib in &project.object_libraries {
-			if !link_targets.contains_key(&LinkPtr::Object(lib.clone())) {
+			if !link_targets.contains(&LinkPtr::Object(lib.clone())) {
 				add_object_lib_target(lib, generator_opts, rules, build_lines, link_targets)?;
 			}
 		}
 
 		for lib in &project.interface_libraries {
 			let key = LinkPtr::Interface(lib.clone());
-			link_targets.entry(key).or_default();
+			if !link_targets.contains(&key) {
+				link_targets.insert(key, interface_link_files(lib));
+			}
 		}
 
 		for exe in &project.executables {
+			if exe.benchmark && !generator_opts.global_opts.benchmarks {
+				continue;
+			}
+			if generator_opts.is_host_executable(exe) {
+				continue;
+			}
 			add_executable_target(exe, generator_opts, rules, build_lines, link_targets)?;
 		}
+
+		for docs in &project.docs {
+			add_docs_target(docs, generator_opts, rules, build_lines)?;
+		}
 		Ok(())
 	}
 }
 
+/// The context recipes' `generator_vars` functions get
+fn star_context(toolchain: &Toolchain, global_opts: &GlobalOptions, build_dir: &Path) -> StarContext {
+	StarContext {
+		c_compiler: toolchain
+			.c_compiler
+			.as_ref()
+			.map(|compiler| StarContextCompiler { target_triple: compiler.target() }),
+		cpp_compiler: toolchain
+			.cpp_compiler
+			.as_ref()
+			.map(|compiler| StarContextCompiler { target_triple: compiler.target() }),
+		global_options: StarGlobalOptions::new(global_opts),
+		package_options: StarPackageOptions(HashMap::new()),
+		profile: global_opts.profile.clone(),
+		source_dir: String::new(),
+		build_dir: build_dir.to_string_lossy().to_string(),
+	}
+}
+
+/// The rules that run the toolchain's compilers and linkers
+fn toolchain_rules(rules: &mut NinjaRules) -> [&mut Option<NinjaRule>; 5] {
+	[
+		&mut rules.compile_c_object,
+		&mut rules.compile_cpp_object,
+		&mut rules.assemble_nasm_object,
+		&mut rules.link_static_lib,
+		&mut rules.link_exe,
+	]
+}
+
+fn wrap_rules(rules: &mut NinjaRules, wrapper: &CommandWrapper) {
+	for rule in toolchain_rules(rules).into_iter().flatten() {
+		if wrapper.wraps(&rule.name) {
+			rule.command.splice(0..0, wrapper.command.iter().cloned());
+		}
+	}
+}
+
+/// Adds the host executables of `project` and its dependencies, and the libraries they link, built with the host
+/// toolchain. Returns the host toolchain's rules, renamed with a `host_` prefix. The other rules are shared with the
+/// target graph, so they're added to `rules`.
+fn add_host_graph(
+	project: &Arc<Project>,
+	host_opts: &GeneratorOpts,
+	host_executables: &BTreeSet<(String, String)>,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> Result<NinjaRules, String> {
+	fn add_link(
+		link: &LinkPtr,
+		host_opts: &GeneratorOpts,
+		rules: &mut NinjaRules,
+		build_lines: &mut Vec<NinjaBuild>,
+		link_targets: &mut LinkTargets,
+	) -> Result<(), String> {
+		if link_targets.contains(link) {
+			return Ok(());
+		}
+		let links = match link {
+			LinkPtr::Static(lib) => lib.link_private.iter().chain(&lib.link_public).cloned().collect(),
+			LinkPtr::Object(lib) => lib.link_private.iter().chain(&lib.link_public).cloned().collect(),
+			LinkPtr::Interface(lib) => lib.links.clone(),
+		};
+		for link in &links {
+			add_link(link, host_opts, rules, build_lines, link_targets)?;
+		}
+		match link {
+			LinkPtr::Static(lib) => {
+				add_static_lib_target(lib, host_opts, rules, build_lines, link_targets)?;
+			}
+			LinkPtr::Object(lib) => {
+				add_object_lib_target(lib, host_opts, rules, build_lines, link_targets)?;
+			}
+			LinkPtr::Interface(lib) => {
+				link_targets.insert(link.clone(), interface_link_files(lib));
+			}
+		}
+		Ok(())
+	}
+	fn add_package(
+		project: &Arc<Project>,
+		host_opts: &GeneratorOpts,
+		host_executables: &BTreeSet<(String, String)>,
+		visited: &mut BTreeSet<String>,
+		rules: &mut NinjaRules,
+		build_lines: &mut Vec<NinjaBuild>,
+		link_targets: &mut LinkTargets,
+	) -> Result<(), String> {
+		if !visited.insert(project.info.name.clone()) {
+			return Ok(());
+		}
+		for dep in &project.dependencies {
+			add_package(dep, host_opts, host_executables, visited, rules, build_lines, link_targets)?;
+		}
+		for exe in &project.executables {
+			if !host_executables.contains(&(project.info.name.clone(), exe.name.clone())) {
+				continue;
+			}
+			for link in &exe.links {
+				add_link(link, host_opts, rules, build_lines, link_targets)?;
+			}
+			add_executable_target(exe, host_opts, rules, build_lines, link_targets)?;
+		}
+		Ok(())
+	}
+
+	let mut host_rules = NinjaRules::default();
+	let first_line = build_lines.len();
+	add_package(
+		project,
+		host_opts,
+		host_executables,
+		&mut BTreeSet::new(),
+		&mut host_rules,
+		build_lines,
+		&mut LinkTargets::default(),
+	)?;
+	if let Some(wrapper) = &host_opts.toolchain.command_wrapper {
+		wrap_rules(&mut host_rules, wrapper);
+	}
+	for rule in toolchain_rules(&mut host_rules).into_iter().flatten() {
+		let host_name = format!("host_{}", rule.name);
+		for line in &mut build_lines[first_line..] {
+			if line.rule_name == rule.name {
+				line.rule_name = host_name.clone();
+			}
+		}
+		rule.name = host_name;
+	}
+	for (rule, host_rule) in [
+		(&mut rules.build_event, host_rules.build_event.take()),
+		(&mut rules.copy_file, host_rules.copy_file.take()),
+		(&mut rules.artifact_store, host_rules.artifact_store.take()),
+	] {
+		if rule.is_none() {
+			*rule = host_rule;
+		}
+	}
+	Ok(host_rules)
+}
+
 fn add_static_lib_target(
 	lib: &Arc<StaticLibrary>,
 	generator_opts: &GeneratorOpts,
 	rules: &mut NinjaRules,
 	build_lines: &mut Vec<NinjaBuild>,
-	link_targets: &mut HashMap<LinkPtr, Vec<String>>,
+	link_targets: &mut LinkTargets,
 ) -> Result<Vec<String>, String> {
 	let GeneratorOpts { toolchain, build_dir, target_platform, star_context, .. } = generator_opts;
 	let mut inputs = Vec::<String>::new();
 
 	let generator_vars = if let Some(gen_func) = &lib.generator_vars {
-		eval_vars(gen_func, star_context.clone(), "generator_vars")?
+		eval_vars(gen_func, star_context.for_package(&lib.project().info), "generator_vars")?
 	} else {
 		StarGeneratorVars::default()
 	};
+	let links = lib
+		.link_private
+		.iter()
+		.chain(&lib.link_public)
+		.cloned()
+		.collect::<Vec<_>>();
+	let interface_vars = eval_interface_vars(&links, || Ok(star_context.clone()))?;
 	let mut includes = lib.public_includes_recursive();
 	includes.extend_from_slice(&lib.private_includes());
 	includes.extend(
@@ -407,19 +1096,67 @@ fn add_static_lib_target(
 			.iter()
 			.map(|x| join_parent(&lib.project().info.path, x).full),
 	);
-	let sources = lib
-		.sources
-		.extended_with(Sources::from_slice(&generator_vars.sources, &lib.project().info.path)?);
+	includes.extend(interface_vars.include_dirs.iter().map(PathBuf::from));
+	let sources = lib.sources.extended_with(Sources::from_slice(
+		&generator_vars.sources,
+		&lib.project().info.path,
+		&generator_opts.toolchain.source_extensions,
+	)?);
 	let mut defines = lib.public_defines_recursive();
 	defines.extend_from_slice(lib.private_defines());
 	defines.extend_from_slice(&generator_vars.defines);
+	defines.extend_from_slice(&interface_vars.defines);
 
-	let source_data = SourceData { includes, defines };
+	let source_data = generator_opts.source_data(includes.clone(), defines, generator::interface_compile_flags(&links));
 
+	let first_object = build_lines.len();
 	add_obj_sources(&sources, generator_opts, lib.as_ref(), &source_data, rules, build_lines, &mut inputs)?;
+	add_pre_build(lib.as_ref(), &lib.pre_build, first_object, generator_opts, rules, build_lines)?;
+	add_depends(lib.as_ref(), &lib.depends, first_object, generator_opts, build_lines);
 
-	let out_name = output_path(build_dir, &lib.project().info.name, lib.output_name(), &target_platform.static_lib_ext);
+	let out_name = output_path(
+		build_dir,
+		&generator_opts.package_dir(&lib.project().info),
+		lib.output_name(),
+		&target_platform.static_lib_ext,
+	);
 	let output_targets = vec![out_name.clone()];
+	// Hits are decided while configuring, so only packages whose content the registry pins are cached. A path or git
+	// dependency could change afterwards while the build copied the stale entry. Build steps could change the library
+	// in ways the key doesn't capture, so libraries with them aren't cached either.
+	let cache_entry = match &generator_opts.global_opts.artifact_cache {
+		Some(cache_dir)
+			if lib.project().info.registry_hash.is_some() && lib.pre_build.is_empty() && lib.post_build.is_empty() =>
+		{
+			let key = artifact_key(lib, &sources, &includes, &build_lines[first_object..], generator_opts)?;
+			let file_name = lib.output_name().to_owned() + &target_platform.static_lib_ext;
+			Some(artifact_cache::entry_path(cache_dir, &key, &file_name))
+		}
+		_ => None,
+	};
+	if let Some(entry) = cache_entry.as_ref().filter(|x| x.exists()) {
+		log::info!("Restoring {} from the artifact cache: {}", lib.name, entry.display());
+		build_lines.truncate(first_object);
+		let rule = rules.copy_file.get_or_insert_with(copy_file);
+		build_lines.push(NinjaBuild {
+			inputs: vec![paths::to_build_string(entry)],
+			implicit: Vec::new(),
+			order_only: Vec::new(),
+			output_targets: output_targets.clone(),
+			rule_name: rule.name.clone(),
+			keyval_set: BTreeMap::new(),
+		});
+		build_lines.push(NinjaBuild {
+			inputs: vec![out_name],
+			implicit: Vec::new(),
+			order_only: Vec::new(),
+			output_targets: vec![generator_opts.library_phony(&lib.name)],
+			rule_name: "phony".to_owned(),
+			keyval_set: BTreeMap::new(),
+		});
+		link_targets.insert(LinkPtr::Static(lib.clone()), output_targets.clone());
+		return Ok(output_targets);
+	}
 	let rule_name = match &rules.link_static_lib {
 		Some(x) => x.name.clone(),
 		None => {
@@ -432,7 +1169,10 @@ fn add_static_lib_target(
 					))
 				}
 			};
-			let link_static_lib_rule = link_static_lib(static_linker);
+			let link_static_lib_rule = match generator_opts.reproducible {
+				Some(_) => link_static_lib(&toolchain::deterministic_static_linker(static_linker)),
+				None => link_static_lib(static_linker),
+			};
 			let rule_name = link_static_lib_rule.name.clone();
 			rules.link_static_lib = Some(link_static_lib_rule);
 			rule_name
@@ -441,38 +1181,137 @@ fn add_static_lib_target(
 	let link_flags = Vec::new();
 	build_lines.push(NinjaBuild {
 		inputs,
+		implicit: Vec::new(),
+		order_only: Vec::new(),
 		output_targets: output_targets.clone(),
 		rule_name,
-		keyval_set: HashMap::from([
+		keyval_set: BTreeMap::from([
 			("TARGET_FILE".to_string(), vec![out_name.clone()]),
 			("LINK_FLAGS".to_string(), link_flags),
 		]),
 	});
+	let post_build =
+		add_post_build(lib.as_ref(), &lib.post_build, vec![out_name.clone()], generator_opts, rules, build_lines)?;
+	let artifact_stamp = match cache_entry {
+		Some(entry) => Some(add_artifact_store(lib, &out_name, &entry, generator_opts, rules, build_lines)?),
+		None => None,
+	};
 	build_lines.push(NinjaBuild {
-		inputs: vec![out_name],
-		output_targets: vec![lib.name.clone()],
+		inputs: [out_name].into_iter().chain(post_build).chain(artifact_stamp).collect(),
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![generator_opts.library_phony(&lib.name)],
 		rule_name: "phony".to_owned(),
-		keyval_set: HashMap::new(),
+		keyval_set: BTreeMap::new(),
 	});
 	link_targets.insert(LinkPtr::Static(lib.clone()), output_targets.clone());
 	Ok(output_targets)
 }
 
+/// The artifact cache key of a static library: the registry's hash of its package, the compilers, the flags and
+/// defines of each object, and the content of the sources and include directories
+fn artifact_key(
+	lib: &StaticLibrary,
+	sources: &Sources,
+	includes: &[PathBuf],
+	compile_lines: &[NinjaBuild],
+	generator_opts: &GeneratorOpts,
+) -> Result<String, String> {
+	let info = &lib.project().info;
+	let toolchain = &generator_opts.toolchain;
+	let mut key = ArtifactKey::new();
+	key.add("package", &info.name);
+	key.add("package_version", info.version.as_deref().unwrap_or_default());
+	key.add("registry_hash", info.registry_hash.as_deref().unwrap_or_default());
+	key.add("output", lib.output_name());
+	for (lang, compiler) in [("c", &toolchain.c_compiler), ("cpp", &toolchain.cpp_compiler)] {
+		if let Some(compiler) = compiler {
+			key.add(lang, &format!("{} {} {}", compiler.id(), compiler.version(), compiler.target()));
+		}
+	}
+	if let Some(assembler) = &toolchain.nasm_assembler {
+		key.add("nasm", &format!("{} {}", assembler.id(), assembler.version()));
+	}
+	if let Some(static_linker) = &toolchain.static_linker {
+		key.add("static_linker", &static_linker.join(" "));
+	}
+	for line in compile_lines {
+		key.add("rule", &line.rule_name);
+		for name in ["DEFINES", "FLAGS"] {
+			key.add(name, &line.keyval_set.get(name).map(|x| x.join(" ")).unwrap_or_default());
+		}
+	}
+	let read_error =
+		|path: &Path, e: std::io::Error| format!("Error hashing {} for the artifact cache: {}", path.display(), e);
+	for src in sources.iter() {
+		key.add_file(&src.name, &src.full)
+			.map_err(|e| read_error(&src.full, e))?;
+	}
+	for dir in includes {
+		if dir.is_dir() {
+			key.add_dir(dir).map_err(|e| read_error(dir, e))?;
+		} else {
+			key.add("missing_dir", &dir.to_string_lossy());
+		}
+	}
+	Ok(key.finish())
+}
+
+/// Adds an edge copying a built static library into the artifact cache, and returns its stamp file
+fn add_artifact_store(
+	lib: &StaticLibrary,
+	out_name: &str,
+	entry: &Path,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> Result<String, String> {
+	if let Some(entry_dir) = entry.parent() {
+		if let Err(e) = std::fs::create_dir_all(entry_dir) {
+			return Err(format!("Error creating directory {}: {}", entry_dir.display(), e));
+		}
+	}
+	let stamp = output_subfolder_path(
+		&generator_opts.build_dir,
+		&generator_opts.package_dir(&lib.project().info),
+		&lib.name,
+		"artifact",
+		".stamp",
+	);
+	let rule = rules.artifact_store.get_or_insert_with(artifact_store);
+	build_lines.push(NinjaBuild {
+		inputs: vec![out_name.to_owned()],
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![stamp.clone()],
+		rule_name: rule.name.clone(),
+		keyval_set: BTreeMap::from([("ENTRY".to_owned(), vec![paths::to_build_string(entry)])]),
+	});
+	Ok(stamp)
+}
+
 fn add_object_lib_target(
 	lib: &Arc<ObjectLibrary>,
 	generator_opts: &GeneratorOpts,
 	rules: &mut NinjaRules,
 	build_lines: &mut Vec<NinjaBuild>,
-	link_targets: &mut HashMap<LinkPtr, Vec<String>>,
+	link_targets: &mut LinkTargets,
 ) -> Result<Vec<String>, String> {
 	let GeneratorOpts { build_dir, target_platform, star_context, .. } = generator_opts;
 	let mut inputs = Vec::<String>::new();
 
 	let generator_vars = if let Some(gen_func) = &lib.generator_vars {
-		eval_vars(gen_func, star_context.clone(), "generator_vars")?
+		eval_vars(gen_func, star_context.for_package(&lib.project().info), "generator_vars")?
 	} else {
 		StarGeneratorVars::default()
 	};
+	let links = lib
+		.link_private
+		.iter()
+		.chain(&lib.link_public)
+		.cloned()
+		.collect::<Vec<_>>();
+	let interface_vars = eval_interface_vars(&links, || Ok(star_context.clone()))?;
 	let mut includes = lib.public_includes_recursive();
 	includes.extend_from_slice(&lib.private_includes());
 	includes.extend(
@@ -481,23 +1320,42 @@ fn add_object_lib_target(
 			.iter()
 			.map(|x| join_parent(&lib.project().info.path, x).full),
 	);
-	let sources = lib
-		.sources
-		.extended_with(Sources::from_slice(&generator_vars.sources, &lib.project().info.path)?);
+	includes.extend(interface_vars.include_dirs.iter().map(PathBuf::from));
+	let sources = lib.sources.extended_with(Sources::from_slice(
+		&generator_vars.sources,
+		&lib.project().info.path,
+		&generator_opts.toolchain.source_extensions,
+	)?);
 	let mut defines = lib.public_defines_recursive();
 	defines.extend_from_slice(lib.private_defines());
 	defines.extend_from_slice(&generator_vars.defines);
+	defines.extend_from_slice(&interface_vars.defines);
 
-	let source_data = SourceData { includes, defines };
+	let source_data = generator_opts.source_data(includes, defines, generator::interface_compile_flags(&links));
 
+	let first_object = build_lines.len();
 	add_obj_sources(&sources, generator_opts, lib.as_ref(), &source_data, rules, build_lines, &mut inputs)?;
+	add_pre_build(lib.as_ref(), &lib.pre_build, first_object, generator_opts, rules, build_lines)?;
+	add_depends(lib.as_ref(), &lib.depends, first_object, generator_opts, build_lines);
+	let post_build = add_post_build(lib.as_ref(), &lib.post_build, inputs.clone(), generator_opts, rules, build_lines)?;
+	// Object libraries have no phony target of their own, except to build the post-build step with the objects
+	if let Some(post_build) = post_build {
+		build_lines.push(NinjaBuild {
+			inputs: inputs.iter().cloned().chain([post_build]).collect(),
+			implicit: Vec::new(),
+			order_only: Vec::new(),
+			output_targets: vec![generator_opts.library_phony(&lib.name)],
+			rule_name: "phony".to_owned(),
+			keyval_set: BTreeMap::new(),
+		});
+	}
 
 	for link in &lib.public_links_recursive() {
 		match link {
 			LinkPtr::Static(_) => {
 				let link_path = output_path(
 					build_dir,
-					&link.project().info.name,
+					&generator_opts.package_dir(&link.project().info),
 					link.output_name(),
 					&target_platform.static_lib_ext,
 				);
@@ -511,7 +1369,6 @@ fn add_object_lib_target(
 	}
 	link_targets.insert(LinkPtr::Object(lib.clone()), inputs.clone());
 	Ok(inputs)
-	// Omit phony rules for object libraries
 }
 
 fn add_executable_target(
@@ -519,26 +1376,21 @@ fn add_executable_target(
 	generator_opts: &GeneratorOpts,
 	rules: &mut NinjaRules,
 	build_lines: &mut Vec<NinjaBuild>,
-	link_targets: &mut HashMap<LinkPtr, Vec<String>>,
+	link_targets: &mut LinkTargets,
 ) -> Result<(), String> {
 	let GeneratorOpts {
-		toolchain,
-		build_dir,
-		profile,
-		global_opts,
-		target_platform,
-		star_context,
-		..
+		toolchain, build_dir, profile, target_platform, star_context, ..
 	} = generator_opts;
 
 	log::debug!("   exe target: {}", exe.name);
 	let mut inputs = Vec::<String>::new();
 
 	let generator_vars = if let Some(gen_func) = &exe.generator_vars {
-		eval_vars(gen_func, star_context.clone(), "generator_vars")?
+		eval_vars(gen_func, star_context.for_package(&exe.project().info), "generator_vars")?
 	} else {
 		StarGeneratorVars::default()
 	};
+	let interface_vars = eval_interface_vars(&exe.links, || Ok(star_context.clone()))?;
 	let mut includes = exe.public_includes_recursive();
 	includes.extend(
 		generator_vars
@@ -546,14 +1398,19 @@ fn add_executable_target(
 			.iter()
 			.map(|x| join_parent(&exe.project().info.path, x).full),
 	);
-	let sources = exe
-		.sources
-		.extended_with(Sources::from_slice(&generator_vars.sources, &exe.project().info.path)?);
+	includes.extend(interface_vars.include_dirs.iter().map(PathBuf::from));
+	let sources = exe.sources.extended_with(Sources::from_slice(
+		&generator_vars.sources,
+		&exe.project().info.path,
+		&generator_opts.toolchain.source_extensions,
+	)?);
 	let mut defines = exe.public_defines_recursive();
 	defines.extend_from_slice(&generator_vars.defines);
+	defines.extend_from_slice(&interface_vars.defines);
 
-	let source_data = SourceData { includes, defines };
+	let source_data = generator_opts.source_data(includes, defines, generator::interface_compile_flags(&exe.links));
 
+	let first_object = build_lines.len();
 	if !sources.c.is_empty() {
 		let c_compiler = get_c_compiler(toolchain, exe.name())?;
 		let rule_compile_c = if let Some(rule) = &rules.compile_c_object {
@@ -562,22 +1419,20 @@ fn add_executable_target(
 			rules.compile_c_object = Some(compile_c_object(c_compiler));
 			rules.compile_c_object.as_ref().unwrap()
 		};
-		let mut c_compile_opts = profile.c_compile_flags.clone();
-		if let Some(c_std) = &global_opts.c_standard {
-			c_compile_opts.push(c_compiler.c_std_flag(c_std)?);
-		}
-		if let Some(true) = global_opts.position_independent_code {
-			if let Some(fpic_flag) = c_compiler.position_independent_executable_flag() {
-				c_compile_opts.push(fpic_flag);
-			}
-		}
+		let c_compile_opts = generator_opts.flags().compile_flags(
+			c_compiler,
+			Language::C,
+			&exe.name,
+			&source_data.compile_flags,
+			true,
+		)?;
 		for src in &sources.c {
 			build_lines.push(add_obj_source(
-				input_path(&src.full, &exe.project().info.path),
+				generator_opts.input_path(&src.full, &exe.project().info.path),
 				&source_data,
 				output_subfolder_path(
 					build_dir,
-					&exe.project().info.name,
+					&generator_opts.package_dir(&exe.project().info),
 					&exe.name,
 					&src.name,
 					&target_platform.obj_ext,
@@ -596,22 +1451,20 @@ fn add_executable_target(
 			rules.compile_cpp_object = Some(compile_cpp_object(cpp_compiler));
 			rules.compile_cpp_object.as_ref().unwrap()
 		};
-		let mut cpp_compile_opts = profile.cpp_compile_flags.clone();
-		if let Some(cpp_std) = &global_opts.cpp_standard {
-			cpp_compile_opts.push(cpp_compiler.cpp_std_flag(cpp_std)?);
-		}
-		if let Some(true) = global_opts.position_independent_code {
-			if let Some(fpic_flag) = cpp_compiler.position_independent_executable_flag() {
-				cpp_compile_opts.push(fpic_flag);
-			}
-		}
+		let cpp_compile_opts = generator_opts.flags().compile_flags(
+			cpp_compiler,
+			Language::Cpp,
+			&exe.name,
+			&source_data.compile_flags,
+			true,
+		)?;
 		for src in &sources.cpp {
 			build_lines.push(add_obj_source(
-				input_path(&src.full, &exe.project().info.path),
+				generator_opts.input_path(&src.full, &exe.project().info.path),
 				&source_data,
 				output_subfolder_path(
 					build_dir,
-					&exe.project().info.name,
+					&generator_opts.package_dir(&exe.project().info),
 					&exe.name,
 					&src.name,
 					&target_platform.obj_ext,
@@ -633,11 +1486,11 @@ fn add_executable_target(
 		let nasm_assemble_opts = &profile.nasm_assemble_flags;
 		for src in &sources.nasm {
 			build_lines.push(add_obj_source(
-				input_path(&src.full, &exe.project().info.path),
+				generator_opts.input_path(&src.full, &exe.project().info.path),
 				&source_data,
 				output_subfolder_path(
 					build_dir,
-					&exe.project().info.name,
+					&generator_opts.package_dir(&exe.project().info),
 					&exe.name,
 					&src.name,
 					&target_platform.obj_ext,
@@ -648,6 +1501,8 @@ fn add_executable_target(
 			));
 		}
 	}
+	add_pre_build(exe.as_ref(), &exe.pre_build, first_object, generator_opts, rules, build_lines)?;
+	add_depends(exe.as_ref(), &exe.depends, first_object, generator_opts, build_lines);
 	for link in &exe.links {
 		let link_outputs = match link_targets.get(link) {
 			Some(x) => x,
@@ -683,38 +1538,610 @@ fn add_executable_target(
 			rule_name
 		}
 	};
-	let mut link_exe_flags = Vec::new();
-	if let Some(true) = global_opts.position_independent_code {
-		if let Some(pie_flag) = toolchain
-			.exe_linker
+	let coverage_dir = paths::to_build_string(&generator_opts.coverage_dir());
+	let mut link_exe_flags = generator_opts.flags().exe_link_flags(&exe.name, &coverage_dir)?;
+	// Only Windows executables have a subsystem, so recipes can set it for every platform
+	if (exe.win32_subsystem.is_some() || exe.entry_point.is_some()) && toolchain.platform() == Some("windows") {
+		let exe_linker = toolchain.exe_linker.as_ref().unwrap();
+		match exe_linker.win32_subsystem_flags(exe.win32_subsystem.as_deref(), exe.entry_point.as_deref()) {
+			Some(flags) => link_exe_flags.extend(flags),
+			None => {
+				return Err(format!("The linker of \"{}\" can't set its Windows subsystem or entry point", exe.name))
+			}
+		}
+	}
+	if (exe.win32_manifest.is_some() || exe.win32_execution_level.is_some()) && toolchain.platform() == Some("windows")
+	{
+		let exe_linker = toolchain.exe_linker.as_ref().unwrap();
+		let manifest = exe
+			.win32_manifest
 			.as_ref()
-			.unwrap()
-			.position_independent_executable_flag()
-		{
-			link_exe_flags.push(pie_flag);
+			.map(|x| paths::to_build_string(&generator_opts.source_path(&x.full)));
+		match exe_linker.win32_manifest_flags(manifest.as_deref(), exe.win32_execution_level.as_deref()) {
+			Some(flags) => link_exe_flags.extend(flags),
+			None => return Err(format!("The linker of \"{}\" can't embed an application manifest", exe.name)),
+		}
+	}
+	// Frameworks only exist on Apple platforms, so recipes can list them for every platform
+	if matches!(toolchain.platform(), Some("macos") | Some("ios")) {
+		let exe_linker = toolchain.exe_linker.as_ref().unwrap();
+		for framework in exe.frameworks_recursive() {
+			match exe_linker.framework_flags(&framework) {
+				Some(flags) => link_exe_flags.extend(flags),
+				None => {
+					return Err(format!("The linker of \"{}\" can't link the framework \"{}\"", exe.name, framework))
+				}
+			}
+		}
+	}
+	// The build rpath comes first so that running from the build directory finds freshly built libraries.
+	// `$ORIGIN` is escaped for Ninja and quoted for the shell.
+	for dir in exe.build_rpath.iter().chain(&exe.install_rpath) {
+		for flag in toolchain.exe_linker.as_ref().unwrap().rpath_flags(dir) {
+			link_exe_flags.push(match flag.contains('$') {
+				true => format!("'{}'", flag.replace('$', "$$")),
+				false => flag,
+			});
+		}
+	}
+	if let Some(bare_metal) = &toolchain.bare_metal {
+		link_exe_flags.extend(bare_metal.link_flags());
+	}
+	// The scripts are implicit inputs of the link, so that editing them relinks the executable
+	let mut link_scripts = Vec::new();
+	if let Some(script) = &exe.linker_script {
+		let script = paths::to_build_string(&generator_opts.source_path(&script.full));
+		match toolchain.exe_linker.as_ref().unwrap().linker_script_flags(&script) {
+			Some(flags) => link_exe_flags.extend(flags),
+			None => return Err(format!("The linker of \"{}\" doesn't support linker scripts", exe.name)),
+		}
+		link_scripts.push(script);
+	}
+	if let Some(script) = &exe.version_script {
+		let script = paths::to_build_string(&generator_opts.source_path(&script.full));
+		match toolchain.exe_linker.as_ref().unwrap().version_script_flags(&script) {
+			Some(flags) => link_exe_flags.extend(flags),
+			None => return Err(format!("The linker of \"{}\" doesn't support version scripts", exe.name)),
+		}
+		link_scripts.push(script);
+	}
+	if exe.map_file || profile.map_files {
+		let map_path = output_path(build_dir, &generator_opts.package_dir(&exe.project().info), &exe.name, ".map");
+		match toolchain.exe_linker.as_ref().unwrap().map_file_flags(&map_path) {
+			Some(flags) => link_exe_flags.extend(flags),
+			None => return Err(format!("The linker of \"{}\" can't write a map file", exe.name)),
 		}
 	}
 	let mut link_flags = link_exe_flags.clone();
+	link_flags.extend(profile.link_flags(toolchain.platform()));
 	link_flags.extend(exe.link_flags_recursive());
-	let out_name = output_path(build_dir, &exe.project().info.name, exe.name.as_ref(), &target_platform.exe_ext);
+	link_flags.extend(interface_vars.link_flags);
+	let link_flags = generator::normalize_link_flags(link_flags);
+	let out_name = output_path(
+		build_dir,
+		&generator_opts.package_dir(&exe.project().info),
+		exe.name.as_ref(),
+		&target_platform.exe_ext,
+	);
 	build_lines.push(NinjaBuild {
 		inputs,
+		implicit: link_scripts,
+		order_only: Vec::new(),
 		output_targets: vec![out_name.clone()],
 		rule_name,
-		keyval_set: HashMap::from([
+		keyval_set: BTreeMap::from([
 			("TARGET_FILE".to_string(), vec![out_name.clone()]),
 			("LINK_FLAGS".to_string(), link_flags),
+			("LINK_LIBRARIES".to_string(), generator_opts.flags().link_libraries()),
 		]),
 	});
+	// Executables that run during the build aren't shipped, so only the target graph signs them
+	let signed = match (&toolchain.sign_command, generator_opts.is_host) {
+		(Some(sign_command), false) => {
+			let rule = rules.sign_exe.get_or_insert_with(|| sign_exe(sign_command));
+			let stamp = output_subfolder_path(
+				&generator_opts.build_dir,
+				&generator_opts.package_dir(&exe.project().info),
+				&exe.name,
+				"sign",
+				".stamp",
+			);
+			build_lines.push(NinjaBuild {
+				inputs: vec![out_name.clone()],
+				implicit: Vec::new(),
+				order_only: Vec::new(),
+				output_targets: vec![stamp.clone()],
+				rule_name: rule.name.clone(),
+				keyval_set: BTreeMap::new(),
+			});
+			Some(stamp)
+		}
+		_ => None,
+	};
+	let binaries = add_binary_formats(exe, &out_name, signed.as_deref(), generator_opts, rules, build_lines)?;
+	// Post-build steps see the signed executable, and the images they may flash
+	let post_build_inputs = [signed.clone().unwrap_or_else(|| out_name.clone())]
+		.into_iter()
+		.chain(binaries.iter().cloned())
+		.collect();
+	let post_build =
+		add_post_build(exe.as_ref(), &exe.post_build, post_build_inputs, generator_opts, rules, build_lines)?;
+	let runtime_files = add_runtime_files(exe, generator_opts, rules, build_lines)?;
 	build_lines.push(NinjaBuild {
-		inputs: vec![out_name],
+		inputs: [out_name]
+			.into_iter()
+			.chain(signed)
+			.chain(binaries)
+			.chain(post_build)
+			.chain(runtime_files)
+			.collect(),
+		implicit: Vec::new(),
+		order_only: Vec::new(),
 		output_targets: vec![exe.name.clone()],
 		rule_name: "phony".to_owned(),
-		keyval_set: HashMap::new(),
+		keyval_set: BTreeMap::new(),
+	});
+	Ok(())
+}
+
+/// Adds an objcopy edge for each of the executable's `binary_formats`. Returns the files they write, `<name>.<format>`
+/// next to the executable. They wait for `sign_stamp`, if the executable is signed, because signing modifies it.
+fn add_binary_formats(
+	exe: &Executable,
+	exe_path: &str,
+	sign_stamp: Option<&str>,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> Result<Vec<String>, String> {
+	if exe.binary_formats.is_empty() {
+		return Ok(Vec::new());
+	}
+	if rules.objcopy.is_none() {
+		let toolchain = &generator_opts.toolchain;
+		let tool = match toolchain.bare_metal.as_ref().and_then(|x| x.objcopy.clone()) {
+			Some(x) => x,
+			None => match toolchain
+				.cpp_compiler
+				.as_ref()
+				.or(toolchain.c_compiler.as_ref())
+				.and_then(|x| binutils_tool(x.as_ref(), "objcopy"))
+			{
+				Some(x) => vec![x],
+				None => {
+					return Err(format!(
+						"No objcopy to create the binary formats of \"{}\". Set bare_metal.objcopy in the toolchain",
+						exe.name
+					))
+				}
+			},
+		};
+		rules.objcopy = Some(objcopy(tool));
+	}
+	let mut ret = Vec::new();
+	for format in &exe.binary_formats {
+		let out_name = output_path(
+			&generator_opts.build_dir,
+			&generator_opts.package_dir(&exe.project().info),
+			&exe.name,
+			&format!(".{}", format),
+		);
+		let objcopy_format = match format.as_str() {
+			"bin" => "binary",
+			"hex" => "ihex",
+			_ => "srec",
+		};
+		build_lines.push(NinjaBuild {
+			inputs: vec![exe_path.to_owned()],
+			implicit: sign_stamp.iter().map(|x| x.to_string()).collect(),
+			order_only: Vec::new(),
+			output_targets: vec![out_name.clone()],
+			rule_name: "objcopy".to_owned(),
+			keyval_set: BTreeMap::from([("FORMAT".to_owned(), vec![objcopy_format.to_owned()])]),
+		});
+		ret.push(out_name);
+	}
+	Ok(ret)
+}
+
+/// Writes the docs target's Doxyfile and adds an edge running doxygen with it.
+/// Doxygen runs in the build directory, which is where the documentation is written to.
+fn add_docs_target(
+	docs: &Docs,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> Result<(), String> {
+	let project_info = &docs.project().info;
+	let build_dir = &generator_opts.build_dir;
+	let package_dir = generator_opts.package_dir(project_info);
+	let target_dir = generator_opts
+		.real_build_dir()
+		.join(&package_dir)
+		.join(docs.name.clone() + ".dir");
+	if let Err(e) = std::fs::create_dir_all(&target_dir) {
+		return Err(format!("Error creating directory {}: {}", target_dir.display(), e));
+	}
+	let content = docs.doxyfile_content(&package_dir.join(&docs.name))?;
+	let doxyfile_path = target_dir.join("Doxyfile");
+	if let Err(e) = std::fs::write(&doxyfile_path, content) {
+		return Err(format!("Error writing {}: {}", doxyfile_path.display(), e));
+	}
+	let doxyfile =
+		output_subfolder_path(build_dir, &generator_opts.package_dir(project_info), &docs.name, "Doxyfile", "");
+	let mut inputs = vec![doxyfile.clone()];
+	inputs.extend(
+		docs.doxyfile
+			.iter()
+			.chain(&docs.sources)
+			.map(|x| generator_opts.input_path(&x.full, &project_info.path)),
+	);
+	let out_name =
+		output_subfolder_path(build_dir, &generator_opts.package_dir(project_info), &docs.name, "docs", ".stamp");
+	let rule = rules.build_docs.get_or_insert_with(build_docs);
+	build_lines.push(NinjaBuild {
+		inputs,
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![out_name.clone()],
+		rule_name: rule.name.clone(),
+		keyval_set: BTreeMap::from([("DOXYFILE".to_owned(), vec![doxyfile])]),
+	});
+	build_lines.push(NinjaBuild {
+		inputs: vec![out_name],
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![docs.name.clone()],
+		rule_name: "phony".to_owned(),
+		keyval_set: BTreeMap::new(),
+	});
+	Ok(())
+}
+
+/// Adds a `coverage` target that builds all executables and reports the coverage data they have written when run.
+/// gcc's data is reported with gcovr, clang's is merged with llvm-profdata and reported with llvm-cov.
+fn add_coverage_target(
+	project: &Project,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> Result<(), String> {
+	let toolchain = &generator_opts.toolchain;
+	let compiler = match toolchain.cpp_compiler.as_ref().or(toolchain.c_compiler.as_ref()) {
+		Some(x) => x,
+		None => return Ok(()),
+	};
+	let executables = build_lines
+		.iter()
+		.filter(|x| x.rule_name == "link_exe")
+		.flat_map(|x| x.output_targets.first().cloned())
+		.collect::<Vec<_>>();
+	let coverage_dir = generator_opts.coverage_dir();
+	if let Err(e) = std::fs::create_dir_all(&coverage_dir) {
+		return Err(format!("Error creating directory {}: {}", coverage_dir.display(), e));
+	}
+	let build_dir = paths::to_build_string(&generator_opts.build_dir);
+	let command = match compiler.id().as_str() {
+		"gcc" => {
+			let source_dir = paths::canonicalize(&project.info.path).unwrap_or_else(|_| project.info.path.clone());
+			format!(
+				"gcovr --root {} --print-summary --html-details {}/coverage/index.html {}",
+				generator_opts.input_path(&source_dir, &project.info.path),
+				build_dir,
+				build_dir
+			)
+		}
+		"clang" | "apple-clang" => {
+			let coverage_dir = paths::to_build_string(&coverage_dir);
+			let profdata = format!("{}/coverage.profdata", coverage_dir);
+			let objects = executables
+				.iter()
+				.enumerate()
+				.map(|(i, x)| if i == 0 { x.clone() } else { format!("-object {}", x) })
+				.collect::<Vec<_>>();
+			format!(
+				"llvm-profdata merge -sparse {}/*.profraw -o {} && llvm-cov report -instr-profile={} {}",
+				coverage_dir,
+				profdata,
+				profdata,
+				objects.join(" ")
+			)
+		}
+		id => return Err(format!("Coverage reports are not supported for compiler \"{}\"", id)),
+	};
+	let rule = rules.coverage_report.get_or_insert_with(coverage_report);
+	let out_name = paths::to_build_string(&generator_opts.build_dir.join("coverage").join("report"));
+	build_lines.push(NinjaBuild {
+		inputs: executables,
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![out_name.clone()],
+		rule_name: rule.name.clone(),
+		keyval_set: BTreeMap::from([("COMMAND".to_owned(), vec![command])]),
+	});
+	build_lines.push(NinjaBuild {
+		inputs: vec![out_name],
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec!["coverage".to_owned()],
+		rule_name: "phony".to_owned(),
+		keyval_set: BTreeMap::new(),
 	});
 	Ok(())
 }
 
+/// The binutils tool `name` that reads the binaries of `compiler`: the one with the compiler's prefix for gcc, e.g.
+/// `arm-none-eabi-size` for `arm-none-eabi-gcc`, or LLVM's for clang, e.g. `llvm-size`
+fn binutils_tool(compiler: &dyn Compiler, name: &str) -> Option<String> {
+	match compiler.id().as_str() {
+		"gcc" => {
+			let cmd = compiler.cmd().into_iter().next()?;
+			let prefix = cmd.strip_suffix("gcc").or_else(|| cmd.strip_suffix("g++"))?;
+			Some(format!("{}{}", prefix, name))
+		}
+		"clang" => Some(format!("llvm-{}", name)),
+		"apple-clang" => Some(name.to_owned()),
+		_ => None,
+	}
+}
+
+/// Adds a `size-report` target that prints the section sizes of every executable and static library.
+/// Like coverage, its output is never written, so it reports again every time it's built.
+fn add_size_report_target(generator_opts: &GeneratorOpts, rules: &mut NinjaRules, build_lines: &mut Vec<NinjaBuild>) {
+	let toolchain = &generator_opts.toolchain;
+	let Some(size_tool) = toolchain
+		.cpp_compiler
+		.as_ref()
+		.or(toolchain.c_compiler.as_ref())
+		.and_then(|x| binutils_tool(x.as_ref(), "size"))
+	else {
+		return;
+	};
+	let binaries = build_lines
+		.iter()
+		.filter(|x| x.rule_name == "link_exe" || x.rule_name == "link_static_lib")
+		.flat_map(|x| x.output_targets.first().cloned())
+		.collect::<Vec<_>>();
+	if binaries.is_empty() {
+		return;
+	}
+	let rule = rules.size_report.get_or_insert_with(|| size_report(size_tool));
+	let out_name = paths::to_build_string(&generator_opts.build_dir.join("size").join("report"));
+	build_lines.push(NinjaBuild {
+		inputs: binaries,
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![out_name.clone()],
+		rule_name: rule.name.clone(),
+		keyval_set: BTreeMap::new(),
+	});
+	build_lines.push(NinjaBuild {
+		inputs: vec![out_name],
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec!["size-report".to_owned()],
+		rule_name: "phony".to_owned(),
+		keyval_set: BTreeMap::new(),
+	});
+}
+
+/// A `default` statement for everything except documentation and lint targets,
+/// so that `ninja` alone doesn't require doxygen or the clang tools
+fn default_targets(build_lines: &[NinjaBuild]) -> String {
+	let mut optional_outputs = HashSet::new();
+	for line in build_lines {
+		if [
+			"build_docs",
+			"lint",
+			"compdb",
+			"coverage_report",
+			"size_report",
+			"regenerate",
+		]
+		.contains(&line.rule_name.as_str())
+			|| (line.rule_name == "phony"
+				&& !line.inputs.is_empty()
+				&& line.inputs.iter().all(|x| optional_outputs.contains(x)))
+		{
+			optional_outputs.extend(line.output_targets.iter());
+		}
+	}
+	let defaults = build_lines
+		.iter()
+		.flat_map(|x| &x.output_targets)
+		.filter(|x| !optional_outputs.contains(x))
+		.map(|x| x.replace(':', "$:"))
+		.collect::<Vec<_>>();
+	if defaults.is_empty() {
+		return String::new();
+	}
+	format!("default {}\n", defaults.join(" "))
+}
+
+/// The C and C++ sources of all of a package's targets
+fn package_sources(project: &Project) -> Vec<PathBuf> {
+	let static_sources = project.static_libraries.iter().map(|x| &x.sources);
+	let object_sources = project.object_libraries.iter().map(|x| &x.sources);
+	let exe_sources = project.executables.iter().map(|x| &x.sources);
+	let sources = static_sources
+		.chain(object_sources)
+		.chain(exe_sources)
+		.flat_map(|x| x.c.iter().chain(&x.cpp))
+		.map(|x| x.full.clone())
+		.collect();
+	deduplicate(sources)
+}
+
+/// Adds the `format`, `format-check` and `tidy` targets.
+/// They cover the root package, and dependencies that call `lint_options()`.
+fn add_lint_targets(
+	project: &Arc<Project>,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) {
+	let mut projects = vec![(project.clone(), project.info.lint.clone().unwrap_or_default())];
+	let mut stack = project.dependencies.clone();
+	let mut seen = HashSet::new();
+	while let Some(dep) = stack.pop() {
+		if !seen.insert(dep.info.name.clone()) {
+			continue;
+		}
+		if let Some(lint) = &dep.info.lint {
+			projects.push((dep.clone(), lint.clone()));
+		}
+		stack.extend(dep.dependencies.iter().cloned());
+	}
+
+	let build_dir = &generator_opts.build_dir;
+	let mut format_outputs = Vec::new();
+	let mut format_check_outputs = Vec::new();
+	let mut tidy_outputs = Vec::new();
+	for (project, lint) in projects {
+		let info = &project.info;
+		let sources = |list: &Option<Vec<PathBuf>>| -> Vec<String> {
+			let paths = list.clone().unwrap_or_else(|| package_sources(&project));
+			paths
+				.iter()
+				.map(|x| generator_opts.input_path(x, &info.path).replace('$', "$$"))
+				.collect()
+		};
+		let format_sources = sources(&lint.format_sources);
+		if lint.format && !format_sources.is_empty() {
+			let mut format_cmd = vec!["clang-format".to_owned()];
+			if let Some(config) = &lint.clang_format_config {
+				format_cmd.push(format!("--style=file:{}", generator_opts.input_path(config, &info.path)));
+			}
+			for (step, flags, outputs) in [
+				("format", vec!["-i"], &mut format_outputs),
+				("format-check", vec!["--dry-run", "--Werror"], &mut format_check_outputs),
+			] {
+				let mut command = format_cmd.clone();
+				command.extend(flags.into_iter().map(str::to_owned));
+				command.extend(format_sources.iter().cloned());
+				outputs.push(add_lint_edge(info, step, command, generator_opts, rules, build_lines));
+			}
+		}
+		let tidy_sources = sources(&lint.tidy_sources);
+		if lint.tidy && !tidy_sources.is_empty() {
+			let compdb_path = paths::to_build_string(&build_dir.join("compile_commands.json"));
+			if !build_lines.iter().any(|x| x.output_targets.contains(&compdb_path)) {
+				let rule = rules.compdb.get_or_insert_with(compdb);
+				build_lines.push(NinjaBuild {
+					inputs: vec![paths::to_build_string(&build_dir.join("build.ninja"))],
+					implicit: Vec::new(),
+					order_only: Vec::new(),
+					output_targets: vec![compdb_path.clone()],
+					rule_name: rule.name.clone(),
+					keyval_set: BTreeMap::new(),
+				});
+			}
+			let mut command = vec![
+				"clang-tidy".to_owned(),
+				"-p".to_owned(),
+				paths::to_build_string(build_dir),
+			];
+			if let Some(config) = &lint.clang_tidy_config {
+				command.push(format!("--config-file={}", generator_opts.input_path(config, &info.path)));
+			}
+			command.extend(tidy_sources);
+			let out_name = add_lint_edge(info, "tidy", command, generator_opts, rules, build_lines);
+			build_lines.last_mut().unwrap().inputs.push(compdb_path);
+			tidy_outputs.push(out_name);
+		}
+	}
+	for (name, outputs) in [
+		("format", format_outputs),
+		("format-check", format_check_outputs),
+		("tidy", tidy_outputs),
+	] {
+		if !outputs.is_empty() {
+			build_lines.push(NinjaBuild {
+				inputs: outputs,
+				implicit: Vec::new(),
+				order_only: Vec::new(),
+				output_targets: vec![name.to_owned()],
+				rule_name: "phony".to_owned(),
+				keyval_set: BTreeMap::new(),
+			});
+		}
+	}
+}
+
+/// Adds an edge running a lint command over a package's sources. Its output is never created so it runs every time.
+fn add_lint_edge(
+	package: &ProjectInfo,
+	step: &str,
+	command: Vec<String>,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> String {
+	let out_name =
+		output_subfolder_path(&generator_opts.build_dir, &generator_opts.package_dir(package), "lint", step, "");
+	let rule = rules.lint.get_or_insert_with(lint);
+	build_lines.push(NinjaBuild {
+		inputs: Vec::new(),
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec![out_name.clone()],
+		rule_name: rule.name.clone(),
+		keyval_set: BTreeMap::from([
+			("COMMAND".to_owned(), command.iter().map(|x| quote_arg(x)).collect()),
+			("DESC".to_owned(), vec![format!("Running {} on {}", step, package.name)]),
+		]),
+	});
+	out_name
+}
+
+/// Adds edges copying the executable's runtime files into its output directory and returns the copies
+fn add_runtime_files(
+	exe: &Executable,
+	generator_opts: &GeneratorOpts,
+	rules: &mut NinjaRules,
+	build_lines: &mut Vec<NinjaBuild>,
+) -> Result<Vec<String>, String> {
+	let project_info = &exe.project().info;
+	let mut outputs = Vec::new();
+	for file in &exe.runtime_files {
+		let file_name = match file.full.file_name() {
+			Some(x) => x.to_string_lossy(),
+			None => return Err(format!("Runtime file \"{}\" of \"{}\" is not a file", file.name, exe.name)),
+		};
+		let out_name =
+			output_path(&generator_opts.build_dir, &generator_opts.package_dir(project_info), &file_name, "");
+		if outputs.contains(&out_name) {
+			return Err(format!(
+				"More than one runtime file of \"{}\" is named \"{}\". Runtime files are copied next to the executable so their names must be unique.",
+				exe.name, file_name
+			));
+		}
+		let input = generator_opts.input_path(&file.full, &project_info.path);
+		// Executables of a package share an output directory, so they may share runtime files
+		if let Some(existing) = build_lines.iter().find(|x| x.output_targets.contains(&out_name)) {
+			if existing.inputs != [input] {
+				return Err(format!(
+					"Runtime file \"{}\" of \"{}\" conflicts with another file copied to {}",
+					file.name, exe.name, out_name
+				));
+			}
+			outputs.push(out_name);
+			continue;
+		}
+		let rule = rules.copy_file.get_or_insert_with(copy_file);
+		build_lines.push(NinjaBuild {
+			inputs: vec![input],
+			implicit: Vec::new(),
+			order_only: Vec::new(),
+			output_targets: vec![out_name.clone()],
+			rule_name: rule.name.clone(),
+			keyval_set: BTreeMap::new(),
+		});
+		outputs.push(out_name);
+	}
+	Ok(outputs)
+}
+
 fn add_obj_sources(
 	sources: &Sources,
 	generator_opts: &GeneratorOpts,
@@ -724,9 +2151,7 @@ fn add_obj_sources(
 	build_lines: &mut Vec<NinjaBuild>,
 	inputs: &mut Vec<String>,
 ) -> Result<(), String> {
-	let GeneratorOpts {
-		toolchain, build_dir, profile, global_opts, target_platform, ..
-	} = generator_opts;
+	let GeneratorOpts { toolchain, build_dir, profile, target_platform, .. } = generator_opts;
 
 	if !sources.c.is_empty() {
 		let c_compiler = get_c_compiler(toolchain, target.name())?;
@@ -736,22 +2161,20 @@ fn add_obj_sources(
 			rules.compile_c_object = Some(compile_c_object(c_compiler));
 			rules.compile_c_object.as_ref().unwrap()
 		};
-		let mut c_compile_opts = profile.c_compile_flags.clone();
-		if let Some(c_std) = &global_opts.c_standard {
-			c_compile_opts.push(c_compiler.c_std_flag(c_std)?);
-		}
-		if let Some(true) = global_opts.position_independent_code {
-			if let Some(fpic_flag) = c_compiler.position_independent_code_flag() {
-				c_compile_opts.push(fpic_flag);
-			}
-		}
+		let c_compile_opts = generator_opts.flags().compile_flags(
+			c_compiler,
+			Language::C,
+			target.name(),
+			&source_data.compile_flags,
+			false,
+		)?;
 		for src in &sources.c {
 			build_lines.push(add_obj_source(
-				input_path(&src.full, &target.project().info.path),
+				generator_opts.input_path(&src.full, &target.project().info.path),
 				source_data,
 				output_subfolder_path(
 					build_dir,
-					&target.project().info.name,
+					&generator_opts.package_dir(&target.project().info),
 					target.name(),
 					&src.name,
 					&target_platform.obj_ext,
@@ -770,22 +2193,20 @@ fn add_obj_sources(
 			rules.compile_cpp_object = Some(compile_cpp_object(cpp_compiler));
 			rules.compile_cpp_object.as_ref().unwrap()
 		};
-		let mut cpp_compile_opts = profile.cpp_compile_flags.clone();
-		if let Some(cpp_std) = &global_opts.cpp_standard {
-			cpp_compile_opts.push(cpp_compiler.cpp_std_flag(cpp_std)?);
-		}
-		if let Some(true) = global_opts.position_independent_code {
-			if let Some(fpic_flag) = cpp_compiler.position_independent_code_flag() {
-				cpp_compile_opts.push(fpic_flag);
-			}
-		}
+		let cpp_compile_opts = generator_opts.flags().compile_flags(
+			cpp_compiler,
+			Language::Cpp,
+			target.name(),
+			&source_data.compile_flags,
+			false,
+		)?;
 		for src in &sources.cpp {
 			build_lines.push(add_obj_source(
-				input_path(&src.full, &target.project().info.path),
+				generator_opts.input_path(&src.full, &target.project().info.path),
 				source_data,
 				output_subfolder_path(
 					build_dir,
-					&target.project().info.name,
+					&generator_opts.package_dir(&target.project().info),
 					target.name(),
 					&src.name,
 					&target_platform.obj_ext,
@@ -807,11 +2228,11 @@ fn add_obj_sources(
 		let nasm_assemble_opts = &profile.nasm_assemble_flags;
 		for src in &sources.nasm {
 			build_lines.push(add_obj_source(
-				input_path(&src.full, &target.project().info.path),
+				generator_opts.input_path(&src.full, &target.project().info.path),
 				source_data,
 				output_subfolder_path(
 					build_dir,
-					&target.project().info.name,
+					&generator_opts.package_dir(&target.project().info),
 					target.name(),
 					&src.name,
 					&target_platform.obj_ext,
@@ -837,9 +2258,11 @@ fn add_obj_source(
 	inputs.push(out_tgt.clone());
 	NinjaBuild {
 		inputs: vec![input],
+		implicit: Vec::new(),
+		order_only: Vec::new(),
 		output_targets: vec![out_tgt.clone()],
 		rule_name,
-		keyval_set: HashMap::from([
+		keyval_set: BTreeMap::from([
 			("DEFINES".to_string(), transform_defines(&source_data.defines)),
 			("FLAGS".to_string(), compile_options),
 			(
@@ -847,7 +2270,7 @@ fn add_obj_source(
 				source_data
 					.includes
 					.iter()
-					.map(|x| "-I".to_owned() + x.to_string_lossy().trim_start_matches(r"\\?\"))
+					.map(|x| "-I".to_owned() + &paths::to_build_string(x))
 					.collect(),
 			),
 			("DEP_FILE".to_owned(), vec![out_tgt + ".d"]),
@@ -998,15 +2421,33 @@ fn test_position_independent_code() {
 					defines_private: Vec::new(),
 					defines_public: Vec::new(),
 					link_flags_public: Vec::new(),
+					link_flags_private: Vec::new(),
+					frameworks: Vec::new(),
 					generator_vars: None,
 					output_name: None,
+					depends: Vec::new(),
+					pre_build: Vec::new(),
+					post_build: Vec::new(),
 				}));
 				add_lib.as_ref().unwrap().clone()
 			}
 		}
 	};
 	let project = Arc::new_cyclic(|weak_parent| Project {
-		info: Arc::new(crate::project::ProjectInfo { name: "test_project".to_owned(), path: PathBuf::from(".") }),
+		info: Arc::new(crate::project::ProjectInfo {
+			name: "test_project".to_owned(),
+			path: PathBuf::from("."),
+			version: None,
+			metadata: Default::default(),
+			api_version: 1,
+			exports: Vec::new(),
+			package_options: HashMap::new(),
+			source_groups: Vec::new(),
+			lint: None,
+			installs: Vec::new(),
+			recipe_files: Vec::new(),
+			registry_hash: None,
+		}),
 		dependencies: Vec::new(),
 		executables: vec![Arc::new(Executable {
 			parent_project: weak_parent.clone(),
@@ -1019,12 +2460,31 @@ fn test_position_independent_code() {
 			include_dirs: Vec::new(),
 			defines: Vec::new(),
 			link_flags: Vec::new(),
+			frameworks: Vec::new(),
+			build_rpath: Vec::new(),
+			install_rpath: Vec::new(),
+			linker_script: None,
+			version_script: None,
+			map_file: false,
+			binary_formats: Vec::new(),
 			generator_vars: None,
 			output_name: None,
+			depends: Vec::new(),
+			pre_build: Vec::new(),
+			post_build: Vec::new(),
+			runtime_files: Vec::new(),
+			benchmark: false,
+			test: false,
+			host: false,
+			win32_subsystem: None,
+			entry_point: None,
+			win32_manifest: None,
+			win32_execution_level: None,
 		})],
 		static_libraries: vec![create_lib(weak_parent)],
 		object_libraries: Vec::new(),
 		interface_libraries: Vec::new(),
+		docs: Vec::new(),
 	});
 	let toolchain = Toolchain {
 		msvc_platforms: vec!["x64".to_owned(), "Win32".to_owned(), "ARM64".to_owned()],
@@ -1033,30 +2493,62 @@ fn test_position_independent_code() {
 		nasm_assembler: Some(Box::new(TestAssembler {})),
 		static_linker: Some(vec!["llvm-ar".to_owned()]),
 		exe_linker: Some(Box::new(TestCompiler {})),
+		command_wrapper: None,
+		source_extensions: Default::default(),
+		tool_dirs: Vec::new(),
+		sign_command: None,
+		bare_metal: None,
 		profile: Default::default(),
+		host: None,
 	};
 	let profile = Default::default();
 	let global_opts = GlobalOptions {
 		c_standard: Some("17".to_owned()),
 		cpp_standard: Some("17".to_owned()),
 		position_independent_code: Some(true),
+		reproducible: None,
+		hermetic: false,
+		strict_sources: true,
+		strict_include_dirs: false,
+		profile: None,
+		benchmarks: false,
+		artifact_cache: None,
+		windows_character_set: None,
+		output_layout: Default::default(),
+		root_package: String::new(),
+		regeneration: None,
 	};
 	let target_platform = TargetPlatform {
 		obj_ext: ".o".to_owned(),
 		static_lib_ext: ".a".to_owned(),
 		exe_ext: String::new(),
 	};
+	let star_context = StarContext {
+		c_compiler: None,
+		cpp_compiler: None,
+		global_options: StarGlobalOptions::new(&global_opts),
+		package_options: StarPackageOptions(HashMap::new()),
+		profile: None,
+		source_dir: String::new(),
+		build_dir: "build".to_owned(),
+	};
 	let mut rules = NinjaRules::default();
 	let mut build_lines = Vec::new();
 	let generator_opts = GeneratorOpts {
 		build_dir: PathBuf::from("build"),
+		relative_to: None,
 		profile,
 		global_opts,
 		target_platform,
 		toolchain,
-		star_context: StarContext { c_compiler: None, cpp_compiler: None },
+		star_context,
+		root_package: "test_project".to_owned(),
+		reproducible: None,
+		host_executables: BTreeSet::new(),
+		host: None,
+		is_host: false,
 	};
-	let mut link_targets = HashMap::new();
+	let mut link_targets = LinkTargets::default();
 	let result = Ninja::generate_inner(&project, &generator_opts, &mut rules, &mut build_lines, &mut link_targets);
 
 	assert!(result.is_ok(), "{}", result.unwrap_err());
@@ -1127,3 +2619,44 @@ fn test_position_independent_code() {
 		1
 	);
 }
+
+#[test]
+fn test_default_targets() {
+	let build = |outputs: &[&str], rule_name: &str, inputs: &[&str]| NinjaBuild {
+		inputs: inputs.iter().map(|x| x.to_string()).collect(),
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: outputs.iter().map(|x| x.to_string()).collect(),
+		rule_name: rule_name.to_owned(),
+		keyval_set: BTreeMap::new(),
+	};
+	let build_lines = [
+		build(&["C:/build/pkg/e"], "link_exe", &["C:/build/pkg/e.dir/main.c.o"]),
+		build(&["e"], "phony", &["C:/build/pkg/e"]),
+		build(&["C:/build/pkg/docs.dir/docs.stamp"], "build_docs", &["C:/build/pkg/docs.dir/Doxyfile"]),
+		build(&["docs"], "phony", &["C:/build/pkg/docs.dir/docs.stamp"]),
+		build(&["C:/build/pkg/lint.dir/format"], "lint", &[]),
+		build(&["format"], "phony", &["C:/build/pkg/lint.dir/format"]),
+	];
+	assert_eq!(default_targets(&build_lines), "default C$:/build/pkg/e e\n");
+}
+
+#[test]
+fn test_build_variables_sorted() {
+	let line = NinjaBuild {
+		inputs: vec!["a.c".to_owned()],
+		implicit: Vec::new(),
+		order_only: Vec::new(),
+		output_targets: vec!["a.o".to_owned()],
+		rule_name: "compile_c_object".to_owned(),
+		keyval_set: BTreeMap::from([
+			("INCLUDES".to_owned(), vec!["-Iinc".to_owned()]),
+			("DEFINES".to_owned(), vec!["-DA".to_owned()]),
+			("FLAGS".to_owned(), vec!["-O2".to_owned()]),
+		]),
+	};
+	assert_eq!(
+		line.as_string(),
+		"build a.o: compile_c_object a.c\n  DEFINES = -DA\n  FLAGS = -O2\n  INCLUDES = -Iinc\n\n"
+	);
+}
@@ -0,0 +1,448 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+};
+
+use flate2::{write::GzEncoder, Compression};
+
+use crate::{
+	generator::{layout, Generator},
+	project::{InstallRule, PackageMetadata, Project},
+	target::{LinkTarget, Target},
+	toolchain::Toolchain,
+};
+
+const HEADER_EXTENSIONS: [&str; 7] = ["h", "hh", "hpp", "hxx", "h++", "inl", "ipp"];
+
+/// A file to install, and where to install it relative to the root of its component
+#[derive(Debug)]
+pub struct InstallEntry {
+	pub component: String,
+	pub source: InstallSource,
+	pub destination: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum InstallSource {
+	/// A file in the source or build directory, which is copied
+	File(PathBuf),
+	/// A file generated when installing, such as a pkg-config file, with these contents
+	Generated(String),
+}
+
+/// The files `install()` calls in the root package's recipe install, once the project is built
+pub fn install_entries(
+	project: &Project,
+	generator: &Generator,
+	build_dir: &Path,
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+) -> Result<Vec<InstallEntry>, String> {
+	let package = &project.info.name;
+	// The root package's outputs are in a directory of its name in every layout
+	let output_dir = layout::output_dir(generator, build_dir, Path::new(package), toolchain, profile)?;
+	let target_platform = generator.target_platform(toolchain);
+	let mut entries = Vec::new();
+	for rule in &project.info.installs {
+		let add =
+			|entries: &mut Vec<InstallEntry>, default_component: &str, default_destination: &str, source: &Path| {
+				let component = rule.component.as_deref().unwrap_or(default_component);
+				let destination = rule.destination.as_deref().unwrap_or(default_destination);
+				add_entries(entries, component, Path::new(destination), source, false)
+			};
+		for file in &rule.files {
+			add(&mut entries, "runtime", &format!("share/{}", package), file)?;
+		}
+		for name in &rule.targets {
+			if let Some(exe) = project.executables.iter().find(|x| &x.name == name) {
+				add(&mut entries, "runtime", "bin", &output_dir.join(exe.name.clone() + &target_platform.exe_ext))?;
+				for file in &exe.runtime_files {
+					add(&mut entries, "runtime", "bin", &file.full)?;
+				}
+			} else if let Some(lib) = project.static_libraries.iter().find(|x| &x.name == name) {
+				let lib_file_name = lib.output_name().to_owned() + &target_platform.static_lib_ext;
+				add(&mut entries, "dev", "lib", &output_dir.join(&lib_file_name))?;
+				add_headers(&mut entries, rule, &lib.include_dirs_public)?;
+				let lib_dir = Path::new(rule.destination.as_deref().unwrap_or("lib"));
+				let include_dir = Path::new(rule.destination.as_deref().unwrap_or("include"));
+				entries.push(InstallEntry {
+					component: rule.component.as_deref().unwrap_or("dev").to_owned(),
+					source: InstallSource::Generated(pkg_config(
+						project,
+						lib.as_ref(),
+						&lib_file_name,
+						lib_dir,
+						include_dir,
+					)),
+					destination: lib_dir.join("pkgconfig").join(lib.name.clone() + ".pc"),
+				});
+			} else if let Some(lib) = project.interface_libraries.iter().find(|x| &x.name == name) {
+				add_headers(&mut entries, rule, &lib.include_dirs)?;
+			} else if let Some(docs) = project.docs.iter().find(|x| &x.name == name) {
+				if let Generator::Msvc = generator {
+					return Err(format!("Cannot install \"{}\": docs targets are only generated for Ninja", name));
+				}
+				let html_dir = build_dir.join(package).join(&docs.name).join("html");
+				add(&mut entries, "docs", &format!("share/doc/{}", package), &html_dir)?;
+			} else if project.object_libraries.iter().any(|x| &x.name == name) {
+				return Err(format!("Cannot install object library \"{}\"", name));
+			} else {
+				return Err(format!("Cannot install \"{}\": package \"{}\" has no such target", name, package));
+			}
+		}
+	}
+	Ok(entries)
+}
+
+/// A pkg-config file for a static library installed to `lib_dir` with its headers in `include_dir`. It's installed
+/// to `<lib_dir>/pkgconfig`, and finds the library relative to itself so the install tree can be moved.
+fn pkg_config(
+	project: &Project,
+	lib: &dyn LinkTarget,
+	lib_file_name: &str,
+	lib_dir: &Path,
+	include_dir: &Path,
+) -> String {
+	let to_slash = |path: &Path| path.iter().map(|x| x.to_string_lossy()).collect::<Vec<_>>().join("/");
+	let prefix = std::iter::repeat_n("/..", lib_dir.iter().count() + 1).collect::<String>();
+	let mut cflags = vec!["-I${includedir}".to_owned()];
+	cflags.extend(lib.public_defines_recursive().iter().map(|x| format!("-D{}", x)));
+	let mut libs = vec![format!("${{libdir}}/{}", lib_file_name)];
+	libs.extend(lib.public_link_flags_recursive());
+	format!(
+		"prefix=${{pcfiledir}}{}\nlibdir=${{prefix}}/{}\nincludedir=${{prefix}}/{}\n\nName: {}\nDescription: {}\nVersion: {}\nCflags: {}\nLibs: {}\n",
+		prefix,
+		to_slash(lib_dir),
+		to_slash(include_dir),
+		lib.name(),
+		project.info.metadata.description.as_deref().unwrap_or(&project.info.name),
+		project.info.version.as_deref().unwrap_or("0.0.0"),
+		cflags.join(" "),
+		libs.join(" "),
+	)
+}
+
+/// The headers in a library's public include directories are installed to the rule's destination, or `include`
+fn add_headers(
+	entries: &mut Vec<InstallEntry>,
+	rule: &InstallRule,
+	include_dirs: &[crate::misc::SourcePath],
+) -> Result<(), String> {
+	let component = rule.component.as_deref().unwrap_or("dev");
+	let include_dir = Path::new(rule.destination.as_deref().unwrap_or("include"));
+	for dir in include_dirs {
+		let mut headers = Vec::new();
+		add_entries(&mut headers, component, include_dir, &dir.full, true)?;
+		for mut entry in headers {
+			// The contents of the include directory are installed, not the directory itself
+			entry.destination = match dir
+				.full
+				.file_name()
+				.map(|x| entry.destination.strip_prefix(include_dir.join(x)))
+			{
+				Some(Ok(x)) => include_dir.join(x),
+				_ => entry.destination,
+			};
+			entries.push(entry);
+		}
+	}
+	Ok(())
+}
+
+/// Adds `source` to `destination`. The contents of directories are added recursively under a directory of the same name.
+fn add_entries(
+	entries: &mut Vec<InstallEntry>,
+	component: &str,
+	destination: &Path,
+	source: &Path,
+	only_headers: bool,
+) -> Result<(), String> {
+	let file_name = match source.file_name() {
+		Some(x) => x,
+		None => return Err(format!("Cannot install \"{}\"", source.display())),
+	};
+	if source.is_dir() {
+		let dir_entries = match fs::read_dir(source) {
+			Ok(x) => x,
+			Err(e) => return Err(format!("Error reading directory {}: {}", source.display(), e)),
+		};
+		let mut children = dir_entries.filter_map(|x| x.ok().map(|x| x.path())).collect::<Vec<_>>();
+		children.sort();
+		for child in children {
+			add_entries(entries, component, &destination.join(file_name), &child, only_headers)?;
+		}
+		return Ok(());
+	}
+	let is_header = source
+		.extension()
+		.is_some_and(|x| HEADER_EXTENSIONS.contains(&x.to_string_lossy().as_ref()));
+	if !only_headers || is_header {
+		entries.push(InstallEntry {
+			component: component.to_owned(),
+			source: InstallSource::File(source.to_owned()),
+			destination: destination.join(file_name),
+		});
+	}
+	Ok(())
+}
+
+/// Copies each component's files into `staging_dir/<component>`, replacing what was staged before.
+/// Returns the staged directory of each component.
+pub fn stage(entries: &[InstallEntry], staging_dir: &Path) -> Result<BTreeMap<String, PathBuf>, String> {
+	let mut components = BTreeMap::new();
+	for entry in entries {
+		let component_dir = staging_dir.join(&entry.component);
+		if !components.contains_key(&entry.component) {
+			if component_dir.exists() {
+				if let Err(e) = fs::remove_dir_all(&component_dir) {
+					return Err(format!("Error removing {}: {}", component_dir.display(), e));
+				}
+			}
+			components.insert(entry.component.clone(), component_dir.clone());
+		}
+		write_entry(entry, &component_dir)?;
+	}
+	Ok(components)
+}
+
+/// Copies every entry into `prefix`, whatever its component, overwriting files that are already installed
+pub fn install(entries: &[InstallEntry], prefix: &Path) -> Result<(), String> {
+	for entry in entries {
+		write_entry(entry, prefix)?;
+	}
+	Ok(())
+}
+
+fn write_entry(entry: &InstallEntry, root: &Path) -> Result<(), String> {
+	let dest = root.join(&entry.destination);
+	if let Some(parent) = dest.parent() {
+		if let Err(e) = fs::create_dir_all(parent) {
+			return Err(format!("Error creating directory {}: {}", parent.display(), e));
+		}
+	}
+	match &entry.source {
+		InstallSource::File(source) => {
+			if let Err(e) = fs::copy(source, &dest) {
+				return Err(format!("Error copying {} to {}: {}", source.display(), dest.display(), e));
+			}
+		}
+		InstallSource::Generated(contents) => {
+			if let Err(e) = fs::write(&dest, contents) {
+				return Err(format!("Error writing {}: {}", dest.display(), e));
+			}
+		}
+	}
+	Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+	Zip,
+	TarGz,
+	Deb,
+}
+
+impl ArchiveFormat {
+	pub fn parse(format: &str) -> Result<Self, String> {
+		match format {
+			"zip" => Ok(ArchiveFormat::Zip),
+			"tar.gz" => Ok(ArchiveFormat::TarGz),
+			"deb" => Ok(ArchiveFormat::Deb),
+			_ => Err(format!("Unknown package format \"{}\". Expected zip, tar.gz or deb", format)),
+		}
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			ArchiveFormat::Zip => "zip",
+			ArchiveFormat::TarGz => "tar.gz",
+			ArchiveFormat::Deb => "deb",
+		}
+	}
+}
+
+/// What a package archive is named after
+pub struct ArchiveInfo<'a> {
+	pub package: &'a str,
+	pub version: &'a str,
+	pub component: &'a str,
+	/// The target triple the package was built for
+	pub target: &'a str,
+	/// Fills in the maintainer, description and homepage of .deb packages
+	pub metadata: &'a PackageMetadata,
+}
+
+impl ArchiveInfo<'_> {
+	pub fn file_name(&self, format: ArchiveFormat) -> Result<String, String> {
+		Ok(match format {
+			ArchiveFormat::Deb => {
+				format!("{}_{}_{}.deb", self.deb_package_name(), self.version, deb_architecture(self.target)?)
+			}
+			_ => format!("{}-{}-{}.{}", self.package, self.version, self.component, format.extension()),
+		})
+	}
+
+	/// The runtime component is named after the package, and others are suffixed with the component, e.g. `zstd-dev`
+	fn deb_package_name(&self) -> String {
+		let name = match self.component {
+			"runtime" => self.package.to_owned(),
+			component => format!("{}-{}", self.package, component),
+		};
+		name.to_lowercase().replace('_', "-")
+	}
+}
+
+/// Debian's name for the architecture of a target triple. "all" is only for packages without binaries, so a target
+/// that isn't one of Debian's architectures is an error.
+pub fn deb_architecture(target: &str) -> Result<&'static str, String> {
+	let arch = target.split('-').next().unwrap_or_default();
+	Ok(match arch {
+		"x86_64" => "amd64",
+		"aarch64" | "arm64" => "arm64",
+		"i386" | "i486" | "i586" | "i686" => "i386",
+		"riscv64" => "riscv64",
+		"powerpc64le" => "ppc64el",
+		"s390x" => "s390x",
+		x if x.starts_with("arm") => "armhf",
+		"" => return Err("Cannot package a .deb: the toolchain's target is unknown".to_owned()),
+		_ => return Err(format!("Cannot package a .deb for target \"{}\": it has no Debian architecture", target)),
+	})
+}
+
+/// Files in `dir`, recursively, relative to `dir` and sorted so archives list them in a stable order
+fn relative_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+	let mut files = Vec::new();
+	let mut stack = vec![PathBuf::new()];
+	while let Some(rel) = stack.pop() {
+		for entry in fs::read_dir(dir.join(&rel))? {
+			let entry = entry?;
+			let path = rel.join(entry.file_name());
+			if entry.file_type()?.is_dir() {
+				stack.push(path);
+			} else {
+				files.push(path);
+			}
+		}
+	}
+	files.sort();
+	Ok(files)
+}
+
+fn archive_path(prefix: &Path, rel: &Path) -> String {
+	prefix
+		.join(rel)
+		.components()
+		.map(|x| x.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+fn tar_gz(dir: &Path, prefix: &Path) -> io::Result<Vec<u8>> {
+	let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+	for rel in relative_files(dir)? {
+		builder.append_path_with_name(dir.join(&rel), archive_path(prefix, &rel))?;
+	}
+	builder.into_inner()?.finish()
+}
+
+fn zip(dir: &Path, prefix: &Path) -> io::Result<Vec<u8>> {
+	let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+	for rel in relative_files(dir)? {
+		let mut options = zip::write::FileOptions::default();
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			options = options.unix_permissions(fs::metadata(dir.join(&rel))?.permissions().mode());
+		}
+		writer.start_file(archive_path(prefix, &rel), options)?;
+		writer.write_all(&fs::read(dir.join(&rel))?)?;
+	}
+	Ok(writer.finish()?.into_inner())
+}
+
+/// A .deb is an `ar` archive of the format version, the package metadata and the files, which are installed under /usr
+fn deb(dir: &Path, info: &ArchiveInfo, architecture: &str) -> io::Result<Vec<u8>> {
+	let mut control = format!(
+		"Package: {}\nVersion: {}\nArchitecture: {}\nMaintainer: {}\nDescription: {} ({})\n",
+		info.deb_package_name(),
+		info.version,
+		architecture,
+		match info.metadata.authors.first() {
+			Some(x) => x.clone(),
+			None => format!("{} maintainers", info.package),
+		},
+		info.metadata.description.as_deref().unwrap_or(info.package),
+		info.component
+	);
+	if let Some(repository) = &info.metadata.repository {
+		control += &format!("Homepage: {}\n", repository);
+	}
+	let mut control_tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+	let mut header = tar::Header::new_gnu();
+	header.set_size(control.len() as u64);
+	header.set_mode(0o644);
+	header.set_cksum();
+	control_tar.append_data(&mut header, "./control", control.as_bytes())?;
+	let control_tar = control_tar.into_inner()?.finish()?;
+	let data_tar = tar_gz(dir, Path::new("./usr"))?;
+
+	let mut ar = b"!<arch>\n".to_vec();
+	for (name, data) in [
+		("debian-binary", b"2.0\n".as_slice()),
+		("control.tar.gz", &control_tar),
+		("data.tar.gz", &data_tar),
+	] {
+		ar.extend(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, "100644", data.len()).as_bytes());
+		ar.extend(data);
+		if data.len() % 2 == 1 {
+			ar.push(b'\n');
+		}
+	}
+	Ok(ar)
+}
+
+/// Packs a staged component into `out_dir` and returns the archive's path
+pub fn write_archive(
+	format: ArchiveFormat,
+	component_dir: &Path,
+	out_dir: &Path,
+	info: &ArchiveInfo,
+) -> Result<PathBuf, String> {
+	let out_path = out_dir.join(info.file_name(format)?);
+	// Archives unpack into a directory named like the archive
+	let prefix = PathBuf::from(format!("{}-{}-{}", info.package, info.version, info.component));
+	let data = match format {
+		ArchiveFormat::Zip => zip(component_dir, &prefix),
+		ArchiveFormat::TarGz => tar_gz(component_dir, &prefix),
+		ArchiveFormat::Deb => deb(component_dir, info, deb_architecture(info.target)?),
+	};
+	let data = match data {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error packing {}: {}", out_path.display(), e)),
+	};
+	if let Err(e) = fs::write(&out_path, data) {
+		return Err(format!("Error writing {}: {}", out_path.display(), e));
+	}
+	Ok(out_path)
+}
+
+#[test]
+fn test_deb_architecture() {
+	assert_eq!(deb_architecture("x86_64-linux-gnu"), Ok("amd64"));
+	assert_eq!(deb_architecture("aarch64-unknown-linux-gnu"), Ok("arm64"));
+	assert_eq!(deb_architecture("i686-pc-linux-gnu"), Ok("i386"));
+	assert_eq!(deb_architecture("armv7-unknown-linux-gnueabihf"), Ok("armhf"));
+	assert!(deb_architecture("wasm32-unknown-emscripten").is_err());
+	assert!(deb_architecture("").is_err());
+	let info = ArchiveInfo {
+		package: "my_lib",
+		version: "1.2.0",
+		component: "dev",
+		target: "x86_64-linux-gnu",
+		metadata: &Default::default(),
+	};
+	assert_eq!(info.file_name(ArchiveFormat::Deb).unwrap(), "my-lib-dev_1.2.0_amd64.deb");
+	assert_eq!(info.file_name(ArchiveFormat::TarGz).unwrap(), "my_lib-1.2.0-dev.tar.gz");
+}
@@ -3,7 +3,10 @@ use std::{
 	sync::{Arc, Weak},
 };
 
+use starlark::values::OwnedFrozenValue;
+
 use crate::{
+	config_header::ConfigHeader,
 	link_type::LinkPtr,
 	misc::SourcePath,
 	project::Project, //
@@ -17,7 +20,19 @@ pub struct InterfaceLibrary {
 	pub links: Vec<LinkPtr>,
 	pub include_dirs: Vec<SourcePath>,
 	pub defines: Vec<String>,
+	/// Flags added to the C and C++ compile commands of the targets that link this library
+	pub compile_flags: Vec<String>,
 	pub link_flags: Vec<String>,
+	/// Library files linked into the executables that link this library, e.g. one found with `find_library()`
+	pub link_files: Vec<PathBuf>,
+	/// Apple frameworks linked into the executables that link this library, e.g. "CoreFoundation"
+	pub frameworks: Vec<String>,
+	/// Evaluated by the generator for each target that links this library
+	pub generator_vars: Option<OwnedFrozenValue>,
+	/// Base name of the `<name>_export.h` header the generator writes for targets linking this library
+	pub export_header: Option<String>,
+	/// The `<name>.h` header of defines the generator writes for targets linking this library
+	pub config_header: Option<ConfigHeader>,
 }
 
 impl Target for InterfaceLibrary {
@@ -1,31 +1,59 @@
+pub mod api_doc;
+mod artifact_cache;
+pub mod cache;
+pub mod check;
+pub mod config;
+mod config_header;
+pub mod dep_graph;
+pub mod diagnostic;
+mod docs;
+pub mod doctor;
 mod executable;
+mod export_header;
 pub mod generator;
+pub mod install;
 mod interface_library;
 mod link_type;
+pub mod listing;
+mod lockfile;
+pub mod mirror;
 mod misc;
+mod network;
 mod object_library;
+mod paths;
+pub mod progress;
 pub mod project;
+pub mod publish;
+pub mod query;
+pub mod recipe_fmt;
+mod session;
 mod starlark_api;
 mod starlark_context;
+mod starlark_docs;
 mod starlark_executable;
 mod starlark_fmt;
 mod starlark_generator;
 mod starlark_global;
 mod starlark_interface_library;
 mod starlark_link_target;
+mod starlark_loader;
 mod starlark_object_library;
+mod starlark_probe;
 mod starlark_project;
 mod starlark_static_library;
 mod static_library;
+pub mod summary;
 pub mod target;
 pub mod toolchain;
+pub mod watch;
+
+pub use session::{Configured, Session};
 
 use std::{
-	collections::{BTreeMap, HashMap},
+	collections::{BTreeMap, BTreeSet, HashMap},
 	fs,
 	path::{Path, PathBuf},
 	sync::{Arc, Mutex},
-	time::Duration,
 };
 
 use anyhow::anyhow;
@@ -48,16 +76,42 @@ use starlark::{
 };
 use tar::Archive;
 
-use project::Project;
-use starlark_api::err_msg;
-use starlark_global::{PkgOpt, StarGlobal};
+use config::Config;
+use dep_graph::{DepGraph, DepSource};
+use diagnostic::Diagnostic;
+use listing::{OptionSummary, TargetSummary};
+use lockfile::{LockedPackage, Lockfile};
+use network::{NetworkConfig, RegistryClient};
+use progress::{Progress, ProgressReader};
+use project::{PackageMetadata, Project};
+use starlark_api::{err_msg, ConfigureError};
+use starlark_global::{PkgOpt, StarEnv, StarGlobal, StarPackage};
+use starlark_interface_library::{StarIfaceLibWrapper, StarIfaceLibrary};
+use starlark_loader::{LoadedModules, RecipeContext, RecipeLoader};
 use starlark_project::StarProject;
+use summary::{DependencySummary, Resolution};
 use toolchain::Toolchain;
 
-type PkgOptMap = HashMap<String, HashMap<String, PkgOpt>>;
+/// An override of a package option. Values from the command line are kept as text until the type of the option is known.
+#[derive(Clone, Debug)]
+enum PkgOptOverride {
+	Value(PkgOpt),
+	Text(String),
+}
+
+impl PkgOptOverride {
+	fn typed_as(&self, declared: &PkgOpt) -> Result<PkgOpt, String> {
+		match self {
+			PkgOptOverride::Value(x) => declared.check(x),
+			PkgOptOverride::Text(x) => declared.parse_as(x),
+		}
+	}
+}
+
+type PkgOptMap = HashMap<String, HashMap<String, PkgOptOverride>>;
 
-const CATAPULT_TOML: &str = "catapult.toml";
-const BUILD_CATAPULT: &str = "build.catapult";
+pub const CATAPULT_TOML: &str = "catapult.toml";
+pub const BUILD_CATAPULT: &str = "build.catapult";
 
 #[derive(Debug, Deserialize)]
 struct Manifest {
@@ -65,45 +119,112 @@ struct Manifest {
 	dependencies: Option<BTreeMap<String, DependencyManifest>>,
 	options: Option<ManifestOptions>,
 	package_options: Option<HashMap<String, PkgOpt>>,
+	/// Only read from the root manifest
+	#[serde(alias = "override")]
+	patch: Option<BTreeMap<String, PatchManifest>>,
+	env: Option<EnvManifest>,
 }
 
 #[derive(Debug, Deserialize)]
 struct PackageManifest {
 	name: String,
-	// version: Option<String>,
+	version: Option<String>,
 	source: Option<String>,
+	/// The recipe API version the package's recipes are written for. Defaults to 1, the first version.
+	api_version: Option<u32>,
+	#[serde(flatten)]
+	metadata: PackageMetadata,
 }
 
 #[derive(Debug, Deserialize)]
 struct DependencyManifest {
 	version: Option<String>,
-	registry: Option<String>,
-	channel: Option<String>,
+	registry: Option<OneOrMany<String>>,
+	channel: Option<OneOrMany<String>>,
 	// ---
 	path: Option<String>,
 	// ---
 	git: Option<String>,
 	// branch: Option<String>,
 	// tag: Option<String>,
-	// rev: Option<String>,
+	rev: Option<String>,
 	options: Option<HashMap<String, PkgOpt>>,
 }
 
+/// Redirects a dependency, at any depth, to a local path or git rev
+#[derive(Debug, Deserialize)]
+struct PatchManifest {
+	path: Option<String>,
+	git: Option<String>,
+	rev: Option<String>,
+}
+
+/// Environment variables the package's recipe may read through `GLOBAL.env`
+#[derive(Debug, Default, Deserialize)]
+struct EnvManifest {
+	#[serde(default)]
+	allow: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+	One(T),
+	Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+	fn into_vec(self) -> Vec<T> {
+		match self {
+			OneOrMany::One(x) => vec![x],
+			OneOrMany::Many(x) => x,
+		}
+	}
+}
+
 #[derive(Debug, Default, Deserialize)]
 struct ManifestOptions {
 	c_standard: Option<String>,
 	cpp_standard: Option<String>,
 	position_independent_code: Option<bool>,
+	reproducible: Option<bool>,
+	hermetic: Option<bool>,
+	strict_sources: Option<bool>,
+	strict_include_dirs: Option<bool>,
+	windows_character_set: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct GlobalOptions {
 	pub c_standard: Option<String>,
 	pub cpp_standard: Option<String>,
 	pub position_independent_code: Option<bool>,
+	/// Strip paths and timestamps from build outputs so that builds of the same tree are bit-identical
+	pub reproducible: Option<bool>,
+	/// Reject paths in recipes that lead outside their package, so the build doesn't depend on the machine's layout
+	pub hermetic: bool,
+	/// Fail configure when a target's source doesn't exist, rather than when it's compiled, unless the target has
+	/// `pre_build` steps that may generate it. On by default.
+	pub strict_sources: bool,
+	/// Fail configure when a target's include directory doesn't exist, rather than only warning
+	pub strict_include_dirs: bool,
+	/// The toolchain profile selected for this build, if any. Generators that build all profiles (MSVC) don't select one.
+	pub profile: Option<String>,
+	/// Whether targets added with `add_benchmark()` are generated. Set by the profile, or by `catapult bench`.
+	pub benchmarks: bool,
+	/// Where static libraries of dependency packages are cached and restored from, if enabled with `--artifact-cache`
+	pub artifact_cache: Option<PathBuf>,
+	/// The character set of Windows targets: `<CharacterSet>` for MSVC, `UNICODE` or `_MBCS` definitions for Ninja
+	pub windows_character_set: Option<generator::CharacterSet>,
+	/// Where the outputs of each package go in the build directory, set with `--layout`
+	pub output_layout: generator::OutputLayout,
+	/// The name of the package being configured, whose outputs `output_layout` doesn't move
+	pub root_package: String,
+	/// How the generated build files configure the build directory again when a file read while configuring changes
+	pub regeneration: Option<generator::Regeneration>,
 }
 
-fn read_manifest(src_dir: &Path) -> Result<Manifest, anyhow::Error> {
+pub(crate) fn read_manifest(src_dir: &Path) -> Result<Manifest, anyhow::Error> {
 	let manifest_path = src_dir.join(CATAPULT_TOML);
 	let catapult_toml = match fs::read_to_string(&manifest_path) {
 		Ok(x) => x,
@@ -112,57 +233,273 @@ fn read_manifest(src_dir: &Path) -> Result<Manifest, anyhow::Error> {
 
 	let manifest = match toml::from_str::<Manifest>(&catapult_toml) {
 		Ok(x) => x,
-		Err(e) => return err_msg(format!("Error reading {}: {}", manifest_path.display(), e)),
+		Err(e) => return Err(Diagnostic::from_toml(&manifest_path, &catapult_toml, &e).into()),
 	};
+	if let Some(version) = &manifest.package.version {
+		if !misc::is_semver(version) {
+			let diagnostic = Diagnostic::new(format!(
+				"Error reading {}: package version \"{}\" is not a semantic version",
+				manifest_path.display(),
+				version
+			));
+			return Err(diagnostic.with_hint("use a version of the form \"1.2.3\"").into());
+		}
+	}
+	if let Some(api_version) = manifest.package.api_version {
+		if api_version == 0 || api_version > starlark_api::LATEST_API_VERSION {
+			let diagnostic = Diagnostic::new(format!(
+				"Error reading {}: package \"{}\" requires recipe API version {}, but this version of catapult supports up to version {}",
+				manifest_path.display(),
+				manifest.package.name,
+				api_version,
+				starlark_api::LATEST_API_VERSION
+			));
+			return Err(diagnostic.with_hint("upgrade catapult").into());
+		}
+	}
 
 	Ok(manifest)
 }
 
-fn map_to_pkg_opt_map(opt_map: BTreeMap<String, BTreeMap<String, String>>) -> Result<PkgOptMap, anyhow::Error> {
-	type SerdeErr = toml::de::Error;
-
-	fn deserialize_pkg_opt(kv: (String, String)) -> Result<(String, PkgOpt), SerdeErr> {
-		let deserializer = toml::de::ValueDeserializer::new(&kv.1);
-		let opt_val = PkgOpt::deserialize(deserializer)?;
-		Ok((kv.0, opt_val))
-	}
-
-	match opt_map
+fn map_to_pkg_opt_map(opt_map: BTreeMap<String, BTreeMap<String, String>>) -> PkgOptMap {
+	opt_map
 		.into_iter()
 		.map(|(k, im)| {
 			let val = im
 				.into_iter()
-				.map(deserialize_pkg_opt)
-				.collect::<Result<HashMap<String, PkgOpt>, _>>()?;
-			Ok((k, val))
+				.map(|(name, text)| (name, PkgOptOverride::Text(text)))
+				.collect();
+			(k, val)
 		})
-		.collect::<Result<PkgOptMap, SerdeErr>>()
-	{
-		Ok(x) => Ok(x),
-		Err(e) => Err(anyhow!(format!("Could not deserialize package option: {}", e))),
-	}
+		.collect()
 }
 
 pub fn parse_project(
 	toolchain: &Toolchain,
+	profile: Option<&str>,
 	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
 ) -> Result<(Arc<Project>, GlobalOptions), anyhow::Error> {
+	let (project, global_options, _) =
+		resolve_project(toolchain, profile, package_options, allow_unknown_options, Default::default())?;
+	match project.into_project(&global_options, &toolchain.source_extensions) {
+		Ok(x) => Ok((x, global_options)),
+		Err(e) => Err(anyhow!(e)),
+	}
+}
+
+/// Like `parse_project`, also returning the resolved dependencies and option values. Recipes see the output
+/// directories of `output_layout`.
+pub fn parse_project_with_summary(
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
+	output_layout: generator::OutputLayout,
+) -> Result<(Arc<Project>, GlobalOptions, Resolution), anyhow::Error> {
+	let (project, global_options, resolver) =
+		resolve_project(toolchain, profile, package_options, allow_unknown_options, output_layout)?;
+	let dependencies = resolver
+		.graph
+		.sources
+		.iter()
+		.map(|(name, source)| DependencySummary {
+			name: name.clone(),
+			source: source.clone(),
+			metadata: resolver
+				.dep_map
+				.get(name)
+				.map(|x| x.metadata.clone())
+				.unwrap_or_default(),
+			hash: match source {
+				DepSource::Registry { .. } => resolver.lockfile.get(name).map(|x| x.hash.clone()),
+				_ => None,
+			},
+		})
+		.collect();
+	let resolution = Resolution { dependencies, options: resolver.options };
+	match project.into_project(&global_options, &toolchain.source_extensions) {
+		Ok(x) => Ok((x, global_options, resolution)),
+		Err(e) => Err(anyhow!(e)),
+	}
+}
+
+/// Resolves and parses all dependencies of the project in the current directory, returning the resolved graph
+pub fn dependency_tree(
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
+) -> Result<DepGraph, anyhow::Error> {
+	let (_, _, resolver) =
+		resolve_project(toolchain, profile, package_options, allow_unknown_options, Default::default())?;
+	Ok(resolver.graph)
+}
+
+/// Every target of the project in the current directory and its dependencies. Recipes are evaluated but nothing is generated.
+pub fn list_targets(
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
+) -> Result<Vec<TargetSummary>, anyhow::Error> {
+	let (project, _) = parse_project(toolchain, profile, package_options, allow_unknown_options)?;
+	Ok(listing::targets(&project))
+}
+
+/// The options of every package in the dependency graph, with their defaults and the values after overrides
+pub fn list_options(
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
+) -> Result<Vec<OptionSummary>, anyhow::Error> {
+	let (_, _, resolver) =
+		resolve_project(toolchain, profile, package_options, allow_unknown_options, Default::default())?;
+	Ok(resolver.options)
+}
+
+/// Issues in the recipes of the project in the current directory and its dependencies, found without generating
+pub fn check_project(
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
+) -> Result<Vec<check::Issue>, anyhow::Error> {
+	let (project, _) = parse_project(toolchain, profile, package_options, allow_unknown_options)?;
+	Ok(check::check(&project))
+}
+
+/// State accumulated while resolving the dependency graph
+struct Resolver {
+	dep_map: BTreeMap<String, Arc<StarProject>>,
+	/// Canonical directory -> package, so path dependencies written differently but pointing at the same package are
+	/// only parsed once
+	path_deps: HashMap<PathBuf, Arc<StarProject>>,
+	/// The `option_path` of every package parsed, to report package option overrides for paths that don't exist
+	option_paths: BTreeSet<String>,
+	/// The name of every package parsed, to report package option overrides for packages that don't exist
+	package_names: BTreeSet<String>,
+	lockfile: Lockfile,
+	graph: DepGraph,
+	patches: BTreeMap<String, PatchManifest>,
+	/// Registry mirrors from the user and repo config
+	config: Config,
+	/// The options of each package, in the order the packages were parsed
+	options: Vec<OptionSummary>,
+	/// Warn about, rather than reject, overrides of options a package doesn't declare
+	allow_unknown_options: bool,
+	/// Created for the first package fetched from a registry, then reused
+	client: Option<RegistryClient>,
+}
+
+impl Resolver {
+	fn client(&mut self) -> Result<&RegistryClient, anyhow::Error> {
+		if self.client.is_none() {
+			self.client = Some(RegistryClient::new(&NetworkConfig::from_env()?)?);
+		}
+		Ok(self.client.as_ref().unwrap())
+	}
+}
+
+fn resolve_project(
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
+	output_layout: generator::OutputLayout,
+) -> Result<(StarProject, GlobalOptions, Resolver), anyhow::Error> {
+	toolchain::probe::clear_search_caches();
 	let src_dir = PathBuf::from(".");
-	let manifest_options = read_manifest(&src_dir)?.options.unwrap_or_default();
+	let manifest = read_manifest(&src_dir)?;
+	let manifest_options = manifest.options.unwrap_or_default();
+	let patches = manifest.patch.unwrap_or_default();
+	for (name, patch) in &patches {
+		if patch.path.is_some() == patch.git.is_some() {
+			return err_msg(format!("Patch for \"{}\" must specify either \"path\" or \"git\"", name));
+		}
+	}
 	let global_options = GlobalOptions {
 		c_standard: manifest_options.c_standard,
 		cpp_standard: manifest_options.cpp_standard,
 		position_independent_code: manifest_options.position_independent_code,
+		reproducible: manifest_options.reproducible,
+		hermetic: manifest_options.hermetic.unwrap_or(false),
+		strict_sources: manifest_options.strict_sources.unwrap_or(true),
+		strict_include_dirs: manifest_options.strict_include_dirs.unwrap_or(false),
+		profile: profile.map(str::to_owned),
+		benchmarks: profile
+			.and_then(|x| toolchain.profile.get(x))
+			.is_some_and(|x| x.benchmarks),
+		artifact_cache: None,
+		windows_character_set: match &manifest_options.windows_character_set {
+			Some(x) => Some(generator::CharacterSet::parse(x).map_err(anyhow::Error::msg)?),
+			None => None,
+		},
+		output_layout,
+		root_package: manifest.package.name.clone(),
+		regeneration: None,
+	};
+	let mut resolver = Resolver {
+		dep_map: BTreeMap::new(),
+		path_deps: HashMap::new(),
+		option_paths: BTreeSet::new(),
+		package_names: BTreeSet::new(),
+		lockfile: Lockfile::read(&src_dir).map_err(|e| anyhow!(e))?,
+		graph: DepGraph { root: manifest.package.name, ..Default::default() },
+		patches,
+		config: config::load(&src_dir).map_err(|e| anyhow!(e))?,
+		options: Vec::new(),
+		allow_unknown_options,
+		client: None,
 	};
-	let mut combined_deps = BTreeMap::new();
-	let package_options = map_to_pkg_opt_map(package_options)?;
-	let project =
-		parse_project_inner(src_dir, &global_options, &package_options, HashMap::new(), toolchain, &mut combined_deps)?;
+	let package_options = map_to_pkg_opt_map(package_options);
+	let root_name = resolver.graph.root.clone();
+	let project = parse_project_inner(
+		src_dir.clone(),
+		&root_name,
+		&global_options,
+		&package_options,
+		HashMap::new(),
+		toolchain,
+		&mut resolver,
+	)?;
 
-	match project.into_project() {
-		Ok(x) => Ok((x, global_options)),
-		Err(e) => Err(anyhow!(e)),
+	for name in resolver.patches.keys() {
+		if !resolver.dep_map.contains_key(name) {
+			log::warn!("Patch for \"{}\" was not used. No package depends on it.", name);
+		}
+	}
+	for key in package_options.keys() {
+		let (known, reason, kind) = match key.contains('/') {
+			true => (&resolver.option_paths, "No package was resolved through that path of dependencies.", "path"),
+			false => (&resolver.package_names, "No package of that name was resolved.", "package"),
+		};
+		if known.contains(key) {
+			continue;
+		}
+		let message = format!("Options for \"{key}\" were not used. {reason}");
+		if resolver.allow_unknown_options {
+			println!("Warning: {}", message);
+			continue;
+		}
+		let mut diagnostic = Diagnostic::new(message);
+		for suggestion in misc::suggestions(key, known) {
+			diagnostic = diagnostic.with_hint(format!("did you mean {kind} '{suggestion}'?"));
+		}
+		return Err(diagnostic
+			.with_hint("pass --allow-unknown-options to ignore unknown options")
+			.into());
+	}
+
+	if !resolver.lockfile.package.is_empty() {
+		resolver.lockfile.write(&src_dir).map_err(|e| anyhow!(e))?;
+		if let Err(e) = cache::register_lockfile(&src_dir.join(lockfile::CATAPULT_LOCK)) {
+			log::warn!("Could not record lockfile use for cache gc: {}", e);
+		}
 	}
+
+	Ok((project, global_options, resolver))
 }
 
 #[derive(Deserialize)]
@@ -173,41 +510,124 @@ struct PackageRecord {
 	manifest: String,
 	recipe: String,
 	// datetime_added: i64,
+	/// A yanked version is only resolved when the lockfile pins it
+	#[serde(default)]
+	yanked: bool,
+	/// A notice to print when the package is used, e.g. why it's deprecated
+	#[serde(default)]
+	deprecated: Option<String>,
+	/// The package to use instead of a deprecated one
+	#[serde(default)]
+	replacement: Option<String>,
 }
 
+struct FetchedPackage {
+	path: PathBuf,
+	locked: LockedPackage,
+}
+
+/// Try each channel in order, falling through to the next one if no registry provides the package on that channel.
+/// Within a channel, try each registry in order, falling through to the next one (e.g. a mirror) if a registry can't provide the package.
+/// If the lockfile recorded a channel that is still listed, it is tried first so that floating channel lists resolve reproducibly.
+/// A yanked version is skipped like a missing one, unless it's the version, channel and hash recorded in the lockfile.
 fn download_from_registry(
-	mut registry: String,
+	client: &RegistryClient,
+	registries: Vec<String>,
 	name: &str,
 	info_version: Option<String>,
-	info_channel: Option<String>,
-) -> Result<PathBuf, anyhow::Error> {
+	info_channel: Option<OneOrMany<String>>,
+	locked: Option<&LockedPackage>,
+) -> Result<FetchedPackage, anyhow::Error> {
 	// Download to tmp dir
 	let version = match &info_version {
 		Some(x) => x,
 		None => return Err(anyhow::anyhow!("Field \"version\" required for dependency \"{}\"", name)),
 	};
-	let channel = match &info_channel {
-		Some(x) => x,
+	let mut channels = match info_channel {
+		Some(x) => x.into_vec(),
 		None => return Err(anyhow::anyhow!("Field \"channel\" required for dependency \"{}\"", name)),
 	};
-	if !registry.ends_with('/') {
-		registry += "/";
+	if channels.is_empty() {
+		return Err(anyhow!("Field \"channel\" is empty for dependency \"{}\"", name));
 	}
-	let url = match reqwest::Url::parse(&registry) {
-		Ok(x) => x,
-		Err(e) => return Err(anyhow::anyhow!(e)),
-	};
-	let url = match url.join(&("get".to_owned() + "/" + name + "/" + version + "/" + channel)) {
-		Ok(x) => x,
-		Err(e) => return Err(anyhow::anyhow!(e)),
-	};
+	if registries.is_empty() {
+		return Err(anyhow!("Field \"registry\" is empty for dependency \"{}\"", name));
+	}
+	if let Some(pos) = channels.iter().position(|x| Some(x) == locked.map(|x| &x.channel)) {
+		let locked = channels.remove(pos);
+		channels.insert(0, locked);
+	}
+	let mut errors = Vec::new();
+	for channel in &channels {
+		for registry in &registries {
+			match fetch_package_record(client, registry, name, version, channel) {
+				Ok(resp_json) => {
+					let pinned = locked.is_some_and(|x| {
+						&x.version == version && &x.channel == channel && x.hash.trim() == resp_json.hash.trim()
+					});
+					if resp_json.yanked && !pinned {
+						let e = anyhow!(
+							"Version {} of \"{}\" has been yanked from channel \"{}\" of {}",
+							version,
+							name,
+							channel,
+							registry
+						);
+						log::warn!("{}", e);
+						errors.push(e.to_string());
+						continue;
+					}
+					if resp_json.yanked {
+						println!(
+							"Warning: Version {} of \"{}\" has been yanked. It is only used because catapult.lock pins it",
+							version, name
+						);
+					}
+					if resp_json.deprecated.is_some() || resp_json.replacement.is_some() {
+						println!("{}", deprecation_notice(name, &resp_json));
+					}
+					let path = unpack_package(client, &resp_json, name, channel)?;
+					let locked = LockedPackage {
+						name: name.to_owned(),
+						version: version.clone(),
+						channel: channel.clone(),
+						registry: registry.clone(),
+						hash: resp_json.hash.trim().to_owned(),
+					};
+					return Ok(FetchedPackage { path, locked });
+				}
+				Err(e) => {
+					log::warn!("{}", e);
+					errors.push(e.to_string());
+				}
+			}
+		}
+	}
+	Err(anyhow!("Could not fetch \"{}\" from any registry or channel:\n    {}", name, errors.join("\n    ")))
+}
+
+fn deprecation_notice(name: &str, record: &PackageRecord) -> String {
+	let mut notice = format!("Warning: Package \"{}\" is deprecated", name);
+	if let Some(message) = &record.deprecated {
+		notice += ": ";
+		notice += message.trim();
+	}
+	if let Some(replacement) = &record.replacement {
+		notice += &format!("\n    Consider using \"{}\" instead", replacement);
+	}
+	notice
+}
+
+fn fetch_package_record(
+	client: &RegistryClient,
+	registry: &str,
+	name: &str,
+	version: &str,
+	channel: &str,
+) -> Result<PackageRecord, anyhow::Error> {
+	let url = network::record_url(registry, name, version, channel)?;
 	println!("Fetching dependency \"{}\" from {} ...", name, url);
-	let resp = match reqwest::blocking::Client::builder()
-		.build()?
-		.get(url.clone())
-		.timeout(Duration::from_secs(10))
-		.send()
-	{
+	let resp = match client.registry_get(url.clone()).send() {
 		Ok(resp) => resp,
 		Err(err) => return Err(anyhow!("Error trying to fetch \"{}\" from {}:\n    {}", name, url, err)),
 	};
@@ -215,23 +635,45 @@ fn download_from_registry(
 		StatusCode::OK => (),
 		x => return Err(anyhow!("Request GET \"{}\" returned status {}", url, x)),
 	}
-	let resp_json = match resp.json::<PackageRecord>() {
-		Ok(x) => x,
-		Err(e) => return Err(anyhow!(e)),
-	};
-	let cache_dir = match dirs::cache_dir() {
-		Some(x) => x,
-		None => return Err(anyhow!("Could not find a HOME directory")),
-	};
-	let pkg_cache_path = cache_dir.join("catapult").join("cache").join(name).join(channel);
+	match resp.json::<PackageRecord>() {
+		Ok(x) => Ok(x),
+		Err(e) => Err(anyhow!(e)),
+	}
+}
+
+fn unpack_package(
+	client: &RegistryClient,
+	resp_json: &PackageRecord,
+	name: &str,
+	channel: &str,
+) -> Result<PathBuf, anyhow::Error> {
+	let pkg_cache_path = cache::package_dir(name, channel).map_err(|e| anyhow!(e))?;
 	println!("pkg_cache_path: {:?}", pkg_cache_path);
 
-	let hash_path = pkg_cache_path.join("catapult.hash");
+	// Hold an advisory lock for the package/channel directory so that concurrent catapult processes
+	// don't download and unpack the same package on top of each other
+	let _lock = cache::lock_entry(&pkg_cache_path).map_err(|e| anyhow!(e))?;
+
+	let manifest_bytes = base64::engine::general_purpose::STANDARD_NO_PAD.decode(&resp_json.manifest)?;
+	let recipe_bytes = base64::engine::general_purpose::STANDARD_NO_PAD.decode(&resp_json.recipe)?;
+	let record_files = [
+		(CATAPULT_TOML, manifest_bytes.as_slice()),
+		(BUILD_CATAPULT, recipe_bytes.as_slice()),
+	];
+
+	let hash_path = pkg_cache_path.join(cache::HASH_FILE);
 	if let Ok(hash) = fs::read_to_string(&hash_path) {
 		if hash.trim() == resp_json.hash.trim() {
-			// This package already exists in the cache. Don't download it again.
-			log::debug!("Package found in cache. It will not be downloaded: {name}");
-			return Ok(pkg_cache_path);
+			match cache::verify_digests(&pkg_cache_path, &record_files) {
+				Ok(()) => {
+					// This package already exists in the cache. Don't download it again.
+					log::debug!("Package found in cache. It will not be downloaded: {name}");
+					return Ok(pkg_cache_path);
+				}
+				Err(e) => {
+					log::warn!("The cached package {} failed verification and will be re-downloaded: {}", name, e)
+				}
+			}
 		} else {
 			log::info!(
 				r#"A cached package was found but its hash does not match the one reported by the registry. It will be re-downloaded.
@@ -244,8 +686,40 @@ Registry hash: {}"#,
 		}
 	}
 
-	let manifest_bytes = base64::engine::general_purpose::STANDARD_NO_PAD.decode(resp_json.manifest)?;
-	let manifest_str = std::str::from_utf8(&manifest_bytes)?;
+	// Unpack into a temporary sibling directory and rename it into place once complete,
+	// so that a partially unpacked package is never mistaken for a valid cache entry
+	let tmp_path = cache::path_with_suffix(&pkg_cache_path, &format!(".tmp-{}", uuid::Uuid::new_v4()));
+	// The hash file is written last. Its presence marks the package as complete.
+	if let Err(e) = unpack_package_into(client, &record_files, name, &tmp_path)
+		.and_then(|_| cache::write_digests(&tmp_path, &record_files).map_err(|e| anyhow!(e)))
+		.and_then(|_| write_cache_file(&tmp_path, cache::HASH_FILE, resp_json.hash.as_bytes()))
+	{
+		let _ = fs::remove_dir_all(&tmp_path);
+		return Err(e);
+	}
+	if pkg_cache_path.exists() {
+		if let Err(e) = fs::remove_dir_all(&pkg_cache_path) {
+			let _ = fs::remove_dir_all(&tmp_path);
+			return Err(anyhow!("Error removing stale cache entry {}: {}", pkg_cache_path.display(), e));
+		}
+	}
+	if let Err(e) = fs::rename(&tmp_path, &pkg_cache_path) {
+		let _ = fs::remove_dir_all(&tmp_path);
+		return Err(anyhow!("Error moving {} to {}: {}", tmp_path.display(), pkg_cache_path.display(), e));
+	}
+
+	Ok(pkg_cache_path)
+}
+
+/// Unpacks the source archive of the package into `dest`, then writes its manifest and recipe from the record.
+/// The hash file, which marks the entry as complete, is left to the caller.
+fn unpack_package_into(
+	client: &RegistryClient,
+	record_files: &[(&str, &[u8]); 2],
+	name: &str,
+	dest: &Path,
+) -> Result<(), anyhow::Error> {
+	let manifest_str = std::str::from_utf8(record_files[0].1)?;
 	let manifest = match toml::from_str::<Manifest>(manifest_str) {
 		Ok(x) => x,
 		Err(e) => return err_msg(format!("Error reading dependency manifest of {}: {}", name, e)),
@@ -254,121 +728,249 @@ Registry hash: {}"#,
 		Some(x) => x,
 		None => return Err(anyhow!("Dependency manifest did not contain source. ({})", name)),
 	};
-	let src_data_resp = match reqwest::blocking::get(&pkg_source_url) {
+	let src_data_resp = match client.source_get(&pkg_source_url).send() {
 		Ok(resp) => resp,
-		Err(err) => panic!("Error: {}", err),
+		Err(err) => {
+			return Err(anyhow!("Error trying to fetch source of \"{}\" from {}:\n    {}", name, pkg_source_url, err))
+		}
 	};
 	match src_data_resp.status() {
 		StatusCode::OK => (),
 		x => return Err(anyhow!("Request GET \"{}\" returned status {}", pkg_source_url, x)),
 	}
-	let tar = GzDecoder::new(src_data_resp);
+	let progress = Progress::new(name, src_data_resp.content_length());
+	let tar = GzDecoder::new(ProgressReader::new(src_data_resp, progress.clone()));
 	let mut archive = Archive::new(tar);
-	archive.unpack(&pkg_cache_path)?;
-
-	let manifest_path = pkg_cache_path.join(CATAPULT_TOML);
-
-	match fs::write(manifest_path, manifest_bytes) {
-		Ok(x) => x,
-		Err(e) => return Err(anyhow!(e)),
-	};
-	let recipe_path = pkg_cache_path.join(BUILD_CATAPULT);
-	let recipe_bytes = base64::engine::general_purpose::STANDARD_NO_PAD.decode(resp_json.recipe)?;
-	match fs::write(recipe_path, recipe_bytes) {
-		Ok(x) => x,
-		Err(e) => return Err(anyhow!(e)),
-	};
+	if let Err(e) = fs::create_dir_all(dest) {
+		return Err(anyhow!("Error creating directory {}: {}", dest.display(), e));
+	}
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		entry.unpack_in(dest)?;
+		if entry.header().entry_type().is_file() {
+			progress.borrow_mut().add_file();
+		}
+	}
+	progress.borrow_mut().finish();
 
-	match fs::write(hash_path, resp_json.hash.as_bytes()) {
-		Ok(x) => x,
-		Err(e) => return Err(anyhow!(e)),
+	for (file_name, contents) in record_files {
+		write_cache_file(dest, file_name, contents)?;
 	}
+	Ok(())
+}
 
-	Ok(pkg_cache_path)
+fn write_cache_file(dir: &Path, file_name: &str, contents: &[u8]) -> Result<(), anyhow::Error> {
+	match fs::write(dir.join(file_name), contents) {
+		Ok(()) => Ok(()),
+		Err(e) => Err(anyhow!(e)),
+	}
 }
 
+/// `option_path` is the chain of dependency names through which the package was reached, starting with the root
+/// package, e.g. `app/zstd/xxhash`. Options for it override those for the package's name.
 fn parse_project_inner(
 	src_dir: PathBuf,
+	option_path: &str,
 	global_options: &GlobalOptions,
 	package_options: &PkgOptMap,
-	mut pkg_opt_underrides: HashMap<String, PkgOpt>,
+	pkg_opt_underrides: HashMap<String, PkgOpt>,
 	toolchain: &Toolchain,
-	dep_map: &mut BTreeMap<String, Arc<StarProject>>,
+	resolver: &mut Resolver,
 ) -> Result<StarProject, anyhow::Error> {
 	log::debug!("parse_project_inner {}", src_dir.display());
 
 	let manifest = read_manifest(&src_dir)?;
 
-	if let Some(pkg_opts) = package_options.get(&manifest.package.name) {
-		for (opt_name, opt_val) in pkg_opts {
-			pkg_opt_underrides.insert(opt_name.clone(), opt_val.clone());
-		}
+	// Options set by the dependent's manifest, overridden by those for the package's name from the config and command
+	// line, overridden by those for the path to the package
+	let mut pkg_opts = pkg_opt_underrides
+		.into_iter()
+		.map(|(name, value)| (name, PkgOptOverride::Value(value)))
+		.collect::<BTreeMap<_, _>>();
+	if let Some(cli_opts) = package_options.get(&manifest.package.name) {
+		pkg_opts.extend(cli_opts.clone());
+	}
+	if let Some(path_opts) = package_options.get(option_path).filter(|_| option_path.contains('/')) {
+		pkg_opts.extend(path_opts.clone());
 	}
-	let mut pkg_opts = package_options.clone();
-	pkg_opts.insert(manifest.package.name.clone(), pkg_opt_underrides);
+	resolver.option_paths.insert(option_path.to_owned());
+	resolver.package_names.insert(manifest.package.name.clone());
 
 	let mut dependent_projects = Vec::new();
 
 	// Parse dependencies before parsing the dependent
-	for (name, info) in manifest.dependencies.unwrap_or(BTreeMap::new()) {
-		if let Some(dep_proj) = dep_map.get(&name) {
+	for (name, mut info) in manifest.dependencies.unwrap_or(BTreeMap::new()) {
+		resolver.graph.add_edge(&manifest.package.name, &name);
+		if let Some(dep_proj) = resolver.dep_map.get(&name) {
+			// Already resolved by another package
 			dependent_projects.push(dep_proj.clone());
+			continue;
+		}
+
+		// Paths are relative to the package that declares them. Patches are declared by the root package.
+		let mut path_base = src_dir.as_path();
+		if let Some(patch) = resolver.patches.get(&name) {
+			println!("Patching dependency \"{}\" requested by \"{}\"", name, manifest.package.name);
+			path_base = Path::new(".");
+			info.registry = None;
+			info.path = patch.path.clone();
+			info.git = patch.git.clone();
+			info.rev = patch.rev.clone();
 		}
 
 		let pkg_opt_underrides = info.options.unwrap_or_default();
+		let dep_option_path = format!("{}/{}", option_path, name);
 
 		if let Some(registry) = info.registry {
-			let dep_path = download_from_registry(registry, &name, info.version, info.channel)?;
-			let dep_proj =
-				parse_project_inner(dep_path, global_options, &pkg_opts, pkg_opt_underrides, toolchain, dep_map)?;
+			let locked = resolver.lockfile.get(&name).cloned();
+			let registries = resolver.config.with_mirrors(registry.into_vec());
+			let fetched = download_from_registry(
+				resolver.client()?,
+				registries,
+				&name,
+				info.version,
+				info.channel,
+				locked.as_ref(),
+			)?;
+			resolver.graph.sources.insert(
+				name.clone(),
+				DepSource::Registry {
+					registry: fetched.locked.registry.clone(),
+					version: fetched.locked.version.clone(),
+					channel: fetched.locked.channel.clone(),
+				},
+			);
+			let registry_hash = fetched.locked.hash.clone();
+			resolver.lockfile.insert(fetched.locked);
+			let mut dep_proj = parse_project_inner(
+				fetched.path,
+				&dep_option_path,
+				global_options,
+				package_options,
+				pkg_opt_underrides,
+				toolchain,
+				resolver,
+			)?;
+			dep_proj.registry_hash = Some(registry_hash);
 			let dep_proj = Arc::new(dep_proj);
 			dependent_projects.push(dep_proj.clone());
-			dep_map.insert(name, dep_proj);
-		} else if info.git.is_some() {
-			// Checkout to tmp dir
-			todo!();
-		} else if let Some(dep_path) = info.path {
+			resolver.dep_map.insert(name, dep_proj);
+		} else if let Some(git_url) = info.git {
+			let checkout_path = cache::git_checkout(&git_url, info.rev.as_deref()).map_err(|e| anyhow!(e))?;
+			resolver
+				.graph
+				.sources
+				.insert(name.clone(), DepSource::Git { url: git_url, rev: info.rev });
 			let dep_proj = parse_project_inner(
-				PathBuf::from(&dep_path),
+				checkout_path,
+				&dep_option_path,
 				global_options,
-				&pkg_opts,
+				package_options,
 				pkg_opt_underrides,
 				toolchain,
-				dep_map,
-			)?; //, globals)?;
+				resolver,
+			)?;
 			let dep_proj = Arc::new(dep_proj);
 			dependent_projects.push(dep_proj.clone());
-			dep_map.insert(name, dep_proj);
+			resolver.dep_map.insert(name, dep_proj);
+		} else if let Some(dep_path) = info.path {
+			let dep_dir = match paths::canonicalize(&path_base.join(&dep_path)) {
+				Ok(x) => x,
+				Err(e) => {
+					return err_msg(format!(
+						"Path \"{}\" of dependency \"{}\" requested by \"{}\" could not be resolved: {}",
+						dep_path, name, manifest.package.name, e
+					))
+				}
+			};
+			resolver
+				.graph
+				.sources
+				.insert(name.clone(), DepSource::Path { path: dep_path.clone() });
+			let dep_proj = match resolver.path_deps.get(&dep_dir) {
+				Some(x) => {
+					log::debug!("{} was already parsed for another dependency", dep_dir.display());
+					x.clone()
+				}
+				None => {
+					let dep_proj = Arc::new(parse_project_inner(
+						dep_dir.clone(),
+						&dep_option_path,
+						global_options,
+						package_options,
+						pkg_opt_underrides,
+						toolchain,
+						resolver,
+					)?);
+					resolver.path_deps.insert(dep_dir, dep_proj.clone());
+					dep_proj
+				}
+			};
+			dependent_projects.push(dep_proj.clone());
+			resolver.dep_map.insert(name, dep_proj);
 		} else {
 			return err_msg("Dependency must specify either \"registry\" or \"git\" or \"path\"".to_owned());
 		}
 	}
 
-	let mut option_overrides = manifest.package_options.unwrap_or_default();
-	if let Some(pkg_opts) = pkg_opts.get(&manifest.package.name) {
-		for (opt_name, opt_val) in pkg_opts {
-			log::debug!("Override option: {opt_name}");
-			if option_overrides.contains_key(opt_name) {
-				option_overrides.insert(opt_name.clone(), opt_val.clone());
-			} else {
-				log::error!("Package \"{}\" does not provide option \"{opt_name}\"", manifest.package.name);
+	let option_defaults = manifest.package_options.unwrap_or_default();
+	let mut option_overrides = option_defaults.clone();
+	for (opt_name, opt_val) in &pkg_opts {
+		log::debug!("Override option: {opt_name}");
+		if let Some(declared) = option_defaults.get(opt_name) {
+			let value = match opt_val.typed_as(declared) {
+				Ok(x) => x,
+				Err(e) => {
+					return Err(Diagnostic::new(format!(
+						"Invalid value for option \"{opt_name}\" of package \"{}\": {e}",
+						manifest.package.name
+					))
+					.into())
+				}
+			};
+			option_overrides.insert(opt_name.clone(), value);
+		} else {
+			let message = format!("Package \"{}\" does not provide option \"{opt_name}\"", manifest.package.name);
+			if resolver.allow_unknown_options {
+				println!("Warning: {}", message);
+				continue;
+			}
+			let mut diagnostic = Diagnostic::new(message);
+			for suggestion in misc::suggestions(opt_name, option_defaults.keys()) {
+				diagnostic = diagnostic.with_hint(format!("did you mean option '{suggestion}'?"));
 			}
+			let mut known_options = option_defaults.keys().map(String::as_str).collect::<Vec<_>>();
+			known_options.sort();
+			diagnostic = diagnostic.with_hint(if known_options.is_empty() {
+				format!("package \"{}\" has no options", manifest.package.name)
+			} else {
+				format!("package \"{}\" provides: {}", manifest.package.name, known_options.join(", "))
+			});
+			return Err(diagnostic
+				.with_hint("pass --allow-unknown-options to ignore unknown options")
+				.into());
 		}
 	}
 
-	let recipe_path = src_dir.join(BUILD_CATAPULT);
-	let starlark_code = match fs::read_to_string(&recipe_path) {
-		Ok(x) => x,
-		Err(e) => return err_msg(format!("Error reading \"{}\": {e}", recipe_path.display())),
-	};
+	let mut option_names = option_defaults.keys().collect::<Vec<_>>();
+	option_names.sort();
+	for name in option_names {
+		resolver.options.push(OptionSummary {
+			package: manifest.package.name.clone(),
+			name: name.clone(),
+			default: option_defaults[name].to_string(),
+			current: option_overrides[name].to_string(),
+		});
+	}
+
 	let this_project = parse_module(
-		manifest.package.name.clone(),
+		&manifest.package,
 		dependent_projects,
 		global_options,
 		option_overrides,
+		&manifest.env.unwrap_or_default().allow,
 		toolchain,
 		src_dir,
-		starlark_code,
 		// context.clone(),
 	)?;
 
@@ -379,56 +981,141 @@ pub(crate) fn setup(
 	project: &Arc<Mutex<StarProject>>,
 	global_options: &GlobalOptions,
 	package_options: HashMap<String, PkgOpt>,
+	env: StarEnv,
 	toolchain: &Toolchain,
-) -> Globals {
+	api_version: u32,
+	builtins: &[Arc<StarIfaceLibrary>],
+) -> Result<Globals, anyhow::Error> {
 	let mut globals_builder = GlobalsBuilder::standard();
 	starlark::environment::LibraryExtension::Print.add(&mut globals_builder);
-	globals_builder.set("GLOBAL", StarGlobal::new(global_options, package_options, toolchain));
-	starlark_api::build_api(project, &mut globals_builder);
-	globals_builder.build()
+	let package = {
+		let project = match project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(format!("Could not lock project mutex: {e}")),
+		};
+		let output_dir = global_options.output_layout.dir_of(
+			&project.name,
+			project.version.as_deref(),
+			&global_options.root_package,
+		);
+		StarPackage::new(&project.name, project.version.as_deref(), &project.metadata, output_dir)
+	};
+	globals_builder.set("GLOBAL", StarGlobal::new(global_options, package, package_options, env, toolchain));
+	starlark_api::build_api(project, toolchain.platform().map(str::to_owned), api_version, &mut globals_builder);
+	starlark_probe::build_probe_api(
+		Arc::new(starlark_probe::Probes::new(global_options, toolchain)),
+		project,
+		&mut globals_builder,
+	);
+	for lib in builtins {
+		globals_builder.set(&lib.name, StarIfaceLibWrapper(lib.clone()));
+	}
+	Ok(globals_builder.build())
+}
+
+/// The Starlark dialect recipes are written in
+pub(crate) fn recipe_dialect() -> Dialect {
+	Dialect {
+		enable_types: DialectTypes::Enable,
+		enable_f_strings: true,
+		..Dialect::default()
+	}
 }
 
 pub(crate) fn parse_module(
-	name: String,
+	package: &PackageManifest,
 	deps: Vec<Arc<StarProject>>,
 	global_options: &GlobalOptions,
 	package_options: HashMap<String, PkgOpt>,
+	env_allowlist: &[String],
 	toolchain: &Toolchain,
 	current_dir: PathBuf,
-	starlark_code: String,
 ) -> Result<StarProject, anyhow::Error> {
-	let dialect = Dialect {
-		enable_types: DialectTypes::Enable,
-		enable_f_strings: true,
-		..Dialect::default()
+	let recipe_path = current_dir.join(BUILD_CATAPULT);
+	let starlark_code = match fs::read_to_string(&recipe_path) {
+		Ok(x) => x,
+		Err(e) => return err_msg(format!("Error reading \"{}\": {e}", recipe_path.display())),
 	};
+	let dialect = recipe_dialect();
 	let ast = match AstModule::parse(BUILD_CATAPULT, starlark_code, &dialect) {
 		Ok(x) => x,
-		Err(e) => panic!("AstModule::parse: {}", e),
+		Err(e) => return Err(Diagnostic::from_starlark(&e).into()),
 	};
-	let project_writable = Arc::new(Mutex::new(StarProject::new(name, current_dir, deps.clone())));
+	let name = package.name.clone();
+	let project_writable = Arc::new(Mutex::new(StarProject::new(
+		name.clone(),
+		package.version.clone(),
+		package.metadata.clone(),
+		package.api_version.unwrap_or(1),
+		current_dir.clone(),
+		deps.clone(),
+		package_options.clone(),
+	)));
 
 	let module = Module::new();
-	for dep_proj in deps {
-		let proj_value = module.heap().alloc(StarProject::clone(&dep_proj));
+	for dep_proj in &deps {
+		let proj_value = module.heap().alloc(StarProject::clone(dep_proj));
 		module.set(&dep_proj.name, proj_value);
 	}
+	let env = StarEnv::new(&name, env_allowlist);
+	let builtins = starlark_interface_library::builtin_libraries(&project_writable, toolchain);
+	let globals = setup(
+		&project_writable,
+		global_options,
+		package_options,
+		env,
+		toolchain,
+		package.api_version.unwrap_or(1),
+		&builtins,
+	)?;
+	let loaded_modules = LoadedModules::default();
 	{
+		let loader = RecipeLoader {
+			package_root: &current_dir,
+			current_dir: current_dir.clone(),
+			globals: &globals,
+			dialect: &dialect,
+			loaded: &loaded_modules,
+		};
+		let context = RecipeContext {
+			package_name: &name,
+			package_root: &current_dir,
+			subdir: PathBuf::new(),
+			globals: &globals,
+			dialect: &dialect,
+			loaded: &loaded_modules,
+			deps: &deps,
+		};
 		let mut eval = Evaluator::new(&module);
+		eval.set_loader(&loader);
+		eval.extra = Some(&context);
 		// eval.enable_static_typechecking(true);
 		// eval.enable_profile(&starlark::eval::ProfileMode::Typecheck)?;
-		let globals = setup(&project_writable, global_options, package_options, toolchain);
-		eval.eval_module(ast, &globals).map_err(|e| e.into_anyhow())?;
+		eval.eval_module(ast, &globals)
+			.map_err(ConfigureError::from_eval_error)?;
 	}
 	let frozen_module = module.freeze()?;
 	let mut project = match project_writable.lock() {
 		Ok(x) => x.clone(),
 		Err(e) => return err_msg(format!("Could not lock project mutex: {e}")),
 	};
-	project.generator_names = frozen_module
-		.names()
-		.filter(|name| name.as_str().starts_with("__gen_"))
-		.map(|name| (name.as_str().to_string(), frozen_module.get(name.as_str()).unwrap()))
+	for lib in project.add_linked_builtins(&builtins) {
+		starlark_interface_library::check_builtin(&name, &lib.name, toolchain).map_err(anyhow::Error::msg)?;
+	}
+	project.recipe_files = [current_dir.join(CATAPULT_TOML), recipe_path]
+		.into_iter()
+		.map(|x| paths::canonicalize(&x).unwrap_or(x))
+		.chain(loaded_modules.files(&current_dir))
 		.collect();
+	// Generator functions are stored in whichever module was being evaluated when the target was added
+	for frozen_module in std::iter::once(frozen_module).chain(loaded_modules.frozen_modules()) {
+		for name in frozen_module.names() {
+			if name.as_str().starts_with("__gen_") {
+				project
+					.generator_names
+					.insert(name.as_str().to_string(), frozen_module.get(name.as_str()).unwrap());
+			}
+		}
+	}
 	Ok(project)
 }
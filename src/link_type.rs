@@ -49,6 +49,24 @@ impl hash::Hash for LinkPtr {
 	}
 }
 
+impl LinkPtr {
+	/// Link flags that only apply to the link of the executables this library is linked into
+	pub(crate) fn private_link_flags(&self) -> Vec<String> {
+		match self {
+			Self::Static(x) => x.link_flags_private.clone(),
+			Self::Object(x) => x.link_flags_private.clone(),
+			Self::Interface(_) => Vec::new(),
+		}
+	}
+	pub(crate) fn frameworks(&self) -> &[String] {
+		match self {
+			Self::Static(x) => &x.frameworks,
+			Self::Object(x) => &x.frameworks,
+			Self::Interface(x) => &x.frameworks,
+		}
+	}
+}
+
 impl Target for LinkPtr {
 	fn name(&self) -> &str {
 		match self {
@@ -0,0 +1,106 @@
+use serde::Serialize;
+
+use crate::project::Project;
+
+/// A target as listed by `catapult targets`
+#[derive(Clone, Debug)]
+pub struct TargetSummary {
+	pub package: String,
+	pub name: String,
+	pub kind: &'static str,
+	pub sources: usize,
+}
+
+/// A package option as listed by `catapult options`
+#[derive(Clone, Debug, Serialize)]
+pub struct OptionSummary {
+	pub package: String,
+	pub name: String,
+	/// The value in the package's `catapult.toml`
+	pub default: String,
+	/// The value after overrides from dependents and the command line
+	pub current: String,
+}
+
+/// All targets of the project and its dependencies. Dependencies are listed before their dependents.
+pub(crate) fn targets(project: &Project) -> Vec<TargetSummary> {
+	let mut out = Vec::new();
+	for project in project.packages() {
+		let package = &project.info.name;
+		let summary = |name: &str, kind, sources| TargetSummary {
+			package: package.clone(),
+			name: name.to_owned(),
+			kind,
+			sources,
+		};
+		for exe in &project.executables {
+			let kind = match (exe.benchmark, exe.test) {
+				(true, _) => "benchmark",
+				(_, true) => "test",
+				_ => "executable",
+			};
+			out.push(summary(&exe.name, kind, exe.sources.iter().count()));
+		}
+		for lib in &project.static_libraries {
+			out.push(summary(&lib.name, "static_library", lib.sources.iter().count()));
+		}
+		for lib in &project.object_libraries {
+			out.push(summary(&lib.name, "object_library", lib.sources.iter().count()));
+		}
+		for lib in &project.interface_libraries {
+			out.push(summary(&lib.name, "interface_library", 0));
+		}
+		for docs in &project.docs {
+			out.push(summary(&docs.name, "docs", docs.sources.len()));
+		}
+	}
+	out
+}
+
+/// One line per target: `<package>::<name>  <kind>  <n> sources`
+pub fn render_targets(targets: &[TargetSummary]) -> String {
+	let labels = targets
+		.iter()
+		.map(|x| format!("{}::{}", x.package, x.name))
+		.collect::<Vec<_>>();
+	let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+	let kind_width = targets.iter().map(|x| x.kind.len()).max().unwrap_or(0);
+	let mut out = String::new();
+	for (label, target) in labels.iter().zip(targets) {
+		out += &format!("{:label_width$}  {:kind_width$}  {} sources\n", label, target.kind, target.sources);
+	}
+	out
+}
+
+/// One line per option, in the form accepted by `--package-option`: `<package>:<option>=<value>  (default: <value>)`
+pub fn render_options(options: &[OptionSummary]) -> String {
+	let settings = options
+		.iter()
+		.map(|x| format!("{}:{}={}", x.package, x.name, x.current))
+		.collect::<Vec<_>>();
+	let width = settings.iter().map(String::len).max().unwrap_or(0);
+	let mut out = String::new();
+	for (setting, option) in settings.iter().zip(options) {
+		out += &format!("{:width$}  (default: {})\n", setting, option.default);
+	}
+	out
+}
+
+#[test]
+fn test_render_options() {
+	let options = [
+		OptionSummary {
+			package: "zstd".to_owned(),
+			name: "legacy".to_owned(),
+			default: "false".to_owned(),
+			current: "true".to_owned(),
+		},
+		OptionSummary {
+			package: "app".to_owned(),
+			name: "threads".to_owned(),
+			default: "4".to_owned(),
+			current: "4".to_owned(),
+		},
+	];
+	assert_eq!(render_options(&options), "zstd:legacy=true  (default: false)\napp:threads=4     (default: 4)\n");
+}
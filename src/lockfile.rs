@@ -0,0 +1,62 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+pub(crate) const CATAPULT_LOCK: &str = "catapult.lock";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Lockfile {
+	#[serde(default)]
+	pub package: Vec<LockedPackage>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct LockedPackage {
+	pub name: String,
+	pub version: String,
+	pub channel: String,
+	/// The registry (or mirror) that served the package
+	pub registry: String,
+	pub hash: String,
+}
+
+impl Lockfile {
+	pub(crate) fn read(src_dir: &Path) -> Result<Lockfile, String> {
+		let lock_path = src_dir.join(CATAPULT_LOCK);
+		if !lock_path.exists() {
+			return Ok(Lockfile::default());
+		}
+		let lock_toml = match fs::read_to_string(&lock_path) {
+			Ok(x) => x,
+			Err(e) => return Err(format!("Error opening {}: {}", lock_path.display(), e)),
+		};
+		match toml::from_str::<Lockfile>(&lock_toml) {
+			Ok(x) => Ok(x),
+			Err(e) => Err(format!("Error reading {}: {}", lock_path.display(), e)),
+		}
+	}
+
+	pub(crate) fn write(&self, src_dir: &Path) -> Result<(), String> {
+		let lock_path = src_dir.join(CATAPULT_LOCK);
+		let lock_toml = match toml::to_string(self) {
+			Ok(x) => x,
+			Err(e) => return Err(format!("Error serializing {}: {}", lock_path.display(), e)),
+		};
+		let content =
+			"# This file is generated by catapult. It is not intended for manual editing.\n\n".to_owned() + &lock_toml;
+		match fs::write(&lock_path, content) {
+			Ok(()) => Ok(()),
+			Err(e) => Err(format!("Error writing {}: {}", lock_path.display(), e)),
+		}
+	}
+
+	pub(crate) fn get(&self, name: &str) -> Option<&LockedPackage> {
+		self.package.iter().find(|x| x.name == name)
+	}
+
+	pub(crate) fn insert(&mut self, package: LockedPackage) {
+		self.package.retain(|x| x.name != package.name);
+		self.package.push(package);
+		self.package.sort_by(|a, b| a.name.cmp(&b.name));
+	}
+}
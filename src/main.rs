@@ -3,42 +3,920 @@ use std::{
 	env, //
 	fs,
 	path,
-	process::ExitCode,
+	process::{self, ExitCode},
+	time::Duration,
 };
 
 use getopts::Options;
 
-use catapult::{generator::Generator, toolchain};
+use catapult::{
+	api_doc, cache, check,
+	config::{self, BuildDirSettings, GivenSettings},
+	diagnostic, doctor,
+	generator::{Generator, OutputLayout, PathStyle},
+	install, listing, mirror,
+	progress::{self, format_size},
+	publish, recipe_fmt, summary, toolchain, watch, Session, BUILD_CATAPULT, CATAPULT_TOML,
+};
 
 fn print_usage(program: &str, opts: Options) {
-	let brief = format!("Usage: {} FILE [options]", program);
+	let brief = format!(
+		"Usage: {} [configure] [options]\n       {} <build|clean|watch> [options]\n       {} run <executable> [options] [-- <args>...]\n       {} <bench|test> [options] [-- <args>...]\n       {} install --prefix <dir> [options]\n       {} package-binary --format <zip|tar.gz|deb> [options]\n       {} <tree|targets|options|check> [options]\n       {} cache <list|gc> [options]\n       {} api-doc [--format <md|json>]\n       {} doctor [--toolchain <file>]\n       {} mirror --dest <dir|url> [options]\n       {} publish --dest <dir|url> --channel <channel> [options]\n       {} fmt [--check] [<file>...]",
+		program, program, program, program, program, program, program, program, program, program, program, program, program
+	);
 	print!("{}", opts.usage(&brief));
 }
 
-fn main() -> ExitCode {
-	env_logger::Builder::from_env(env_logger::Env::default().filter_or("CATAPULT_LOG", "off"))
-		.format_timestamp(None)
-		.init();
+fn print_cache_entry(entry: &cache::CacheEntry) {
+	let last_used = match entry.last_used.map(|x| x.elapsed()) {
+		Some(Ok(x)) => format!("{} days ago", x.as_secs() / (24 * 60 * 60)),
+		Some(Err(_)) => "just now".to_owned(),
+		None => "not in any known lockfile".to_owned(),
+	};
+	println!(
+		"{} ({}) {:>10}  last used: {}  {}",
+		entry.name,
+		entry.channel,
+		format_size(entry.size),
+		last_used,
+		entry.path.display()
+	);
+}
 
-	let args: Vec<String> = env::args().collect();
-	let program = args[0].clone();
+fn cache_main(program: &str, args: &[String]) -> ExitCode {
+	const MAX_SIZE: &str = "max-size";
+	const MAX_AGE: &str = "max-age";
+
+	let mut opts = Options::new();
+	opts.optopt("", MAX_SIZE, "gc: Remove the least recently used packages until the cache fits", "<size, e.g. 2G>");
+	opts.optopt(
+		"",
+		MAX_AGE,
+		"gc: Only keep packages referenced by a lockfile used within this time",
+		"<age, e.g. 30d>",
+	);
+	opts.optflag("h", "help", "print this help menu");
+	let brief = format!("Usage: {} cache <list|gc> [options]", program);
+	let matches = match opts.parse(args) {
+		Ok(m) => m,
+		Err(f) => {
+			println!("Error: {}", f);
+			print!("{}", opts.usage(&brief));
+			return ExitCode::FAILURE;
+		}
+	};
+	if matches.opt_present("h") {
+		print!("{}", opts.usage(&brief));
+		return ExitCode::SUCCESS;
+	}
+
+	match matches.free.first().map(String::as_str) {
+		Some("list") => {
+			let entries = match cache::list() {
+				Ok(x) => x,
+				Err(e) => {
+					println!("Error: {}", e);
+					return ExitCode::FAILURE;
+				}
+			};
+			for entry in &entries {
+				print_cache_entry(entry);
+			}
+			println!("{} packages, {}", entries.len(), format_size(entries.iter().map(|x| x.size).sum()));
+		}
+		Some("gc") => {
+			let max_size = match matches.opt_str(MAX_SIZE).map(|x| cache::parse_size(&x)).transpose() {
+				Ok(x) => x,
+				Err(e) => {
+					println!("Error: {}", e);
+					return ExitCode::FAILURE;
+				}
+			};
+			let max_age = match matches.opt_str(MAX_AGE).map(|x| cache::parse_age(&x)).transpose() {
+				Ok(x) => x,
+				Err(e) => {
+					println!("Error: {}", e);
+					return ExitCode::FAILURE;
+				}
+			};
+			let report = match cache::gc(max_size, max_age) {
+				Ok(x) => x,
+				Err(e) => {
+					println!("Error: {}", e);
+					return ExitCode::FAILURE;
+				}
+			};
+			for entry in &report.removed {
+				println!("Removed {} ({}) {}", entry.name, entry.channel, format_size(entry.size));
+			}
+			println!(
+				"Removed {} packages ({}). {} packages remain ({}).",
+				report.removed.len(),
+				format_size(report.removed.iter().map(|x| x.size).sum()),
+				report.kept.len(),
+				format_size(report.kept.iter().map(|x| x.size).sum()),
+			);
+		}
+		Some(x) => {
+			println!("Error: Unknown cache command '{}'", x);
+			print!("{}", opts.usage(&brief));
+			return ExitCode::FAILURE;
+		}
+		None => {
+			print!("{}", opts.usage(&brief));
+			return ExitCode::FAILURE;
+		}
+	}
+	ExitCode::SUCCESS
+}
+
+/// Prints the reference documentation of the builtins available to recipes
+fn api_doc_main(program: &str, args: &[String]) -> ExitCode {
+	const API_VERSION: &str = "api-version";
+
+	let mut opts = Options::new();
+	opts.optopt("", FORMAT, "The output format (default: md)", "<md|json>");
+	opts.optopt("", API_VERSION, "Document this version of the recipe API (default: the latest)", "<version>");
+	opts.optflag("h", "help", "print this help menu");
+	let brief = format!("Usage: {} api-doc [options]", program);
+	let matches = match opts.parse(args) {
+		Ok(m) => m,
+		Err(f) => {
+			println!("Error: {}", f);
+			print!("{}", opts.usage(&brief));
+			return ExitCode::FAILURE;
+		}
+	};
+	if matches.opt_present("h") {
+		print!("{}", opts.usage(&brief));
+		return ExitCode::SUCCESS;
+	}
+
+	let format = match api_doc::ApiDocFormat::parse(matches.opt_str(FORMAT).as_deref().unwrap_or("md")) {
+		Ok(x) => x,
+		Err(e) => {
+			println!("Error: {}", e);
+			return ExitCode::FAILURE;
+		}
+	};
+	let api_version = match matches.opt_str(API_VERSION).map(|x| x.parse::<u32>()).transpose() {
+		Ok(x) => x,
+		Err(e) => {
+			println!("Error: Invalid --{}: {}", API_VERSION, e);
+			return ExitCode::FAILURE;
+		}
+	};
+	match api_doc::render(format, api_version) {
+		Ok(x) => println!("{}", x),
+		Err(e) => {
+			println!("Error: {}", e);
+			return ExitCode::FAILURE;
+		}
+	}
+	ExitCode::SUCCESS
+}
+
+/// Checks the tools of a toolchain file by building trivial programs with each profile
+fn doctor_main(program: &str, args: &[String]) -> ExitCode {
+	let mut opts = Options::new();
+	opts.optopt("T", TOOLCHAIN, "Specify a path to a toolchain file", "<path-to-toolchain-file>");
+	opts.optflag("h", "help", "print this help menu");
+	let brief = format!("Usage: {} doctor [options]", program);
+	let matches = match opts.parse(args) {
+		Ok(m) => m,
+		Err(f) => {
+			println!("Error: {}", f);
+			print!("{}", opts.usage(&brief));
+			return ExitCode::FAILURE;
+		}
+	};
+	if matches.opt_present("h") {
+		print!("{}", opts.usage(&brief));
+		return ExitCode::SUCCESS;
+	}
+
+	let toolchain_path = match matches.opt_str(TOOLCHAIN).map(path::PathBuf::from) {
+		Some(x) => x,
+		None => match default_toolchain_path() {
+			Ok(x) => x,
+			Err(e) => {
+				println!("{}", e);
+				return ExitCode::FAILURE;
+			}
+		},
+	};
+	let checks = doctor::diagnose(&toolchain_path);
+	print!("{}", doctor::render_checks(&checks));
+	if !doctor::passed(&checks) {
+		return ExitCode::FAILURE;
+	}
+	ExitCode::SUCCESS
+}
+
+/// Copies the registry packages in catapult.lock to a directory or registry for builds without internet access
+fn mirror_main(program: &str, args: &[String]) -> ExitCode {
+	const DEST: &str = "dest";
+	const BASE_URL: &str = "base-url";
+
+	let mut opts = Options::new();
+	opts.optopt("S", SOURCE_DIR, "Specify the source directory", "<path-to-source>");
+	opts.optopt("", DEST, "The directory or registry URL to copy the packages to", "<dir|url>");
+	opts.optopt(
+		"",
+		BASE_URL,
+		"The URL the mirror is served at. Required for a directory (default: the destination URL)",
+		"<url>",
+	);
+	opts.optflag("h", "help", "print this help menu");
+	let brief = format!("Usage: {} mirror --dest <dir|url> [options]", program);
+	let matches = match opts.parse(args) {
+		Ok(m) => m,
+		Err(f) => {
+			println!("Error: {}", f);
+			print!("{}", opts.usage(&brief));
+			return ExitCode::FAILURE;
+		}
+	};
+	if matches.opt_present("h") {
+		print!("{}", opts.usage(&brief));
+		return ExitCode::SUCCESS;
+	}
+
+	let Some(dest) = matches.opt_str(DEST) else {
+		println!("Error: --{} is required", DEST);
+		print!("{}", opts.usage(&brief));
+		return ExitCode::FAILURE;
+	};
+	let src_dir = path::PathBuf::from(matches.opt_str(SOURCE_DIR).unwrap_or_else(|| ".".to_owned()));
+	let mirrored = match mirror::mirror(&src_dir, &dest, matches.opt_str(BASE_URL).as_deref()) {
+		Ok(x) => x,
+		Err(e) => {
+			println!("Error: {}", e);
+			return ExitCode::FAILURE;
+		}
+	};
+	for package in &mirrored {
+		println!(
+			"Mirrored {} {} ({}) {}",
+			package.name,
+			package.version,
+			package.channel,
+			format_size(package.source_size)
+		);
+	}
+	println!("Mirrored {} packages to {}", mirrored.len(), dest);
+	ExitCode::SUCCESS
+}
+
+/// Packs the package's sources and writes them with a record to a registry, or a directory served as one
+fn publish_main(program: &str, args: &[String]) -> ExitCode {
+	const DEST: &str = "dest";
+	const CHANNEL: &str = "channel";
+	const BASE_URL: &str = "base-url";
+
+	let mut opts = Options::new();
+	opts.optopt("S", SOURCE_DIR, "Specify the source directory", "<path-to-source>");
+	opts.optopt("", DEST, "The directory or registry URL to publish the package to", "<dir|url>");
+	opts.optopt("", CHANNEL, "The channel to publish the package on", "<channel>");
+	opts.optopt(
+		"",
+		BASE_URL,
+		"The URL the registry is served at. Required for a directory (default: the destination URL)",
+		"<url>",
+	);
+	opts.optflag("h", "help", "print this help menu");
+	let brief = format!("Usage: {} publish --dest <dir|url> --channel <channel> [options]", program);
+	let matches = match opts.parse(args) {
+		Ok(m) => m,
+		Err(f) => {
+			println!("Error: {}", f);
+			print!("{}", opts.usage(&brief));
+			return ExitCode::FAILURE;
+		}
+	};
+	if matches.opt_present("h") {
+		print!("{}", opts.usage(&brief));
+		return ExitCode::SUCCESS;
+	}
+
+	let (Some(dest), Some(channel)) = (matches.opt_str(DEST), matches.opt_str(CHANNEL)) else {
+		println!("Error: --{} and --{} are required", DEST, CHANNEL);
+		print!("{}", opts.usage(&brief));
+		return ExitCode::FAILURE;
+	};
+	let src_dir = path::PathBuf::from(matches.opt_str(SOURCE_DIR).unwrap_or_else(|| ".".to_owned()));
+	match publish::publish(&src_dir, &dest, &channel, matches.opt_str(BASE_URL).as_deref()) {
+		Ok(x) => {
+			println!("Published {} {} ({}) {} to {}", x.name, x.version, x.channel, format_size(x.source_size), dest);
+			ExitCode::SUCCESS
+		}
+		Err(e) => {
+			println!("Error: {}", e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+/// Rewrites recipes in the canonical layout, or with `--check` only lists the ones that aren't in it
+fn fmt_main(program: &str, args: &[String]) -> ExitCode {
+	const CHECK: &str = "check";
+
+	let mut opts = Options::new();
+	opts.optflag("", CHECK, "Don't write the files. List those that would change and fail if there are any");
+	opts.optflag("h", "help", "print this help menu");
+	let brief = format!(
+		"Usage: {} fmt [options] [<file>...]\n\nFormats {} in the current directory when no files are given",
+		program, BUILD_CATAPULT
+	);
+	let matches = match opts.parse(args) {
+		Ok(m) => m,
+		Err(f) => {
+			println!("Error: {}", f);
+			print!("{}", opts.usage(&brief));
+			return ExitCode::FAILURE;
+		}
+	};
+	if matches.opt_present("h") {
+		print!("{}", opts.usage(&brief));
+		return ExitCode::SUCCESS;
+	}
+
+	let check = matches.opt_present(CHECK);
+	let files = if matches.free.is_empty() {
+		vec![BUILD_CATAPULT.to_owned()]
+	} else {
+		matches.free
+	};
+	let mut unformatted = false;
+	for file in files {
+		let source = match fs::read_to_string(&file) {
+			Ok(x) => x,
+			Err(e) => {
+				println!("Error reading \"{}\": {}", file, e);
+				return ExitCode::FAILURE;
+			}
+		};
+		let formatted = match recipe_fmt::format_recipe(&file, &source) {
+			Ok(x) => x,
+			Err(e) => {
+				println!("{}", render_error(&e));
+				return ExitCode::FAILURE;
+			}
+		};
+		if formatted == source {
+			continue;
+		}
+		if check {
+			println!("{} is not formatted", file);
+			unformatted = true;
+		} else if let Err(e) = fs::write(&file, formatted) {
+			println!("Error writing \"{}\": {}", file, e);
+			return ExitCode::FAILURE;
+		} else {
+			println!("Formatted {}", file);
+		}
+	}
+	if unformatted {
+		return ExitCode::FAILURE;
+	}
+	ExitCode::SUCCESS
+}
+
+/// `tree`, `targets`, `options` and `check` only evaluate recipes and print what they find
+fn query_main(
+	command: &str,
+	json: bool,
+	src_dir: &str,
+	toolchain_path: path::PathBuf,
+	profile: Option<&str>,
+	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
+) -> ExitCode {
+	let toolchain_path = match path::absolute(&toolchain_path) {
+		Ok(x) => x,
+		Err(e) => {
+			println!("Error resolving path: {} (path: {})", e, toolchain_path.display());
+			return ExitCode::FAILURE;
+		}
+	};
+	match env::set_current_dir(src_dir) {
+		Ok(x) => x,
+		Err(e) => {
+			println!("Error setting cwd: {} (path: {})", e, src_dir);
+			return ExitCode::FAILURE;
+		}
+	};
+	let toolchain = match toolchain::get_toolchain(&toolchain_path, false) {
+		Ok(x) => x,
+		Err(e) => {
+			println!("Toolchain error: {}", e);
+			return ExitCode::FAILURE;
+		}
+	};
+	if let Some(profile) = profile {
+		if let Err(e) = toolchain.get_profile(profile) {
+			println!("{}", e);
+			return ExitCode::FAILURE;
+		}
+	}
+	let mut issues_found = false;
+	let output = match command {
+		"check" => catapult::check_project(&toolchain, profile, package_options, allow_unknown_options).and_then(|x| {
+			issues_found = !x.is_empty();
+			match json {
+				true => Ok(serde_json::to_string_pretty(&x)? + "\n"),
+				false => Ok(check::render_issues(&x)),
+			}
+		}),
+		"tree" => catapult::dependency_tree(&toolchain, profile, package_options, allow_unknown_options)
+			.map(|graph| graph.render()),
+		"targets" => catapult::list_targets(&toolchain, profile, package_options, allow_unknown_options)
+			.map(|x| listing::render_targets(&x)),
+		_ => catapult::list_options(&toolchain, profile, package_options, allow_unknown_options)
+			.map(|x| listing::render_options(&x)),
+	};
+	match output {
+		Ok(x) => print!("{}", x),
+		Err(e) => {
+			print!("{}", render_error(&e));
+			return ExitCode::FAILURE;
+		}
+	}
+	if issues_found {
+		return ExitCode::FAILURE;
+	}
+	ExitCode::SUCCESS
+}
+
+/// Options for `catapult run`
+struct RunOptions {
+	exe_name: String,
+	args: Vec<String>,
+	cwd: Option<path::PathBuf>,
+	env: Vec<(String, String)>,
+}
+
+/// Builds the executable with the generator's build tool, then runs it and forwards its exit code
+fn run_main(build_cmd: process::Command, exe_path: &path::Path, run_opts: RunOptions) -> ExitCode {
+	if run_build_tool(build_cmd) != ExitCode::SUCCESS {
+		return ExitCode::FAILURE;
+	}
+	let cwd = match run_opts.cwd {
+		Some(x) => x,
+		None => exe_path.parent().map(path::Path::to_owned).unwrap_or_default(),
+	};
+	let status = process::Command::new(exe_path)
+		.args(&run_opts.args)
+		.current_dir(&cwd)
+		.envs(run_opts.env)
+		.status();
+	match status {
+		Ok(status) => match status.code() {
+			// Windows exit codes don't fit in a u8, and truncating them could turn a failure into success
+			Some(code) => match u8::try_from(code) {
+				Ok(x) => ExitCode::from(x),
+				Err(_) => ExitCode::FAILURE,
+			},
+			None => {
+				println!("\"{}\" was terminated by a signal", run_opts.exe_name);
+				ExitCode::FAILURE
+			}
+		},
+		Err(e) => {
+			println!("Error running {} (cwd: {}): {}", exe_path.display(), cwd.display(), e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+/// Builds and runs each of the executables, e.g. the benchmarks, passing each of them `args`
+fn run_each(kind: &str, executables: Vec<(String, process::Command, path::PathBuf)>, args: &[String]) -> ExitCode {
+	if executables.is_empty() {
+		println!("No {kind}s. They are added with add_{kind}().");
+		return ExitCode::SUCCESS;
+	}
+	let count = executables.len();
+	let mut failed = Vec::new();
+	for (name, build_cmd, exe_path) in executables {
+		println!("Running {} \"{}\"", kind, name);
+		let run_opts = RunOptions {
+			exe_name: name.clone(),
+			args: args.to_vec(),
+			cwd: None,
+			env: Vec::new(),
+		};
+		if run_main(build_cmd, &exe_path, run_opts) != ExitCode::SUCCESS {
+			failed.push(name);
+		}
+	}
+	if failed.is_empty() {
+		println!("All {} {}s passed", count, kind);
+		ExitCode::SUCCESS
+	} else {
+		println!("Failed {}s: {}", kind, failed.join(", "));
+		ExitCode::FAILURE
+	}
+}
+
+/// The command that builds each of the root package's executables in `names`, and the path it builds it to
+fn root_executables(
+	names: Vec<String>,
+	project: &catapult::project::Project,
+	generator: &Generator,
+	build_dir: &path::Path,
+	toolchain: &toolchain::Toolchain,
+	profile: Option<&str>,
+) -> Result<Vec<(String, process::Command, path::PathBuf)>, String> {
+	let package_name = &project.info.name;
+	let mut executables = Vec::new();
+	for name in names {
+		// The root package's outputs are in a directory of its name in every layout
+		let (cmd, exe_path) = generator.build_executable(
+			build_dir,
+			package_name,
+			path::Path::new(package_name),
+			&name,
+			toolchain,
+			profile,
+		)?;
+		executables.push((name, cmd, exe_path));
+	}
+	Ok(executables)
+}
 
-	const SOURCE_DIR: &str = "source-dir";
-	const BUILD_DIR: &str = "build-dir";
-	const GENERATOR: &str = "generator";
-	const TOOLCHAIN: &str = "toolchain";
-	const PROFILE: &str = "profile";
-	const PACKAGE_OPTION: &str = "package-option";
+struct PackageOptions {
+	format: install::ArchiveFormat,
+	package: String,
+	version: String,
+	target: String,
+	metadata: catapult::project::PackageMetadata,
+	entries: Vec<install::InstallEntry>,
+}
+
+fn package_binary_options(
+	matches: &getopts::Matches,
+	project: &catapult::project::Project,
+	generator: &Generator,
+	build_dir: &path::Path,
+	toolchain: &toolchain::Toolchain,
+	profile: Option<&str>,
+) -> Result<PackageOptions, String> {
+	let format = match matches.opt_str(FORMAT) {
+		Some(x) => install::ArchiveFormat::parse(&x)?,
+		None => return Err(format!("Error: Required option '--{}' missing", FORMAT)),
+	};
+	let entries = install_entries(matches, project, generator, build_dir, toolchain, profile)?;
+	let target = match toolchain.c_compiler.as_ref().or(toolchain.cpp_compiler.as_ref()) {
+		// The placeholder compiler of the MSVC generator doesn't know its target
+		Some(x) if x.id() != "MSVC" => x.target(),
+		_ => String::new(),
+	};
+	// Checked before building, rather than once the .deb is written
+	if let install::ArchiveFormat::Deb = format {
+		install::deb_architecture(&target)?;
+	}
+	Ok(PackageOptions {
+		format,
+		package: project.info.name.clone(),
+		version: project.info.version.clone().unwrap_or_else(|| "0.0.0".to_owned()),
+		target,
+		metadata: project.info.metadata.clone(),
+		entries,
+	})
+}
+
+/// What the root package's `install()` rules install, limited to the components given with `--component`
+fn install_entries(
+	matches: &getopts::Matches,
+	project: &catapult::project::Project,
+	generator: &Generator,
+	build_dir: &path::Path,
+	toolchain: &toolchain::Toolchain,
+	profile: Option<&str>,
+) -> Result<Vec<install::InstallEntry>, String> {
+	let mut entries = install::install_entries(project, generator, build_dir, toolchain, profile)?;
+	let components = matches.opt_strs(COMPONENT);
+	if !components.is_empty() {
+		if let Some(x) = components
+			.iter()
+			.find(|x| !entries.iter().any(|entry| &&entry.component == x))
+		{
+			return Err(format!("Nothing is installed to component \"{}\"", x));
+		}
+		entries.retain(|x| components.contains(&x.component));
+	}
+	if entries.is_empty() {
+		return Err("Nothing to install. Files and targets are installed with install().".to_owned());
+	}
+	Ok(entries)
+}
 
+/// Builds the project, then copies what it installs into `prefix`
+fn install_main(build_cmd: process::Command, entries: &[install::InstallEntry], prefix: &path::Path) -> ExitCode {
+	if run_build_tool(build_cmd) != ExitCode::SUCCESS {
+		return ExitCode::FAILURE;
+	}
+	if let Err(e) = install::install(entries, prefix) {
+		println!("{}", e);
+		return ExitCode::FAILURE;
+	}
+	println!("Installed {} files to {}", entries.len(), prefix.display());
+	ExitCode::SUCCESS
+}
+
+/// Stages the install tree of each component in `<build-dir>/package` and writes an archive of each beside it
+fn package_binary_main(opts: PackageOptions, build_dir: &path::Path) -> ExitCode {
+	let package_dir = build_dir.join("package");
+	let components = match install::stage(&opts.entries, &package_dir) {
+		Ok(x) => x,
+		Err(e) => {
+			println!("{}", e);
+			return ExitCode::FAILURE;
+		}
+	};
+	for (component, component_dir) in components {
+		let info = install::ArchiveInfo {
+			package: &opts.package,
+			version: &opts.version,
+			component: &component,
+			target: &opts.target,
+			metadata: &opts.metadata,
+		};
+		match install::write_archive(opts.format, &component_dir, &package_dir, &info) {
+			Ok(x) => println!("Packaged {}", x.display()),
+			Err(e) => {
+				println!("{}", e);
+				return ExitCode::FAILURE;
+			}
+		}
+	}
+	ExitCode::SUCCESS
+}
+
+/// How build files are generated, besides the project and toolchain
+/// Configures and builds the project, then repeats whenever a manifest, recipe, source or the toolchain file changes
+fn watch_main(
+	session: Session,
+	generator: Generator,
+	build_dir: &path::Path,
+	toolchain_path: &path::Path,
+	profile_name: Option<&str>,
+) -> ExitCode {
+	const POLL_INTERVAL: Duration = Duration::from_millis(500);
+	const DEBOUNCE: Duration = Duration::from_millis(300);
+
+	// Until the project has been parsed successfully, only the root manifest, recipe and toolchain are known. They're
+	// canonicalized like the files `watched_files()` finds, so their snapshots carry over once it's known.
+	let watched = [
+		path::PathBuf::from(CATAPULT_TOML),
+		path::PathBuf::from(BUILD_CATAPULT),
+		toolchain_path.to_owned(),
+	]
+	.into_iter()
+	.map(|x| fs::canonicalize(&x).unwrap_or(x))
+	.collect::<Vec<_>>();
+	// The snapshot is taken before building, so files saved while the build runs trigger another build
+	let mut watcher = watch::Watcher::new(watched);
+	loop {
+		let mut build = || -> Result<(), String> {
+			let toolchain = session.toolchain().map_err(|e| e.to_string())?;
+			let configured = session.parse_with(&toolchain).map_err(|e| render_error(&e))?;
+			watcher.set_files(watch::watched_files(&configured.project, &[toolchain_path.to_owned()], build_dir));
+			let mut build_cmd =
+				generator.build_command(build_dir, &configured.project.info.name, None, &toolchain, profile_name)?;
+			session.write(&configured, toolchain).map_err(|e| e.to_string())?;
+			match build_cmd.status() {
+				Ok(status) if status.success() => Ok(()),
+				Ok(status) => Err(format!("Build failed ({})", status)),
+				Err(e) => Err(format!("Error running {:?}: {}", build_cmd.get_program(), e)),
+			}
+		};
+		match build() {
+			Ok(()) => println!("Build succeeded"),
+			Err(e) => println!("{}", e.trim_end()),
+		}
+		println!("Watching {} files for changes...", watcher.file_count());
+		for path in watcher.wait(POLL_INTERVAL, DEBOUNCE) {
+			println!("Changed: {}", path.display());
+		}
+	}
+}
+
+/// The package that provides executable `exe_name`, searching the root package first
+fn find_executable_package<'a>(
+	project: &'a catapult::project::Project,
+	exe_name: &str,
+) -> Option<&'a catapult::project::ProjectInfo> {
+	if project.executables.iter().any(|x| x.name == exe_name) {
+		return Some(&project.info);
+	}
+	project
+		.dependencies
+		.iter()
+		.find_map(|dep| find_executable_package(dep, exe_name))
+}
+
+/// The commands that take the shared options. `cache` has its own.
+enum Command {
+	/// `catapult configure`, or the bare `catapult -S <dir> -B <dir> -G <generator>` invocation
+	Configure,
+	Build,
+	Clean,
+	Run(String),
+	/// Builds and runs the root package's benchmarks
+	Bench,
+	/// Builds and runs the root package's tests
+	Test,
+	/// Builds the project and copies what the root package's `install()` rules install into a prefix
+	Install,
+	/// Builds the project, stages the root package's `install()` rules and packs each component into an archive
+	PackageBinary,
+	Watch,
+	/// `tree`, `targets`, `options` and `check` only evaluate recipes. A build dir and generator aren't needed.
+	Query(String),
+}
+
+const SOURCE_DIR: &str = "source-dir";
+const BUILD_DIR: &str = "build-dir";
+const GENERATOR: &str = "generator";
+const TOOLCHAIN: &str = "toolchain";
+const PROFILE: &str = "profile";
+const PACKAGE_OPTION: &str = "package-option";
+const CWD: &str = "cwd";
+const ENV: &str = "env";
+const SUMMARY_JSON: &str = "summary-json";
+const ALLOW_UNKNOWN_OPTIONS: &str = "allow-unknown-options";
+const RELATIVE_PATHS: &str = "relative-paths";
+const ARTIFACT_CACHE: &str = "artifact-cache";
+const LAYOUT: &str = "layout";
+const QUIET: &str = "quiet";
+const FORMAT: &str = "format";
+const COMPONENT: &str = "component";
+const PREFIX: &str = "prefix";
+
+/// The directory inside the build dir that `catapult bench` generates into
+const BENCH_DIR: &str = "_bench";
+
+/// Configure errors are rendered with their location and hints, in color when printing to a terminal
+fn render_error(e: &anyhow::Error) -> String {
+	diagnostic::from_error(e).render(diagnostic::use_color())
+}
+
+/// Flags shared by all commands except `cache`
+fn common_options() -> Options {
 	let mut opts = Options::new();
 	opts.optopt("S", SOURCE_DIR, "Specify the source directory", "<path-to-source>");
 	opts.optopt("B", BUILD_DIR, "Specify the build directory", "<path-to-build>");
 	opts.optopt("G", GENERATOR, "Specify a build system generator", "<generator-name>");
 	opts.optopt("T", TOOLCHAIN, "Specify a path to a toolchain file", "<path-to-toolchain-file>");
 	opts.optopt("P", PROFILE, "Specify the profile to build", "<profile-name>");
-	opts.optmulti("p", PACKAGE_OPTION, "Override a package option", "<package name>:<option>=<value>");
+	opts.optmulti("p", PACKAGE_OPTION, "Override a package option", "<package name or path>:<option>=<value>");
+	opts.optflag("", ALLOW_UNKNOWN_OPTIONS, "Warn about, rather than reject, overrides of undeclared package options");
+	opts.optflag("", RELATIVE_PATHS, "Write paths relative to the build directory in generated build files");
+	opts.optopt("", SUMMARY_JSON, "Write the resolved configuration as JSON", "<path>");
+	opts.optopt("", ARTIFACT_CACHE, "Cache built static libraries of dependency packages in a directory", "<path>");
+	opts.optopt(
+		"",
+		LAYOUT,
+		"Where package outputs go in the build directory: flat (default) or isolated, which puts dependencies under _deps/<name>-<version>",
+		"<layout>",
+	);
+	opts.optflag("q", QUIET, "Don't draw progress bars while fetching dependencies");
 	opts.optflag("h", "help", "print this help menu");
-	let matches = match opts.parse(&args[1..]) {
+	opts
+}
+
+fn default_toolchain_path() -> Result<path::PathBuf, String> {
+	let cache_dir = match dirs::config_dir() {
+		Some(x) => x,
+		None => return Err("Could not find a config directory".to_owned()),
+	};
+	let tc_path = cache_dir.join("default_toolchain.toml");
+	if !tc_path.exists() {
+		// Create a default toolchain file if one doesn't already exist
+		match fs::File::create(&tc_path) {
+			Ok(_) => { // TODO(Travers)
+			}
+			Err(e) => return Err(format!("Could not create a default toolchain file: {}", e)),
+		};
+	}
+	Ok(tc_path)
+}
+
+fn parse_package_options(package_opts_vec: Vec<String>) -> Result<BTreeMap<String, BTreeMap<String, String>>, String> {
+	type InnerMap = BTreeMap<String, String>;
+	let mut package_options = BTreeMap::<String, InnerMap>::new();
+	for pkg_opt in package_opts_vec {
+		let (pkg_name, opt_name, opt_val) =
+			match pkg_opt.split_once(':') {
+				Some((pkg_name, opt)) => match opt.split_once('=') {
+					Some((opt_name, opt_val)) => (pkg_name, opt_name, opt_val),
+					None => return Err(
+						"Invalid package-option. Option must be specified as <package name or path>:<package option>=<value>"
+							.to_owned(),
+					),
+				},
+				None => {
+					return Err(
+						"Invalid package-option. Option must be specified as <package name or path>:<package option>=<value>"
+							.to_owned(),
+					)
+				}
+			};
+		package_options
+			.entry(pkg_name.to_owned())
+			.or_default()
+			.insert(opt_name.to_owned(), opt_val.to_owned());
+	}
+	Ok(package_options)
+}
+
+fn run_options(exe_name: String, matches: &getopts::Matches, original_dir: &path::Path) -> Result<RunOptions, String> {
+	let mut env = Vec::new();
+	for var in matches.opt_strs(ENV) {
+		match var.split_once('=') {
+			Some((name, value)) => env.push((name.to_owned(), value.to_owned())),
+			None => return Err("Invalid env. Must be specified as <name>=<value>".to_owned()),
+		}
+	}
+	Ok(RunOptions {
+		exe_name,
+		args: matches.free.clone(),
+		cwd: matches.opt_str(CWD).map(|x| original_dir.join(x)),
+		env,
+	})
+}
+
+/// Runs the generator's build tool and reports failure
+fn run_build_tool(mut cmd: process::Command) -> ExitCode {
+	match cmd.status() {
+		Ok(status) if status.success() => ExitCode::SUCCESS,
+		Ok(status) => {
+			println!("{:?} failed ({})", cmd.get_program(), status);
+			ExitCode::FAILURE
+		}
+		Err(e) => {
+			println!("Error running {:?}: {}", cmd.get_program(), e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+fn main() -> ExitCode {
+	env_logger::Builder::from_env(env_logger::Env::default().filter_or("CATAPULT_LOG", "off"))
+		.format_timestamp(None)
+		.init();
+
+	let args: Vec<String> = env::args().collect();
+	let program = args[0].clone();
+
+	let (command, opt_args) = match args.get(1).map(String::as_str) {
+		Some("cache") => return cache_main(&program, &args[2..]),
+		Some("api-doc") => return api_doc_main(&program, &args[2..]),
+		Some("fmt") => return fmt_main(&program, &args[2..]),
+		Some("doctor") => return doctor_main(&program, &args[2..]),
+		Some("mirror") => return mirror_main(&program, &args[2..]),
+		Some("publish") => return publish_main(&program, &args[2..]),
+		Some("configure") => (Command::Configure, &args[2..]),
+		Some("build") => (Command::Build, &args[2..]),
+		Some("clean") => (Command::Clean, &args[2..]),
+		Some("watch") => (Command::Watch, &args[2..]),
+		Some("bench") => (Command::Bench, &args[2..]),
+		Some("test") => (Command::Test, &args[2..]),
+		Some("install") => (Command::Install, &args[2..]),
+		Some("package-binary") => (Command::PackageBinary, &args[2..]),
+		Some(x @ ("tree" | "targets" | "options" | "check")) => (Command::Query(x.to_owned()), &args[2..]),
+		// `catapult run <executable>` configures the build dir if it isn't already, then builds and runs the executable
+		Some("run") => match args.get(2) {
+			Some(x) => (Command::Run(x.clone()), &args[3..]),
+			None => {
+				println!(
+					"Error: Missing executable name. Usage: {} run <executable> [options] [-- <args>...]",
+					program
+				);
+				return ExitCode::FAILURE;
+			}
+		},
+		_ => (Command::Configure, &args[1..]),
+	};
+
+	let mut opts = common_options();
+	if let Command::Run(_) = command {
+		opts.optopt("", CWD, "Working directory for the executable (default: its output directory)", "<path>");
+		opts.optmulti("", ENV, "Set an environment variable for the executable", "<name>=<value>");
+	}
+	if let Command::PackageBinary = command {
+		opts.optopt("", FORMAT, "The archive format", "<zip|tar.gz|deb>");
+		opts.optmulti("", COMPONENT, "Package only this component (default: all)", "<component>");
+	}
+	if let Command::Install = command {
+		opts.optopt("", PREFIX, "The directory to install to", "<path>");
+		opts.optmulti("", COMPONENT, "Install only this component (default: all)", "<component>");
+	}
+	if matches!(&command, Command::Query(x) if x == "check") {
+		opts.optopt("", FORMAT, "The output format (default: text)", "<text|json>");
+	}
+	let matches = match opts.parse(opt_args) {
 		Ok(m) => m,
 		Err(f) => {
 			println!("Error: {}", f);
@@ -50,10 +928,42 @@ fn main() -> ExitCode {
 		print_usage(&program, opts);
 		return ExitCode::SUCCESS;
 	}
+	progress::set_quiet(matches.opt_present(QUIET));
+
+	// The settings of an already configured build dir are reused, so they needn't be given again
+	let persisted = match matches.opt_str(BUILD_DIR) {
+		Some(x) if !matches!(command, Command::Query(_)) => match BuildDirSettings::read(path::Path::new(&x)) {
+			Ok(x) => x,
+			Err(e) => {
+				println!("Error: {}", e);
+				return ExitCode::FAILURE;
+			}
+		},
+		_ => None,
+	};
+
+	let src_dir = match matches
+		.opt_str(SOURCE_DIR)
+		.or_else(|| persisted.as_ref().map(|x| x.source_dir.display().to_string()))
+	{
+		Some(x) => x,
+		None => {
+			println!("Error: Required option '--{}' missing", SOURCE_DIR);
+			print_usage(&program, opts);
+			return ExitCode::FAILURE;
+		}
+	};
+	let config = match config::load(path::Path::new(&src_dir)) {
+		Ok(x) => x,
+		Err(e) => {
+			println!("{}", e);
+			return ExitCode::FAILURE;
+		}
+	};
 
 	let mut all_required_opts_present = true;
-	let mut match_str = |opt: &str| -> String {
-		match matches.opt_str(opt) {
+	let mut match_str = |opt: &str, fallback: Option<String>| -> String {
+		match matches.opt_str(opt).or(fallback) {
 			Some(x) => x,
 			None => {
 				println!("Error: Required option '--{}' missing", opt);
@@ -62,67 +972,79 @@ fn main() -> ExitCode {
 			}
 		}
 	};
-	let src_dir = match_str(SOURCE_DIR);
-	let build_dir = match_str(BUILD_DIR);
-	let generator_str = match_str(GENERATOR);
+	let (build_dir, generator_str) = if let Command::Query(_) = command {
+		(String::new(), String::new())
+	} else {
+		let persisted_generator = persisted.as_ref().map(|x| x.generator.clone());
+		(match_str(BUILD_DIR, None), match_str(GENERATOR, persisted_generator.or(config.generator.clone())))
+	};
 	if !all_required_opts_present {
 		print_usage(&program, opts);
 		return ExitCode::FAILURE;
 	}
 
-	let toolchain_path = match matches.opt_str(TOOLCHAIN) {
-		Some(x) => path::PathBuf::from(x),
-		None => {
-			let cache_dir = match dirs::config_dir() {
-				Some(x) => x,
-				None => {
-					println!("Could not find a config directory");
-					return ExitCode::FAILURE;
-				}
-			};
-			let tc_path = cache_dir.join("default_toolchain.toml");
-			if !tc_path.exists() {
-				// Create a default toolchain file if one doesn't already exist
-				match fs::File::create(&tc_path) {
-					Ok(_) => { // TODO(Travers)
-					}
-					Err(e) => {
-						println!("Could not create a default toolchain file: {}", e);
-						return ExitCode::FAILURE;
-					}
-				};
+	let toolchain_path = match matches
+		.opt_str(TOOLCHAIN)
+		.map(path::PathBuf::from)
+		.or(persisted.as_ref().map(|x| x.toolchain.clone()))
+		.or(config.toolchain.clone())
+	{
+		Some(x) => x,
+		None => match default_toolchain_path() {
+			Ok(x) => x,
+			Err(e) => {
+				println!("{}", e);
+				return ExitCode::FAILURE;
 			}
-			tc_path
-		}
+		},
 	};
 
-	let profile_opt = matches.opt_str(PROFILE);
+	let profile_opt = matches
+		.opt_str(PROFILE)
+		.or(persisted.as_ref().and_then(|x| x.profile.clone()))
+		.or(config.profile.clone());
 
-	let package_opts_vec = matches.opt_strs(PACKAGE_OPTION);
-	type InnerMap = BTreeMap<String, String>;
-	let mut package_options = BTreeMap::<String, InnerMap>::new();
-	for pkg_opt in package_opts_vec {
-		let (pkg_name, opt) = match pkg_opt.split_once(':') {
-			Some(x) => x,
-			None => {
-				println!("Invalid package-option. Option must be specified as <package name>:<package option>=<value>");
-				return ExitCode::FAILURE;
-			}
+	let cli_package_options = match parse_package_options(matches.opt_strs(PACKAGE_OPTION)) {
+		Ok(x) => x,
+		Err(e) => {
+			println!("{}", e);
+			return ExitCode::FAILURE;
+		}
+	};
+	let mut package_options = match &persisted {
+		Some(x) => x.package_options.clone(),
+		None => config.package_options(),
+	};
+	for (pkg_name, opts) in cli_package_options.clone() {
+		package_options.entry(pkg_name).or_default().extend(opts);
+	}
+	let allow_unknown_options =
+		matches.opt_present(ALLOW_UNKNOWN_OPTIONS) || persisted.as_ref().is_some_and(|x| x.allow_unknown_options);
+
+	if let Command::Query(query) = &command {
+		// Only `check` has a --format option
+		let format = if query == "check" {
+			matches.opt_str(FORMAT)
+		} else {
+			None
 		};
-		let (opt_name, opt_val) = match opt.split_once('=') {
-			Some(x) => x,
-			None => {
-				println!("Invalid package-option. Option must be specified as <package name>:<package option>=<value>");
+		let json = match format.as_deref() {
+			None | Some("text") => false,
+			Some("json") => true,
+			Some(x) => {
+				println!("Error: Unknown format \"{}\". Expected one of: text, json", x);
 				return ExitCode::FAILURE;
 			}
 		};
-		if let Some(map) = package_options.get_mut(pkg_name) {
-			map.insert(opt_name.to_owned(), opt_val.to_owned());
-		} else {
-			let mut inner_map = InnerMap::new();
-			inner_map.insert(opt_name.to_owned(), opt_val.to_owned());
-			package_options.insert(pkg_name.to_owned(), inner_map);
-		}
+		return query_main(
+			query,
+			json,
+			&src_dir,
+			toolchain_path,
+			profile_opt.as_deref(),
+			package_options,
+			allow_unknown_options,
+		);
 	}
 
 	println!("     source-dir: {}", src_dir);
@@ -183,46 +1105,305 @@ fn main() -> ExitCode {
 	} else {
 		original_dir.join(toolchain_path)
 	};
-	let toolchain = match toolchain::get_toolchain(&toolchain_path, matches!(generator, Generator::Msvc)) {
+	if let Some(persisted) = &persisted {
+		let given_src_dir = matches
+			.opt_str(SOURCE_DIR)
+			.map(|_| env::current_dir().unwrap_or_default());
+		let given_toolchain = matches.opt_str(TOOLCHAIN).map(|_| toolchain_path.as_path());
+		// Building selects one of the profiles the MSVC generator generated
+		let given_profile = match generator {
+			Generator::Ninja => matches.opt_str(PROFILE),
+			Generator::Msvc => None,
+		};
+		let given_layout = matches.opt_str(LAYOUT);
+		let given_artifact_cache = matches.opt_str(ARTIFACT_CACHE).map(|x| original_dir.join(x));
+		if let Err(e) = persisted.check_conflicts(&GivenSettings {
+			source_dir: given_src_dir.as_deref(),
+			generator: matches.opt_str(GENERATOR).as_deref(),
+			toolchain: given_toolchain,
+			profile: given_profile.as_deref(),
+			layout: given_layout.as_deref(),
+			artifact_cache: given_artifact_cache.as_deref(),
+			package_options: Some(&cli_package_options),
+		}) {
+			println!("Error: {}", e);
+			return ExitCode::FAILURE;
+		}
+	}
+
+	let output_layout = match matches
+		.opt_str(LAYOUT)
+		.or(persisted.as_ref().and_then(|x| x.layout.clone()))
+		.or(config.layout.clone())
+	{
+		Some(x) => match OutputLayout::parse(&x) {
+			Ok(x) => Some(x),
+			Err(e) => {
+				println!("{}", e);
+				return ExitCode::FAILURE;
+			}
+		},
+		None => None,
+	};
+	let relative_paths = matches.opt_present(RELATIVE_PATHS) || persisted.as_ref().is_some_and(|x| x.relative_paths);
+	let artifact_cache = matches
+		.opt_str(ARTIFACT_CACHE)
+		.map(|x| original_dir.join(x))
+		.or(persisted.as_ref().and_then(|x| x.artifact_cache.clone()))
+		.or(config.artifact_cache.clone());
+
+	// A profile set in a config file doesn't apply when generating for MSVC
+	let msvc_generate = matches!(generator, Generator::Msvc) && matches!(command, Command::Configure | Command::Watch);
+	if msvc_generate && matches.opt_present(PROFILE) {
+		// The MSVC generator generates all profiles but building selects one
+		println!("--profile is incompatible with MSVC generator");
+		return ExitCode::FAILURE;
+	}
+	let profile_opt = if msvc_generate { None } else { profile_opt };
+
+	let new_session = |build_dir: &path::Path| {
+		let mut session = Session::new(env::current_dir().unwrap_or_default(), build_dir, &toolchain_path, generator)
+			.allow_unknown_options(allow_unknown_options)
+			.path_style(if relative_paths {
+				PathStyle::Relative
+			} else {
+				PathStyle::Absolute
+			});
+		if let Some(profile_name) = &profile_opt {
+			session = session.profile(profile_name);
+		}
+		for (pkg_name, opts) in &package_options {
+			for (opt_name, opt_val) in opts {
+				session = session.package_option(pkg_name, opt_name, opt_val);
+			}
+		}
+		if let Some(output_layout) = output_layout {
+			session = session.output_layout(output_layout);
+		}
+		if let Some(artifact_cache) = &artifact_cache {
+			session = session.artifact_cache(artifact_cache);
+		}
+		session
+	};
+	let session = new_session(&build_dir_path);
+	let toolchain = match session.toolchain() {
 		Ok(x) => x,
 		Err(e) => {
-			println!("Toolchain error: {}", e);
+			println!("{}", e);
 			return ExitCode::FAILURE;
 		}
 	};
 
-	// Check selected profile is provided by toolchain
-	let profile = if let Some(prof) = profile_opt {
-		if let Generator::Msvc = generator {
-			println!("--profile is incompatible with MSVC generator");
+	if let Command::Watch = command {
+		return watch_main(session, generator, &build_dir_path, &toolchain_path, profile_opt.as_deref());
+	}
+
+	let configured = match session.parse_with(&toolchain) {
+		Ok(x) => x,
+		Err(e) => {
+			print!("{}", render_error(&e));
 			return ExitCode::FAILURE;
-		};
-		match toolchain.profile.get(&prof) {
+		}
+	};
+	let project = configured.project.clone();
+
+	if let Some(summary_path) = matches.opt_str(SUMMARY_JSON) {
+		let summary_path = original_dir.join(summary_path);
+		if let Err(e) = summary::write(
+			&summary_path,
+			&generator_str,
+			&toolchain_path,
+			&toolchain,
+			profile_opt.as_deref(),
+			&configured.resolution,
+		) {
+			println!("{}", e);
+			return ExitCode::FAILURE;
+		}
+	}
+
+	// The build tool command to run once the build dir is configured, and for `run`, the executable it builds
+	let build = match &command {
+		Command::Configure | Command::Watch | Command::Query(_) | Command::Bench => None,
+		Command::Build | Command::PackageBinary | Command::Install | Command::Test => Some(
+			generator
+				.build_command(&build_dir_path, &project.info.name, None, &toolchain, profile_opt.as_deref())
+				.map(|cmd| (cmd, None)),
+		),
+		Command::Clean => Some(
+			generator
+				.clean_command(&build_dir_path, &project.info.name, &toolchain, profile_opt.as_deref())
+				.map(|cmd| (cmd, None)),
+		),
+		Command::Run(exe_name) => match find_executable_package(&project, exe_name) {
+			Some(package) => Some(
+				generator
+					.build_executable(
+						&build_dir_path,
+						&project.info.name,
+						&configured
+							.global_options
+							.output_layout
+							.package_dir(package, &project.info.name),
+						exe_name,
+						&toolchain,
+						profile_opt.as_deref(),
+					)
+					.map(|(cmd, exe_path)| (cmd, Some(exe_path))),
+			),
 			None => {
-				println!("Selected profile is not provided by toolchain");
+				println!("No executable named \"{}\"", exe_name);
 				return ExitCode::FAILURE;
 			}
-			Some(x) => x.clone(),
-		}
-	} else {
-		Default::default()
+		},
 	};
-
-	let (project, global_opts) = match catapult::parse_project(&toolchain, package_options) {
+	let build = match build.transpose() {
 		Ok(x) => x,
 		Err(e) => {
 			println!("{}", e);
 			return ExitCode::FAILURE;
 		}
 	};
+	// `bench` generates into its own directory inside the build dir, so that its benchmarks are generated even if the
+	// profile doesn't enable them without changing what the build dir builds
+	if let Command::Bench = command {
+		let bench_dir = build_dir_path.join(BENCH_DIR);
+		if let Err(e) = fs::create_dir_all(&bench_dir) {
+			println!("Error creating directory: {} (path: {})", e, bench_dir.display());
+			return ExitCode::FAILURE;
+		}
+		let names = project
+			.executables
+			.iter()
+			.filter(|x| x.benchmark)
+			.map(|x| x.name.clone())
+			.collect();
+		let benchmarks =
+			match root_executables(names, &project, &generator, &bench_dir, &toolchain, profile_opt.as_deref()) {
+				Ok(x) => x,
+				Err(e) => {
+					println!("{}", e);
+					return ExitCode::FAILURE;
+				}
+			};
+		// Regenerating would configure the build dir, not this one. `bench` generates it again each time instead.
+		if let Err(e) = new_session(&bench_dir).benchmarks(true).write(&configured, toolchain) {
+			println!("{}", e);
+			return ExitCode::FAILURE;
+		}
+		return run_each("benchmark", benchmarks, &matches.free);
+	}
+
+	// `package-binary` resolves what to install before the project is consumed by generating
+	let package = match command {
+		Command::PackageBinary => match package_binary_options(
+			&matches,
+			&project,
+			&generator,
+			&build_dir_path,
+			&toolchain,
+			profile_opt.as_deref(),
+		) {
+			Ok(x) => Some(x),
+			Err(e) => {
+				println!("{}", e);
+				return ExitCode::FAILURE;
+			}
+		},
+		_ => None,
+	};
+
+	// `install` copies into the prefix once the project is built
+	let install = match command {
+		Command::Install => {
+			let entries = match matches.opt_str(PREFIX) {
+				Some(prefix) => {
+					install_entries(&matches, &project, &generator, &build_dir_path, &toolchain, profile_opt.as_deref())
+						.map(|x| (x, original_dir.join(prefix)))
+				}
+				None => Err(format!("Error: Required option '--{}' missing", PREFIX)),
+			};
+			match entries {
+				Ok(x) => Some(x),
+				Err(e) => {
+					println!("{}", e);
+					return ExitCode::FAILURE;
+				}
+			}
+		}
+		_ => None,
+	};
+
+	let tests = match command {
+		Command::Test => {
+			let names = project
+				.executables
+				.iter()
+				.filter(|x| x.test)
+				.map(|x| x.name.clone())
+				.collect();
+			match root_executables(names, &project, &generator, &build_dir_path, &toolchain, profile_opt.as_deref()) {
+				Ok(x) => Some(x),
+				Err(e) => {
+					println!("{}", e);
+					return ExitCode::FAILURE;
+				}
+			}
+		}
+		_ => None,
+	};
 
-	match generator.generate(project, global_opts, &build_dir_path, toolchain, profile) {
+	let run_opts = match command {
+		Command::Run(exe_name) => match run_options(exe_name, &matches, &original_dir) {
+			Ok(x) => Some(x),
+			Err(e) => {
+				println!("{}", e);
+				return ExitCode::FAILURE;
+			}
+		},
+		_ => None,
+	};
+
+	// Commands other than `configure` only configure the build dir if it hasn't been already, or if they were given
+	// settings it wasn't configured with
+	let settings = match session.settings() {
 		Ok(x) => x,
 		Err(e) => {
 			println!("{}", e);
 			return ExitCode::FAILURE;
 		}
 	};
+	let up_to_date = generator.build_file(&build_dir_path, &project.info.name).exists() && persisted == Some(settings);
+	if build.is_none() || !up_to_date {
+		if let Err(e) = session.write(&configured, toolchain) {
+			println!("{}", e);
+			return ExitCode::FAILURE;
+		}
+	}
+
+	let build = match (build, install) {
+		(Some((build_cmd, _)), Some((entries, prefix))) => return install_main(build_cmd, &entries, &prefix),
+		(build, _) => build,
+	};
+	let build = match (build, tests) {
+		(Some((build_cmd, _)), Some(tests)) => {
+			if run_build_tool(build_cmd) != ExitCode::SUCCESS {
+				return ExitCode::FAILURE;
+			}
+			return run_each("test", tests, &matches.free);
+		}
+		(build, _) => build,
+	};
 
-	ExitCode::SUCCESS
+	match (build, run_opts, package) {
+		(Some((build_cmd, _)), _, Some(package)) => {
+			if run_build_tool(build_cmd) != ExitCode::SUCCESS {
+				return ExitCode::FAILURE;
+			}
+			package_binary_main(package, &build_dir_path)
+		}
+		(Some((build_cmd, Some(exe_path))), Some(run_opts), _) => run_main(build_cmd, &exe_path, run_opts),
+		(Some((build_cmd, _)), _, _) => run_build_tool(build_cmd),
+		(None, _, _) => ExitCode::SUCCESS,
+	}
 }
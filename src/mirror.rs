@@ -0,0 +1,186 @@
+//! `catapult mirror`: copies the registry packages pinned in `catapult.lock` to a directory or registry that builds
+//! without internet access can use as a mirror
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use base64::Engine;
+use reqwest::{StatusCode, Url};
+
+use crate::{
+	lockfile::{self, LockedPackage, Lockfile},
+	network::{self, NetworkConfig, RegistryClient},
+};
+
+/// Where mirrored and published records and sources are written. Both use the registry layout: records at
+/// `get/<name>/<version>/<channel>` and sources under `sources/`.
+pub(crate) enum Destination {
+	Dir(PathBuf),
+	/// A registry that accepts `PUT` requests at the paths it serves
+	Registry(Url),
+}
+
+pub struct MirroredPackage {
+	pub name: String,
+	pub version: String,
+	pub channel: String,
+	/// Size of the source archive in bytes
+	pub source_size: u64,
+}
+
+/// Downloads the record and source archive of every registry package in the lockfile of `src_dir`, rewrites the
+/// source in each manifest to point into the mirror, and writes both to `dest`.
+/// `dest` is a directory or the URL of a registry. For a directory, `base_url` is the URL it will be served at.
+pub fn mirror(src_dir: &Path, dest: &str, base_url: Option<&str>) -> Result<Vec<MirroredPackage>, String> {
+	let (dest, base_url) = destination(dest, base_url)?;
+	let lockfile = Lockfile::read(src_dir)?;
+	if lockfile.package.is_empty() {
+		return Err(format!(
+			"No packages to mirror. {} is missing or empty. Configure the project first to resolve its dependencies",
+			src_dir.join(lockfile::CATAPULT_LOCK).display()
+		));
+	}
+	let client = NetworkConfig::from_env()
+		.and_then(|x| RegistryClient::new(&x))
+		.map_err(|e| e.to_string())?;
+	let mut mirrored = Vec::new();
+	for locked in &lockfile.package {
+		println!("Mirroring {} {} ({}) from {} ...", locked.name, locked.version, locked.channel, locked.registry);
+		mirrored.push(mirror_package(&client, locked, &dest, &base_url)?);
+	}
+	Ok(mirrored)
+}
+
+/// `dest`, a directory or the URL of a registry, and the URL it's served at, which manifests point their sources to
+pub(crate) fn destination(dest: &str, base_url: Option<&str>) -> Result<(Destination, Url), String> {
+	if dest.starts_with("http://") || dest.starts_with("https://") {
+		let url = parse_base_url(base_url.unwrap_or(dest))?;
+		return Ok((Destination::Registry(parse_base_url(dest)?), url));
+	}
+	match base_url {
+		Some(x) => Ok((Destination::Dir(PathBuf::from(dest)), parse_base_url(x)?)),
+		None => Err(
+			"A base URL is required to write to a directory, so manifests can point to the sources it will serve"
+				.to_owned(),
+		),
+	}
+}
+
+/// A URL that relative paths can be joined to
+fn parse_base_url(url: &str) -> Result<Url, String> {
+	let url = if url.ends_with('/') {
+		url.to_owned()
+	} else {
+		url.to_owned() + "/"
+	};
+	Url::parse(&url).map_err(|e| format!("Invalid URL \"{}\": {}", url, e))
+}
+
+fn mirror_package(
+	client: &RegistryClient,
+	locked: &LockedPackage,
+	dest: &Destination,
+	base_url: &Url,
+) -> Result<MirroredPackage, String> {
+	let record_url = network::record_url(&locked.registry, &locked.name, &locked.version, &locked.channel)
+		.map_err(|e| e.to_string())?;
+	let record = get(client.registry_get(record_url.clone()), &record_url)?;
+	let mut record = match serde_json::from_slice::<serde_json::Value>(&record) {
+		Ok(serde_json::Value::Object(x)) => x,
+		Ok(_) => return Err(format!("The record at {} is not a JSON object", record_url)),
+		Err(e) => return Err(format!("Error reading the record at {}: {}", record_url, e)),
+	};
+	match record.get("hash").and_then(|x| x.as_str()) {
+		Some(x) if x.trim() == locked.hash => {}
+		_ => return Err(format!("The hash of {} from {} no longer matches catapult.lock", locked.name, record_url)),
+	}
+
+	let engine = base64::engine::general_purpose::STANDARD_NO_PAD;
+	let manifest = match record
+		.get("manifest")
+		.and_then(|x| x.as_str())
+		.map(|x| engine.decode(x))
+	{
+		Some(Ok(x)) => String::from_utf8_lossy(&x).into_owned(),
+		_ => return Err(format!("The record at {} has no valid manifest", record_url)),
+	};
+	let mut manifest = match toml::from_str::<toml::Table>(&manifest) {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error reading the manifest of {}: {}", locked.name, e)),
+	};
+	let package = match manifest.get_mut("package").and_then(|x| x.as_table_mut()) {
+		Some(x) => x,
+		None => return Err(format!("The manifest of {} has no [package] section", locked.name)),
+	};
+	let source_url = match package.get("source").and_then(|x| x.as_str()) {
+		Some(x) => x.to_owned(),
+		None => return Err(format!("The manifest of {} has no source", locked.name)),
+	};
+	let source = get(client.source_get(&source_url), &source_url)?;
+
+	let source_path = format!(
+		"sources/{}/{}/{}/{}-{}.tar.gz",
+		locked.name, locked.version, locked.channel, locked.name, locked.version
+	);
+	let mirrored_source_url = base_url
+		.join(&source_path)
+		.map_err(|e| format!("Invalid source URL for {}: {}", locked.name, e))?;
+	package.insert("source".to_owned(), toml::Value::String(mirrored_source_url.to_string()));
+	let manifest = toml::to_string(&manifest).map_err(|e| e.to_string())?;
+	record.insert("manifest".to_owned(), serde_json::Value::String(engine.encode(manifest)));
+	let record = serde_json::to_vec(&record).map_err(|e| e.to_string())?;
+
+	let record_path = format!("get/{}/{}/{}", locked.name, locked.version, locked.channel);
+	let source_size = source.len() as u64;
+	put(client, dest, &source_path, source)?;
+	put(client, dest, &record_path, record)?;
+	Ok(MirroredPackage {
+		name: locked.name.clone(),
+		version: locked.version.clone(),
+		channel: locked.channel.clone(),
+		source_size,
+	})
+}
+
+pub(crate) fn get(request: reqwest::blocking::RequestBuilder, url: impl std::fmt::Display) -> Result<Vec<u8>, String> {
+	let resp = match request.send() {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error fetching {}: {}", url, e)),
+	};
+	match resp.status() {
+		StatusCode::OK => (),
+		x => return Err(format!("Request GET \"{}\" returned status {}", url, x)),
+	}
+	match resp.bytes() {
+		Ok(x) => Ok(x.to_vec()),
+		Err(e) => Err(format!("Error fetching {}: {}", url, e)),
+	}
+}
+
+/// Writes `body` to `path` under the destination, a record or a source archive
+pub(crate) fn put(client: &RegistryClient, dest: &Destination, path: &str, body: Vec<u8>) -> Result<(), String> {
+	match dest {
+		Destination::Dir(dir) => {
+			let file_path = dir.join(path);
+			if let Some(parent) = file_path.parent() {
+				if let Err(e) = fs::create_dir_all(parent) {
+					return Err(format!("Error creating directory {}: {}", parent.display(), e));
+				}
+			}
+			fs::write(&file_path, body).map_err(|e| format!("Error writing {}: {}", file_path.display(), e))
+		}
+		Destination::Registry(url) => {
+			let url = url.join(path).map_err(|e| format!("Invalid URL for {}: {}", path, e))?;
+			let resp = match client.registry_put(url.clone(), body).send() {
+				Ok(x) => x,
+				Err(e) => return Err(format!("Error uploading to {}: {}", url, e)),
+			};
+			match resp.status() {
+				x if x.is_success() => Ok(()),
+				x => Err(format!("Request PUT \"{}\" returned status {}", url, x)),
+			}
+		}
+	}
+}
@@ -1,18 +1,136 @@
 use std::{
 	borrow::Borrow,
-	path::{Path, PathBuf},
+	path::{Component, Path, PathBuf},
 };
 
+use serde::Deserialize;
+
+use crate::paths;
+
 #[derive(Clone, Debug)]
 pub struct SourcePath {
 	pub full: PathBuf,
 	pub name: String,
 }
 
+/// Converts a name to the form used as a prefix for C macros, e.g. `my-lib` -> `MY_LIB`
+pub(crate) fn macro_prefix(name: &str) -> String {
+	name.chars()
+		.map(|c| {
+			if c.is_ascii_alphanumeric() {
+				c.to_ascii_uppercase()
+			} else {
+				'_'
+			}
+		})
+		.collect()
+}
+
+/// Whether `version` is a semantic version, e.g. `1.2.3`, `1.0.0-rc.1` or `1.0.0+build.5`
+pub(crate) fn is_semver(version: &str) -> bool {
+	fn is_numeric_id(x: &str) -> bool {
+		!x.is_empty() && x.chars().all(|c| c.is_ascii_digit()) && (x == "0" || !x.starts_with('0'))
+	}
+	fn is_ident(x: &str) -> bool {
+		!x.is_empty() && x.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+	}
+	let (version, build) = match version.split_once('+') {
+		Some((v, b)) => (v, Some(b)),
+		None => (version, None),
+	};
+	let (core, pre) = match version.split_once('-') {
+		Some((c, p)) => (c, Some(p)),
+		None => (version, None),
+	};
+	let core = core.split('.').collect::<Vec<_>>();
+	core.len() == 3
+		&& core.iter().all(|x| is_numeric_id(x))
+		&& pre.is_none_or(|p| {
+			p.split('.')
+				.all(|x| is_ident(x) && (!x.chars().all(|c| c.is_ascii_digit()) || is_numeric_id(x)))
+		}) && build.is_none_or(|b| b.split('.').all(is_ident))
+}
+
+/// The edit distance between `a` and `b`, counting a swap of adjacent characters as one edit
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+	let a = a.chars().collect::<Vec<_>>();
+	let b = b.chars().collect::<Vec<_>>();
+	// d[i][j] is the distance between the first i chars of a and the first j chars of b
+	let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+	for (i, row) in d.iter_mut().enumerate() {
+		row[0] = i;
+	}
+	d[0] = (0..=b.len()).collect();
+	for i in 1..=a.len() {
+		for j in 1..=b.len() {
+			let cost = usize::from(a[i - 1] != b[j - 1]);
+			d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+			if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+				d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+			}
+		}
+	}
+	d[a.len()][b.len()]
+}
+
+/// Candidates close enough to `name` to be a likely typo, closest first
+pub(crate) fn suggestions<'a>(name: &str, candidates: impl IntoIterator<Item = &'a String>) -> Vec<&'a str> {
+	let max_distance = (name.chars().count() / 3).max(1);
+	let mut close = candidates
+		.into_iter()
+		.map(|x| (edit_distance(name, x), x.as_str()))
+		.filter(|(distance, _)| *distance <= max_distance)
+		.collect::<Vec<_>>();
+	close.sort();
+	close.into_iter().map(|(_, x)| x).collect()
+}
+
+/// `path` relative to the directory `base`. Both must be absolute. Returns `None` if they share nothing but the root,
+/// as a relative path would then depend on where each is on the machine.
+pub(crate) fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+	if !path.is_absolute() || !base.is_absolute() {
+		return None;
+	}
+	let path = path.components().collect::<Vec<_>>();
+	let base = base.components().collect::<Vec<_>>();
+	let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+	if !path[..common].iter().any(|x| matches!(x, Component::Normal(_))) {
+		return None;
+	}
+	let mut ret = PathBuf::new();
+	for _ in common..base.len() {
+		ret.push("..");
+	}
+	ret.extend(&path[common..]);
+	if ret.as_os_str().is_empty() {
+		ret.push(".");
+	}
+	Some(ret)
+}
+
+/// `path` with `.` and `..` components resolved without touching the file system. `..` at the root stays at the root.
+pub(crate) fn lexically_normal(path: &Path) -> PathBuf {
+	let mut ret = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => {
+				if ret.file_name().is_some() {
+					ret.pop();
+				} else if !ret.has_root() {
+					ret.push("..");
+				}
+			}
+			_ => ret.push(component),
+		}
+	}
+	ret
+}
+
 pub(crate) fn join_parent(parent_path: &Path, x: &String) -> SourcePath {
 	let joined = parent_path.join(x); // If x is absolute, it replaces the current path.
 	match joined.try_exists() {
-		Ok(true) => match joined.canonicalize() {
+		Ok(true) => match paths::canonicalize(&joined) {
 			Ok(path) => SourcePath { full: path, name: x.clone() },
 			Err(e) => {
 				log::warn!("Could not canonicalize path \"{}\": {}", joined.to_string_lossy(), e);
@@ -28,20 +146,64 @@ pub(crate) fn join_parent(parent_path: &Path, x: &String) -> SourcePath {
 			SourcePath { full: joined, name: x.clone() }
 		}
 	}
-	// TODO(Travers): Check if there's a way to make clang/gcc/msvc support UNC paths
-	// Implement dunce::canonicalize() ?
 }
 
-pub(crate) fn is_c_source(src_filename: &str) -> bool {
-	src_filename.ends_with(".c") || src_filename.ends_with(".C")
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SourceKind {
+	C,
+	Cpp,
+	Nasm,
+	/// Headers can be listed with sources, e.g. so they show in IDEs, but aren't compiled
+	Header,
 }
 
-pub(crate) fn is_cpp_source(src_filename: &str) -> bool {
-	src_filename.ends_with(".cpp") || src_filename.ends_with(".cc")
+const C_EXTENSIONS: [&str; 1] = [".c"];
+const CPP_EXTENSIONS: [&str; 4] = [".cpp", ".cc", ".cxx", ".c++"];
+const NASM_EXTENSIONS: [&str; 2] = [".asm", ".nasm"];
+const HEADER_EXTENSIONS: [&str; 7] = [".h", ".hh", ".hpp", ".hxx", ".h++", ".inl", ".inc"];
+
+/// Extensions that decide how sources are built, in addition to the built-in ones. Set in the `[source_extensions]`
+/// table of the toolchain file. Extensions match regardless of case.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceExtensions {
+	#[serde(default)]
+	pub c: Vec<String>,
+	#[serde(default)]
+	pub cpp: Vec<String>,
+	#[serde(default)]
+	pub nasm: Vec<String>,
+	#[serde(default)]
+	pub header: Vec<String>,
 }
 
-pub(crate) fn is_nasm_source(src_filename: &str) -> bool {
-	src_filename.ends_with(".asm")
+impl SourceExtensions {
+	/// The kind of source `src_filename` is, or None if its extension is unknown.
+	/// Configured extensions are matched first, so they can change the kind of a built-in one.
+	pub(crate) fn classify(&self, src_filename: &str) -> Option<SourceKind> {
+		let name = src_filename.to_lowercase();
+		let configured = [
+			(SourceKind::C, &self.c),
+			(SourceKind::Cpp, &self.cpp),
+			(SourceKind::Nasm, &self.nasm),
+			(SourceKind::Header, &self.header),
+		];
+		for (kind, extensions) in configured {
+			if extensions.iter().any(|x| name.ends_with(&x.to_lowercase())) {
+				return Some(kind);
+			}
+		}
+		let built_in = [
+			(SourceKind::C, &C_EXTENSIONS[..]),
+			(SourceKind::Cpp, &CPP_EXTENSIONS[..]),
+			(SourceKind::Nasm, &NASM_EXTENSIONS[..]),
+			(SourceKind::Header, &HEADER_EXTENSIONS[..]),
+		];
+		built_in
+			.into_iter()
+			.find(|(_, extensions)| extensions.iter().any(|x| name.ends_with(x)))
+			.map(|(kind, _)| kind)
+	}
 }
 
 #[derive(Clone, Debug, Default)]
@@ -64,21 +226,110 @@ impl Sources {
 		}
 	}
 
-	pub(crate) fn from_slice(sources: &[String], parent_path: &Path) -> Result<Self, String> {
+	/// Sorts `sources` by kind. Headers are left out.
+	pub(crate) fn from_slice(
+		sources: &[String],
+		parent_path: &Path,
+		extensions: &SourceExtensions,
+	) -> Result<Self, String> {
 		sources
 			.iter()
 			.map(|x| join_parent(parent_path, x))
 			.try_fold(Sources::default(), |mut acc, src| {
-				if is_c_source(&src.name) {
-					acc.c.push(src);
-				} else if is_cpp_source(&src.name) {
-					acc.cpp.push(src);
-				} else if is_nasm_source(&src.name) {
-					acc.nasm.push(src);
-				} else {
-					return Err(format!("Unknown source type: {}", &src.name));
+				match extensions.classify(&src.name) {
+					Some(SourceKind::C) => acc.c.push(src),
+					Some(SourceKind::Cpp) => acc.cpp.push(src),
+					Some(SourceKind::Nasm) => acc.nasm.push(src),
+					Some(SourceKind::Header) => {}
+					None => {
+						return Err(format!(
+							"Unknown source type: {}. Extensions can be added in the [source_extensions] table of the toolchain file.",
+							&src.name
+						))
+					}
 				}
 				Ok(acc)
 			})
 	}
 }
+
+#[test]
+fn test_is_semver() {
+	for valid in [
+		"0.0.0",
+		"1.2.3",
+		"10.20.30",
+		"1.0.0-rc.1",
+		"1.0.0-alpha-1",
+		"1.0.0+build.5",
+		"1.0.0-0.3.7+sha.5114f85",
+	] {
+		assert!(is_semver(valid), "{valid}");
+	}
+	for invalid in [
+		"",
+		"1",
+		"1.2",
+		"1.2.3.4",
+		"01.2.3",
+		"1.2.x",
+		"1.2.3-",
+		"1.2.3-01",
+		"1.2.3+",
+		"1.2.3-a..b",
+		"v1.2.3",
+	] {
+		assert!(!is_semver(invalid), "{invalid}");
+	}
+}
+
+#[test]
+fn test_suggestions() {
+	assert_eq!(edit_distance("with_tests", "with_tests"), 0);
+	assert_eq!(edit_distance("opttion", "option"), 1);
+	assert_eq!(edit_distance("levle", "level"), 1);
+	assert_eq!(edit_distance("", "abc"), 3);
+	let options = ["with_tests", "with_docs", "shared"].map(String::from);
+	assert_eq!(suggestions("with_test", &options), ["with_tests"]);
+	assert_eq!(suggestions("with_dcos", &options), ["with_docs"]);
+	assert!(suggestions("static", &options).is_empty());
+}
+
+#[test]
+fn test_relative_path() {
+	let root = std::env::current_dir().unwrap();
+	let build = root.join("proj").join("build");
+	assert_eq!(relative_path(&root.join("proj/src/a.c"), &build), Some(PathBuf::from("../src/a.c")));
+	assert_eq!(relative_path(&build.join("core/a.o"), &build), Some(PathBuf::from("core/a.o")));
+	assert_eq!(relative_path(&build, &build), Some(PathBuf::from(".")));
+	assert_eq!(relative_path(Path::new("src/a.c"), &build), None);
+	if let Some(first) = root.components().find(|x| matches!(x, Component::Normal(_))) {
+		let other_root = root
+			.ancestors()
+			.last()
+			.unwrap()
+			.join(first.as_os_str().to_string_lossy().to_string() + "-other");
+		assert_eq!(relative_path(&other_root, &build), None);
+	}
+}
+
+#[test]
+fn test_lexically_normal() {
+	assert_eq!(lexically_normal(Path::new("/pkg/./src/../a.c")), PathBuf::from("/pkg/a.c"));
+	assert_eq!(lexically_normal(Path::new("/pkg/../../a.c")), PathBuf::from("/a.c"));
+	assert_eq!(lexically_normal(Path::new("src/../../a.c")), PathBuf::from("../a.c"));
+}
+
+#[test]
+fn test_source_extensions() {
+	let mut extensions = SourceExtensions::default();
+	assert_eq!(extensions.classify("a.CXX"), Some(SourceKind::Cpp));
+	assert_eq!(extensions.classify("a.c++"), Some(SourceKind::Cpp));
+	assert_eq!(extensions.classify("a.C"), Some(SourceKind::C));
+	assert_eq!(extensions.classify("a.Hpp"), Some(SourceKind::Header));
+	assert_eq!(extensions.classify("a.ixx"), None);
+	extensions.cpp.push(".ixx".to_owned());
+	extensions.cpp.push(".C".to_owned());
+	assert_eq!(extensions.classify("a.ixx"), Some(SourceKind::Cpp));
+	assert_eq!(extensions.classify("a.c"), Some(SourceKind::Cpp));
+}
@@ -0,0 +1,146 @@
+use std::{env, fs, path::PathBuf, time::Duration};
+
+use anyhow::anyhow;
+use reqwest::{
+	blocking::{Client, ClientBuilder, RequestBuilder},
+	IntoUrl, Url,
+};
+
+const CA_BUNDLE_ENV: &str = "CATAPULT_CA_BUNDLE";
+const TLS_VERIFY_ENV: &str = "CATAPULT_TLS_VERIFY";
+const TIMEOUT_ENV: &str = "CATAPULT_HTTP_TIMEOUT";
+const REGISTRY_TOKEN_ENV: &str = "CATAPULT_REGISTRY_TOKEN";
+/// How long to wait for a connection to a registry or package source
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long registries have to answer a package lookup, which is small
+const RECORD_TIMEOUT: Duration = Duration::from_secs(10);
+const PROXY_ENV_VARS: [&str; 6] = [
+	"HTTP_PROXY",
+	"http_proxy",
+	"HTTPS_PROXY",
+	"https_proxy",
+	"NO_PROXY",
+	"no_proxy",
+];
+
+/// Settings for requests made to registries and package sources.
+/// Proxies are read by reqwest from the standard HTTP_PROXY, HTTPS_PROXY and NO_PROXY environment variables.
+#[derive(Debug, Default)]
+pub(crate) struct NetworkConfig {
+	/// A PEM file containing additional root certificates, e.g. for an internal registry
+	pub ca_bundle: Option<PathBuf>,
+	pub accept_invalid_certs: bool,
+	/// How long a whole request, including downloading a package's source, may take. Unlimited if not set.
+	pub timeout: Option<Duration>,
+	/// Sent to registries as a bearer token, but not to package sources, which may be hosted elsewhere
+	pub registry_token: Option<String>,
+}
+
+impl NetworkConfig {
+	pub(crate) fn from_env() -> Result<NetworkConfig, anyhow::Error> {
+		let ca_bundle = env::var_os(CA_BUNDLE_ENV).map(PathBuf::from);
+		let accept_invalid_certs = match env::var(TLS_VERIFY_ENV) {
+			Err(_) => false,
+			Ok(x) => match x.to_ascii_lowercase().as_str() {
+				"1" | "true" => false,
+				"0" | "false" => true,
+				_ => {
+					return Err(anyhow!("Invalid value for {TLS_VERIFY_ENV}: \"{x}\". Expected \"true\" or \"false\""))
+				}
+			},
+		};
+		let timeout = match env::var(TIMEOUT_ENV) {
+			Err(_) => None,
+			Ok(x) => match x.parse::<u64>() {
+				Ok(secs) => Some(Duration::from_secs(secs)),
+				Err(_) => {
+					return Err(anyhow!("Invalid value for {TIMEOUT_ENV}: \"{x}\". Expected a number of seconds"))
+				}
+			},
+		};
+		let registry_token = env::var(REGISTRY_TOKEN_ENV).ok().filter(|x| !x.is_empty());
+		Ok(NetworkConfig { ca_bundle, accept_invalid_certs, timeout, registry_token })
+	}
+}
+
+/// The single place HTTP clients are configured so that all downloads behave the same
+pub(crate) fn client_builder(config: &NetworkConfig) -> Result<ClientBuilder, anyhow::Error> {
+	for var in PROXY_ENV_VARS {
+		if let Ok(val) = env::var(var) {
+			log::debug!("{var}={val}");
+		}
+	}
+	let mut builder = Client::builder();
+	if let Some(ca_bundle) = &config.ca_bundle {
+		let pem = match fs::read(ca_bundle) {
+			Ok(x) => x,
+			Err(e) => return Err(anyhow!("Error reading CA bundle \"{}\": {}", ca_bundle.display(), e)),
+		};
+		let certs = match reqwest::Certificate::from_pem_bundle(&pem) {
+			Ok(x) => x,
+			Err(e) => return Err(anyhow!("Error parsing CA bundle \"{}\": {}", ca_bundle.display(), e)),
+		};
+		log::info!("Using {} certificate(s) from {}", certs.len(), ca_bundle.display());
+		for cert in certs {
+			builder = builder.add_root_certificate(cert);
+		}
+	}
+	if config.accept_invalid_certs {
+		log::warn!("TLS certificate verification is disabled ({TLS_VERIFY_ENV})");
+		builder = builder.danger_accept_invalid_certs(true);
+	}
+	Ok(builder)
+}
+
+/// The HTTP client shared by every registry lookup and package download of a run, so connections are reused and all
+/// requests have the same timeouts, proxies and certificates
+pub(crate) struct RegistryClient {
+	client: Client,
+	registry_token: Option<String>,
+}
+
+impl RegistryClient {
+	pub(crate) fn new(config: &NetworkConfig) -> Result<RegistryClient, anyhow::Error> {
+		let mut builder = client_builder(config)?.connect_timeout(CONNECT_TIMEOUT);
+		if let Some(timeout) = config.timeout {
+			builder = builder.timeout(timeout);
+		}
+		Ok(RegistryClient {
+			client: builder.build()?,
+			registry_token: config.registry_token.clone(),
+		})
+	}
+
+	/// A request to a registry's API
+	pub(crate) fn registry_get(&self, url: impl IntoUrl) -> RequestBuilder {
+		let request = self.client.get(url).timeout(RECORD_TIMEOUT);
+		match &self.registry_token {
+			Some(token) => request.bearer_auth(token),
+			None => request,
+		}
+	}
+
+	/// A download of a package's source
+	pub(crate) fn source_get(&self, url: impl IntoUrl) -> RequestBuilder {
+		self.client.get(url)
+	}
+
+	/// An upload of a package record or source to a registry, e.g. by `catapult mirror`
+	pub(crate) fn registry_put(&self, url: impl IntoUrl, body: Vec<u8>) -> RequestBuilder {
+		let request = self.client.put(url).body(body);
+		match &self.registry_token {
+			Some(token) => request.bearer_auth(token),
+			None => request,
+		}
+	}
+}
+
+/// The URL of the record of a package version on a channel of `registry`
+pub(crate) fn record_url(registry: &str, name: &str, version: &str, channel: &str) -> Result<Url, anyhow::Error> {
+	let mut registry = registry.to_owned();
+	if !registry.ends_with('/') {
+		registry += "/";
+	}
+	let url = Url::parse(&registry)?;
+	Ok(url.join(&("get".to_owned() + "/" + name + "/" + version + "/" + channel))?)
+}
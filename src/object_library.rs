@@ -24,10 +24,22 @@ pub struct ObjectLibrary {
 	pub defines_private: Vec<String>,
 	pub defines_public: Vec<String>,
 	pub link_flags_public: Vec<String>,
+	/// Added to the link of executables that link this library, directly or through other libraries, but not passed on
+	/// as link flags of the libraries that link it
+	pub link_flags_private: Vec<String>,
+	/// Apple frameworks linked into the executables that link this library, e.g. "CoreFoundation"
+	pub frameworks: Vec<String>,
 
 	pub generator_vars: Option<OwnedFrozenValue>,
 
 	pub output_name: Option<String>,
+
+	/// Executables and static libraries, as (package, name), built before this target's pre-build steps and sources
+	pub depends: Vec<(String, String)>,
+	/// Commands run before the target's sources are compiled
+	pub pre_build: Vec<String>,
+	/// Commands run after the target is built
+	pub post_build: Vec<String>,
 }
 
 impl Target for ObjectLibrary {
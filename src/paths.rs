@@ -0,0 +1,88 @@
+//! Windows extended-length path handling.
+//!
+//! `fs::canonicalize()` returns paths like `\\?\C:\src\a.c` on Windows. Compilers and Visual Studio don't accept
+//! this syntax, so paths are converted back to their legacy form wherever that doesn't change what they refer to.
+
+use std::{
+	borrow::Cow,
+	io,
+	path::{Path, PathBuf},
+};
+
+const VERBATIM_PREFIX: &str = r"\\?\";
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+/// Legacy paths longer than this need the extended-length syntax
+const MAX_PATH: usize = 260;
+const RESERVED_NAMES: [&str; 22] = [
+	"CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+	"LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Whether `component` means the same thing without the extended-length prefix.
+/// Legacy paths strip trailing dots and spaces and treat device names specially.
+fn is_legacy_safe(component: &str) -> bool {
+	let stem = component.split('.').next().unwrap_or_default().trim_end();
+	!component.is_empty()
+		&& component != "."
+		&& component != ".."
+		&& !component.ends_with(['.', ' '])
+		&& !component.contains(['<', '>', ':', '"', '/', '|', '?', '*'])
+		&& !component.chars().any(|c| c.is_control())
+		&& !RESERVED_NAMES.iter().any(|x| x.eq_ignore_ascii_case(stem))
+}
+
+/// `path` without the extended-length prefix if the legacy form refers to the same file, e.g.
+/// `\\?\C:\src` -> `C:\src` and `\\?\UNC\server\share` -> `\\server\share`
+pub(crate) fn simplify_str(path: &str) -> Cow<'_, str> {
+	let (legacy, rest) = if let Some(rest) = path.strip_prefix(VERBATIM_UNC_PREFIX) {
+		(Cow::Owned(r"\\".to_owned() + rest), rest.splitn(3, '\\').nth(2).unwrap_or_default())
+	} else if let Some(rest) = path.strip_prefix(VERBATIM_PREFIX) {
+		let bytes = rest.as_bytes();
+		let is_disk = bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\';
+		if !is_disk {
+			return Cow::Borrowed(path);
+		}
+		(Cow::Borrowed(rest), &rest[3..])
+	} else {
+		return Cow::Borrowed(path);
+	};
+	if legacy.len() >= MAX_PATH || !rest.split('\\').filter(|x| !x.is_empty()).all(is_legacy_safe) {
+		return Cow::Borrowed(path);
+	}
+	legacy
+}
+
+/// `path` without the extended-length prefix, where that is safe
+pub(crate) fn simplified(path: &Path) -> PathBuf {
+	match path.to_str().map(simplify_str) {
+		Some(Cow::Owned(x)) => PathBuf::from(x),
+		Some(Cow::Borrowed(x)) => PathBuf::from(x),
+		None => path.to_owned(),
+	}
+}
+
+/// Like `fs::canonicalize()`, without the extended-length prefix where it isn't needed
+pub(crate) fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+	path.canonicalize().map(|x| simplified(&x))
+}
+
+/// `path` as written in a generated build file
+pub(crate) fn to_build_string(path: &Path) -> String {
+	simplify_str(&path.to_string_lossy()).into_owned()
+}
+
+#[test]
+fn test_simplify() {
+	assert_eq!(simplify_str(r"\\?\C:\src\a.c"), r"C:\src\a.c");
+	assert_eq!(simplify_str(r"\\?\c:\"), r"c:\");
+	assert_eq!(simplify_str(r"\\?\UNC\server\share\a.c"), r"\\server\share\a.c");
+	assert_eq!(simplify_str(r"C:\src\a.c"), r"C:\src\a.c");
+	assert_eq!(simplify_str("/home/user/a.c"), "/home/user/a.c");
+	// The legacy form of these would refer to something else
+	assert_eq!(simplify_str(r"\\?\C:\src\con.c"), r"\\?\C:\src\con.c");
+	assert_eq!(simplify_str(r"\\?\C:\src\a.c."), r"\\?\C:\src\a.c.");
+	assert_eq!(simplify_str(r"\\?\C:\src\..\a.c"), r"\\?\C:\src\..\a.c");
+	assert_eq!(simplify_str(r"\\?\Volume{1234}\a.c"), r"\\?\Volume{1234}\a.c");
+	let long = format!(r"\\?\C:\{}", "a".repeat(MAX_PATH));
+	assert_eq!(simplify_str(&long), long);
+}
@@ -0,0 +1,131 @@
+//! Progress of package downloads. Drawn as a bar when stdout is a terminal, otherwise only logged when done.
+
+use std::{
+	cell::RefCell,
+	io::{self, IsTerminal, Read, Write},
+	rc::Rc,
+	sync::atomic::{AtomicBool, Ordering},
+	time::{Duration, Instant},
+};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// How often the bar is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 30;
+
+/// Don't draw progress bars, e.g. for `--quiet`
+pub fn set_quiet(quiet: bool) {
+	QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn format_size(size: u64) -> String {
+	const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+	let mut size = size as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// The bytes downloaded and files unpacked of a package, whose source is unpacked as it downloads
+pub(crate) struct Progress {
+	name: String,
+	/// From the response's Content-Length, if it had one
+	total_bytes: Option<u64>,
+	bytes: u64,
+	files: u64,
+	draw: bool,
+	last_draw: Option<Instant>,
+}
+
+impl Progress {
+	pub(crate) fn new(name: &str, total_bytes: Option<u64>) -> Rc<RefCell<Progress>> {
+		Rc::new(RefCell::new(Progress {
+			name: name.to_owned(),
+			total_bytes,
+			bytes: 0,
+			files: 0,
+			draw: !QUIET.load(Ordering::Relaxed) && io::stdout().is_terminal(),
+			last_draw: None,
+		}))
+	}
+
+	fn add_bytes(&mut self, bytes: u64) {
+		self.bytes += bytes;
+		self.redraw();
+	}
+
+	pub(crate) fn add_file(&mut self) {
+		self.files += 1;
+		self.redraw();
+	}
+
+	fn files(&self) -> String {
+		match self.files {
+			1 => "1 file".to_owned(),
+			n => format!("{} files", n),
+		}
+	}
+
+	fn line(&self) -> String {
+		let files = self.files();
+		match self.total_bytes {
+			Some(total) if total > 0 => {
+				let filled = ((self.bytes.min(total) * BAR_WIDTH as u64) / total) as usize;
+				format!(
+					"Fetching {} [{}{}] {} / {}, {}",
+					self.name,
+					"#".repeat(filled),
+					" ".repeat(BAR_WIDTH - filled),
+					format_size(self.bytes),
+					format_size(total),
+					files
+				)
+			}
+			_ => format!("Fetching {} {}, {}", self.name, format_size(self.bytes), files),
+		}
+	}
+
+	fn redraw(&mut self) {
+		if !self.draw || self.last_draw.is_some_and(|x| x.elapsed() < REDRAW_INTERVAL) {
+			return;
+		}
+		self.last_draw = Some(Instant::now());
+		let mut stdout = io::stdout();
+		let _ = write!(stdout, "\r\x1b[2K{}", self.line());
+		let _ = stdout.flush();
+	}
+
+	/// Replaces the bar with a summary line
+	pub(crate) fn finish(&mut self) {
+		let summary = format!("Fetched {}: {}, {}", self.name, format_size(self.bytes), self.files());
+		if self.draw {
+			println!("\r\x1b[2K{}", summary);
+		} else {
+			log::info!("{}", summary);
+		}
+	}
+}
+
+/// Counts the bytes read from a download
+pub(crate) struct ProgressReader<R> {
+	inner: R,
+	progress: Rc<RefCell<Progress>>,
+}
+
+impl<R> ProgressReader<R> {
+	pub(crate) fn new(inner: R, progress: Rc<RefCell<Progress>>) -> Self {
+		ProgressReader { inner, progress }
+	}
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		self.progress.borrow_mut().add_bytes(n as u64);
+		Ok(n)
+	}
+}
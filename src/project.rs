@@ -1,12 +1,18 @@
 use std::{
+	collections::{BTreeSet, HashMap},
 	path::PathBuf, //
 	sync::Arc,
 };
 
+use allocative::Allocative;
+use serde::{Deserialize, Serialize};
+
 use crate::{
+	docs::Docs,
 	executable::Executable, //
 	interface_library::InterfaceLibrary,
 	object_library::ObjectLibrary,
+	starlark_global::PkgOpt,
 	static_library::StaticLibrary,
 };
 
@@ -14,6 +20,84 @@ use crate::{
 pub struct ProjectInfo {
 	pub name: String,
 	pub path: PathBuf,
+	/// The `version` from the package's `catapult.toml`, if it has one
+	pub version: Option<String>,
+	/// The license, description, authors and repository from the package's `catapult.toml`
+	pub metadata: PackageMetadata,
+	/// The recipe API version from the package's `catapult.toml`
+	pub api_version: u32,
+	/// The libraries dependent recipes can use through the package's value, with their Starlark type
+	pub exports: Vec<(String, String)>,
+	/// The package's options after overrides, as seen by its recipe
+	pub package_options: HashMap<String, PkgOpt>,
+	/// Groups declared with `source_group()`, used to lay out sources in IDEs
+	pub source_groups: Vec<SourceGroup>,
+	/// Settings for the `format` and `tidy` targets, from `lint_options()`
+	pub lint: Option<LintOptions>,
+	/// What `catapult package-binary` installs, from `install()`
+	pub installs: Vec<InstallRule>,
+	/// The package's `catapult.toml`, `build.catapult` and the files its recipes load, read while configuring
+	pub recipe_files: Vec<PathBuf>,
+	/// The hash the registry recorded for the package's source archive, if it was fetched from one, which pins its
+	/// content
+	pub registry_hash: Option<String>,
+}
+
+/// Descriptive fields of a package's `catapult.toml`, none of which affect the build
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Allocative)]
+pub struct PackageMetadata {
+	/// An SPDX license expression, e.g. "MIT OR Apache-2.0"
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub license: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub description: Option<String>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub authors: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub repository: Option<String>,
+}
+
+/// A named group of source files, shown as a folder in IDEs.
+/// Nested groups are separated with `/`, e.g. `crypto/aes`.
+#[derive(Clone, Debug, Allocative)]
+pub struct SourceGroup {
+	pub name: String,
+	pub sources: Vec<PathBuf>,
+}
+
+/// Targets and files to install into a component, from one call to `install()`.
+/// Without a component or destination, they're chosen from the kind of each target.
+#[derive(Clone, Debug, Allocative)]
+pub struct InstallRule {
+	pub targets: Vec<String>,
+	pub files: Vec<PathBuf>,
+	pub component: Option<String>,
+	pub destination: Option<String>,
+}
+
+/// Which files the `format`, `format-check` and `tidy` targets check, and with which config files.
+/// Without file lists, the package's C and C++ sources are checked.
+#[derive(Clone, Debug, Allocative)]
+pub struct LintOptions {
+	pub format: bool,
+	pub tidy: bool,
+	pub format_sources: Option<Vec<PathBuf>>,
+	pub tidy_sources: Option<Vec<PathBuf>>,
+	pub clang_format_config: Option<PathBuf>,
+	pub clang_tidy_config: Option<PathBuf>,
+}
+
+impl Default for LintOptions {
+	fn default() -> Self {
+		LintOptions {
+			format: true,
+			tidy: true,
+			format_sources: None,
+			tidy_sources: None,
+			clang_format_config: None,
+			clang_tidy_config: None,
+		}
+	}
 }
 
 #[derive(Debug)]
@@ -24,4 +108,23 @@ pub struct Project {
 	pub static_libraries: Vec<Arc<StaticLibrary>>,
 	pub object_libraries: Vec<Arc<ObjectLibrary>>,
 	pub interface_libraries: Vec<Arc<InterfaceLibrary>>,
+	pub docs: Vec<Arc<Docs>>,
+}
+
+impl Project {
+	/// This package and its dependencies, each once. Dependencies are listed before their dependents.
+	pub fn packages(&self) -> Vec<&Project> {
+		fn collect<'a>(project: &'a Project, visited: &mut BTreeSet<String>, out: &mut Vec<&'a Project>) {
+			if !visited.insert(project.info.name.clone()) {
+				return;
+			}
+			for dep in &project.dependencies {
+				collect(dep, visited, out);
+			}
+			out.push(project);
+		}
+		let mut out = Vec::new();
+		collect(self, &mut BTreeSet::new(), &mut out);
+		out
+	}
 }
@@ -0,0 +1,165 @@
+//! `catapult publish`: packs the sources of the package in a directory and writes them, with a record, to a registry
+//! or a directory in the registry layout, as `catapult mirror` does for the packages it copies
+
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use base64::Engine;
+use flate2::{write::GzEncoder, Compression};
+use reqwest::StatusCode;
+
+use crate::{
+	cache, config,
+	mirror::{self, Destination},
+	network::{NetworkConfig, RegistryClient},
+	read_manifest, BUILD_CATAPULT, CATAPULT_TOML,
+};
+
+pub struct PublishedPackage {
+	pub name: String,
+	pub version: String,
+	pub channel: String,
+	/// Size of the source archive in bytes
+	pub source_size: u64,
+}
+
+/// Publishes the package in `src_dir` to `channel` of `dest`, a directory or the URL of a registry. For a directory,
+/// `base_url` is the URL it will be served at. A version that's already published isn't replaced.
+pub fn publish(src_dir: &Path, dest: &str, channel: &str, base_url: Option<&str>) -> Result<PublishedPackage, String> {
+	let (dest, base_url) = mirror::destination(dest, base_url)?;
+	let manifest = read_manifest(src_dir).map_err(|e| e.to_string())?;
+	let name = manifest.package.name;
+	let version = match manifest.package.version {
+		Some(x) => x,
+		None => return Err(format!("{} has no version. Published packages need one", CATAPULT_TOML)),
+	};
+	for (dep_name, dep) in manifest.dependencies.unwrap_or_default() {
+		if dep.path.is_some() || dep.git.is_some() {
+			return Err(format!(
+				"Dependency \"{}\" is a path or git dependency, which dependents of a published package can't fetch",
+				dep_name
+			));
+		}
+	}
+	let client = NetworkConfig::from_env()
+		.and_then(|x| RegistryClient::new(&x))
+		.map_err(|e| e.to_string())?;
+	let record_path = format!("get/{}/{}/{}", name, version, channel);
+	if is_published(&client, &dest, &record_path)? {
+		return Err(format!("{} {} is already published to channel \"{}\"", name, version, channel));
+	}
+
+	let source_path = format!("sources/{}/{}/{}/{}-{}.tar.gz", name, version, channel, name, version);
+	let source_url = base_url
+		.join(&source_path)
+		.map_err(|e| format!("Invalid source URL for {}: {}", name, e))?;
+	let manifest_path = src_dir.join(CATAPULT_TOML);
+	let mut manifest = match fs::read_to_string(&manifest_path).map(|x| toml::from_str::<toml::Table>(&x)) {
+		Ok(Ok(x)) => x,
+		Ok(Err(e)) => return Err(format!("Error reading {}: {}", manifest_path.display(), e)),
+		Err(e) => return Err(format!("Error reading {}: {}", manifest_path.display(), e)),
+	};
+	if let Some(package) = manifest.get_mut("package").and_then(|x| x.as_table_mut()) {
+		package.insert("source".to_owned(), toml::Value::String(source_url.to_string()));
+	}
+	let manifest = toml::to_string(&manifest).map_err(|e| e.to_string())?;
+	let recipe_path = src_dir.join(BUILD_CATAPULT);
+	let recipe = fs::read(&recipe_path).map_err(|e| format!("Error reading {}: {}", recipe_path.display(), e))?;
+
+	let source = source_archive(src_dir).map_err(|e| format!("Error packing {}: {}", src_dir.display(), e))?;
+	let engine = base64::engine::general_purpose::STANDARD_NO_PAD;
+	let record = serde_json::json!({
+		"hash": cache::sha256_hex(&source),
+		"manifest": engine.encode(manifest),
+		"recipe": engine.encode(recipe),
+	});
+	let record = serde_json::to_vec(&record).map_err(|e| e.to_string())?;
+
+	let source_size = source.len() as u64;
+	// The record is written last, so the version isn't visible until its source is
+	mirror::put(&client, &dest, &source_path, source)?;
+	mirror::put(&client, &dest, &record_path, record)?;
+	Ok(PublishedPackage { name, version, channel: channel.to_owned(), source_size })
+}
+
+/// Whether the registry has a record at `record_path`. Only a 404 means it doesn't; any other failure is an error, so
+/// that an unreachable or misbehaving registry isn't taken for one without the version.
+fn is_published(client: &RegistryClient, dest: &Destination, record_path: &str) -> Result<bool, String> {
+	match dest {
+		Destination::Dir(dir) => Ok(dir.join(record_path).exists()),
+		Destination::Registry(url) => {
+			let url = url
+				.join(record_path)
+				.map_err(|e| format!("Invalid URL for {}: {}", record_path, e))?;
+			let resp = match client.registry_get(url.clone()).send() {
+				Ok(x) => x,
+				Err(e) => return Err(format!("Error fetching {}: {}", url, e)),
+			};
+			match resp.status() {
+				x if x.is_success() => Ok(true),
+				StatusCode::NOT_FOUND => Ok(false),
+				x => Err(format!("Request GET \"{}\" returned status {}", url, x)),
+			}
+		}
+	}
+}
+
+/// A .tar.gz of the files in `src_dir`, which dependents unpack in place of the package's directory. Hidden files
+/// and directories, such as `.git`, and build directories are left out.
+fn source_archive(src_dir: &Path) -> io::Result<Vec<u8>> {
+	let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+	for rel in source_files(src_dir)? {
+		let name = rel.iter().map(|x| x.to_string_lossy()).collect::<Vec<_>>().join("/");
+		builder.append_path_with_name(src_dir.join(&rel), name)?;
+	}
+	builder.into_inner()?.finish()
+}
+
+/// Files in `dir`, recursively, relative to `dir` and sorted so the archive lists them in a stable order
+fn source_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+	let mut files = Vec::new();
+	let mut stack = vec![PathBuf::new()];
+	while let Some(rel) = stack.pop() {
+		for entry in fs::read_dir(dir.join(&rel))? {
+			let entry = entry?;
+			if entry.file_name().to_string_lossy().starts_with('.') {
+				continue;
+			}
+			let path = rel.join(entry.file_name());
+			if !entry.file_type()?.is_dir() {
+				files.push(path);
+			} else if !entry.path().join(config::BUILD_DIR_SETTINGS).exists() {
+				stack.push(path);
+			}
+		}
+	}
+	files.sort();
+	Ok(files)
+}
+
+#[test]
+fn test_source_files() {
+	let dir = std::env::temp_dir().join(format!("catapult-source-files-{}", uuid::Uuid::new_v4()));
+	for sub in ["src/b", "src/a", ".git", "build"] {
+		fs::create_dir_all(dir.join(sub)).unwrap();
+	}
+	for file in [
+		"src/b/z.c",
+		"src/a/y.c",
+		"src/x.c",
+		"catapult.toml",
+		".hidden",
+		".git/HEAD",
+		"build/out.o",
+	] {
+		fs::write(dir.join(file), "").unwrap();
+	}
+	fs::write(dir.join("build").join(config::BUILD_DIR_SETTINGS), "").unwrap();
+
+	let files = source_files(&dir).unwrap();
+	let _ = fs::remove_dir_all(&dir);
+	let expected = ["catapult.toml", "src/a/y.c", "src/b/z.c", "src/x.c"];
+	assert_eq!(files, expected.iter().map(PathBuf::from).collect::<Vec<_>>());
+}
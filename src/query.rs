@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+	generator::{self, layout, Generator, Language, Reproducible, TargetFlags},
+	paths,
+	project::Project,
+	target::{LinkTarget, Target},
+	toolchain::{compiler::Compiler, Toolchain},
+	GlobalOptions,
+};
+
+/// A target with the settings it's built with resolved, for tools that inspect a project without reading the
+/// generated build files. Values set by `generator_vars` aren't included, since generators evaluate them per target.
+#[derive(Clone, Debug)]
+pub struct ResolvedTarget {
+	pub package: String,
+	pub name: String,
+	/// "executable", "static_library", "object_library" or "interface_library"
+	pub kind: &'static str,
+	pub sources: Vec<PathBuf>,
+	/// The target's include directories, followed by those of the libraries it links
+	pub include_dirs: Vec<PathBuf>,
+	pub defines: Vec<String>,
+	/// The flags C sources are compiled with, as the Ninja generator assembles them: the profile's, those of the
+	/// project options such as `c_standard` and those of the interface libraries the target links. Empty if the
+	/// toolchain has no C compiler.
+	pub c_compile_flags: Vec<String>,
+	pub cpp_compile_flags: Vec<String>,
+	/// The flags an executable is linked with, followed by the profile's `link_libraries`, or those a library passes
+	/// on to the executables that link it
+	pub link_flags: Vec<String>,
+	/// The executable or static library the target builds, or None for object and interface libraries
+	pub output_path: Option<PathBuf>,
+}
+
+/// Every target of `project` and its dependencies, as `generator` builds them into `build_dir` with `profile`.
+/// Dependencies are listed before their dependents.
+pub fn resolve_targets(
+	project: &Project,
+	generator: &Generator,
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+	build_dir: &Path,
+	global_opts: &GlobalOptions,
+) -> Result<Vec<ResolvedTarget>, String> {
+	let profile_flags = match profile {
+		Some(name) => toolchain.get_profile(name)?.clone(),
+		None => Default::default(),
+	};
+	let reproducible = Reproducible::new(project, global_opts, build_dir)?;
+	let flags = TargetFlags {
+		toolchain,
+		profile: &profile_flags,
+		global_opts,
+		reproducible: reproducible.as_ref(),
+	};
+	// The C and C++ flags, or none for a language the toolchain has no compiler for
+	let compile_flags = |interface_flags: &[String], name: &str, executable: bool| {
+		let language_flags = |compiler: Option<&dyn Compiler>, language| match compiler {
+			Some(x) => flags.compile_flags(x, language, name, interface_flags, executable),
+			None => Ok(Vec::new()),
+		};
+		Ok::<_, String>((
+			language_flags(toolchain.c_compiler.as_deref(), Language::C)?,
+			language_flags(toolchain.cpp_compiler.as_deref(), Language::Cpp)?,
+		))
+	};
+	let platform = generator.target_platform(toolchain);
+	let mut out = Vec::new();
+	for package in project.packages() {
+		let package_name = &package.info.name;
+		let output_dir = layout::output_dir(
+			generator,
+			build_dir,
+			&global_opts.output_layout.package_dir(&package.info, &project.info.name),
+			toolchain,
+			profile,
+		)?;
+		for exe in &package.executables {
+			let (c_compile_flags, cpp_compile_flags) =
+				compile_flags(&generator::interface_compile_flags(&exe.links), &exe.name, true)?;
+			let coverage_dir = build_dir.join("coverage");
+			let mut link_flags = flags.exe_link_flags(&exe.name, &paths::to_build_string(&coverage_dir))?;
+			link_flags.extend(profile_flags.link_flags(toolchain.platform()));
+			link_flags.extend(exe.link_flags_recursive());
+			let mut link_flags = generator::normalize_link_flags(link_flags);
+			link_flags.extend(flags.link_libraries());
+			let mut defines = exe.public_defines_recursive();
+			defines.extend(flags.defines());
+			out.push(ResolvedTarget {
+				package: package_name.clone(),
+				name: exe.name.clone(),
+				kind: "executable",
+				sources: exe.sources.iter().map(|x| x.full.clone()).collect(),
+				include_dirs: exe.public_includes_recursive(),
+				defines,
+				c_compile_flags,
+				cpp_compile_flags,
+				link_flags,
+				output_path: Some(output_dir.join(exe.name.clone() + &platform.exe_ext)),
+			});
+		}
+		for lib in &package.static_libraries {
+			let links = lib
+				.link_private
+				.iter()
+				.chain(&lib.link_public)
+				.cloned()
+				.collect::<Vec<_>>();
+			let (c_compile_flags, cpp_compile_flags) =
+				compile_flags(&generator::interface_compile_flags(&links), &lib.name, false)?;
+			let mut include_dirs = lib.public_includes_recursive();
+			include_dirs.extend(lib.private_includes());
+			let mut defines = lib.public_defines_recursive();
+			defines.extend_from_slice(lib.private_defines());
+			defines.extend(flags.defines());
+			out.push(ResolvedTarget {
+				package: package_name.clone(),
+				name: lib.name.clone(),
+				kind: "static_library",
+				sources: lib.sources.iter().map(|x| x.full.clone()).collect(),
+				include_dirs,
+				defines,
+				c_compile_flags,
+				cpp_compile_flags,
+				link_flags: lib.public_link_flags_recursive(),
+				output_path: Some(output_dir.join(lib.output_name().to_owned() + &platform.static_lib_ext)),
+			});
+		}
+		for lib in &package.object_libraries {
+			let links = lib
+				.link_private
+				.iter()
+				.chain(&lib.link_public)
+				.cloned()
+				.collect::<Vec<_>>();
+			let (c_compile_flags, cpp_compile_flags) =
+				compile_flags(&generator::interface_compile_flags(&links), &lib.name, false)?;
+			let mut include_dirs = lib.public_includes_recursive();
+			include_dirs.extend(lib.private_includes());
+			let mut defines = lib.public_defines_recursive();
+			defines.extend_from_slice(lib.private_defines());
+			defines.extend(flags.defines());
+			out.push(ResolvedTarget {
+				package: package_name.clone(),
+				name: lib.name.clone(),
+				kind: "object_library",
+				sources: lib.sources.iter().map(|x| x.full.clone()).collect(),
+				include_dirs,
+				defines,
+				c_compile_flags,
+				cpp_compile_flags,
+				link_flags: lib.public_link_flags_recursive(),
+				output_path: None,
+			});
+		}
+		for lib in &package.interface_libraries {
+			out.push(ResolvedTarget {
+				package: package_name.clone(),
+				name: lib.name.clone(),
+				kind: "interface_library",
+				sources: Vec::new(),
+				include_dirs: lib.public_includes_recursive(),
+				defines: lib.public_defines_recursive(),
+				c_compile_flags: lib.compile_flags.clone(),
+				cpp_compile_flags: lib.compile_flags.clone(),
+				link_flags: lib.public_link_flags_recursive(),
+				output_path: None,
+			});
+		}
+	}
+	Ok(out)
+}
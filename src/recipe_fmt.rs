@@ -0,0 +1,413 @@
+//! `catapult fmt`: rewrites recipes in one layout, in the style of buildifier.
+//! The AST drops comments, so the recipe is parsed to check it, then its tokens are re-printed.
+
+use starlark::syntax::AstModule;
+use starlark_syntax::{
+	codemap::CodeMap,
+	lexer::{Lexer, Token},
+};
+
+use crate::{diagnostic::Diagnostic, recipe_dialect};
+
+const INDENT: &str = "    ";
+
+struct Leaf {
+	token: Token,
+	start: usize,
+	end: usize,
+}
+
+struct Group {
+	open: Leaf,
+	children: Vec<Node>,
+	close: Leaf,
+}
+
+enum Node {
+	Leaf(Leaf),
+	Group(Group),
+}
+
+impl Node {
+	fn start(&self) -> usize {
+		match self {
+			Node::Leaf(x) => x.start,
+			Node::Group(x) => x.open.start,
+		}
+	}
+
+	fn end(&self) -> usize {
+		match self {
+			Node::Leaf(x) => x.end,
+			Node::Group(x) => x.close.end,
+		}
+	}
+
+	fn token(&self) -> Option<&Token> {
+		match self {
+			Node::Leaf(x) => Some(&x.token),
+			Node::Group(_) => None,
+		}
+	}
+
+	/// Whether the node can end an operand, so a following `(` or `[` is a call or an index
+	/// and a following `-` or `*` is binary
+	fn ends_operand(&self) -> bool {
+		matches!(
+			self.token(),
+			None | Some(Token::Identifier(_) | Token::Int(_) | Token::Float(_) | Token::String(_) | Token::FString(_))
+		)
+	}
+}
+
+/// Where a sequence of nodes is, which decides the spacing around `:`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Context {
+	Statement,
+	Call,
+	Paren,
+	List,
+	Subscript,
+	Dict,
+}
+
+/// A statement, or a comment on a line of its own
+enum Line {
+	Code(Vec<Node>),
+	Comment(Leaf),
+}
+
+struct Printer<'a> {
+	source: &'a str,
+}
+
+impl Printer<'_> {
+	fn text(&self, leaf: &Leaf) -> &str {
+		&self.source[leaf.start..leaf.end]
+	}
+
+	fn column(&self, offset: usize) -> usize {
+		offset - self.source[..offset].rfind('\n').map_or(0, |x| x + 1)
+	}
+
+	fn spans_lines(&self, start: usize, end: usize) -> bool {
+		self.source[start..end].contains('\n')
+	}
+
+	fn leaf(&self, leaf: &Leaf) -> String {
+		match leaf.token {
+			Token::String(_) | Token::FString(_) => double_quoted(self.text(leaf)),
+			Token::Comment(_) => self.text(leaf).trim_end().to_owned(),
+			_ => self.text(leaf).to_owned(),
+		}
+	}
+
+	/// Nodes on one line, separated by the spaces buildifier would put between them
+	fn inline(&self, nodes: &[&Node], indent: usize, context: Context) -> String {
+		let mut ret = String::new();
+		for (i, node) in nodes.iter().enumerate() {
+			let prev = i.checked_sub(1).map(|i| nodes[i]);
+			let prev_prev = i.checked_sub(2).map(|i| nodes[i]);
+			if let Some(prev) = prev {
+				if matches!(node.token(), Some(Token::Comment(_))) {
+					ret += "  ";
+				} else if space_between(prev, prev_prev, node, context) {
+					ret += " ";
+				}
+			}
+			match node {
+				Node::Leaf(x) => ret += &self.leaf(x),
+				Node::Group(x) => ret += &self.group(x, prev, indent),
+			}
+		}
+		ret
+	}
+
+	fn group(&self, group: &Group, prev: Option<&Node>, indent: usize) -> String {
+		let after_operand = prev.is_some_and(|x| x.ends_operand());
+		let context = match (&group.open.token, after_operand) {
+			(Token::OpeningRound, true) => Context::Call,
+			(Token::OpeningRound, false) => Context::Paren,
+			(Token::OpeningSquare, true) => Context::Subscript,
+			(Token::OpeningSquare, false) => Context::List,
+			_ => Context::Dict,
+		};
+		let open = self.text(&group.open);
+		let close = self.text(&group.close);
+		if !self.spans_lines(group.open.start, group.close.end) {
+			let children = group.children.iter().collect::<Vec<_>>();
+			return format!("{}{}{}", open, self.inline(&children, indent, context), close);
+		}
+		if group.children.is_empty() {
+			return format!("{}{}", open, close);
+		}
+
+		// Multi-line groups get one element per line. A trailing comma is added where it can't change the meaning:
+		// to lists, dicts and calls, and to tuples, which already have commas.
+		let has_comma = group.children.iter().any(|x| matches!(x.token(), Some(Token::Comma)));
+		let is_comprehension = group.children.iter().any(|x| matches!(x.token(), Some(Token::For)));
+		let add_comma =
+			has_comma || (matches!(context, Context::Call | Context::List | Context::Dict) && !is_comprehension);
+
+		let mut head_comment = None;
+		let mut lines = Vec::new();
+		let mut item = Vec::new();
+		let mut item_comment: Option<String> = None;
+		let mut prev_end = group.open.end;
+		for child in &group.children {
+			match child.token() {
+				Some(Token::Comma) => {
+					let mut line = self.inline(&item, indent + 1, context) + ",";
+					if let Some(comment) = item_comment.take() {
+						line += &format!("  {}", comment);
+					}
+					lines.push(line);
+					item.clear();
+				}
+				Some(Token::Comment(_)) => {
+					let Node::Leaf(leaf) = child else { unreachable!() };
+					let comment = self.leaf(leaf);
+					let trailing = !self.spans_lines(prev_end, leaf.start);
+					if let Some(pending) = item_comment.take() {
+						lines.push(format!("{}  {}", self.inline(&item, indent + 1, context), pending));
+						item.clear();
+					}
+					if trailing && !item.is_empty() {
+						item_comment = Some(comment);
+					} else if trailing && lines.is_empty() {
+						head_comment = Some(comment);
+					} else if trailing {
+						let last: &mut String = lines.last_mut().unwrap();
+						*last += &format!("  {}", comment);
+					} else {
+						if !item.is_empty() {
+							lines.push(self.inline(&item, indent + 1, context));
+							item.clear();
+						}
+						lines.push(comment);
+					}
+				}
+				_ => {
+					// The comment was on a line in the middle of an element, which carries on on the next line
+					if let Some(pending) = item_comment.take() {
+						lines.push(format!("{}  {}", self.inline(&item, indent + 1, context), pending));
+						item.clear();
+					}
+					item.push(child);
+				}
+			}
+			prev_end = child.end();
+		}
+		if !item.is_empty() {
+			let mut line = self.inline(&item, indent + 1, context);
+			if add_comma {
+				line += ",";
+			}
+			if let Some(comment) = item_comment.take() {
+				line += &format!("  {}", comment);
+			}
+			lines.push(line);
+		}
+
+		let mut ret = open.to_owned();
+		if let Some(comment) = head_comment {
+			ret += &format!("  {}", comment);
+		}
+		for line in lines {
+			ret += &format!("\n{}{}", INDENT.repeat(indent + 1), line);
+		}
+		ret += &format!("\n{}{}", INDENT.repeat(indent), close);
+		ret
+	}
+}
+
+fn is_unary_operator(node: &Node) -> bool {
+	matches!(node.token(), Some(Token::Minus | Token::Plus | Token::Tilde | Token::Star | Token::StarStar))
+}
+
+fn space_between(prev: &Node, prev_prev: Option<&Node>, node: &Node, context: Context) -> bool {
+	if matches!(node.token(), Some(Token::Comma | Token::Colon | Token::Semicolon | Token::Dot))
+		|| matches!(prev.token(), Some(Token::Dot))
+	{
+		return false;
+	}
+	if matches!(prev.token(), Some(Token::Colon)) {
+		// Slices are written `x[1:2]`
+		return context != Context::Subscript;
+	}
+	if matches!(node, Node::Group(g) if g.open.token != Token::OpeningCurly)
+		&& (prev.ends_operand() || matches!(prev.token(), Some(Token::Load)))
+	{
+		return false;
+	}
+	if is_unary_operator(prev) && !prev_prev.is_some_and(|x| x.ends_operand()) {
+		return false;
+	}
+	true
+}
+
+/// Single-quoted strings without quotes or escapes inside are rewritten with double quotes
+fn double_quoted(text: &str) -> String {
+	let prefix_len = text.find(['\'', '"']).unwrap_or(0);
+	let (prefix, literal) = text.split_at(prefix_len);
+	let quote = if literal.starts_with("'''") { "'''" } else { "'" };
+	if !literal.starts_with(quote) || literal.len() < 2 * quote.len() {
+		return text.to_owned();
+	}
+	let content = &literal[quote.len()..literal.len() - quote.len()];
+	if content.contains(['"', '\\']) {
+		return text.to_owned();
+	}
+	let new_quote = if quote == "'''" { "\"\"\"" } else { "\"" };
+	format!("{}{}{}{}", prefix, new_quote, content, new_quote)
+}
+
+fn parse_lines(filename: &str, source: &str) -> Result<Vec<Line>, anyhow::Error> {
+	let dialect = recipe_dialect();
+	let codemap = CodeMap::new(filename.to_owned(), source.to_owned());
+	let mut lines = Vec::new();
+	let mut statement = Vec::new();
+	// The open brackets, each with the nodes inside it so far
+	let mut groups: Vec<(Leaf, Vec<Node>)> = Vec::new();
+	for lexeme in Lexer::new(source, &dialect, codemap) {
+		let (start, token, end) = lexeme.map_err(|e| Diagnostic::new(e.to_string()))?;
+		let leaf = Leaf { token, start, end };
+		match leaf.token {
+			Token::Indent | Token::Dedent => {}
+			Token::Newline => {
+				if !statement.is_empty() {
+					lines.push(Line::Code(std::mem::take(&mut statement)));
+				}
+			}
+			Token::Comment(_) if groups.is_empty() && statement.is_empty() => lines.push(Line::Comment(leaf)),
+			Token::OpeningRound | Token::OpeningSquare | Token::OpeningCurly => groups.push((leaf, Vec::new())),
+			Token::ClosingRound | Token::ClosingSquare | Token::ClosingCurly => {
+				let (open, children) = groups.pop().ok_or_else(|| Diagnostic::new("Unbalanced brackets"))?;
+				let node = Node::Group(Group { open, children, close: leaf });
+				match groups.last_mut() {
+					Some((_, parent)) => parent.push(node),
+					None => statement.push(node),
+				}
+			}
+			_ => match groups.last_mut() {
+				Some((_, parent)) => parent.push(Node::Leaf(leaf)),
+				None => statement.push(Node::Leaf(leaf)),
+			},
+		}
+	}
+	if !statement.is_empty() {
+		lines.push(Line::Code(statement));
+	}
+	Ok(lines)
+}
+
+/// `source` in the canonical layout. `filename` is only used in error messages.
+pub fn format_recipe(filename: &str, source: &str) -> Result<String, anyhow::Error> {
+	let dialect = recipe_dialect();
+	if let Err(e) = AstModule::parse(filename, source.to_owned(), &dialect) {
+		return Err(Diagnostic::from_starlark(&e).into());
+	}
+
+	let printer = Printer { source };
+	let mut out = String::new();
+	// The source columns of the open blocks, as the lexer tracks them
+	let mut blocks = vec![0];
+	let mut comments: Vec<Leaf> = Vec::new();
+	let mut prev_end: Option<usize> = None;
+	let mut emit = |out: &mut String, start: usize, end: usize, indent: usize, text: &str| {
+		// Runs of blank lines are kept as one
+		if prev_end.is_some_and(|x| source[x..start].matches('\n').count() > 1) {
+			out.push('\n');
+		}
+		*out += &format!("{}{}\n", INDENT.repeat(indent), text);
+		prev_end = Some(end);
+	};
+	for line in parse_lines(filename, source)? {
+		let nodes = match line {
+			Line::Comment(leaf) => {
+				comments.push(leaf);
+				continue;
+			}
+			Line::Code(x) => x,
+		};
+		let prev_blocks = blocks.clone();
+		let column = printer.column(nodes[0].start());
+		while blocks.last().is_some_and(|x| *x > column) {
+			blocks.pop();
+		}
+		if blocks.last().is_some_and(|x| *x < column) {
+			blocks.push(column);
+		}
+		// A comment before a statement is indented like the deepest block it's within, either the one the statement
+		// is in or a block that the statement ends
+		let comment_blocks = if prev_blocks.len() > blocks.len() {
+			&prev_blocks
+		} else {
+			&blocks
+		};
+		for comment in comments.drain(..) {
+			let column = printer.column(comment.start);
+			let indent = comment_blocks
+				.iter()
+				.filter(|x| **x <= column)
+				.count()
+				.saturating_sub(1);
+			emit(&mut out, comment.start, comment.end, indent, &printer.leaf(&comment));
+		}
+		let indent = blocks.len() - 1;
+		let text = printer.inline(&nodes.iter().collect::<Vec<_>>(), indent, Context::Statement);
+		emit(&mut out, nodes[0].start(), nodes[nodes.len() - 1].end(), indent, &text);
+	}
+	for comment in comments.drain(..) {
+		let column = printer.column(comment.start);
+		let indent = blocks.iter().filter(|x| **x <= column).count().saturating_sub(1);
+		emit(&mut out, comment.start, comment.end, indent, &printer.leaf(&comment));
+	}
+
+	// The formatter only moves whitespace, commas and quotes, so anything that no longer parses is a bug here
+	if let Err(e) = AstModule::parse(filename, out.clone(), &dialect) {
+		return Err(Diagnostic::new(format!("Formatting {} produced invalid code", filename))
+			.with_hint(e.to_string())
+			.into());
+	}
+	Ok(out)
+}
+
+#[test]
+fn test_format_recipe() {
+	let source = r#"
+# A comment
+load('//tools.star', 'helper')
+mylib = add_static_library(name='mylib', sources = ['a.cpp',
+  "b.cpp"],   # trailing
+    include_dirs_public = [ "." ])
+
+
+def helper(x, *args, **kwargs):
+  if x[1:2] == -1:   # check
+    return {'a':x}
+  # end of body
+  return [y for y in args]
+"#;
+	let expected = r#"# A comment
+load("//tools.star", "helper")
+mylib = add_static_library(
+    name = "mylib",
+    sources = [
+        "a.cpp",
+        "b.cpp",
+    ],  # trailing
+    include_dirs_public = ["."],
+)
+
+def helper(x, *args, **kwargs):
+    if x[1:2] == -1:  # check
+        return {"a": x}
+    # end of body
+    return [y for y in args]
+"#;
+	let formatted = format_recipe("build.catapult", source).unwrap();
+	assert_eq!(formatted, expected);
+	assert_eq!(format_recipe("build.catapult", &formatted).unwrap(), formatted);
+	assert!(format_recipe("build.catapult", "add_executable(").is_err());
+}
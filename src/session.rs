@@ -0,0 +1,309 @@
+use std::{
+	collections::BTreeMap,
+	env, fs,
+	path::{Path, PathBuf},
+	process::Command,
+	sync::Arc,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+	config::{self, BuildDirSettings},
+	generator::{Generator, OutputLayout, PathStyle, Regeneration},
+	project::Project,
+	query::{self, ResolvedTarget},
+	summary::Resolution,
+	toolchain::{self, Toolchain},
+	GlobalOptions,
+};
+
+/// Configures a build directory the way `catapult configure` does, for tools that embed Catapult, e.g.
+/// ```no_run
+/// use catapult::{generator::Generator, Session};
+///
+/// let configured = Session::new("src", "build", "toolchain.toml", Generator::Ninja)
+///     .profile("Release")
+///     .package_option("zstd", "multithread", "true")
+///     .generate()?;
+/// println!("{} has {} executables", configured.project.info.name, configured.project.executables.len());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+/// Settings that aren't given fall back to the config files of the source directory, like on the command line. The
+/// build directory is configured with the same settings when a file read while configuring changes, and later
+/// commands on it can read them with `BuildDirSettings::read()`.
+///
+/// Recipes are evaluated with the source directory as the current directory, so `parse()` and `generate()` change
+/// the process's current directory while they run and sessions shouldn't run on several threads at once.
+pub struct Session {
+	source_dir: PathBuf,
+	build_dir: PathBuf,
+	toolchain_path: PathBuf,
+	generator: Generator,
+	profile: Option<String>,
+	package_options: BTreeMap<String, BTreeMap<String, String>>,
+	allow_unknown_options: bool,
+	path_style: PathStyle,
+	output_layout: Option<OutputLayout>,
+	artifact_cache: Option<PathBuf>,
+	benchmarks: bool,
+}
+
+/// A parsed project and what was resolved to parse it
+pub struct Configured {
+	pub project: Arc<Project>,
+	pub global_options: GlobalOptions,
+	/// The dependencies and the value of every package option
+	pub resolution: Resolution,
+}
+
+impl Session {
+	/// Relative paths are relative to the current directory
+	pub fn new(
+		source_dir: impl Into<PathBuf>,
+		build_dir: impl Into<PathBuf>,
+		toolchain_path: impl Into<PathBuf>,
+		generator: Generator,
+	) -> Session {
+		Session {
+			source_dir: source_dir.into(),
+			build_dir: build_dir.into(),
+			toolchain_path: toolchain_path.into(),
+			generator,
+			profile: None,
+			package_options: BTreeMap::new(),
+			allow_unknown_options: false,
+			path_style: PathStyle::Absolute,
+			output_layout: None,
+			artifact_cache: None,
+			benchmarks: false,
+		}
+	}
+
+	/// Selects a profile of the toolchain. The MSVC generator generates every profile, so it only uses this to build.
+	pub fn profile(mut self, name: &str) -> Session {
+		self.profile = Some(name.to_owned());
+		self
+	}
+
+	/// Sets option `name` of package `package`, like `--package-option package:name=value`
+	pub fn package_option(mut self, package: &str, name: &str, value: &str) -> Session {
+		self.package_options
+			.entry(package.to_owned())
+			.or_default()
+			.insert(name.to_owned(), value.to_owned());
+		self
+	}
+
+	/// Ignores package options that no package declares instead of failing
+	pub fn allow_unknown_options(mut self, allow: bool) -> Session {
+		self.allow_unknown_options = allow;
+		self
+	}
+
+	pub fn path_style(mut self, path_style: PathStyle) -> Session {
+		self.path_style = path_style;
+		self
+	}
+
+	pub fn output_layout(mut self, output_layout: OutputLayout) -> Session {
+		self.output_layout = Some(output_layout);
+		self
+	}
+
+	/// Like `--artifact-cache`
+	pub fn artifact_cache(mut self, dir: impl Into<PathBuf>) -> Session {
+		self.artifact_cache = Some(dir.into());
+		self
+	}
+
+	/// Generates the benchmarks even if the profile doesn't enable them, as `catapult bench` does. `catapult
+	/// configure` wouldn't generate them, so these build files don't configure the build directory again.
+	pub fn benchmarks(mut self, benchmarks: bool) -> Session {
+		self.benchmarks = benchmarks;
+		self
+	}
+
+	/// Resolves and parses the project and its dependencies without writing to the build directory
+	pub fn parse(&self) -> Result<Configured, anyhow::Error> {
+		let toolchain = self.toolchain()?;
+		self.parse_with(&toolchain)
+	}
+
+	/// Parses the project, then writes the build files to the build directory
+	pub fn generate(&self) -> Result<Configured, anyhow::Error> {
+		let toolchain = self.toolchain()?;
+		let configured = self.parse_with(&toolchain)?;
+		self.write(&configured, toolchain)?;
+		Ok(configured)
+	}
+
+	/// Reads the toolchain file and checks it has the selected profile
+	pub fn toolchain(&self) -> Result<Toolchain, anyhow::Error> {
+		// Relative paths in the toolchain file are relative to it, so they mustn't depend on the current directory
+		let toolchain_path = env::current_dir()?.join(&self.toolchain_path);
+		let toolchain = toolchain::get_toolchain(&toolchain_path, matches!(self.generator, Generator::Msvc))
+			.map_err(|e| anyhow!("Toolchain error: {}", e))?;
+		if let Some(name) = self.selected_profile()? {
+			toolchain.get_profile(&name).map_err(|e| anyhow!(e))?;
+		}
+		Ok(toolchain)
+	}
+
+	/// `parse()` with a toolchain from `toolchain()`, for callers that need it afterwards
+	pub fn parse_with(&self, toolchain: &Toolchain) -> Result<Configured, anyhow::Error> {
+		let settings = self.settings()?;
+		let output_layout = match &settings.layout {
+			Some(x) => OutputLayout::parse(x).map_err(|e| anyhow!(e))?,
+			None => OutputLayout::Flat,
+		};
+		let original_dir = env::current_dir()?;
+		env::set_current_dir(&self.source_dir)
+			.map_err(|e| anyhow!("Error setting cwd: {} (path: {})", e, self.source_dir.display()))?;
+		// The settings have no profile for the MSVC generator, which generates every profile, so recipes don't see one
+		let parsed = crate::parse_project_with_summary(
+			toolchain,
+			settings.profile.as_deref(),
+			settings.package_options,
+			self.allow_unknown_options,
+			output_layout,
+		);
+		env::set_current_dir(&original_dir)?;
+		let (project, global_options, resolution) = parsed?;
+		Ok(Configured { project, global_options, resolution })
+	}
+
+	/// Writes the build files of a project from `parse_with()` to the build directory, creating it if needed, and
+	/// saves the settings there
+	pub fn write(&self, configured: &Configured, toolchain: Toolchain) -> Result<(), anyhow::Error> {
+		let settings = self.settings()?;
+		let profile = match &settings.profile {
+			Some(name) => toolchain.get_profile(name).map_err(|e| anyhow!(e))?.clone(),
+			None => Default::default(),
+		};
+		let build_dir = env::current_dir()?.join(&self.build_dir);
+		fs::create_dir_all(&build_dir)
+			.map_err(|e| anyhow!("Error creating directory: {} (path: {})", e, build_dir.display()))?;
+		let mut global_options = configured.global_options.clone();
+		global_options.artifact_cache = settings.artifact_cache.clone();
+		global_options.benchmarks |= self.benchmarks;
+		if !self.benchmarks {
+			let config = config::load(&self.source_dir).map_err(|e| anyhow!(e))?;
+			let mut inputs = vec![settings.toolchain.clone()];
+			inputs.extend(config.files);
+			global_options.regeneration =
+				Some(Regeneration { command: regenerate_command(&settings, &build_dir), inputs });
+		}
+		self.generator
+			.generate(configured.project.clone(), global_options, &build_dir, toolchain, profile, self.path_style)
+			.map_err(|e| anyhow!(e))?;
+		settings.write(&build_dir).map_err(|e| anyhow!(e))
+	}
+
+	/// The settings `write()` saves to the build directory, with those that weren't given taken from config files
+	pub fn settings(&self) -> Result<BuildDirSettings, anyhow::Error> {
+		let config = config::load(&self.source_dir).map_err(|e| anyhow!(e))?;
+		let mut package_options = config.package_options();
+		for (package, options) in &self.package_options {
+			package_options
+				.entry(package.clone())
+				.or_default()
+				.extend(options.clone());
+		}
+		let current_dir = env::current_dir()?;
+		Ok(BuildDirSettings {
+			source_dir: fs::canonicalize(&self.source_dir)
+				.map_err(|e| anyhow!("Error resolving path: {} (path: {})", e, self.source_dir.display()))?,
+			generator: self.generator.name().to_owned(),
+			toolchain: current_dir.join(&self.toolchain_path),
+			// A profile set in a config file doesn't apply when generating for MSVC
+			profile: match self.generator {
+				Generator::Ninja => self.profile.clone().or(config.profile),
+				Generator::Msvc => None,
+			},
+			package_options,
+			layout: self.output_layout.map(|x| x.name().to_owned()).or(config.layout),
+			relative_paths: self.path_style == PathStyle::Relative,
+			artifact_cache: self
+				.artifact_cache
+				.as_ref()
+				.map(|x| current_dir.join(x))
+				.or(config.artifact_cache),
+			allow_unknown_options: self.allow_unknown_options,
+		})
+	}
+
+	/// The profile to build and resolve targets with: the one the settings configure, or for MSVC, which generates
+	/// every profile, the one given to `profile()`
+	fn selected_profile(&self) -> Result<Option<String>, anyhow::Error> {
+		Ok(match self.generator {
+			Generator::Ninja => self.settings()?.profile,
+			Generator::Msvc => self.profile.clone(),
+		})
+	}
+
+	/// The command that builds `target`, or every target, in a build directory written by `generate()`
+	pub fn build_command(&self, configured: &Configured, target: Option<&str>) -> Result<Command, anyhow::Error> {
+		let toolchain = self.toolchain()?;
+		let profile = self.selected_profile()?;
+		let build_dir = env::current_dir()?.join(&self.build_dir);
+		self.generator
+			.build_command(&build_dir, &configured.project.info.name, target, &toolchain, profile.as_deref())
+			.map_err(|e| anyhow!(e))
+	}
+
+	/// The targets of the project and its dependencies with their settings resolved for the selected profile
+	pub fn targets(&self, configured: &Configured) -> Result<Vec<ResolvedTarget>, anyhow::Error> {
+		let toolchain = self.toolchain()?;
+		let profile = self.selected_profile()?;
+		let build_dir = env::current_dir()?.join(&self.build_dir);
+		query::resolve_targets(
+			&configured.project,
+			&self.generator,
+			&toolchain,
+			profile.as_deref(),
+			&build_dir,
+			&configured.global_options,
+		)
+		.map_err(|e| anyhow!(e))
+	}
+}
+
+/// The command line that configures a build directory again with `settings`, for build files to run when a manifest,
+/// recipe, config file or the toolchain changes
+fn regenerate_command(settings: &BuildDirSettings, build_dir: &Path) -> Vec<String> {
+	let mut command = vec![
+		env::current_exe()
+			.map(|x| x.to_string_lossy().into_owned())
+			.unwrap_or_else(|_| "catapult".to_owned()),
+		"configure".to_owned(),
+		"-q".to_owned(),
+		format!("--source-dir={}", settings.source_dir.display()),
+		format!("--build-dir={}", build_dir.display()),
+		format!("--generator={}", settings.generator),
+		format!("--toolchain={}", settings.toolchain.display()),
+	];
+	if let Some(profile) = &settings.profile {
+		command.push(format!("--profile={}", profile));
+	}
+	for (package, options) in &settings.package_options {
+		for (name, value) in options {
+			command.push(format!("--package-option={}:{}={}", package, name, value));
+		}
+	}
+	for (flag, present) in [
+		("--allow-unknown-options", settings.allow_unknown_options),
+		("--relative-paths", settings.relative_paths),
+	] {
+		if present {
+			command.push(flag.to_owned());
+		}
+	}
+	if let Some(layout) = &settings.layout {
+		command.push(format!("--layout={}", layout));
+	}
+	if let Some(artifact_cache) = &settings.artifact_cache {
+		command.push(format!("--artifact-cache={}", artifact_cache.display()));
+	}
+	command
+}
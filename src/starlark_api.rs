@@ -3,6 +3,7 @@ use std::sync::{Arc, Mutex};
 
 use allocative::Allocative;
 use starlark::{
+	codemap::FileSpan,
 	environment::GlobalsBuilder,
 	eval::{
 		Arguments, //
@@ -11,7 +12,9 @@ use starlark::{
 	},
 	typing::Ty,
 	values::{
+		dict::DictOf,
 		list::UnpackList, //
+		structs::AllocStruct,
 		type_repr::StarlarkTypeRepr,
 		AllocValue,
 		FrozenValue,
@@ -25,20 +28,71 @@ use starlark::{
 };
 
 use crate::{
+	config_header::{ConfigHeader, ConfigValue},
+	diagnostic::{Diagnostic, Location},
+	misc::{join_parent, macro_prefix},
+	project::{InstallRule, LintOptions, SourceGroup},
+	starlark_docs::StarDocs,
 	starlark_executable::{StarExecutable, StarExecutableWrapper},
+	starlark_global::PkgOpt,
 	starlark_interface_library::{StarIfaceLibWrapper, StarIfaceLibrary},
-	starlark_link_target::StarLinkTarget,
+	starlark_link_target::{StarLinkTarget, Visibility},
+	starlark_loader::RecipeContext,
 	starlark_object_library::{StarGeneratorVars, StarObjLibWrapper, StarObjectLibrary},
-	starlark_project::StarProject,
+	starlark_project::{StarAlias, StarAliasTarget, StarProject},
 	starlark_static_library::{StarStaticLibWrapper, StarStaticLibrary},
+	toolchain::compiler::PLATFORMS,
 };
 
 const GEN_PREFIX: &str = "__gen_";
 
+/// The newest recipe API version. Packages select the version they're written for with `api_version` in
+/// `catapult.toml`, and keep the builtins and signatures of that version when later versions change them.
+pub(crate) const LATEST_API_VERSION: u32 = 1;
+
 pub(super) fn err_msg<T>(msg: String) -> Result<T, anyhow::Error> {
 	Err(anyhow::Error::msg(msg))
 }
 
+/// An error raised by a recipe with `fail()`
+#[derive(Clone, Debug)]
+pub(crate) struct ConfigureError {
+	pub package: String,
+	pub location: Option<FileSpan>,
+	pub message: String,
+}
+
+impl fmt::Display for ConfigureError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.location {
+			Some(location) => write!(f, "Error in package \"{}\" at {}: {}", self.package, location, self.message),
+			None => write!(f, "Error in package \"{}\": {}", self.package, self.message),
+		}
+	}
+}
+
+impl std::error::Error for ConfigureError {}
+
+impl ConfigureError {
+	/// Recipe evaluation errors are returned as a `Diagnostic` pointing at the failing expression.
+	/// Errors raised with `fail()` point at the `fail()` call instead of starlark's innermost frame.
+	pub(crate) fn from_eval_error(e: starlark::Error) -> anyhow::Error {
+		if let starlark::ErrorKind::Other(x) = e.kind() {
+			if let Some(configure_error) = x.downcast_ref::<ConfigureError>() {
+				let diagnostic = Diagnostic::new(format!(
+					"Error in package \"{}\": {}",
+					configure_error.package, configure_error.message
+				));
+				return anyhow::Error::new(match &configure_error.location {
+					Some(location) => diagnostic.with_location(Location::from_file_span(location)),
+					None => diagnostic,
+				});
+			}
+		}
+		anyhow::Error::new(Diagnostic::from_starlark(&e))
+	}
+}
+
 #[derive(Debug, Clone, ProvidesStaticType, NoSerialize, Allocative)]
 pub struct Context {
 	pub compiler_id: String,
@@ -72,10 +126,80 @@ impl<'v> StarlarkValue<'v> for Context {
 	}
 }
 
+/// Returned by `add_*()` for a target that isn't enabled for the platform being built for
+#[derive(Debug, Clone, ProvidesStaticType, NoSerialize, Allocative)]
+struct DisabledTarget {
+	name: String,
+}
+impl fmt::Display for DisabledTarget {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "DisabledTarget(\"{}\")", self.name)
+	}
+}
+impl<'v> AllocValue<'v> for DisabledTarget {
+	fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+		heap.alloc_simple(self)
+	}
+}
+
+#[starlark::values::starlark_value(type = "DisabledTarget")]
+impl<'v> StarlarkValue<'v> for DisabledTarget {
+	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
+		match attribute {
+			"name" => Some(heap.alloc(self.name.clone())),
+			_ => None,
+		}
+	}
+	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
+		attribute == "name"
+	}
+
+	fn dir_attr(&self) -> Vec<String> {
+		vec!["name".to_owned()]
+	}
+}
+
+/// The quoted name of a disabled target, for the messages that report it being skipped like `is_enabled` does
+fn disabled_name(value: Value) -> String {
+	match starlark::values::ValueLike::downcast_ref::<DisabledTarget>(value) {
+		Some(x) => format!("\"{}\"", x.name),
+		None => value.to_str(),
+	}
+}
+
+/// The targets, as (package, name), that a target `depends` on
+fn get_depends(depends: Vec<Value>) -> Result<Vec<(String, String)>, anyhow::Error> {
+	let mut ret = Vec::with_capacity(depends.len());
+	for dep in depends {
+		match dep.get_type() {
+			"DisabledTarget" => {
+				log::info!("Not depending on target {}: not enabled for this platform", disabled_name(dep))
+			}
+			"Executable" => match StarExecutableWrapper::from_value(dep) {
+				Some(x) => ret.push((x.0.package_name.clone(), x.0.name.clone())),
+				None => return err_msg(format!("Could not unpack \"depends\" {}", dep.get_type())),
+			},
+			"StaticLibrary" => match StarStaticLibWrapper::from_value(dep) {
+				Some(x) => ret.push((x.0.package_name.clone(), x.0.name.clone())),
+				None => return err_msg(format!("Could not unpack \"depends\" {}", dep.get_type())),
+			},
+			_ => {
+				return err_msg(format!(
+					"\"depends\" takes executables and static libraries, not {}: {}",
+					dep.to_str(),
+					dep.get_type()
+				))
+			}
+		}
+	}
+	Ok(ret)
+}
+
 fn get_link_targets(links: Vec<Value>) -> Result<Vec<Arc<dyn StarLinkTarget>>, anyhow::Error> {
 	let mut link_targets = Vec::<Arc<dyn StarLinkTarget>>::with_capacity(links.len());
 	for link in links {
 		match link.get_type() {
+			"DisabledTarget" => log::info!("Not linking target {}: not enabled for this platform", disabled_name(link)),
 			"InterfaceLibrary" => match StarIfaceLibWrapper::from_value(link) {
 				Some(x) => link_targets.push(x.0.clone()),
 				None => return err_msg(format!("Could not unpack \"link\" {}", link.get_type())),
@@ -95,6 +219,7 @@ fn get_link_targets(links: Vec<Value>) -> Result<Vec<Arc<dyn StarLinkTarget>>, a
 }
 
 struct ImplAddStaticLibrary {
+	platform: Option<String>,
 	signature: ParametersSpec<starlark::values::FrozenValue>,
 	project: Arc<Mutex<StarProject>>,
 }
@@ -105,25 +230,43 @@ impl starlark::values::function::NativeFunc for ImplAddStaticLibrary {
 		eval: &mut starlark::eval::Evaluator<'module, '_>,
 		parameters: &Arguments<'module, '_>,
 	) -> Result<starlark::values::Value<'module>, starlark::Error> {
-		let args: [Cell<Option<Value<'module>>>; 10] = self.signature.collect_into(parameters, eval.heap())?;
+		let args: [Cell<Option<Value<'module>>>; 18] = self.signature.collect_into(parameters, eval.heap())?;
 
 		let name: String = Arguments::check_required("name", args[0].get())?;
-		let sources: Vec<String> = required_list("sources", args[1].get())?;
+		let sources: Vec<String> = package_paths(required_list("sources", args[1].get())?, eval);
 		let link_private = get_link_targets(optional_list("link_private", args[2].get())?)?;
 		let link_public = get_link_targets(optional_list("link_public", args[3].get())?)?;
-		let include_dirs_private: Vec<String> = optional_list("include_dirs_private", args[4].get())?;
-		let include_dirs_public: Vec<String> = optional_list("include_dirs_public", args[5].get())?;
-		let defines_private: Vec<String> = optional_list("defines_private", args[6].get())?;
+		let include_dirs_private: Vec<String> =
+			package_paths(optional_list("include_dirs_private", args[4].get())?, eval);
+		let include_dirs_public: Vec<String> =
+			package_paths(optional_list("include_dirs_public", args[5].get())?, eval);
+		let mut defines_private: Vec<String> = optional_list("defines_private", args[6].get())?;
 		let defines_public: Vec<String> = optional_list("defines_public", args[7].get())?;
 		let link_flags_public: Vec<String> = optional_list("link_flags_public", args[8].get())?;
-		let generator_vars = generator_func(args[9].get(), eval);
+		let link_flags_private: Vec<String> = optional_list("link_flags_private", args[9].get())?;
+		let generator_vars = generator_func(args[10].get(), eval);
+		let inject_package_defines: bool =
+			Arguments::check_optional("package_defines", args[11].get())?.unwrap_or(false);
+		let visibility = visibility_arg(args[12].get())?;
+		let platforms = platforms_arg(args[13].get())?;
+		let pre_build: Vec<String> = optional_list("pre_build", args[14].get())?;
+		let post_build: Vec<String> = optional_list("post_build", args[15].get())?;
+		let frameworks: Vec<String> = optional_list("frameworks", args[16].get())?;
+		let depends = get_depends(optional_list("depends", args[17].get())?)?;
 
 		let mut project = match self.project.lock() {
 			Ok(x) => x,
 			Err(e) => return err_msg(e.to_string())?,
 		};
+		if !is_enabled(&project, &name, platforms.as_deref(), self.platform.as_deref()) {
+			return Ok(eval.heap().alloc(DisabledTarget { name }));
+		}
+		if inject_package_defines {
+			defines_private.extend(package_defines(&project));
+		}
 		let lib = Arc::new(StarStaticLibrary {
 			parent_project: Arc::downgrade(&self.project),
+			package_name: project.name.clone(),
 			name,
 			sources,
 			link_private,
@@ -133,8 +276,14 @@ impl starlark::values::function::NativeFunc for ImplAddStaticLibrary {
 			defines_private,
 			defines_public,
 			link_flags_public,
+			link_flags_private,
+			frameworks,
 			generator_vars,
+			visibility,
 			output_name: None, // TODO(Travers)
+			depends,
+			pre_build,
+			post_build,
 		});
 		project.static_libraries.push(lib.clone());
 
@@ -143,6 +292,7 @@ impl starlark::values::function::NativeFunc for ImplAddStaticLibrary {
 }
 
 struct ImplAddObjectLibrary {
+	platform: Option<String>,
 	signature: ParametersSpec<FrozenValue>,
 	project: Arc<Mutex<StarProject>>,
 }
@@ -153,23 +303,40 @@ impl starlark::values::function::NativeFunc for ImplAddObjectLibrary {
 		eval: &mut starlark::eval::Evaluator<'module, 'loader>,
 		parameters: &Arguments<'module, 'args>,
 	) -> Result<starlark::values::Value<'module>, starlark::Error> {
-		let args: [Cell<Option<Value<'module>>>; 10] = self.signature.collect_into(parameters, eval.heap())?;
+		let args: [Cell<Option<Value<'module>>>; 18] = self.signature.collect_into(parameters, eval.heap())?;
 
 		let name: String = Arguments::check_required("name", args[0].get())?;
-		let sources: Vec<String> = required_list("sources", args[1].get())?;
+		let sources: Vec<String> = package_paths(required_list("sources", args[1].get())?, eval);
 		let link_private = get_link_targets(optional_list("link_private", args[2].get())?)?;
 		let link_public = get_link_targets(optional_list("link_public", args[3].get())?)?;
-		let include_dirs_private: Vec<String> = optional_list("include_dirs_private", args[4].get())?;
-		let include_dirs_public: Vec<String> = optional_list("include_dirs_public", args[5].get())?;
-		let defines_private: Vec<String> = optional_list("defines_private", args[6].get())?;
+		let include_dirs_private: Vec<String> =
+			package_paths(optional_list("include_dirs_private", args[4].get())?, eval);
+		let include_dirs_public: Vec<String> =
+			package_paths(optional_list("include_dirs_public", args[5].get())?, eval);
+		let mut defines_private: Vec<String> = optional_list("defines_private", args[6].get())?;
 		let defines_public: Vec<String> = optional_list("defines_public", args[7].get())?;
 		let link_flags_public: Vec<String> = optional_list("link_flags_public", args[8].get())?;
-		let generator_vars = generator_func(args[9].get(), eval);
+		let link_flags_private: Vec<String> = optional_list("link_flags_private", args[9].get())?;
+		let generator_vars = generator_func(args[10].get(), eval);
+		let inject_package_defines: bool =
+			Arguments::check_optional("package_defines", args[11].get())?.unwrap_or(false);
+		let visibility = visibility_arg(args[12].get())?;
+		let platforms = platforms_arg(args[13].get())?;
+		let pre_build: Vec<String> = optional_list("pre_build", args[14].get())?;
+		let post_build: Vec<String> = optional_list("post_build", args[15].get())?;
+		let frameworks: Vec<String> = optional_list("frameworks", args[16].get())?;
+		let depends = get_depends(optional_list("depends", args[17].get())?)?;
 
 		let mut project = match self.project.lock() {
 			Ok(x) => x,
 			Err(e) => return err_msg(e.to_string())?,
 		};
+		if !is_enabled(&project, &name, platforms.as_deref(), self.platform.as_deref()) {
+			return Ok(eval.heap().alloc(DisabledTarget { name }));
+		}
+		if inject_package_defines {
+			defines_private.extend(package_defines(&project));
+		}
 		let lib = Arc::new(StarObjectLibrary {
 			parent_project: Arc::downgrade(&self.project),
 			name,
@@ -181,8 +348,14 @@ impl starlark::values::function::NativeFunc for ImplAddObjectLibrary {
 			defines_private,
 			defines_public,
 			link_flags_public,
+			link_flags_private,
+			frameworks,
 			generator_vars,
+			visibility,
 			output_name: None, // TODO(Travers)
+			depends,
+			pre_build,
+			post_build,
 		});
 		project.object_libraries.push(lib.clone());
 
@@ -191,6 +364,7 @@ impl starlark::values::function::NativeFunc for ImplAddObjectLibrary {
 }
 
 struct ImplAddInterfaceLibrary {
+	platform: Option<String>,
 	signature: ParametersSpec<FrozenValue>,
 	project: Arc<Mutex<StarProject>>,
 }
@@ -201,25 +375,139 @@ impl starlark::values::function::NativeFunc for ImplAddInterfaceLibrary {
 		eval: &mut starlark::eval::Evaluator<'module, 'loader>,
 		parameters: &Arguments<'module, 'args>,
 	) -> Result<starlark::values::Value<'module>, starlark::Error> {
-		let args: [Cell<Option<Value<'module>>>; 5] = self.signature.collect_into(parameters, eval.heap())?;
+		let args: [Cell<Option<Value<'module>>>; 9] = self.signature.collect_into(parameters, eval.heap())?;
 
 		let name: String = Arguments::check_required("name", args[0].get())?;
 		let links = get_link_targets(optional_list("link", args[1].get())?)?;
-		let include_dirs: Vec<String> = optional_list("include_dirs", args[2].get())?;
+		let include_dirs: Vec<String> = package_paths(optional_list("include_dirs", args[2].get())?, eval);
 		let defines: Vec<String> = optional_list("defines", args[3].get())?;
 		let link_flags: Vec<String> = optional_list("link_flags", args[4].get())?;
+		let generator_vars = generator_func(args[5].get(), eval);
+		let visibility = visibility_arg(args[6].get())?;
+		let platforms = platforms_arg(args[7].get())?;
+		let frameworks: Vec<String> = optional_list("frameworks", args[8].get())?;
 
 		let mut project = match self.project.lock() {
 			Ok(x) => x,
 			Err(e) => return err_msg(e.to_string())?,
 		};
+		if !is_enabled(&project, &name, platforms.as_deref(), self.platform.as_deref()) {
+			return Ok(eval.heap().alloc(DisabledTarget { name }));
+		}
 		let lib = Arc::new(StarIfaceLibrary {
 			parent_project: Arc::downgrade(&self.project),
 			name,
 			links,
 			include_dirs,
 			defines,
+			compile_flags: Vec::new(),
 			link_flags,
+			link_files: Vec::new(),
+			frameworks,
+			generator_vars,
+			export_header: None,
+			config_header: None,
+			visibility,
+		});
+		project.interface_libraries.push(lib.clone());
+
+		Ok(eval.heap().alloc(StarIfaceLibWrapper(lib)))
+	}
+}
+
+struct ImplGenerateExportHeader {
+	signature: ParametersSpec<FrozenValue>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplGenerateExportHeader {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut starlark::eval::Evaluator<'module, 'loader>,
+		parameters: &Arguments<'module, 'args>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 1] = self.signature.collect_into(parameters, eval.heap())?;
+		let name: String = Arguments::check_required("name", args[0].get())?;
+
+		let mut project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		let lib = Arc::new(StarIfaceLibrary {
+			parent_project: Arc::downgrade(&self.project),
+			name: name.clone() + "_export",
+			links: Vec::new(),
+			include_dirs: Vec::new(),
+			defines: Vec::new(),
+			compile_flags: Vec::new(),
+			link_flags: Vec::new(),
+			link_files: Vec::new(),
+			frameworks: Vec::new(),
+			generator_vars: None,
+			export_header: Some(name),
+			config_header: None,
+			visibility: Visibility::Public,
+		});
+		project.interface_libraries.push(lib.clone());
+
+		Ok(eval.heap().alloc(StarIfaceLibWrapper(lib)))
+	}
+}
+
+struct ImplGenerateConfigHeader {
+	signature: ParametersSpec<FrozenValue>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplGenerateConfigHeader {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut starlark::eval::Evaluator<'module, 'loader>,
+		parameters: &Arguments<'module, 'args>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 2] = self.signature.collect_into(parameters, eval.heap())?;
+		let name: String = Arguments::check_required("name", args[0].get())?;
+		let defines: DictOf<&str, Value> = Arguments::check_required("defines", args[1].get())?;
+		let defines = defines
+			.collect_entries()
+			.into_iter()
+			.map(|(define, value)| {
+				let value = if let Some(x) = value.unpack_bool() {
+					ConfigValue::Bool(x)
+				} else if let Some(x) = value.unpack_i32() {
+					ConfigValue::Int(x)
+				} else if let Some(x) = value.unpack_str() {
+					ConfigValue::Str(x.to_owned())
+				} else {
+					return err_msg(format!(
+						"Value of define \"{}\" in config header \"{}\" must be a bool, int or string, not {}",
+						define,
+						name,
+						value.get_type()
+					));
+				};
+				Ok((define.to_owned(), value))
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let mut project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		let lib = Arc::new(StarIfaceLibrary {
+			parent_project: Arc::downgrade(&self.project),
+			name: name.clone(),
+			links: Vec::new(),
+			include_dirs: Vec::new(),
+			defines: Vec::new(),
+			compile_flags: Vec::new(),
+			link_flags: Vec::new(),
+			link_files: Vec::new(),
+			frameworks: Vec::new(),
+			generator_vars: None,
+			export_header: None,
+			config_header: Some(ConfigHeader { name, defines }),
+			visibility: Visibility::Public,
 		});
 		project.interface_libraries.push(lib.clone());
 
@@ -227,7 +515,16 @@ impl starlark::values::function::NativeFunc for ImplAddInterfaceLibrary {
 	}
 }
 
+/// The `requestedExecutionLevel`s of a Windows application manifest
+const WIN32_EXECUTION_LEVELS: [&str; 3] = ["asInvoker", "highestAvailable", "requireAdministrator"];
+
+/// The `binary_formats` objcopy can convert executables to: raw binary, Intel HEX and Motorola S-record
+const BINARY_FORMATS: [&str; 3] = ["bin", "hex", "srec"];
+
 struct ImplAddExecutable {
+	platform: Option<String>,
+	benchmark: bool,
+	test: bool,
 	signature: ParametersSpec<FrozenValue>,
 	project: Arc<Mutex<StarProject>>,
 }
@@ -238,165 +535,608 @@ impl starlark::values::function::NativeFunc for ImplAddExecutable {
 		eval: &mut Evaluator<'module, '_>,
 		parameters: &Arguments<'module, '_>,
 	) -> Result<starlark::values::Value<'module>, starlark::Error> {
-		let args: [_; 7] = self.signature.collect_into(parameters, eval.heap())?;
+		let args: [_; 25] = self.signature.collect_into(parameters, eval.heap())?;
 
 		let name: String = Arguments::check_required("name", args[0].get())?;
-		let sources: Vec<String> = required_list("sources", args[1].get())?;
+		let sources: Vec<String> = package_paths(required_list("sources", args[1].get())?, eval);
 		let links = get_link_targets(optional_list("link", args[2].get())?)?;
-		let include_dirs: Vec<String> = optional_list("include_dirs", args[3].get())?;
-		let defines: Vec<String> = optional_list("defines", args[4].get())?;
+		let include_dirs: Vec<String> = package_paths(optional_list("include_dirs", args[3].get())?, eval);
+		let mut defines: Vec<String> = optional_list("defines", args[4].get())?;
 		let link_flags: Vec<String> = optional_list("link_flags", args[5].get())?;
 		let generator_vars = generator_func(args[6].get(), eval);
+		let inject_package_defines: bool =
+			Arguments::check_optional("package_defines", args[7].get())?.unwrap_or(false);
+		let platforms = platforms_arg(args[8].get())?;
+		let pre_build: Vec<String> = optional_list("pre_build", args[9].get())?;
+		let post_build: Vec<String> = optional_list("post_build", args[10].get())?;
+		let runtime_files: Vec<String> = package_paths(optional_list("runtime_files", args[11].get())?, eval);
+		let host: bool = Arguments::check_optional("host", args[12].get())?.unwrap_or(false);
+		let win32_subsystem: Option<String> = Arguments::check_optional("win32_subsystem", args[13].get())?;
+		let entry_point: Option<String> = Arguments::check_optional("entry_point", args[14].get())?;
+		let win32_manifest: Option<String> = Arguments::check_optional("win32_manifest", args[15].get())?;
+		let win32_manifest = win32_manifest.map(|x| package_paths(vec![x], eval).remove(0));
+		let win32_execution_level: Option<String> = Arguments::check_optional("win32_execution_level", args[16].get())?;
+		let frameworks: Vec<String> = optional_list("frameworks", args[17].get())?;
+		let build_rpath: Vec<String> = optional_list("build_rpath", args[18].get())?;
+		let install_rpath: Vec<String> = optional_list("install_rpath", args[19].get())?;
+		let linker_script: Option<String> = Arguments::check_optional("linker_script", args[20].get())?;
+		let linker_script = linker_script.map(|x| package_paths(vec![x], eval).remove(0));
+		let version_script: Option<String> = Arguments::check_optional("version_script", args[21].get())?;
+		let version_script = version_script.map(|x| package_paths(vec![x], eval).remove(0));
+		let map_file: bool = Arguments::check_optional("map_file", args[22].get())?.unwrap_or(false);
+		let binary_formats: Vec<String> = optional_list("binary_formats", args[23].get())?;
+		let depends = get_depends(optional_list("depends", args[24].get())?)?;
+		if let Some(subsystem) = &win32_subsystem {
+			if subsystem != "console" && subsystem != "windows" {
+				return err_msg(format!(
+					"Unknown win32_subsystem \"{}\" for \"{}\". Expected one of: console, windows",
+					subsystem, name
+				))?;
+			}
+		}
+		if let Some(format) = binary_formats.iter().find(|x| !BINARY_FORMATS.contains(&x.as_str())) {
+			return err_msg(format!(
+				"Unknown binary format \"{}\" for \"{}\". Expected one of: {}",
+				format,
+				name,
+				BINARY_FORMATS.join(", ")
+			))?;
+		}
+		if let Some(level) = &win32_execution_level {
+			if !WIN32_EXECUTION_LEVELS.contains(&level.as_str()) {
+				return err_msg(format!(
+					"Unknown win32_execution_level \"{}\" for \"{}\". Expected one of: {}",
+					level,
+					name,
+					WIN32_EXECUTION_LEVELS.join(", ")
+				))?;
+			}
+		}
 
 		let mut project = match self.project.lock() {
 			Ok(x) => x,
 			Err(e) => return err_msg(e.to_string())?,
 		};
+		if !is_enabled(&project, &name, platforms.as_deref(), self.platform.as_deref()) {
+			return Ok(eval.heap().alloc(DisabledTarget { name }));
+		}
+		if inject_package_defines {
+			defines.extend(package_defines(&project));
+		}
 		let exe = Arc::new(StarExecutable {
 			parent_project: Arc::downgrade(&self.project),
+			package_name: project.name.clone(),
 			name,
 			sources,
 			links,
 			include_dirs,
 			defines,
 			link_flags,
+			frameworks,
+			build_rpath,
+			install_rpath,
+			linker_script,
+			version_script,
+			map_file,
+			binary_formats,
 			generator_vars,
 			output_name: None, // TODO(Travers)
+			depends,
+			pre_build,
+			post_build,
+			runtime_files,
+			benchmark: self.benchmark,
+			test: self.test,
+			host,
+			win32_subsystem,
+			entry_point,
+			win32_manifest,
+			win32_execution_level,
 		});
 		project.executables.push(exe.clone());
 		Ok(eval.heap().alloc(StarExecutableWrapper(exe)))
 	}
 }
 
-struct ImplGeneratorVar {
+struct ImplAddSubdirectory {
 	signature: ParametersSpec<FrozenValue>,
 }
 
-impl starlark::values::function::NativeFunc for ImplGeneratorVar {
+impl starlark::values::function::NativeFunc for ImplAddSubdirectory {
 	fn invoke<'module, 'loader, 'extra, 'args>(
 		&self,
-		eval: &mut starlark::eval::Evaluator<'module, 'loader>,
-		parameters: &Arguments<'module, 'args>,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
 	) -> Result<starlark::values::Value<'module>, starlark::Error> {
-		let args: [Cell<Option<Value<'module>>>; 4] = self.signature.collect_into(parameters, eval.heap())?;
-		let ret = StarGeneratorVars {
-			sources: optional_list("sources", args[0].get())?,
-			include_dirs: optional_list("include_dirs", args[1].get())?,
-			defines: optional_list("defines", args[2].get())?,
-			link_flags: optional_list("link_flags", args[3].get())?,
+		let args: [Cell<Option<Value<'module>>>; 1] = self.signature.collect_into(parameters, eval.heap())?;
+		let subdir: String = Arguments::check_required("subdir", args[0].get())?;
+		let frozen_module = match RecipeContext::from_eval(eval) {
+			Some(context) => context.eval_subdirectory(&subdir)?,
+			None => return err_msg("add_subdirectory() can only be called while evaluating a recipe".to_owned())?,
 		};
-		Ok(eval.heap().alloc(ret))
+		// Return the subdirectory's top-level values (e.g. its targets) so the parent recipe can use them
+		let mut fields = Vec::new();
+		for name in frozen_module.names() {
+			if name.as_str().starts_with('_') {
+				continue;
+			}
+			let value = frozen_module.get(name.as_str())?;
+			fields.push((name.as_str().to_owned(), value.owned_value(eval.frozen_heap())));
+		}
+		Ok(eval.heap().alloc(AllocStruct(fields)))
 	}
 }
 
-pub(crate) fn build_api(project: &Arc<Mutex<StarProject>>, builder: &mut GlobalsBuilder) {
-	{
-		let function_name = "add_static_library";
-		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
-		sig_builder.no_more_positional_only_args();
-		sig_builder.required("name");
-		sig_builder.required("sources");
-		sig_builder.optional("link_private");
-		sig_builder.optional("link_public");
-		sig_builder.optional("include_dirs_private");
-		sig_builder.optional("include_dirs_public");
-		sig_builder.optional("defines_private");
-		sig_builder.optional("defines_public");
-		sig_builder.optional("link_flags_public");
-		sig_builder.optional("generator_vars");
-		let signature = sig_builder.finish();
-		let documentation = {
-			let parameter_types = Vec::<Ty>::from([
-				<&str>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<Value>>::starlark_type_repr(),
-				<Vec<Value>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<StarGeneratorVars>::starlark_type_repr(),
-			]);
-			starlark::values::function::NativeCallableRawDocs {
-				rust_docstring: None,
-				signature: signature.clone(),
-				parameter_types,
-				return_type: <StarStaticLibWrapper>::starlark_type_repr(),
-				as_type: None,
-			}
+struct ImplAlias {
+	signature: ParametersSpec<FrozenValue>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplAlias {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 2] = self.signature.collect_into(parameters, eval.heap())?;
+		let name: String = Arguments::check_required("name", args[0].get())?;
+		let actual: Value = Arguments::check_required("actual", args[1].get())?;
+
+		let actual_target = if let Some(x) = StarStaticLibWrapper::from_value(actual) {
+			StarAliasTarget::Static(x.0.clone())
+		} else if let Some(x) = StarObjLibWrapper::from_value(actual) {
+			StarAliasTarget::Object(x.0.clone())
+		} else if let Some(x) = StarIfaceLibWrapper::from_value(actual) {
+			StarAliasTarget::Interface(x.0.clone())
+		} else {
+			return err_msg(format!("Cannot alias {}: {}. Expected a library", actual.to_str(), actual.get_type()))?;
 		};
-		builder.set_function(
-			function_name,
-			false,
-			documentation,
-			None,
-			Some(StarStaticLibWrapper::starlark_type_repr()),
-			None,
-			ImplAddStaticLibrary { signature, project: project.clone() },
-		);
+		let mut project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		if project.target_value(&name, true, eval.heap()).is_some() {
+			return err_msg(format!("Package \"{}\" already has a target named \"{}\"", project.name, name))?;
+		}
+		project.aliases.push(StarAlias { name, actual: actual_target });
+
+		Ok(actual)
 	}
-	{
-		let function_name = "add_object_library";
-		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
-		sig_builder.no_more_positional_only_args();
-		sig_builder.required("name");
-		sig_builder.required("sources");
-		sig_builder.optional("link_private");
-		sig_builder.optional("link_public");
-		sig_builder.optional("include_dirs_private");
-		sig_builder.optional("include_dirs_public");
-		sig_builder.optional("defines_private");
-		sig_builder.optional("defines_public");
-		sig_builder.optional("link_flags_public");
-		sig_builder.optional("generator_vars");
-		let signature = sig_builder.finish();
-		let documentation = {
-			let parameter_types = Vec::<Ty>::from([
-				<&str>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<Value>>::starlark_type_repr(),
-				<Vec<Value>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<StarGeneratorVars>::starlark_type_repr(),
-			]);
-			starlark::values::function::NativeCallableRawDocs {
-				rust_docstring: None,
-				signature: signature.clone(),
-				parameter_types,
-				return_type: <StarObjLibWrapper>::starlark_type_repr(),
-				as_type: None,
+}
+
+struct ImplDep {
+	signature: ParametersSpec<FrozenValue>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplDep {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 1] = self.signature.collect_into(parameters, eval.heap())?;
+		let label: String = Arguments::check_required("label", args[0].get())?;
+		let (package, target) = match label.split_once("::") {
+			Some(x) => x,
+			None => return err_msg(format!("Invalid target label \"{}\". Expected \"<package>::<target>\"", label))?,
+		};
+
+		let project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		let found = if package == project.name {
+			project.target_value(target, true, eval.heap())
+		} else {
+			match project.dependencies.iter().find(|x| x.name == package) {
+				Some(dep) => dep.target_value(target, false, eval.heap()),
+				None => {
+					return err_msg(format!(
+						"Package \"{}\" is not a dependency of \"{}\" (looking up \"{}\")",
+						package, project.name, label
+					))?
+				}
 			}
 		};
-		builder.set_function(
-			function_name,
-			false,
-			documentation,
-			None,
-			Some(StarObjLibWrapper::starlark_type_repr()),
-			None,
-			ImplAddObjectLibrary { signature, project: project.clone() },
-		);
+		match found {
+			Some(x) => Ok(x),
+			None => err_msg(format!("Package \"{}\" has no public target \"{}\"", package, target))?,
+		}
 	}
-	{
-		let mut sig_builder = ParametersSpec::new("add_interface_library".to_owned());
-		sig_builder.no_more_positional_only_args();
-		sig_builder.required("name");
-		sig_builder.optional("link");
-		sig_builder.optional("include_dirs");
-		sig_builder.optional("defines");
-		sig_builder.optional("link_flags");
-		let signature = sig_builder.finish();
-		let documentation = {
-			let parameter_types = Vec::<Ty>::from([
-				<&str>::starlark_type_repr(),
-				<Vec<Value>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
-				<Vec<&str>>::starlark_type_repr(),
+}
+
+/// The package name and recipe location of the current `fail()`/`warn()` call
+fn message_context(eval: &Evaluator) -> (String, Option<FileSpan>) {
+	let package = match RecipeContext::from_eval(eval) {
+		Some(context) => context.package_name.to_owned(),
+		None => String::new(),
+	};
+	(package, eval.call_stack_top_location())
+}
+
+fn message_arg(arg: Option<Value>) -> Result<String, anyhow::Error> {
+	let msg: Value = Arguments::check_required("msg", arg)?;
+	Ok(match msg.unpack_str() {
+		Some(x) => x.to_owned(),
+		None => msg.to_str(),
+	})
+}
+
+struct ImplFail {
+	signature: ParametersSpec<FrozenValue>,
+}
+
+impl starlark::values::function::NativeFunc for ImplFail {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 1] = self.signature.collect_into(parameters, eval.heap())?;
+		let message = message_arg(args[0].get())?;
+		let (package, location) = message_context(eval);
+		Err(anyhow::Error::new(ConfigureError { package, location, message }).into())
+	}
+}
+
+struct ImplWarn {
+	signature: ParametersSpec<FrozenValue>,
+}
+
+impl starlark::values::function::NativeFunc for ImplWarn {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 1] = self.signature.collect_into(parameters, eval.heap())?;
+		let message = message_arg(args[0].get())?;
+		match message_context(eval) {
+			(package, Some(location)) => println!("Warning in package \"{}\" at {}: {}", package, location, message),
+			(package, None) => println!("Warning in package \"{}\": {}", package, message),
+		}
+		Ok(Value::new_none())
+	}
+}
+
+struct ImplSourceGroup {
+	signature: ParametersSpec<FrozenValue>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplSourceGroup {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 2] = self.signature.collect_into(parameters, eval.heap())?;
+		let name: String = Arguments::check_required("name", args[0].get())?;
+		let sources: Vec<String> = package_paths(required_list("sources", args[1].get())?, eval);
+		if name.split(['/', '\\']).any(str::is_empty) {
+			return err_msg(format!("Invalid source group name \"{}\"", name))?;
+		}
+		let mut project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		let sources = sources.iter().map(|x| join_parent(&project.path, x).full).collect();
+		project.source_groups.push(SourceGroup { name, sources });
+		Ok(Value::new_none())
+	}
+}
+
+struct ImplInstall {
+	signature: ParametersSpec<FrozenValue>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplInstall {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 4] = self.signature.collect_into(parameters, eval.heap())?;
+		let target_values: Vec<Value> = optional_list("targets", args[0].get())?;
+		let files: Vec<String> = package_paths(optional_list("files", args[1].get())?, eval);
+		let component: Option<String> = Arguments::check_optional("component", args[2].get())?;
+		let destination: Option<String> = Arguments::check_optional("destination", args[3].get())?;
+		let mut targets = Vec::new();
+		for value in target_values {
+			if let Some(name) = value.unpack_str() {
+				targets.push(name.to_owned());
+			} else if let Some(x) = StarExecutableWrapper::from_value(value) {
+				targets.push(x.0.name.clone());
+			} else if let Some(x) = StarStaticLibWrapper::from_value(value) {
+				targets.push(x.0.name.clone());
+			} else if let Some(x) = StarIfaceLibWrapper::from_value(value) {
+				targets.push(x.0.name.clone());
+			} else if value.get_type() != "DisabledTarget" {
+				return err_msg(format!("Cannot install {}: {}", value.to_str(), value.get_type()))?;
+			}
+		}
+		if let Some(component) = &component {
+			if component.is_empty()
+				|| !component
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+			{
+				return err_msg(format!("Invalid component name \"{}\"", component))?;
+			}
+		}
+		let mut project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		let files = files.iter().map(|x| join_parent(&project.path, x).full).collect();
+		project
+			.installs
+			.push(InstallRule { targets, files, component, destination });
+		Ok(Value::new_none())
+	}
+}
+
+struct ImplLintOptions {
+	signature: ParametersSpec<FrozenValue>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplLintOptions {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 6] = self.signature.collect_into(parameters, eval.heap())?;
+		let format: bool = Arguments::check_optional("format", args[0].get())?.unwrap_or(true);
+		let tidy: bool = Arguments::check_optional("tidy", args[1].get())?.unwrap_or(true);
+		let format_sources: Option<Vec<String>> = match args[2].get() {
+			Some(x) => Some(package_paths(required_list("format_sources", Some(x))?, eval)),
+			None => None,
+		};
+		let tidy_sources: Option<Vec<String>> = match args[3].get() {
+			Some(x) => Some(package_paths(required_list("tidy_sources", Some(x))?, eval)),
+			None => None,
+		};
+		let clang_format_config: Option<String> = Arguments::check_optional("clang_format_config", args[4].get())?;
+		let clang_tidy_config: Option<String> = Arguments::check_optional("clang_tidy_config", args[5].get())?;
+		let clang_format_config = clang_format_config.map(|x| package_paths(vec![x], eval).remove(0));
+		let clang_tidy_config = clang_tidy_config.map(|x| package_paths(vec![x], eval).remove(0));
+		let mut project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		let full_path = |x: &String| join_parent(&project.path, x).full;
+		let lint = LintOptions {
+			format,
+			tidy,
+			format_sources: format_sources.map(|x| x.iter().map(full_path).collect()),
+			tidy_sources: tidy_sources.map(|x| x.iter().map(full_path).collect()),
+			clang_format_config: clang_format_config.as_ref().map(full_path),
+			clang_tidy_config: clang_tidy_config.as_ref().map(full_path),
+		};
+		project.lint = Some(lint);
+		Ok(Value::new_none())
+	}
+}
+
+struct ImplAddDocs {
+	signature: ParametersSpec<FrozenValue>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplAddDocs {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 3] = self.signature.collect_into(parameters, eval.heap())?;
+		let name: String = Arguments::check_required("name", args[0].get())?;
+		let doxyfile: Option<String> = Arguments::check_optional("doxyfile", args[1].get())?;
+		let doxyfile = doxyfile.map(|x| package_paths(vec![x], eval).remove(0));
+		let sources: Vec<String> = package_paths(optional_list("sources", args[2].get())?, eval);
+		if doxyfile.is_none() && sources.is_empty() {
+			return err_msg(format!("add_docs(\"{}\") requires a doxyfile or sources", name))?;
+		}
+		let mut project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		if project.target_value(&name, true, eval.heap()).is_some() || project.docs.iter().any(|x| x.name == name) {
+			return err_msg(format!("Package \"{}\" already has a target named \"{}\"", project.name, name))?;
+		}
+		project.docs.push(StarDocs { name, doxyfile, sources });
+		Ok(Value::new_none())
+	}
+}
+
+struct ImplGeneratorVar {
+	signature: ParametersSpec<FrozenValue>,
+}
+
+impl starlark::values::function::NativeFunc for ImplGeneratorVar {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut starlark::eval::Evaluator<'module, 'loader>,
+		parameters: &Arguments<'module, 'args>,
+	) -> Result<starlark::values::Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 4] = self.signature.collect_into(parameters, eval.heap())?;
+		let ret = StarGeneratorVars {
+			sources: optional_list("sources", args[0].get())?,
+			include_dirs: optional_list("include_dirs", args[1].get())?,
+			defines: optional_list("defines", args[2].get())?,
+			link_flags: optional_list("link_flags", args[3].get())?,
+		};
+		Ok(eval.heap().alloc(ret))
+	}
+}
+
+/// `platform` is the name of the platform being built for, used to skip targets restricted with `platforms`.
+/// `api_version` is the recipe API version the package selected, between 1 and `LATEST_API_VERSION`.
+pub(crate) fn build_api(
+	project: &Arc<Mutex<StarProject>>,
+	platform: Option<String>,
+	api_version: u32,
+	builder: &mut GlobalsBuilder,
+) {
+	{
+		let function_name = "add_static_library";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		sig_builder.required("sources");
+		sig_builder.optional("link_private");
+		sig_builder.optional("link_public");
+		sig_builder.optional("include_dirs_private");
+		sig_builder.optional("include_dirs_public");
+		sig_builder.optional("defines_private");
+		sig_builder.optional("defines_public");
+		sig_builder.optional("link_flags_public");
+		sig_builder.optional("link_flags_private");
+		sig_builder.optional("generator_vars");
+		sig_builder.optional("package_defines");
+		sig_builder.optional("visibility");
+		sig_builder.optional("platforms");
+		sig_builder.optional("pre_build");
+		sig_builder.optional("post_build");
+		sig_builder.optional("frameworks");
+		sig_builder.optional("depends");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<Value>>::starlark_type_repr(),
+				<Vec<Value>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<StarGeneratorVars>::starlark_type_repr(),
+				<bool>::starlark_type_repr(),
+				<&str>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<Value>>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <StarStaticLibWrapper>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			Some(StarStaticLibWrapper::starlark_type_repr()),
+			None,
+			ImplAddStaticLibrary {
+				signature,
+				project: project.clone(),
+				platform: platform.clone(),
+			},
+		);
+	}
+	{
+		let function_name = "add_object_library";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		sig_builder.required("sources");
+		sig_builder.optional("link_private");
+		sig_builder.optional("link_public");
+		sig_builder.optional("include_dirs_private");
+		sig_builder.optional("include_dirs_public");
+		sig_builder.optional("defines_private");
+		sig_builder.optional("defines_public");
+		sig_builder.optional("link_flags_public");
+		sig_builder.optional("link_flags_private");
+		sig_builder.optional("generator_vars");
+		sig_builder.optional("package_defines");
+		sig_builder.optional("visibility");
+		sig_builder.optional("platforms");
+		sig_builder.optional("pre_build");
+		sig_builder.optional("post_build");
+		sig_builder.optional("frameworks");
+		sig_builder.optional("depends");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<Value>>::starlark_type_repr(),
+				<Vec<Value>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<StarGeneratorVars>::starlark_type_repr(),
+				<bool>::starlark_type_repr(),
+				<&str>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<Value>>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <StarObjLibWrapper>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			Some(StarObjLibWrapper::starlark_type_repr()),
+			None,
+			ImplAddObjectLibrary {
+				signature,
+				project: project.clone(),
+				platform: platform.clone(),
+			},
+		);
+	}
+	{
+		let mut sig_builder = ParametersSpec::new("add_interface_library".to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		sig_builder.optional("link");
+		sig_builder.optional("include_dirs");
+		sig_builder.optional("defines");
+		sig_builder.optional("link_flags");
+		sig_builder.optional("generator_vars");
+		sig_builder.optional("visibility");
+		sig_builder.optional("platforms");
+		sig_builder.optional("frameworks");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<Vec<Value>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<StarGeneratorVars>::starlark_type_repr(),
+				<&str>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
 				<Vec<&str>>::starlark_type_repr(),
 			]);
 			starlark::values::function::NativeCallableRawDocs {
@@ -414,11 +1154,20 @@ pub(crate) fn build_api(project: &Arc<Mutex<StarProject>>, builder: &mut Globals
 			None,
 			Some(StarIfaceLibWrapper::starlark_type_repr()),
 			None,
-			ImplAddInterfaceLibrary { signature, project: project.clone() },
+			ImplAddInterfaceLibrary {
+				signature,
+				project: project.clone(),
+				platform: platform.clone(),
+			},
 		);
 	}
-	{
-		let mut sig_builder = ParametersSpec::new("add_executable".to_owned());
+	// Benchmarks are executables that are only built when benchmarks are enabled. Tests are always built.
+	for (function_name, benchmark, test) in [
+		("add_executable", false, false),
+		("add_benchmark", true, false),
+		("add_test", false, true),
+	] {
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
 		sig_builder.no_more_positional_only_args();
 		sig_builder.required("name");
 		sig_builder.required("sources");
@@ -427,6 +1176,24 @@ pub(crate) fn build_api(project: &Arc<Mutex<StarProject>>, builder: &mut Globals
 		sig_builder.optional("defines");
 		sig_builder.optional("link_flags");
 		sig_builder.optional("generator_vars");
+		sig_builder.optional("package_defines");
+		sig_builder.optional("platforms");
+		sig_builder.optional("pre_build");
+		sig_builder.optional("post_build");
+		sig_builder.optional("runtime_files");
+		sig_builder.optional("host");
+		sig_builder.optional("win32_subsystem");
+		sig_builder.optional("entry_point");
+		sig_builder.optional("win32_manifest");
+		sig_builder.optional("win32_execution_level");
+		sig_builder.optional("frameworks");
+		sig_builder.optional("build_rpath");
+		sig_builder.optional("install_rpath");
+		sig_builder.optional("linker_script");
+		sig_builder.optional("version_script");
+		sig_builder.optional("map_file");
+		sig_builder.optional("binary_formats");
+		sig_builder.optional("depends");
 		let signature = sig_builder.finish();
 
 		let documentation = {
@@ -438,6 +1205,24 @@ pub(crate) fn build_api(project: &Arc<Mutex<StarProject>>, builder: &mut Globals
 				<Vec<&str>>::starlark_type_repr(),
 				<Vec<&str>>::starlark_type_repr(),
 				<StarGeneratorVars>::starlark_type_repr(),
+				<bool>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<bool>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<bool>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Vec<Value>>::starlark_type_repr(),
 			]);
 			starlark::values::function::NativeCallableRawDocs {
 				rust_docstring: None,
@@ -449,15 +1234,296 @@ pub(crate) fn build_api(project: &Arc<Mutex<StarProject>>, builder: &mut Globals
 		};
 
 		builder.set_function(
-			"add_executable",
+			function_name,
 			false,
 			documentation,
 			None,
 			Some(StarExecutableWrapper::starlark_type_repr()),
 			None,
-			ImplAddExecutable { signature, project: project.clone() },
+			ImplAddExecutable {
+				signature,
+				project: project.clone(),
+				platform: platform.clone(),
+				benchmark,
+				test,
+			},
+		);
+	}
+	{
+		let function_name = "generate_export_header";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([<&str>::starlark_type_repr()]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <Value>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			Some(StarIfaceLibWrapper::starlark_type_repr()),
+			None,
+			ImplGenerateExportHeader { signature, project: project.clone() },
+		);
+	}
+	{
+		let function_name = "generate_config_header";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		sig_builder.required("defines");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<DictOf<&str, Value>>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <Value>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			Some(StarIfaceLibWrapper::starlark_type_repr()),
+			None,
+			ImplGenerateConfigHeader { signature, project: project.clone() },
+		);
+	}
+	{
+		let function_name = "alias";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		sig_builder.required("actual");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([<&str>::starlark_type_repr(), <Value>::starlark_type_repr()]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <Value>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplAlias { signature, project: project.clone() },
+		);
+	}
+	{
+		let function_name = "dep";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.required("label");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([<&str>::starlark_type_repr()]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <Value>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplDep { signature, project: project.clone() },
+		);
+	}
+	{
+		let function_name = "add_subdirectory";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.required("subdir");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([<&str>::starlark_type_repr()]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <Value>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(function_name, false, documentation, None, None, None, ImplAddSubdirectory { signature });
+	}
+	{
+		let function_name = "source_group";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.required("name");
+		sig_builder.required("sources");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([<&str>::starlark_type_repr(), <Vec<&str>>::starlark_type_repr()]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <starlark::values::none::NoneType>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplSourceGroup { signature, project: project.clone() },
 		);
 	}
+	{
+		let function_name = "install";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.optional("targets");
+		sig_builder.optional("files");
+		sig_builder.optional("component");
+		sig_builder.optional("destination");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<Vec<Value>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <starlark::values::none::NoneType>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplInstall { signature, project: project.clone() },
+		);
+	}
+	{
+		let function_name = "lint_options";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.optional("format");
+		sig_builder.optional("tidy");
+		sig_builder.optional("format_sources");
+		sig_builder.optional("tidy_sources");
+		sig_builder.optional("clang_format_config");
+		sig_builder.optional("clang_tidy_config");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<bool>::starlark_type_repr(),
+				<bool>::starlark_type_repr(),
+				<Option<Vec<&str>>>::starlark_type_repr(),
+				<Option<Vec<&str>>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <starlark::values::none::NoneType>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplLintOptions { signature, project: project.clone() },
+		);
+	}
+	{
+		let function_name = "add_docs";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		sig_builder.optional("doxyfile");
+		sig_builder.optional("sources");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<Option<&str>>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <starlark::values::none::NoneType>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplAddDocs { signature, project: project.clone() },
+		);
+	}
+	for (function_name, is_fail) in [("fail", true), ("warn", false)] {
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.required("msg");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([<Value>::starlark_type_repr()]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <starlark::values::none::NoneType>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		if is_fail {
+			builder.set_function(function_name, false, documentation, None, None, None, ImplFail { signature });
+		} else {
+			builder.set_function(function_name, false, documentation, None, None, None, ImplWarn { signature });
+		}
+	}
 	{
 		let function_name = "generator_vars";
 		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
@@ -492,6 +1558,13 @@ pub(crate) fn build_api(project: &Arc<Mutex<StarProject>>, builder: &mut Globals
 			ImplGeneratorVar { signature },
 		);
 	}
+
+	// The builtins above are version 1 of the API. A later version that renames or removes a parameter registers
+	// the builtin again here for packages that select it, replacing the version 1 definition.
+	match api_version {
+		1 => {}
+		_ => unreachable!("Recipe API version {} is rejected when reading catapult.toml", api_version),
+	}
 }
 
 fn required_list<'a, T: UnpackValue<'a>>(name: &str, arg: Option<Value<'a>>) -> anyhow::Result<Vec<T>> {
@@ -500,7 +1573,10 @@ fn required_list<'a, T: UnpackValue<'a>>(name: &str, arg: Option<Value<'a>>) ->
 	Ok(items)
 }
 
-fn optional_list<'module, T: UnpackValue<'module>>(name: &str, arg: Option<Value<'module>>) -> anyhow::Result<Vec<T>> {
+pub(super) fn optional_list<'module, T: UnpackValue<'module>>(
+	name: &str,
+	arg: Option<Value<'module>>,
+) -> anyhow::Result<Vec<T>> {
 	match arg {
 		None => Ok(Vec::new()),
 		Some(x) => Ok(UnpackList::unpack_value(x)
@@ -516,6 +1592,80 @@ fn optional_list<'module, T: UnpackValue<'module>>(name: &str, arg: Option<Value
 	}
 }
 
+/// Paths in a recipe added with `add_subdirectory()` are relative to that recipe, but targets resolve paths relative to the package root
+pub(super) fn package_paths(paths: Vec<String>, eval: &Evaluator) -> Vec<String> {
+	match RecipeContext::from_eval(eval) {
+		Some(context) => paths.into_iter().map(|x| context.package_path(x)).collect(),
+		None => paths,
+	}
+}
+
+fn visibility_arg(arg: Option<Value>) -> Result<Visibility, anyhow::Error> {
+	let visibility: Option<String> = Arguments::check_optional("visibility", arg)?;
+	match visibility.as_deref() {
+		None | Some("public") => Ok(Visibility::Public),
+		Some("private") => Ok(Visibility::Private),
+		Some(x) => err_msg(format!("Invalid visibility \"{}\". Expected \"public\" or \"private\"", x)),
+	}
+}
+
+fn platforms_arg(arg: Option<Value>) -> Result<Option<Vec<String>>, anyhow::Error> {
+	let platforms: Option<Vec<String>> = match arg {
+		None => None,
+		Some(x) => Some(UnpackList::unpack_value_err(x)?.items),
+	};
+	if let Some(unknown) = platforms.iter().flatten().find(|x| !PLATFORMS.contains(&x.as_str())) {
+		return err_msg(format!("Unknown platform \"{}\". Expected one of: {}", unknown, PLATFORMS.join(", ")));
+	}
+	Ok(platforms)
+}
+
+/// Whether a target restricted to `platforms` is built for `platform`.
+/// Disabled targets are dropped before generation and skipped when linked by other targets.
+fn is_enabled(project: &StarProject, name: &str, platforms: Option<&[String]>, platform: Option<&str>) -> bool {
+	let (platforms, platform) = match (platforms, platform) {
+		(Some(platforms), Some(platform)) => (platforms, platform),
+		(Some(_), None) => {
+			log::warn!(
+				"Could not determine the target platform. Enabling target \"{}\" of package \"{}\"",
+				name,
+				project.name
+			);
+			return true;
+		}
+		(None, _) => return true,
+	};
+	if platforms.iter().any(|x| x == platform) {
+		return true;
+	}
+	log::info!(
+		"Skipping target \"{}\" of package \"{}\": only enabled for {} (building for {})",
+		name,
+		project.name,
+		platforms.join(", "),
+		platform
+	);
+	false
+}
+
+/// `<PKG>_VERSION="<version>"` and `<PKG>_<OPTION>` for each bool package option that is enabled
+fn package_defines(project: &StarProject) -> Vec<String> {
+	let prefix = macro_prefix(&project.name);
+	let mut defines = Vec::new();
+	if let Some(version) = &project.version {
+		defines.push(format!("{}_VERSION=\"{}\"", prefix, version));
+	}
+	let mut enabled = project
+		.package_options
+		.iter()
+		.filter(|(_, val)| matches!(val, PkgOpt::Bool(true)))
+		.map(|(name, _)| format!("{}_{}", prefix, macro_prefix(name)))
+		.collect::<Vec<_>>();
+	enabled.sort();
+	defines.extend(enabled);
+	defines
+}
+
 fn generator_func<'module>(arg: Option<Value<'module>>, eval: &mut Evaluator<'module, '_>) -> Option<String> {
 	match arg {
 		None => None,
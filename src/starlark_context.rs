@@ -12,13 +12,37 @@ use starlark::{
 	},
 };
 
+use crate::{
+	project::ProjectInfo,
+	starlark_global::{StarGlobalOptions, StarPackageOptions},
+};
+
 const PAD: &str = "";
 const INDENT_SIZE: usize = 4;
 
+/// The configure state passed to `generator_vars` callbacks
 #[derive(Clone, Debug, Allocative, ProvidesStaticType, NoSerialize)]
 pub(crate) struct StarContext {
 	pub c_compiler: Option<StarContextCompiler>,
 	pub cpp_compiler: Option<StarContextCompiler>,
+	pub global_options: StarGlobalOptions,
+	pub package_options: StarPackageOptions,
+	/// `None` when the generator builds every profile (MSVC)
+	pub profile: Option<String>,
+	pub source_dir: String,
+	pub build_dir: String,
+}
+
+impl StarContext {
+	/// Returns a copy of this context with the package options and source directory of `project`
+	pub(crate) fn for_package(&self, project: &ProjectInfo) -> StarContext {
+		let source_dir = std::path::absolute(&project.path).unwrap_or_else(|_| project.path.clone());
+		StarContext {
+			package_options: StarPackageOptions(project.package_options.clone()),
+			source_dir: source_dir.to_string_lossy().to_string(),
+			..self.clone()
+		}
+	}
 }
 
 impl fmt::Display for StarContext {
@@ -38,6 +62,11 @@ impl fmt::Display for StarContext {
 		} else {
 			writeln!(f, "None")?;
 		}
+		writeln!(f, "{PAD:width_plus$}global_options: {:width_plus$}", self.global_options)?;
+		writeln!(f, "{PAD:width_plus$}package_options: {:width_plus$}", self.package_options)?;
+		writeln!(f, "{PAD:width_plus$}profile: {}", self.profile.as_deref().unwrap_or("None"))?;
+		writeln!(f, "{PAD:width_plus$}source_dir: {}", self.source_dir)?;
+		writeln!(f, "{PAD:width_plus$}build_dir: {}", self.build_dir)?;
 		write!(f, "{PAD:width$}}}")
 	}
 }
@@ -48,6 +77,11 @@ impl<'v> StarlarkValue<'v> for StarContext {
 		match attribute {
 			"c_compiler" => Some(heap.alloc(self.c_compiler.clone())),
 			"cpp_compiler" => Some(heap.alloc(self.cpp_compiler.clone())),
+			"global_options" => Some(heap.alloc(self.global_options.clone())),
+			"package_options" => Some(heap.alloc(self.package_options.clone())),
+			"profile" => Some(heap.alloc(self.profile.clone())),
+			"source_dir" => Some(heap.alloc(self.source_dir.clone())),
+			"build_dir" => Some(heap.alloc(self.build_dir.clone())),
 			_ => None,
 		}
 	}
@@ -55,13 +89,22 @@ impl<'v> StarlarkValue<'v> for StarContext {
 	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
 		#[allow(clippy::match_like_matches_macro)]
 		match attribute {
-			"c_compiler" | "cpp_compiler" => true,
+			"c_compiler" | "cpp_compiler" | "global_options" | "package_options" | "profile" | "source_dir"
+			| "build_dir" => true,
 			_ => false,
 		}
 	}
 
 	fn dir_attr(&self) -> Vec<String> {
-		let attrs = vec!["c_compiler".to_owned(), "cpp_compiler".to_owned()];
+		let attrs = vec![
+			"c_compiler".to_owned(),
+			"cpp_compiler".to_owned(),
+			"global_options".to_owned(),
+			"package_options".to_owned(),
+			"profile".to_owned(),
+			"source_dir".to_owned(),
+			"build_dir".to_owned(),
+		];
 		attrs
 	}
 }
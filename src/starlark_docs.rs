@@ -0,0 +1,23 @@
+use std::{path::Path, sync::Weak};
+
+use allocative::Allocative;
+
+use crate::{docs::Docs, misc::join_parent, project::Project};
+
+#[derive(Clone, Debug, Allocative)]
+pub(super) struct StarDocs {
+	pub name: String,
+	pub doxyfile: Option<String>,
+	pub sources: Vec<String>,
+}
+
+impl StarDocs {
+	pub fn as_docs(&self, parent_project: Weak<Project>, parent_path: &Path) -> Docs {
+		Docs {
+			parent_project,
+			name: self.name.clone(),
+			doxyfile: self.doxyfile.as_ref().map(|x| join_parent(parent_path, x)),
+			sources: self.sources.iter().map(|x| join_parent(parent_path, x)).collect(),
+		}
+	}
+}
@@ -27,8 +27,9 @@ use starlark::{
 
 use super::{
 	executable::Executable,
+	generator,
 	link_type::LinkPtr,
-	misc::{join_parent, Sources},
+	misc::{join_parent, SourceExtensions, Sources},
 	project::Project,
 	starlark_fmt::{format_link_targets, format_strings},
 	starlark_link_target::{PtrLinkTarget, StarLinkTarget},
@@ -38,6 +39,8 @@ use super::{
 #[derive(Debug, Allocative)]
 pub(super) struct StarExecutable {
 	pub parent_project: Weak<Mutex<StarProject>>,
+	/// The name of the parent project, which can be locked while recipes read attributes
+	pub package_name: String,
 
 	pub name: String,
 	pub sources: Vec<String>,
@@ -45,10 +48,34 @@ pub(super) struct StarExecutable {
 	pub include_dirs: Vec<String>,
 	pub defines: Vec<String>,
 	pub link_flags: Vec<String>,
+	pub frameworks: Vec<String>,
+	pub build_rpath: Vec<String>,
+	pub install_rpath: Vec<String>,
+	pub linker_script: Option<String>,
+	pub version_script: Option<String>,
+	pub map_file: bool,
+	pub binary_formats: Vec<String>,
 
 	pub generator_vars: Option<String>,
 
 	pub output_name: Option<String>,
+
+	pub depends: Vec<(String, String)>,
+	/// Commands run before the target's sources are compiled
+	pub pre_build: Vec<String>,
+	/// Commands run after the target is built
+	pub post_build: Vec<String>,
+	/// Files copied next to the executable, e.g. DLLs and data files
+	pub runtime_files: Vec<String>,
+	/// Added with `add_benchmark()`
+	pub benchmark: bool,
+	/// Added with `add_test()`
+	pub test: bool,
+	pub host: bool,
+	pub win32_subsystem: Option<String>,
+	pub entry_point: Option<String>,
+	pub win32_manifest: Option<String>,
+	pub win32_execution_level: Option<String>,
 }
 
 impl fmt::Display for StarExecutable {
@@ -86,14 +113,22 @@ impl StarExecutable {
 		parent_path: &Path,
 		link_map: &mut StarLinkTargetCache,
 		gen_name_map: &HashMap<String, OwnedFrozenValue>,
+		source_extensions: &SourceExtensions,
 	) -> Result<Executable, String> {
-		let sources = Sources::from_slice(&self.sources, parent_path)?;
+		let sources = Sources::from_slice(&self.sources, parent_path, source_extensions)?;
 		let mut links = Vec::<LinkPtr>::new();
 		for link in &self.links {
 			let ptr = PtrLinkTarget(link.clone());
 			let link_target = match link_map.get(&ptr) {
 				Some(x) => x,
-				None => link.as_link_target(parent_project.clone(), parent_path, ptr, link_map, gen_name_map)?,
+				None => link.as_link_target(
+					parent_project.clone(),
+					parent_path,
+					ptr,
+					link_map,
+					gen_name_map,
+					source_extensions,
+				)?,
 			};
 			links.push(link_target);
 		}
@@ -105,6 +140,13 @@ impl StarExecutable {
 			include_dirs: self.include_dirs.iter().map(|x| join_parent(parent_path, x)).collect(),
 			defines: self.defines.clone(),
 			link_flags: self.link_flags.clone(),
+			frameworks: self.frameworks.clone(),
+			build_rpath: self.build_rpath.clone(),
+			install_rpath: self.install_rpath.clone(),
+			linker_script: self.linker_script.as_ref().map(|x| join_parent(parent_path, x)),
+			version_script: self.version_script.as_ref().map(|x| join_parent(parent_path, x)),
+			map_file: self.map_file,
+			binary_formats: self.binary_formats.clone(),
 			generator_vars: match &self.generator_vars {
 				None => None,
 				Some(id) => match gen_name_map.get(id) {
@@ -113,6 +155,17 @@ impl StarExecutable {
 				},
 			},
 			output_name: self.output_name.clone(),
+			depends: self.depends.clone(),
+			pre_build: self.pre_build.clone(),
+			post_build: self.post_build.clone(),
+			runtime_files: self.runtime_files.iter().map(|x| join_parent(parent_path, x)).collect(),
+			benchmark: self.benchmark,
+			test: self.test,
+			host: self.host,
+			win32_subsystem: self.win32_subsystem.clone(),
+			entry_point: self.entry_point.clone(),
+			win32_manifest: self.win32_manifest.as_ref().map(|x| join_parent(parent_path, x)),
+			win32_execution_level: self.win32_execution_level.clone(),
 		})
 	}
 }
@@ -129,6 +182,9 @@ fn executable_methods() -> Option<&'static Methods> {
 	RES.methods(executable_methods_impl)
 }
 
+/// `path` is where the executable is built to, for use in build steps. It's only known once the build is generated.
+const ATTRIBUTES: [&str; 2] = ["include_dirs", "path"];
+
 #[derive(Debug, Allocative, ProvidesStaticType, NoSerialize)]
 pub(super) struct StarExecutableWrapper(pub(super) Arc<StarExecutable>);
 
@@ -147,17 +203,17 @@ impl<'v> StarlarkValue<'v> for StarExecutableWrapper {
 	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
 		match attribute {
 			"include_dirs" => Some(heap.alloc(self.0.include_dirs.clone())),
+			"path" => Some(heap.alloc(generator::executable_placeholder(&self.0.package_name, &self.0.name))),
 			_ => None,
 		}
 	}
 
 	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
-		attribute == "include_dirs"
+		ATTRIBUTES.contains(&attribute)
 	}
 
 	fn dir_attr(&self) -> Vec<String> {
-		let attrs = vec!["include_dirs".to_owned()];
-		attrs
+		ATTRIBUTES.map(str::to_owned).to_vec()
 	}
 }
 
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use starlark::{
 	environment::Module,
 	eval::Evaluator,
@@ -9,8 +11,12 @@ use starlark::{
 };
 
 use crate::{
+	export_header,
+	link_type::LinkPtr,
+	misc::join_parent,
 	starlark_context::StarContext, //
 	starlark_object_library::StarGeneratorVars,
+	target::{LinkTarget, Target},
 };
 
 pub(crate) fn eval_vars(func: &OwnedFrozenValue, ctx: StarContext, name: &str) -> Result<StarGeneratorVars, String> {
@@ -27,3 +33,58 @@ pub(crate) fn eval_vars(func: &OwnedFrozenValue, ctx: StarContext, name: &str) -
 	};
 	Ok(generator_vars)
 }
+
+/// Evaluates the generator functions of the interface libraries reachable through `links` and writes the export and
+/// config headers they provide. Include dirs are resolved against the package of the interface library that generated
+/// them. `ctx` is only called if one of the libraries has a generator function or generated header.
+pub(crate) fn eval_interface_vars(
+	links: &[LinkPtr],
+	ctx: impl Fn() -> Result<StarContext, String>,
+) -> Result<StarGeneratorVars, String> {
+	let mut vars = StarGeneratorVars::default();
+	let mut seen = Vec::new();
+	for link in links
+		.iter()
+		.flat_map(|x| std::iter::once(x.clone()).chain(x.public_links_recursive()))
+	{
+		let lib = match &link {
+			LinkPtr::Interface(x) => x.clone(),
+			_ => continue,
+		};
+		if seen.contains(&link) {
+			continue;
+		}
+		seen.push(link);
+		let project_info = &lib.project().info;
+		if let Some(base_name) = &lib.export_header {
+			let build_dir = PathBuf::from(ctx()?.build_dir);
+			vars.include_dirs
+				.push(export_header::write(&build_dir, &project_info.name, base_name)?);
+			vars.defines.push(export_header::static_define(base_name));
+		}
+		if let Some(header) = &lib.config_header {
+			let build_dir = PathBuf::from(ctx()?.build_dir);
+			vars.include_dirs.push(header.write(&build_dir, &project_info.name)?);
+		}
+		let gen_func = match &lib.generator_vars {
+			Some(x) => x,
+			None => continue,
+		};
+		let lib_vars = eval_vars(gen_func, ctx()?.for_package(project_info), "generator_vars")?;
+		if !lib_vars.sources.is_empty() {
+			return Err(format!(
+				"Generator function of interface library \"{}\" returned sources. Interface libraries can't have sources.",
+				lib.name
+			));
+		}
+		vars.include_dirs.extend(
+			lib_vars
+				.include_dirs
+				.iter()
+				.map(|x| join_parent(&project_info.path, x).full.to_string_lossy().to_string()),
+		);
+		vars.defines.extend(lib_vars.defines);
+		vars.link_flags.extend(lib_vars.link_flags);
+	}
+	Ok(vars)
+}
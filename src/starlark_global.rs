@@ -1,11 +1,20 @@
 use core::fmt;
-use std::collections::HashMap;
+use std::{
+	collections::{BTreeMap, HashMap},
+	path::Path,
+};
 
 use allocative::Allocative;
 use serde::Deserialize;
 use starlark::{
-	starlark_simple_value,
+	environment::{
+		Methods, //
+		MethodsBuilder,
+		MethodsStatic,
+	},
+	starlark_module, starlark_simple_value,
 	values::{
+		none::NoneOr,
 		AllocValue,
 		Heap, //
 		NoSerialize,
@@ -16,7 +25,7 @@ use starlark::{
 };
 
 use super::GlobalOptions;
-use crate::toolchain::Toolchain;
+use crate::{project::PackageMetadata, toolchain::Toolchain};
 
 const PAD: &str = "";
 const INDENT_SIZE: usize = 4;
@@ -40,6 +49,49 @@ impl fmt::Display for PkgOpt {
 	}
 }
 
+impl PkgOpt {
+	fn type_name(&self) -> &'static str {
+		match self {
+			PkgOpt::Bool(_) => "bool",
+			PkgOpt::Int(_) => "int",
+			PkgOpt::Float(_) => "float",
+			PkgOpt::String(_) => "string",
+		}
+	}
+
+	/// Parses text given on the command line as the type of this option.
+	/// String options take the text as-is, unless it is a quoted TOML string.
+	pub(crate) fn parse_as(&self, text: &str) -> Result<PkgOpt, String> {
+		let parsed = match self {
+			PkgOpt::Bool(_) => match text {
+				"true" => Some(PkgOpt::Bool(true)),
+				"false" => Some(PkgOpt::Bool(false)),
+				_ => None,
+			},
+			PkgOpt::Int(_) => text.parse().ok().map(PkgOpt::Int),
+			PkgOpt::Float(_) => text.parse().ok().map(PkgOpt::Float),
+			PkgOpt::String(_) => Some(PkgOpt::String(
+				String::deserialize(toml::de::ValueDeserializer::new(text)).unwrap_or_else(|_| text.to_owned()),
+			)),
+		};
+		match parsed {
+			Some(x) => Ok(x),
+			None => Err(format!("expected {}, found `{}`", self.type_name(), text)),
+		}
+	}
+
+	/// `value`, if it has the type of this option. Integers are accepted for float options.
+	pub(crate) fn check(&self, value: &PkgOpt) -> Result<PkgOpt, String> {
+		match (self, value) {
+			(PkgOpt::Float(_), PkgOpt::Int(x)) => Ok(PkgOpt::Float(*x as f64)),
+			(PkgOpt::String(_), PkgOpt::String(x)) => Ok(PkgOpt::String(x.clone())),
+			_ if self.type_name() == value.type_name() => Ok(value.clone()),
+			(_, PkgOpt::String(x)) => Err(format!("expected {}, found string \"{}\"", self.type_name(), x)),
+			_ => Err(format!("expected {}, found {} {}", self.type_name(), value.type_name(), value)),
+		}
+	}
+}
+
 impl<'de> Deserialize<'de> for PkgOpt {
 	fn deserialize<D>(d: D) -> Result<Self, <D as serde::Deserializer<'de>>::Error>
 	where
@@ -95,15 +147,20 @@ impl<'de> Deserialize<'de> for PkgOpt {
 
 #[derive(Debug, Allocative, ProvidesStaticType, NoSerialize)]
 pub(super) struct StarGlobal {
+	package: StarPackage,
 	global_options: StarGlobalOptions,
 	package_options: StarPackageOptions,
 	toolchain: StarToolchain,
+	profile: Option<StarProfile>,
+	env: StarEnv,
 }
 
 impl StarGlobal {
 	pub(super) fn new(
 		options: &GlobalOptions,
+		package: StarPackage,
 		package_options: HashMap<String, PkgOpt>,
+		env: StarEnv,
 		toolchain: &Toolchain,
 	) -> StarGlobal {
 		let c_compiler = toolchain.c_compiler.as_ref().map(|compiler| StarCompiler {
@@ -118,14 +175,22 @@ impl StarGlobal {
 			id: assembler.id(),
 			version: StarVersion::from_str(assembler.version()),
 		});
+		let profile = options.profile.as_ref().map(|name| {
+			let profile = toolchain.profile.get(name).cloned().unwrap_or_default();
+			StarProfile {
+				name: name.clone(),
+				c_compile_flags: profile.c_compile_flags,
+				cpp_compile_flags: profile.cpp_compile_flags,
+				nasm_assemble_flags: profile.nasm_assemble_flags,
+			}
+		});
 		StarGlobal {
-			global_options: StarGlobalOptions {
-				c_standard: options.c_standard.clone(),
-				cpp_standard: options.cpp_standard.clone(),
-				position_independent_code: options.position_independent_code,
-			},
+			package,
+			global_options: StarGlobalOptions::new(options),
 			package_options: StarPackageOptions(package_options),
 			toolchain: StarToolchain { c_compiler, cpp_compiler, nasm_assembler },
+			profile,
+			env,
 		}
 	}
 }
@@ -137,11 +202,22 @@ impl fmt::Display for StarGlobal {
 		write!(
 			f,
 			r#"Global {{
+{PAD:width_plus$}package: {:width_plus$},
 {PAD:width_plus$}global_options: {:width_plus$},
 {PAD:width_plus$}package_options: {:width_plus$},
 {PAD:width_plus$}toolchain: {:width_plus$},
+{PAD:width_plus$}profile: {},
+{PAD:width_plus$}env: {:width_plus$},
 {PAD:width$}}}"#,
-			self.global_options, self.package_options, self.toolchain,
+			self.package,
+			self.global_options,
+			self.package_options,
+			self.toolchain,
+			match &self.profile {
+				Some(x) => format!("{:width_plus$}", x),
+				None => "None".to_owned(),
+			},
+			self.env,
 		)
 	}
 }
@@ -150,9 +226,12 @@ impl fmt::Display for StarGlobal {
 impl<'v> StarlarkValue<'v> for StarGlobal {
 	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
 		match attribute {
+			"package" => Some(heap.alloc(self.package.clone())),
 			"global_options" => Some(heap.alloc(self.global_options.clone())),
 			"package_options" => Some(heap.alloc(self.package_options.clone())),
 			"toolchain" => Some(heap.alloc(self.toolchain.clone())),
+			"profile" => Some(heap.alloc(self.profile.clone())),
+			"env" => Some(heap.alloc(self.env.clone())),
 			_ => None,
 		}
 	}
@@ -160,16 +239,19 @@ impl<'v> StarlarkValue<'v> for StarGlobal {
 	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
 		#[allow(clippy::match_like_matches_macro)]
 		match attribute {
-			"global_options" | "package_options" | "toolchain" => true,
+			"package" | "global_options" | "package_options" | "toolchain" | "profile" | "env" => true,
 			_ => false,
 		}
 	}
 
 	fn dir_attr(&self) -> Vec<String> {
 		let attrs = vec![
+			"package".to_owned(),
 			"global_options".to_owned(),
 			"package_options".to_owned(),
 			"toolchain".to_owned(),
+			"profile".to_owned(),
+			"env".to_owned(),
 		];
 		attrs
 	}
@@ -177,11 +259,129 @@ impl<'v> StarlarkValue<'v> for StarGlobal {
 
 starlark_simple_value!(StarGlobal);
 
+/// The name, version and metadata of the package whose recipe is being evaluated
+#[derive(Clone, Debug, Allocative, ProvidesStaticType, NoSerialize)]
+pub(super) struct StarPackage {
+	name: String,
+	version: Option<StarVersion>,
+	metadata: PackageMetadata,
+	/// The package's directory in the build directory, with `/` separators. See `generator::layout`.
+	output_dir: String,
+}
+
+impl StarPackage {
+	pub(super) fn new(
+		name: &str,
+		version: Option<&str>,
+		metadata: &PackageMetadata,
+		output_dir: impl AsRef<Path>,
+	) -> StarPackage {
+		StarPackage {
+			name: name.to_owned(),
+			version: version.map(|x| StarVersion::from_str(x.to_owned())),
+			metadata: metadata.clone(),
+			output_dir: output_dir
+				.as_ref()
+				.iter()
+				.map(|x| x.to_string_lossy())
+				.collect::<Vec<_>>()
+				.join("/"),
+		}
+	}
+}
+
+impl fmt::Display for StarPackage {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+		let width = f.width().unwrap_or(0);
+		let width_plus = width + INDENT_SIZE;
+		write!(
+			f,
+			r#"Package {{
+{PAD:width_plus$}name: "{}",
+{PAD:width_plus$}version: {},
+{PAD:width_plus$}license: {},
+{PAD:width_plus$}description: {},
+{PAD:width_plus$}authors: {:?},
+{PAD:width_plus$}repository: {},
+{PAD:width_plus$}output_dir: "{}",
+{PAD:width$}}}"#,
+			self.name,
+			match &self.version {
+				Some(x) => format!("{:width_plus$}", x),
+				None => "None".to_owned(),
+			},
+			fmt_opt_str(&self.metadata.license),
+			fmt_opt_str(&self.metadata.description),
+			self.metadata.authors,
+			fmt_opt_str(&self.metadata.repository),
+			self.output_dir,
+		)
+	}
+}
+
+#[starlark::values::starlark_value(type = "Package")]
+impl<'v> StarlarkValue<'v> for StarPackage {
+	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
+		match attribute {
+			"name" => Some(heap.alloc(self.name.clone())),
+			"version" => Some(heap.alloc(self.version.clone())),
+			"license" => Some(heap.alloc(self.metadata.license.clone())),
+			"description" => Some(heap.alloc(self.metadata.description.clone())),
+			"authors" => Some(heap.alloc(self.metadata.authors.clone())),
+			"repository" => Some(heap.alloc(self.metadata.repository.clone())),
+			"output_dir" => Some(heap.alloc(self.output_dir.clone())),
+			_ => None,
+		}
+	}
+
+	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
+		#[allow(clippy::match_like_matches_macro)]
+		match attribute {
+			"name" | "version" | "license" | "description" | "authors" | "repository" | "output_dir" => true,
+			_ => false,
+		}
+	}
+
+	fn dir_attr(&self) -> Vec<String> {
+		let attrs = vec![
+			"name".to_owned(),
+			"version".to_owned(),
+			"license".to_owned(),
+			"description".to_owned(),
+			"authors".to_owned(),
+			"repository".to_owned(),
+			"output_dir".to_owned(),
+		];
+		attrs
+	}
+}
+
+starlark_simple_value!(StarPackage);
+
+fn fmt_opt_str(x: &Option<String>) -> String {
+	match x {
+		Some(x) => format!("\"{}\"", x),
+		None => "None".to_owned(),
+	}
+}
+
 #[derive(Clone, Debug, Allocative, ProvidesStaticType, NoSerialize)]
 pub(super) struct StarGlobalOptions {
 	c_standard: Option<String>,
 	cpp_standard: Option<String>,
 	position_independent_code: Option<bool>,
+	reproducible: Option<bool>,
+}
+
+impl StarGlobalOptions {
+	pub(super) fn new(options: &GlobalOptions) -> StarGlobalOptions {
+		StarGlobalOptions {
+			c_standard: options.c_standard.clone(),
+			cpp_standard: options.cpp_standard.clone(),
+			position_independent_code: options.position_independent_code,
+			reproducible: options.reproducible,
+		}
+	}
 }
 
 impl fmt::Display for StarGlobalOptions {
@@ -194,12 +394,14 @@ impl fmt::Display for StarGlobalOptions {
 {PAD:width_plus$}c_standard: {},
 {PAD:width_plus$}cpp_standard: {},
 {PAD:width_plus$}position_independent_code: {},
+{PAD:width_plus$}reproducible: {},
 {PAD:width$}}}"#,
 			self.c_standard.as_deref().unwrap_or("None"),
 			self.cpp_standard.as_deref().unwrap_or("None"),
 			self.position_independent_code
 				.map(|x| x.to_string())
-				.unwrap_or("None".to_owned())
+				.unwrap_or("None".to_owned()),
+			self.reproducible.map(|x| x.to_string()).unwrap_or("None".to_owned())
 		)
 	}
 }
@@ -211,6 +413,7 @@ impl<'v> StarlarkValue<'v> for StarGlobalOptions {
 			"c_standard" => Some(heap.alloc(self.c_standard.clone())),
 			"cpp_standard" => Some(heap.alloc(self.cpp_standard.clone())),
 			"position_independent_code" => Some(heap.alloc(self.position_independent_code)),
+			"reproducible" => Some(heap.alloc(self.reproducible)),
 			_ => None,
 		}
 	}
@@ -218,7 +421,7 @@ impl<'v> StarlarkValue<'v> for StarGlobalOptions {
 	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
 		#[allow(clippy::match_like_matches_macro)]
 		match attribute {
-			"c_standard" | "cpp_standard" | "position_independent_code" => true,
+			"c_standard" | "cpp_standard" | "position_independent_code" | "reproducible" => true,
 			_ => false,
 		}
 	}
@@ -228,6 +431,7 @@ impl<'v> StarlarkValue<'v> for StarGlobalOptions {
 			"c_standard".to_owned(),
 			"cpp_standard".to_owned(),
 			"position_independent_code".to_owned(),
+			"reproducible".to_owned(),
 		];
 		attrs
 	}
@@ -236,7 +440,7 @@ impl<'v> StarlarkValue<'v> for StarGlobalOptions {
 starlark_simple_value!(StarGlobalOptions);
 
 #[derive(Clone, Debug, Allocative, ProvidesStaticType, NoSerialize)]
-struct StarPackageOptions(HashMap<String, PkgOpt>);
+pub(super) struct StarPackageOptions(pub(super) HashMap<String, PkgOpt>);
 
 impl fmt::Display for StarPackageOptions {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
@@ -343,6 +547,122 @@ impl<'v> StarlarkValue<'v> for StarToolchain {
 
 starlark_simple_value!(StarToolchain);
 
+#[derive(Clone, Debug, Allocative, ProvidesStaticType, NoSerialize)]
+pub(super) struct StarProfile {
+	name: String,
+	c_compile_flags: Vec<String>,
+	cpp_compile_flags: Vec<String>,
+	nasm_assemble_flags: Vec<String>,
+}
+
+impl fmt::Display for StarProfile {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+		let width = f.width().unwrap_or(0);
+		let width_plus = width + INDENT_SIZE;
+		write!(
+			f,
+			r#"Profile {{
+{PAD:width_plus$}name: "{}",
+{PAD:width_plus$}c_compile_flags: {:?},
+{PAD:width_plus$}cpp_compile_flags: {:?},
+{PAD:width_plus$}nasm_assemble_flags: {:?},
+{PAD:width$}}}"#,
+			self.name, self.c_compile_flags, self.cpp_compile_flags, self.nasm_assemble_flags
+		)
+	}
+}
+
+#[starlark::values::starlark_value(type = "Profile")]
+impl<'v> StarlarkValue<'v> for StarProfile {
+	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
+		match attribute {
+			"name" => Some(heap.alloc(self.name.clone())),
+			"c_compile_flags" => Some(heap.alloc(self.c_compile_flags.clone())),
+			"cpp_compile_flags" => Some(heap.alloc(self.cpp_compile_flags.clone())),
+			"nasm_assemble_flags" => Some(heap.alloc(self.nasm_assemble_flags.clone())),
+			_ => None,
+		}
+	}
+
+	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
+		#[allow(clippy::match_like_matches_macro)]
+		match attribute {
+			"name" | "c_compile_flags" | "cpp_compile_flags" | "nasm_assemble_flags" => true,
+			_ => false,
+		}
+	}
+
+	fn dir_attr(&self) -> Vec<String> {
+		let attrs = vec![
+			"name".to_owned(),
+			"c_compile_flags".to_owned(),
+			"cpp_compile_flags".to_owned(),
+			"nasm_assemble_flags".to_owned(),
+		];
+		attrs
+	}
+}
+
+starlark_simple_value!(StarProfile);
+
+/// The environment variables a package allowlisted in its `catapult.toml`, captured when its recipe is evaluated
+#[derive(Clone, Debug, Allocative, ProvidesStaticType, NoSerialize)]
+pub(super) struct StarEnv {
+	package: String,
+	vars: BTreeMap<String, Option<String>>,
+}
+
+impl StarEnv {
+	pub(super) fn new(package: &str, allowlist: &[String]) -> StarEnv {
+		let vars = allowlist
+			.iter()
+			.map(|name| (name.clone(), std::env::var(name).ok()))
+			.collect();
+		StarEnv { package: package.to_owned(), vars }
+	}
+}
+
+impl fmt::Display for StarEnv {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Env {{ allow: {:?} }}", self.vars.keys().collect::<Vec<_>>())
+	}
+}
+
+#[starlark::values::starlark_value(type = "Env")]
+impl<'v> StarlarkValue<'v> for StarEnv {
+	fn get_methods() -> Option<&'static Methods> {
+		env_methods()
+	}
+}
+
+starlark_simple_value!(StarEnv);
+
+#[starlark_module]
+fn env_methods_impl(builder: &mut MethodsBuilder) {
+	fn get(this: &StarEnv, name: &str, default: Option<&str>) -> anyhow::Result<NoneOr<String>> {
+		let value = match this.vars.get(name) {
+			Some(x) => x,
+			None => {
+				return Err(anyhow::anyhow!(
+					"Environment variable \"{}\" is not allowed for package \"{}\". Add it to [env] allow in its catapult.toml",
+					name,
+					this.package
+				))
+			}
+		};
+		log::info!("Package \"{}\" read environment variable {}: {:?}", this.package, name, value);
+		match value.clone().or(default.map(str::to_owned)) {
+			Some(x) => Ok(NoneOr::Other(x)),
+			None => Ok(NoneOr::None),
+		}
+	}
+}
+
+fn env_methods() -> Option<&'static Methods> {
+	static RES: MethodsStatic = MethodsStatic::new();
+	RES.methods(env_methods_impl)
+}
+
 #[derive(Clone, Debug, Allocative, ProvidesStaticType, NoSerialize)]
 pub(super) struct StarCompiler {
 	id: String,
@@ -447,7 +767,7 @@ pub(super) struct StarVersion {
 }
 
 impl StarVersion {
-	fn from_str(ver: String) -> StarVersion {
+	pub(super) fn from_str(ver: String) -> StarVersion {
 		let str = ver.clone();
 		let (semver, revision) = ver.split_once('-').unwrap_or((&ver, ""));
 		let mut semver = semver.split('.');
@@ -504,3 +824,73 @@ impl<'v> StarlarkValue<'v> for StarVersion {
 }
 
 starlark_simple_value!(StarVersion);
+
+/// A program found with `find_program()`
+#[derive(Clone, Debug, Allocative, ProvidesStaticType, NoSerialize)]
+pub(super) struct StarProgram {
+	pub path: String,
+	pub version: Option<StarVersion>,
+}
+
+impl fmt::Display for StarProgram {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+		let width = f.width().unwrap_or(0);
+		let width_plus = width + INDENT_SIZE;
+		writeln!(f, "Program {{\n{PAD:width_plus$}path: \"{}\",", self.path)?;
+		match &self.version {
+			Some(x) => writeln!(f, "{PAD:width_plus$}version: {:width_plus$},", x)?,
+			None => writeln!(f, "{PAD:width_plus$}version: None,")?,
+		}
+		write!(f, "{PAD:width$}}}")
+	}
+}
+
+#[starlark::values::starlark_value(type = "Program")]
+impl<'v> StarlarkValue<'v> for StarProgram {
+	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
+		match attribute {
+			"path" => Some(heap.alloc(self.path.clone())),
+			"version" => Some(match &self.version {
+				Some(x) => heap.alloc(x.clone()),
+				None => Value::new_none(),
+			}),
+			_ => None,
+		}
+	}
+
+	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
+		#[allow(clippy::match_like_matches_macro)]
+		match attribute {
+			"path" | "version" => true,
+			_ => false,
+		}
+	}
+
+	fn dir_attr(&self) -> Vec<String> {
+		vec!["path".to_owned(), "version".to_owned()]
+	}
+}
+
+starlark_simple_value!(StarProgram);
+
+#[test]
+fn test_pkg_opt_types() {
+	let parse = |declared: PkgOpt, text: &str| declared.parse_as(text).map(|x| x.to_string());
+	assert_eq!(parse(PkgOpt::Bool(false), "true"), Ok("true".to_owned()));
+	assert!(parse(PkgOpt::Bool(false), "yes").is_err());
+	assert_eq!(parse(PkgOpt::Int(0), "-3"), Ok("-3".to_owned()));
+	assert!(parse(PkgOpt::Int(0), "1.5").is_err());
+	assert_eq!(parse(PkgOpt::Float(0.0), "1.5"), Ok("1.5".to_owned()));
+	assert_eq!(parse(PkgOpt::String(String::new()), "hello"), Ok("hello".to_owned()));
+	assert_eq!(parse(PkgOpt::String(String::new()), "\"3\""), Ok("3".to_owned()));
+
+	assert!(PkgOpt::Bool(false).check(&PkgOpt::Bool(true)).is_ok());
+	assert_eq!(
+		PkgOpt::Bool(false)
+			.check(&PkgOpt::String("true".to_owned()))
+			.map(|x| x.to_string()),
+		Err("expected bool, found string \"true\"".to_owned())
+	);
+	assert!(PkgOpt::Float(0.0).check(&PkgOpt::Int(2)).is_ok());
+	assert!(PkgOpt::Int(0).check(&PkgOpt::Float(2.0)).is_err());
+}
@@ -1,7 +1,7 @@
 use core::fmt;
 use std::{
 	collections::HashMap,
-	path::Path,
+	path::{Path, PathBuf},
 	sync::{Arc, Mutex, Weak},
 };
 
@@ -25,14 +25,19 @@ use starlark::{
 	},
 };
 
-use crate::{link_type::LinkPtr, misc::join_parent};
+use crate::{
+	config_header::ConfigHeader,
+	link_type::LinkPtr,
+	misc::{join_parent, SourceExtensions},
+};
 
 use super::{
 	interface_library::InterfaceLibrary, //
 	project::Project,
 	starlark_fmt::{format_link_targets, format_strings},
-	starlark_link_target::{PtrLinkTarget, StarLinkTarget},
+	starlark_link_target::{link_values, PtrLinkTarget, StarLinkTarget, Visibility},
 	starlark_project::{StarLinkTargetCache, StarProject},
+	toolchain::{probe::ProbeCompiler, Toolchain},
 };
 
 #[derive(Clone, Debug, ProvidesStaticType, Allocative)]
@@ -42,7 +47,14 @@ pub(super) struct StarIfaceLibrary {
 	pub links: Vec<Arc<dyn StarLinkTarget>>,
 	pub include_dirs: Vec<String>,
 	pub defines: Vec<String>,
+	pub compile_flags: Vec<String>,
 	pub link_flags: Vec<String>,
+	pub link_files: Vec<String>,
+	pub frameworks: Vec<String>,
+	pub generator_vars: Option<String>,
+	pub export_header: Option<String>,
+	pub config_header: Option<ConfigHeader>,
+	pub visibility: Visibility,
 }
 
 impl fmt::Display for StarIfaceLibrary {
@@ -54,13 +66,27 @@ impl fmt::Display for StarIfaceLibrary {
   links: [{}],
   include_dirs: [{}],
   defines: [{}],
+  compile_flags: [{}],
   link_flags: [{}],
+  link_files: [{}],
+  generator_vars: {},
+  export_header: {},
+  config_header: {},
 }}"#,
 			self.name,
 			format_link_targets(&self.links),
 			format_strings(&self.include_dirs),
 			format_strings(&self.defines),
-			format_strings(&self.link_flags)
+			format_strings(&self.compile_flags),
+			format_strings(&self.link_flags),
+			format_strings(&self.link_files),
+			if self.generator_vars.is_some() {
+				"(generated)"
+			} else {
+				"None"
+			},
+			self.export_header.as_deref().unwrap_or("None"),
+			self.config_header.as_ref().map_or("None", |x| x.name.as_str()),
 		)
 	}
 }
@@ -73,8 +99,9 @@ impl StarLinkTarget for StarIfaceLibrary {
 		ptr: PtrLinkTarget,
 		link_map: &mut StarLinkTargetCache,
 		gen_name_map: &HashMap<String, OwnedFrozenValue>,
+		source_extensions: &SourceExtensions,
 	) -> Result<LinkPtr, String> {
-		let data = self.as_library(parent, parent_path, link_map, gen_name_map)?;
+		let data = self.as_library(parent, parent_path, link_map, gen_name_map, source_extensions)?;
 		let arc = Arc::new(data);
 		// let ptr = PtrLinkTarget(arc.clone());
 		link_map.insert_interface(ptr, arc.clone());
@@ -92,6 +119,10 @@ impl StarLinkTarget for StarIfaceLibrary {
 	fn name(&self) -> String {
 		self.name.clone()
 	}
+
+	fn to_value<'v>(self: Arc<Self>, heap: &'v Heap) -> Value<'v> {
+		heap.alloc(StarIfaceLibWrapper(self))
+	}
 }
 
 impl StarIfaceLibrary {
@@ -101,6 +132,7 @@ impl StarIfaceLibrary {
 		parent_path: &Path,
 		link_map: &mut StarLinkTargetCache,
 		gen_name_map: &HashMap<String, OwnedFrozenValue>,
+		source_extensions: &SourceExtensions,
 	) -> Result<InterfaceLibrary, String> {
 		Ok(InterfaceLibrary {
 			parent_project: parent_project.clone(),
@@ -114,16 +146,38 @@ impl StarIfaceLibrary {
 					if let Some(lt) = link_map.get(&ptr) {
 						Ok(lt)
 					} else {
-						x.as_link_target(parent_project.clone(), parent_path, ptr, link_map, gen_name_map)
+						x.as_link_target(
+							parent_project.clone(),
+							parent_path,
+							ptr,
+							link_map,
+							gen_name_map,
+							source_extensions,
+						)
 					}
 				})
 				.collect::<Result<_, _>>()?,
 			defines: self.defines.clone(),
+			compile_flags: self.compile_flags.clone(),
 			link_flags: self.link_flags.clone(),
+			link_files: self.link_files.iter().map(PathBuf::from).collect(),
+			frameworks: self.frameworks.clone(),
+			generator_vars: match &self.generator_vars {
+				None => None,
+				Some(id) => match gen_name_map.get(id) {
+					Some(x) => Some(x.clone()),
+					None => return Err(format!("Could not find generator id in map: {}", id)),
+				},
+			},
+			export_header: self.export_header.clone(),
+			config_header: self.config_header.clone(),
 		})
 	}
 }
 
+/// What dependents of the library get from it
+const ATTRIBUTES: [&str; 5] = ["include_dirs", "defines", "compile_flags", "link_flags", "links"];
+
 #[derive(Clone, Debug, ProvidesStaticType, NoSerialize, Allocative)]
 pub(super) struct StarIfaceLibWrapper(pub(super) Arc<StarIfaceLibrary>);
 
@@ -141,16 +195,19 @@ impl<'v> StarlarkValue<'v> for StarIfaceLibWrapper {
 	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
 		match attribute {
 			"include_dirs" => Some(heap.alloc(self.0.public_includes_recursive())),
+			"defines" => Some(heap.alloc(self.0.defines.clone())),
+			"compile_flags" => Some(heap.alloc(self.0.compile_flags.clone())),
+			"link_flags" => Some(heap.alloc(self.0.link_flags.clone())),
+			"links" => Some(heap.alloc(link_values(&self.0.links, heap))),
 			_ => None,
 		}
 	}
 	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
-		attribute == "include_dirs"
+		ATTRIBUTES.contains(&attribute)
 	}
 
 	fn dir_attr(&self) -> Vec<String> {
-		let attrs = vec!["include_dirs".to_owned()];
-		attrs
+		ATTRIBUTES.map(str::to_owned).to_vec()
 	}
 }
 
@@ -168,6 +225,77 @@ fn library_methods() -> Option<&'static Methods> {
 	RES.methods(library_methods_impl)
 }
 
+/// Interface libraries recipes can link without adding them, e.g. `threads`. One is added to the package when a
+/// target of the package links it.
+pub(super) fn builtin_libraries(
+	project: &Arc<Mutex<StarProject>>,
+	toolchain: &Toolchain,
+) -> Vec<Arc<StarIfaceLibrary>> {
+	let compiler = toolchain.c_compiler.as_ref().or(toolchain.cpp_compiler.as_ref());
+	let threads_flags = compiler.map(|x| x.threads_flags()).unwrap_or_default();
+	let (openmp_compile_flags, openmp_link_flags) = compiler.and_then(|x| x.openmp_flags()).unwrap_or_default();
+	let builtin = |name: &str, compile_flags: Vec<String>, link_flags: Vec<String>| {
+		Arc::new(StarIfaceLibrary {
+			parent_project: Arc::downgrade(project),
+			name: name.to_owned(),
+			links: Vec::new(),
+			include_dirs: Vec::new(),
+			defines: Vec::new(),
+			compile_flags,
+			link_flags,
+			link_files: Vec::new(),
+			frameworks: Vec::new(),
+			generator_vars: None,
+			export_header: None,
+			config_header: None,
+			visibility: Visibility::Private,
+		})
+	};
+	vec![
+		builtin("threads", threads_flags.clone(), threads_flags),
+		builtin("openmp", openmp_compile_flags, openmp_link_flags),
+	]
+}
+
+/// Errors if the toolchain can't provide builtin library `name`, which package `package` links
+pub(super) fn check_builtin(package: &str, name: &str, toolchain: &Toolchain) -> Result<(), String> {
+	if name != "openmp" {
+		return Ok(());
+	}
+	let (compiler, ext) = match (&toolchain.c_compiler, &toolchain.cpp_compiler) {
+		(Some(x), _) => (x, ".c"),
+		(None, Some(x)) => (x, ".cpp"),
+		(None, None) => {
+			return Err(format!("Package \"{}\" links openmp, but the toolchain has no C or C++ compiler", package))
+		}
+	};
+	let (flags, _) = match compiler.openmp_flags() {
+		Some(x) => x,
+		None => {
+			return Err(format!(
+				"Package \"{}\" links openmp, but the {} compiler doesn't support OpenMP",
+				package,
+				compiler.id()
+			))
+		}
+	};
+	// The MSVC generator's compiler can't be run, but Visual Studio always has the OpenMP runtime
+	let probe = match ProbeCompiler::new(compiler.as_ref(), Vec::new()) {
+		Some(x) => x,
+		None => return Ok(()),
+	};
+	let source = "#include <omp.h>\nint main(void) { return omp_get_max_threads(); }\n";
+	match probe.compiles(source, ext, &flags, true)? {
+		true => Ok(()),
+		false => Err(format!(
+			"Package \"{}\" links openmp, but \"{}\" can't compile OpenMP code with {}. Is its OpenMP runtime (e.g. libgomp or libomp) installed?",
+			package,
+			compiler.cmd().join(" "),
+			flags.join(" ")
+		)),
+	}
+}
+
 // pub(crate) struct InterfaceLibPartial {
 // 	base: Arc<StarIfaceLibrary>,
 // 	links: Vec<LinkPtrPartial>,
@@ -6,10 +6,11 @@ use std::{
 };
 
 use allocative::Allocative;
-use starlark::values::OwnedFrozenValue;
+use starlark::values::{Heap, OwnedFrozenValue, Value};
 
 use super::{
 	link_type::LinkPtr,
+	misc::SourceExtensions,
 	project::Project, //
 	starlark_project::StarLinkTargetCache,
 };
@@ -22,12 +23,27 @@ pub(super) trait StarLinkTarget: Send + Sync + fmt::Debug + Allocative {
 		ptr: PtrLinkTarget,
 		link_map: &mut StarLinkTargetCache,
 		gen_name_map: &HashMap<String, OwnedFrozenValue>,
+		source_extensions: &SourceExtensions,
 	) -> Result<LinkPtr, String>;
 
 	fn name(&self) -> String;
+	/// The value recipes refer to the target by, e.g. a `StaticLibrary`
+	fn to_value<'v>(self: Arc<Self>, heap: &'v Heap) -> Value<'v>;
 	fn public_includes_recursive(&self) -> Vec<String>;
 }
 
+/// `links` as the values recipes refer to them by
+pub(super) fn link_values<'v>(links: &[Arc<dyn StarLinkTarget>], heap: &'v Heap) -> Vec<Value<'v>> {
+	links.iter().map(|x| x.clone().to_value(heap)).collect()
+}
+
+/// Whether a library can be accessed by dependent packages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Allocative)]
+pub(super) enum Visibility {
+	Public,
+	Private,
+}
+
 #[derive(Clone)]
 pub(super) struct PtrLinkTarget(pub Arc<dyn StarLinkTarget>);
 
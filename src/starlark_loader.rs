@@ -0,0 +1,212 @@
+use std::{
+	cell::RefCell,
+	collections::HashMap,
+	fs,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use anyhow::anyhow;
+use starlark::{
+	any::ProvidesStaticType,
+	environment::{FrozenModule, Globals, Module},
+	eval::{Evaluator, FileLoader},
+	syntax::{AstModule, Dialect},
+};
+
+use crate::{diagnostic::Diagnostic, starlark_api::ConfigureError, starlark_project::StarProject, BUILD_CATAPULT};
+
+/// Modules loaded while evaluating one package's recipe. Each file is only evaluated once.
+#[derive(Default)]
+pub(crate) struct LoadedModules {
+	modules: RefCell<HashMap<PathBuf, FrozenModule>>,
+	/// Files currently being evaluated, used to detect load cycles
+	loading: RefCell<Vec<PathBuf>>,
+	/// Recipes evaluated by `add_subdirectory()`, keyed by their directory relative to the package root
+	subdirectories: RefCell<HashMap<PathBuf, FrozenModule>>,
+}
+
+impl LoadedModules {
+	/// The files evaluated with `load()` and `add_subdirectory()`, for a package rooted at `package_root`
+	pub(crate) fn files(&self, package_root: &Path) -> Vec<PathBuf> {
+		let mut files: Vec<PathBuf> = self.modules.borrow().keys().cloned().collect();
+		files.extend(
+			self.subdirectories
+				.borrow()
+				.keys()
+				.map(|x| package_root.join(x).join(BUILD_CATAPULT)),
+		);
+		files.sort();
+		files
+	}
+
+	pub(crate) fn frozen_modules(&self) -> Vec<FrozenModule> {
+		let mut modules: Vec<FrozenModule> = self.modules.borrow().values().cloned().collect();
+		modules.extend(self.subdirectories.borrow().values().cloned());
+		modules
+	}
+}
+
+/// Everything needed to evaluate a recipe of the current package. Made available to builtins through `Evaluator::extra`.
+#[derive(ProvidesStaticType)]
+pub(crate) struct RecipeContext<'a> {
+	pub package_name: &'a str,
+	pub package_root: &'a Path,
+	/// The directory of the recipe being evaluated, relative to the package root
+	pub subdir: PathBuf,
+	pub globals: &'a Globals,
+	pub dialect: &'a Dialect,
+	pub loaded: &'a LoadedModules,
+	pub deps: &'a [Arc<StarProject>],
+}
+
+impl<'a> RecipeContext<'a> {
+	pub(crate) fn from_eval<'e>(eval: &'e Evaluator) -> Option<&'e RecipeContext<'e>> {
+		eval.extra.and_then(|x| x.downcast_ref::<RecipeContext>())
+	}
+
+	/// Makes a path from the recipe being evaluated relative to the package root
+	pub(crate) fn package_path(&self, path: String) -> String {
+		if self.subdir.as_os_str().is_empty() || Path::new(&path).is_absolute() {
+			path
+		} else {
+			self.subdir.join(path).to_string_lossy().to_string()
+		}
+	}
+
+	/// Evaluates `build.catapult` of a subdirectory of the current recipe, adding its targets to the same project
+	pub(crate) fn eval_subdirectory(&self, subdir: &str) -> anyhow::Result<FrozenModule> {
+		let subdir_path = self.subdir.join(subdir);
+		let full_dir = self.package_root.join(&subdir_path);
+		let (package_root, canonical_dir) = match (fs::canonicalize(self.package_root), fs::canonicalize(&full_dir)) {
+			(Ok(x), Ok(y)) => (x, y),
+			(_, Err(e)) | (Err(e), _) => {
+				return Err(anyhow!("Error adding subdirectory \"{}\" ({}): {}", subdir, full_dir.display(), e))
+			}
+		};
+		let subdir_path = match canonical_dir.strip_prefix(&package_root) {
+			Ok(x) if !x.as_os_str().is_empty() => x.to_owned(),
+			_ => return Err(anyhow!("Subdirectory must be within the package: {}", subdir)),
+		};
+		if self.loaded.subdirectories.borrow().contains_key(&subdir_path) {
+			return Err(anyhow!("Subdirectory \"{}\" has already been added", subdir_path.display()));
+		}
+
+		let recipe_path = canonical_dir.join(BUILD_CATAPULT);
+		let code = match fs::read_to_string(&recipe_path) {
+			Ok(x) => x,
+			Err(e) => return Err(anyhow!("Error reading \"{}\": {}", recipe_path.display(), e)),
+		};
+		let ast = AstModule::parse(&subdir_path.join(BUILD_CATAPULT).to_string_lossy(), code, self.dialect)
+			.map_err(|e| Diagnostic::from_starlark(&e))?;
+
+		let module = Module::new();
+		for dep_proj in self.deps {
+			let proj_value = module.heap().alloc(StarProject::clone(dep_proj));
+			module.set(&dep_proj.name, proj_value);
+		}
+		let child_context = RecipeContext {
+			package_name: self.package_name,
+			package_root: self.package_root,
+			subdir: subdir_path.clone(),
+			globals: self.globals,
+			dialect: self.dialect,
+			loaded: self.loaded,
+			deps: self.deps,
+		};
+		let loader = RecipeLoader {
+			package_root: self.package_root,
+			current_dir: self.package_root.join(&subdir_path),
+			globals: self.globals,
+			dialect: self.dialect,
+			loaded: self.loaded,
+		};
+		{
+			let mut eval = Evaluator::new(&module);
+			eval.set_loader(&loader);
+			eval.extra = Some(&child_context);
+			eval.eval_module(ast, self.globals)
+				.map_err(ConfigureError::from_eval_error)?;
+		}
+		let frozen = module.freeze()?;
+		self.loaded
+			.subdirectories
+			.borrow_mut()
+			.insert(subdir_path, frozen.clone());
+		Ok(frozen)
+	}
+}
+
+/// Resolves `load()` paths for a recipe.
+/// Paths starting with `//` are relative to the package root. Other paths (optionally prefixed with `:`) are relative to the directory of the file containing the `load()`.
+/// Loaded files must be within the package.
+pub(crate) struct RecipeLoader<'a> {
+	pub package_root: &'a Path,
+	pub current_dir: PathBuf,
+	pub globals: &'a Globals,
+	pub dialect: &'a Dialect,
+	pub loaded: &'a LoadedModules,
+}
+
+impl<'a> RecipeLoader<'a> {
+	fn resolve(&self, path: &str) -> anyhow::Result<PathBuf> {
+		let full_path = if let Some(rel) = path.strip_prefix("//") {
+			self.package_root.join(rel)
+		} else if Path::new(path).is_absolute() {
+			return Err(anyhow!("load() path must be relative to the package (\"//\") or the current file: {}", path));
+		} else {
+			self.current_dir.join(path.strip_prefix(':').unwrap_or(path))
+		};
+		let full_path = match fs::canonicalize(&full_path) {
+			Ok(x) => x,
+			Err(e) => return Err(anyhow!("Error loading \"{}\" ({}): {}", path, full_path.display(), e)),
+		};
+		let package_root = fs::canonicalize(self.package_root)?;
+		if !full_path.starts_with(&package_root) {
+			return Err(anyhow!("load() path is outside of the package: {}", path));
+		}
+		Ok(full_path)
+	}
+}
+
+impl<'a> FileLoader for RecipeLoader<'a> {
+	fn load(&self, path: &str) -> anyhow::Result<FrozenModule> {
+		let full_path = self.resolve(path)?;
+		if let Some(module) = self.loaded.modules.borrow().get(&full_path) {
+			return Ok(module.clone());
+		}
+		if self.loaded.loading.borrow().contains(&full_path) {
+			return Err(anyhow!("Cyclic load() of {}", full_path.display()));
+		}
+
+		let code = match fs::read_to_string(&full_path) {
+			Ok(x) => x,
+			Err(e) => return Err(anyhow!("Error reading \"{}\": {}", full_path.display(), e)),
+		};
+		let ast = AstModule::parse(&full_path.to_string_lossy(), code, self.dialect)
+			.map_err(|e| Diagnostic::from_starlark(&e))?;
+		let child_loader = RecipeLoader {
+			package_root: self.package_root,
+			current_dir: full_path.parent().map(Path::to_owned).unwrap_or_default(),
+			globals: self.globals,
+			dialect: self.dialect,
+			loaded: self.loaded,
+		};
+
+		self.loaded.loading.borrow_mut().push(full_path.clone());
+		let module = Module::new();
+		let result = {
+			let mut eval = Evaluator::new(&module);
+			eval.set_loader(&child_loader);
+			eval.eval_module(ast, self.globals)
+				.map(|_| ())
+				.map_err(ConfigureError::from_eval_error)
+		};
+		self.loaded.loading.borrow_mut().pop();
+		result?;
+
+		let frozen = module.freeze()?;
+		self.loaded.modules.borrow_mut().insert(full_path, frozen.clone());
+		Ok(frozen)
+	}
+}
@@ -28,11 +28,11 @@ use starlark::{
 
 use super::{
 	link_type::LinkPtr,
-	misc::{join_parent, Sources},
+	misc::{join_parent, SourceExtensions, Sources},
 	object_library::ObjectLibrary,
 	project::Project,
 	starlark_fmt::{format_link_targets, format_strings},
-	starlark_link_target::{PtrLinkTarget, StarLinkTarget},
+	starlark_link_target::{link_values, PtrLinkTarget, StarLinkTarget, Visibility},
 	starlark_project::{StarLinkTargetCache, StarProject},
 };
 
@@ -48,10 +48,19 @@ pub(super) struct StarObjectLibrary {
 	pub defines_private: Vec<String>,
 	pub defines_public: Vec<String>,
 	pub link_flags_public: Vec<String>,
+	pub link_flags_private: Vec<String>,
+	pub frameworks: Vec<String>,
 
 	pub generator_vars: Option<String>,
+	pub visibility: Visibility,
 
 	pub output_name: Option<String>,
+
+	pub depends: Vec<(String, String)>,
+	/// Commands run before the target's sources are compiled
+	pub pre_build: Vec<String>,
+	/// Commands run after the target is built
+	pub post_build: Vec<String>,
 }
 
 impl fmt::Display for StarObjectLibrary {
@@ -68,6 +77,7 @@ impl fmt::Display for StarObjectLibrary {
   defines_private: [{}],
   defines_public: [{}],
   link_flags_public: [{}],
+  link_flags_private: [{}],
   generator_vars: {},
 }}"#,
 			self.name,
@@ -79,6 +89,7 @@ impl fmt::Display for StarObjectLibrary {
 			format_strings(&self.defines_private),
 			format_strings(&self.defines_public),
 			format_strings(&self.link_flags_public),
+			format_strings(&self.link_flags_private),
 			if self.generator_vars.is_some() {
 				"(generated)"
 			} else {
@@ -96,8 +107,9 @@ impl StarLinkTarget for StarObjectLibrary {
 		ptr: PtrLinkTarget,
 		link_map: &mut StarLinkTargetCache,
 		gen_name_map: &HashMap<String, OwnedFrozenValue>,
+		source_extensions: &SourceExtensions,
 	) -> Result<LinkPtr, String> {
-		let arc = Arc::new(self.as_library(parent, parent_path, link_map, gen_name_map)?);
+		let arc = Arc::new(self.as_library(parent, parent_path, link_map, gen_name_map, source_extensions)?);
 		link_map.insert_object(ptr, arc.clone());
 		Ok(LinkPtr::Object(arc))
 	}
@@ -106,6 +118,10 @@ impl StarLinkTarget for StarObjectLibrary {
 		self.name.clone()
 	}
 
+	fn to_value<'v>(self: Arc<Self>, heap: &'v Heap) -> Value<'v> {
+		heap.alloc(StarObjLibWrapper(self))
+	}
+
 	fn public_includes_recursive(&self) -> Vec<String> {
 		self.include_dirs_private.clone()
 		// for link in &self.link_public {
@@ -122,11 +138,12 @@ impl StarObjectLibrary {
 		parent_path: &Path,
 		link_map: &mut StarLinkTargetCache,
 		gen_name_map: &HashMap<String, OwnedFrozenValue>,
+		source_extensions: &SourceExtensions,
 	) -> Result<ObjectLibrary, String> {
 		Ok(ObjectLibrary {
 			parent_project: parent_project.clone(),
 			name: self.name.clone(),
-			sources: Sources::from_slice(&self.sources, parent_path)?,
+			sources: Sources::from_slice(&self.sources, parent_path, source_extensions)?,
 			include_dirs_private: self
 				.include_dirs_private
 				.iter()
@@ -145,7 +162,14 @@ impl StarObjectLibrary {
 					if let Some(lt) = link_map.get(&ptr) {
 						Ok(lt)
 					} else {
-						x.as_link_target(parent_project.clone(), parent_path, ptr, link_map, gen_name_map)
+						x.as_link_target(
+							parent_project.clone(),
+							parent_path,
+							ptr,
+							link_map,
+							gen_name_map,
+							source_extensions,
+						)
 					}
 				})
 				.collect::<Result<_, _>>()?,
@@ -157,13 +181,22 @@ impl StarObjectLibrary {
 					if let Some(lt) = link_map.get(&ptr) {
 						Ok(lt)
 					} else {
-						x.as_link_target(parent_project.clone(), parent_path, ptr, link_map, gen_name_map)
+						x.as_link_target(
+							parent_project.clone(),
+							parent_path,
+							ptr,
+							link_map,
+							gen_name_map,
+							source_extensions,
+						)
 					}
 				})
 				.collect::<Result<_, _>>()?,
 			defines_private: self.defines_private.clone(),
 			defines_public: self.defines_public.clone(),
 			link_flags_public: self.link_flags_public.clone(),
+			link_flags_private: self.link_flags_private.clone(),
+			frameworks: self.frameworks.clone(),
 			generator_vars: match &self.generator_vars {
 				None => None,
 				Some(id) => match gen_name_map.get(id) {
@@ -172,10 +205,17 @@ impl StarObjectLibrary {
 				},
 			},
 			output_name: self.output_name.clone(),
+			depends: self.depends.clone(),
+			pre_build: self.pre_build.clone(),
+			post_build: self.post_build.clone(),
 		})
 	}
 }
 
+/// What dependents of the library get from it: its public include directories, defines, link flags and links, and
+/// the sources it's built from
+const ATTRIBUTES: [&str; 5] = ["include_dirs", "defines", "link_flags", "sources", "links"];
+
 #[derive(Clone, Debug, ProvidesStaticType, NoSerialize, Allocative)]
 pub(super) struct StarObjLibWrapper(pub(super) Arc<StarObjectLibrary>);
 
@@ -193,16 +233,19 @@ impl<'v> StarlarkValue<'v> for StarObjLibWrapper {
 	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
 		match attribute {
 			"include_dirs" => Some(heap.alloc(self.0.public_includes_recursive())),
+			"defines" => Some(heap.alloc(self.0.defines_public.clone())),
+			"link_flags" => Some(heap.alloc(self.0.link_flags_public.clone())),
+			"sources" => Some(heap.alloc(self.0.sources.clone())),
+			"links" => Some(heap.alloc(link_values(&self.0.link_public, heap))),
 			_ => None,
 		}
 	}
 	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
-		attribute == "include_dirs"
+		ATTRIBUTES.contains(&attribute)
 	}
 
 	fn dir_attr(&self) -> Vec<String> {
-		let attrs = vec!["include_dirs".to_owned()];
-		attrs
+		ATTRIBUTES.map(str::to_owned).to_vec()
 	}
 }
 
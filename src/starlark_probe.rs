@@ -0,0 +1,510 @@
+use core::cell::Cell;
+use std::{
+	path::PathBuf,
+	sync::{Arc, Mutex},
+};
+
+use starlark::{
+	environment::GlobalsBuilder,
+	eval::{
+		Arguments, //
+		Evaluator,
+		ParametersSpec,
+	},
+	typing::Ty,
+	values::{type_repr::StarlarkTypeRepr, FrozenValue, Value},
+};
+
+use crate::{
+	starlark_api::{err_msg, optional_list, package_paths},
+	starlark_global::{StarProgram, StarVersion},
+	starlark_interface_library::{StarIfaceLibWrapper, StarIfaceLibrary},
+	starlark_link_target::Visibility,
+	starlark_project::StarProject,
+	toolchain::{
+		compiler::Compiler,
+		probe::{self, ProbeCompiler},
+		Toolchain,
+	},
+	GlobalOptions,
+};
+
+/// The toolchain's C and C++ compilers, configured as they will be for the build, for recipes to test their capabilities
+pub(crate) struct Probes {
+	c: Result<ProbeCompiler, String>,
+	cpp: Result<ProbeCompiler, String>,
+	/// The target triple whose library file names `find_library()` looks for
+	target: String,
+	/// The toolchain's `tool_dirs`, searched by `find_program()` before `PATH`
+	tool_dirs: Vec<PathBuf>,
+}
+
+impl Probes {
+	pub(crate) fn new(global_options: &GlobalOptions, toolchain: &Toolchain) -> Probes {
+		let profile = global_options.profile.as_ref().and_then(|x| toolchain.profile.get(x));
+		let probe = |compiler: &Option<Box<dyn Compiler>>,
+		             lang: &str,
+		             std_flag: Option<Result<String, String>>,
+		             profile_flags: Option<&Vec<String>>| {
+			let compiler = match compiler {
+				Some(x) => x,
+				None => return Err(format!("No {} compiler is configured in the toolchain", lang)),
+			};
+			let mut flags = Vec::new();
+			if let Some(Ok(std_flag)) = std_flag {
+				flags.push(std_flag);
+			}
+			if let Some(profile_flags) = profile_flags {
+				flags.extend(profile_flags.iter().cloned());
+			}
+			match ProbeCompiler::new(compiler.as_ref(), flags) {
+				Some(x) => Ok(x),
+				None => Err("Compiler checks are not supported with the MSVC generator".to_owned()),
+			}
+		};
+		let c = probe(
+			&toolchain.c_compiler,
+			"C",
+			global_options
+				.c_standard
+				.as_ref()
+				.and_then(|std| toolchain.c_compiler.as_ref().map(|x| x.c_std_flag(std))),
+			profile.map(|x| &x.c_compile_flags),
+		);
+		let cpp = probe(
+			&toolchain.cpp_compiler,
+			"C++",
+			global_options
+				.cpp_standard
+				.as_ref()
+				.and_then(|std| toolchain.cpp_compiler.as_ref().map(|x| x.cpp_std_flag(std))),
+			profile.map(|x| &x.cpp_compile_flags),
+		);
+		let target = match toolchain.c_compiler.as_ref().or(toolchain.cpp_compiler.as_ref()) {
+			// The placeholder compiler of the MSVC generator doesn't know its target
+			Some(x) if x.id() == "MSVC" => "x86_64-pc-windows-msvc".to_owned(),
+			Some(x) => x.target(),
+			None => String::new(),
+		};
+		Probes { c, cpp, target, tool_dirs: toolchain.tool_dirs.clone() }
+	}
+
+	/// The library directories of the C compiler, or the C++ compiler if there's no C compiler. Empty if neither can
+	/// be run at configure time.
+	fn library_dirs(&self) -> Result<Vec<PathBuf>, String> {
+		match (&self.c, &self.cpp) {
+			(Ok(x), _) | (_, Ok(x)) => x.library_dirs(),
+			_ => Ok(Vec::new()),
+		}
+	}
+
+	/// Returns the compiler and source file extension for a language
+	pub(crate) fn get(&self, lang: Option<&str>) -> Result<(&ProbeCompiler, &'static str), anyhow::Error> {
+		let (compiler, ext) = match lang.unwrap_or("cpp") {
+			"c" => (&self.c, ".c"),
+			"cpp" => (&self.cpp, ".cpp"),
+			x => return err_msg(format!("Unknown language \"{}\". Expected \"c\" or \"cpp\"", x)),
+		};
+		match compiler {
+			Ok(x) => Ok((x, ext)),
+			Err(e) => err_msg(e.clone()),
+		}
+	}
+}
+
+struct ImplCheckCompileFlag {
+	signature: ParametersSpec<FrozenValue>,
+	probes: Arc<Probes>,
+}
+
+impl starlark::values::function::NativeFunc for ImplCheckCompileFlag {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 2] = self.signature.collect_into(parameters, eval.heap())?;
+		let flag: String = Arguments::check_required("flag", args[0].get())?;
+		let lang: Option<String> = Arguments::check_optional("lang", args[1].get())?;
+		let (compiler, ext) = self.probes.get(lang.as_deref())?;
+		let result = compiler.compiles("int main(void) { return 0; }\n", ext, std::slice::from_ref(&flag), true);
+		let result = result.map_err(anyhow::Error::msg)?;
+		log::info!("check_compile_flag({}): {}", flag, result);
+		Ok(Value::new_bool(result))
+	}
+}
+
+struct ImplCheckSourceCompiles {
+	signature: ParametersSpec<FrozenValue>,
+	probes: Arc<Probes>,
+}
+
+impl starlark::values::function::NativeFunc for ImplCheckSourceCompiles {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 3] = self.signature.collect_into(parameters, eval.heap())?;
+		let source: String = Arguments::check_required("source", args[0].get())?;
+		let lang: Option<String> = Arguments::check_optional("lang", args[1].get())?;
+		let flags: Vec<String> = optional_list("flags", args[2].get())?;
+		let (compiler, ext) = self.probes.get(lang.as_deref())?;
+		let result = compiler
+			.compiles(&source, ext, &flags, false)
+			.map_err(anyhow::Error::msg)?;
+		log::info!("check_source_compiles(): {}", result);
+		Ok(Value::new_bool(result))
+	}
+}
+
+struct ImplCheckIncludeFile {
+	signature: ParametersSpec<FrozenValue>,
+	probes: Arc<Probes>,
+}
+
+impl starlark::values::function::NativeFunc for ImplCheckIncludeFile {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 2] = self.signature.collect_into(parameters, eval.heap())?;
+		let header: String = Arguments::check_required("header", args[0].get())?;
+		let lang: Option<String> = Arguments::check_optional("lang", args[1].get())?;
+		let (compiler, ext) = self.probes.get(lang.as_deref())?;
+		let source = format!("#include <{}>\nint main(void) {{ return 0; }}\n", header);
+		let result = compiler
+			.compiles(&source, ext, &[], false)
+			.map_err(anyhow::Error::msg)?;
+		log::info!("check_include_file({}): {}", header, result);
+		Ok(Value::new_bool(result))
+	}
+}
+
+struct ImplCheckSymbolExists {
+	signature: ParametersSpec<FrozenValue>,
+	probes: Arc<Probes>,
+}
+
+impl starlark::values::function::NativeFunc for ImplCheckSymbolExists {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 3] = self.signature.collect_into(parameters, eval.heap())?;
+		let symbol: String = Arguments::check_required("symbol", args[0].get())?;
+		let headers: Vec<String> = optional_list("headers", args[1].get())?;
+		let lang: Option<String> = Arguments::check_optional("lang", args[2].get())?;
+		let (compiler, ext) = self.probes.get(lang.as_deref())?;
+		let result = compiler
+			.compiles(&symbol_source(&symbol, &headers), ext, &[], false)
+			.map_err(anyhow::Error::msg)?;
+		log::info!("check_symbol_exists({}): {}", symbol, result);
+		Ok(Value::new_bool(result))
+	}
+}
+
+struct ImplFindLibrary {
+	signature: ParametersSpec<FrozenValue>,
+	probes: Arc<Probes>,
+	project: Arc<Mutex<StarProject>>,
+}
+
+impl starlark::values::function::NativeFunc for ImplFindLibrary {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 3] = self.signature.collect_into(parameters, eval.heap())?;
+		let name: String = Arguments::check_required("name", args[0].get())?;
+		let paths: Vec<String> = optional_list("paths", args[1].get())?;
+		let required: Option<bool> = Arguments::check_optional("required", args[2].get())?;
+
+		let package_path = match self.project.lock() {
+			Ok(x) => x.path.clone(),
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		let mut dirs = package_paths(paths, eval)
+			.into_iter()
+			.map(|x| package_path.join(x))
+			.collect::<Vec<_>>();
+		dirs.extend(self.probes.library_dirs().map_err(anyhow::Error::msg)?);
+		let file_names = probe::library_file_names(&name, &self.probes.target);
+		let found = probe::find_library(&file_names, &dirs).map_err(anyhow::Error::msg)?;
+		log::info!("find_library({}): {}", name, found.as_ref().map_or("not found".into(), |x| x.to_string_lossy()));
+		let path = match found {
+			Some(x) => x,
+			None if required.unwrap_or(true) => {
+				return err_msg(format!(
+					"Library \"{}\" not found. Looked for {} in:\n  {}",
+					name,
+					file_names.join(", "),
+					dirs.iter()
+						.map(|x| x.display().to_string())
+						.collect::<Vec<_>>()
+						.join("\n  ")
+				))?
+			}
+			None => return Ok(Value::new_none()),
+		};
+
+		let mut project = match self.project.lock() {
+			Ok(x) => x,
+			Err(e) => return err_msg(e.to_string())?,
+		};
+		let lib = Arc::new(StarIfaceLibrary {
+			parent_project: Arc::downgrade(&self.project),
+			name,
+			links: Vec::new(),
+			include_dirs: Vec::new(),
+			defines: Vec::new(),
+			compile_flags: Vec::new(),
+			link_flags: Vec::new(),
+			link_files: vec![path.to_string_lossy().into_owned()],
+			frameworks: Vec::new(),
+			generator_vars: None,
+			export_header: None,
+			config_header: None,
+			visibility: Visibility::Public,
+		});
+		project.interface_libraries.push(lib.clone());
+
+		Ok(eval.heap().alloc(StarIfaceLibWrapper(lib)))
+	}
+}
+
+struct ImplFindProgram {
+	signature: ParametersSpec<FrozenValue>,
+	probes: Arc<Probes>,
+}
+
+impl starlark::values::function::NativeFunc for ImplFindProgram {
+	fn invoke<'module, 'loader, 'extra, 'args>(
+		&self,
+		eval: &mut Evaluator<'module, '_>,
+		parameters: &Arguments<'module, '_>,
+	) -> Result<Value<'module>, starlark::Error> {
+		let args: [Cell<Option<Value<'module>>>; 3] = self.signature.collect_into(parameters, eval.heap())?;
+		let name: String = Arguments::check_required("name", args[0].get())?;
+		let version_arg: Option<String> = Arguments::check_optional("version_arg", args[1].get())?;
+		let required: Option<bool> = Arguments::check_optional("required", args[2].get())?;
+
+		let found =
+			probe::find_program(&name, &self.probes.tool_dirs, version_arg.as_deref()).map_err(anyhow::Error::msg)?;
+		log::info!(
+			"find_program({}): {}",
+			name,
+			found.as_ref().map_or("not found".into(), |x| x.path.to_string_lossy())
+		);
+		match found {
+			Some(x) => Ok(eval.heap().alloc(StarProgram {
+				path: x.path.to_string_lossy().into_owned(),
+				version: x.version.map(StarVersion::from_str),
+			})),
+			None if required.unwrap_or(true) => {
+				err_msg(format!("Program \"{}\" not found in the toolchain's tool_dirs or PATH", name))?
+			}
+			None => Ok(Value::new_none()),
+		}
+	}
+}
+
+/// A program that only compiles if `symbol` is declared as a function, variable or macro by one of `headers`
+fn symbol_source(symbol: &str, headers: &[String]) -> String {
+	let mut source = String::new();
+	for header in headers {
+		source += &format!("#include <{}>\n", header);
+	}
+	source += &format!(
+		"int main(int argc, char **argv) {{\n\t(void)argv;\n#ifndef {symbol}\n\treturn ((int *)(&{symbol}))[argc];\n#else\n\t(void)argc;\n\treturn 0;\n#endif\n}}\n"
+	);
+	source
+}
+
+pub(crate) fn build_probe_api(probes: Arc<Probes>, project: &Arc<Mutex<StarProject>>, builder: &mut GlobalsBuilder) {
+	{
+		let function_name = "check_compile_flag";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("flag");
+		sig_builder.optional("lang");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([<&str>::starlark_type_repr(), <&str>::starlark_type_repr()]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <bool>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplCheckCompileFlag { signature, probes: probes.clone() },
+		);
+	}
+	{
+		let function_name = "check_source_compiles";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("source");
+		sig_builder.optional("lang");
+		sig_builder.optional("flags");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<&str>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <bool>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplCheckSourceCompiles { signature, probes: probes.clone() },
+		);
+	}
+	{
+		let function_name = "check_include_file";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("header");
+		sig_builder.optional("lang");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([<&str>::starlark_type_repr(), <&str>::starlark_type_repr()]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <bool>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplCheckIncludeFile { signature, probes: probes.clone() },
+		);
+	}
+	{
+		let function_name = "check_symbol_exists";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("symbol");
+		sig_builder.optional("headers");
+		sig_builder.optional("lang");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<&str>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <bool>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplCheckSymbolExists { signature, probes: probes.clone() },
+		);
+	}
+	{
+		let function_name = "find_library";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		sig_builder.optional("paths");
+		sig_builder.optional("required");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<Vec<&str>>::starlark_type_repr(),
+				<bool>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <Value>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplFindLibrary { signature, probes: probes.clone(), project: project.clone() },
+		);
+	}
+	{
+		let function_name = "find_program";
+		let mut sig_builder = ParametersSpec::new(function_name.to_owned());
+		sig_builder.no_more_positional_only_args();
+		sig_builder.required("name");
+		sig_builder.optional("version_arg");
+		sig_builder.optional("required");
+		let signature = sig_builder.finish();
+		let documentation = {
+			let parameter_types = Vec::<Ty>::from([
+				<&str>::starlark_type_repr(),
+				<&str>::starlark_type_repr(),
+				<bool>::starlark_type_repr(),
+			]);
+			starlark::values::function::NativeCallableRawDocs {
+				rust_docstring: None,
+				signature: signature.clone(),
+				parameter_types,
+				return_type: <Value>::starlark_type_repr(),
+				as_type: None,
+			}
+		};
+		builder.set_function(
+			function_name,
+			false,
+			documentation,
+			None,
+			None,
+			None,
+			ImplFindProgram { signature, probes },
+		);
+	}
+}
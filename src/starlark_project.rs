@@ -1,7 +1,7 @@
 use core::fmt;
 use std::{
 	collections::{HashMap, HashSet},
-	path::PathBuf,
+	path::{self, PathBuf},
 	sync::{Arc, Weak},
 };
 
@@ -28,29 +28,60 @@ use starlark::{
 use crate::{
 	interface_library::InterfaceLibrary,
 	link_type::LinkPtr,
+	misc::{self, SourceExtensions},
 	object_library::ObjectLibrary,
-	project::{Project, ProjectInfo},
-	starlark_executable::StarExecutable, //
+	project::{InstallRule, LintOptions, PackageMetadata, Project, ProjectInfo, SourceGroup},
+	starlark_docs::StarDocs,
+	starlark_executable::{StarExecutable, StarExecutableWrapper}, //
+	starlark_global::PkgOpt,
 	starlark_interface_library::{StarIfaceLibWrapper, StarIfaceLibrary},
-	starlark_link_target::PtrLinkTarget,
+	starlark_link_target::{PtrLinkTarget, Visibility},
 	starlark_object_library::{StarObjLibWrapper, StarObjectLibrary},
 	starlark_static_library::{StarStaticLibWrapper, StarStaticLibrary},
 	static_library::StaticLibrary,
+	GlobalOptions,
 };
 
 #[derive(Clone, Debug, ProvidesStaticType, NoSerialize, Allocative)]
 pub(super) struct StarProject {
 	pub name: String,
+	pub version: Option<String>,
+	pub metadata: PackageMetadata,
+	pub api_version: u32,
 	pub path: PathBuf,
 	pub dependencies: Vec<Arc<StarProject>>,
 	pub executables: Vec<Arc<StarExecutable>>,
 	pub static_libraries: Vec<Arc<StarStaticLibrary>>,
 	pub object_libraries: Vec<Arc<StarObjectLibrary>>,
 	pub interface_libraries: Vec<Arc<StarIfaceLibrary>>,
+	pub docs: Vec<StarDocs>,
+	pub package_options: HashMap<String, PkgOpt>,
+	pub aliases: Vec<StarAlias>,
+	pub source_groups: Vec<SourceGroup>,
+	pub lint: Option<LintOptions>,
+	pub installs: Vec<InstallRule>,
+	/// The manifest, recipe and loaded modules of the package
+	pub recipe_files: Vec<PathBuf>,
+	/// The hash the registry recorded for the package's source archive, if it was fetched from one
+	pub registry_hash: Option<String>,
 
 	pub generator_names: HashMap<String, OwnedFrozenValue>,
 }
 
+/// A library made accessible to dependent packages under another name with `alias()`
+#[derive(Clone, Debug, Allocative)]
+pub(super) struct StarAlias {
+	pub name: String,
+	pub actual: StarAliasTarget,
+}
+
+#[derive(Clone, Debug, Allocative)]
+pub(super) enum StarAliasTarget {
+	Static(Arc<StarStaticLibrary>),
+	Object(Arc<StarObjectLibrary>),
+	Interface(Arc<StarIfaceLibrary>),
+}
+
 impl fmt::Display for StarProject {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		write!(f, r#"Project {{}}"#)
@@ -71,53 +102,29 @@ impl<'v> StarlarkValue<'v> for StarProject {
 		project_methods()
 	}
 	fn get_attr(&self, attribute: &str, heap: &'v Heap) -> Option<Value<'v>> {
-		for lib in &self.static_libraries {
-			if lib.name == attribute {
-				return Some(heap.alloc(StarStaticLibWrapper(lib.clone())));
-			}
-		}
-		for lib in &self.object_libraries {
-			if lib.name == attribute {
-				return Some(heap.alloc(StarObjLibWrapper(lib.clone())));
-			}
-		}
-		for lib in &self.interface_libraries {
-			if lib.name == attribute {
-				return Some(heap.alloc(StarIfaceLibWrapper(lib.clone())));
-			}
-		}
-		None
+		self.target_value(attribute, false, heap)
 	}
 	fn has_attr(&self, attribute: &str, _: &'v Heap) -> bool {
-		for lib in &self.static_libraries {
-			if lib.name == attribute {
-				return true;
-			}
-		}
-		for lib in &self.object_libraries {
-			if lib.name == attribute {
-				return true;
-			}
-		}
-		for lib in &self.interface_libraries {
-			if lib.name == attribute {
-				return true;
-			}
-		}
-		false
+		self.dir_attr().iter().any(|x| x == attribute)
 	}
 
 	fn dir_attr(&self) -> Vec<String> {
 		let mut attrs = Vec::new();
-		for lib in &self.static_libraries {
+		for lib in self.public_static_libraries() {
 			attrs.push(lib.name.to_owned());
 		}
-		for lib in &self.object_libraries {
+		for lib in self.public_object_libraries() {
 			attrs.push(lib.name.to_owned());
 		}
-		for lib in &self.interface_libraries {
+		for lib in self.public_interface_libraries() {
 			attrs.push(lib.name.to_owned());
 		}
+		for alias in &self.aliases {
+			attrs.push(alias.name.to_owned());
+		}
+		for exe in &self.executables {
+			attrs.push(exe.name.to_owned());
+		}
 		attrs
 	}
 }
@@ -188,35 +195,317 @@ impl StarLinkTargetCache {
 }
 
 impl StarProject {
-	pub fn new(name: String, path: PathBuf, dependencies: Vec<Arc<StarProject>>) -> Self {
+	/// Adds the libraries of `builtins` that targets of the package link and returns them
+	pub fn add_linked_builtins(&mut self, builtins: &[Arc<StarIfaceLibrary>]) -> Vec<Arc<StarIfaceLibrary>> {
+		let links = self
+			.executables
+			.iter()
+			.flat_map(|x| &x.links)
+			.chain(
+				self.static_libraries
+					.iter()
+					.flat_map(|x| x.link_private.iter().chain(&x.link_public)),
+			)
+			.chain(
+				self.object_libraries
+					.iter()
+					.flat_map(|x| x.link_private.iter().chain(&x.link_public)),
+			)
+			.chain(self.interface_libraries.iter().flat_map(|x| &x.links))
+			.map(|x| PtrLinkTarget(x.clone()))
+			.collect::<HashSet<_>>();
+		let linked = builtins
+			.iter()
+			.filter(|x| links.contains(&PtrLinkTarget((*x).clone())))
+			.cloned()
+			.collect::<Vec<_>>();
+		self.interface_libraries.extend(linked.iter().cloned());
+		linked
+	}
+
+	/// Targets created with `visibility = "private"` are only accessible from within the package
+	fn public_static_libraries(&self) -> impl Iterator<Item = &Arc<StarStaticLibrary>> {
+		self.static_libraries
+			.iter()
+			.filter(|lib| lib.visibility == Visibility::Public)
+	}
+	fn public_object_libraries(&self) -> impl Iterator<Item = &Arc<StarObjectLibrary>> {
+		self.object_libraries
+			.iter()
+			.filter(|lib| lib.visibility == Visibility::Public)
+	}
+	fn public_interface_libraries(&self) -> impl Iterator<Item = &Arc<StarIfaceLibrary>> {
+		self.interface_libraries
+			.iter()
+			.filter(|lib| lib.visibility == Visibility::Public)
+	}
+
+	/// The libraries and aliases recipes of dependent packages can use through this package's value,
+	/// with their Starlark type
+	fn exports(&self) -> Vec<(String, String)> {
+		let mut exports = Vec::new();
+		exports.extend(
+			self.public_static_libraries()
+				.map(|x| (x.name.clone(), "StaticLibrary".to_owned())),
+		);
+		exports.extend(
+			self.public_object_libraries()
+				.map(|x| (x.name.clone(), "ObjectLibrary".to_owned())),
+		);
+		exports.extend(
+			self.public_interface_libraries()
+				.map(|x| (x.name.clone(), "InterfaceLibrary".to_owned())),
+		);
+		for alias in &self.aliases {
+			let ty = match alias.actual {
+				StarAliasTarget::Static(_) => "StaticLibrary",
+				StarAliasTarget::Object(_) => "ObjectLibrary",
+				StarAliasTarget::Interface(_) => "InterfaceLibrary",
+			};
+			exports.push((alias.name.clone(), ty.to_owned()));
+		}
+		exports.extend(
+			self.executables
+				.iter()
+				.map(|x| (x.name.clone(), "Executable".to_owned())),
+		);
+		exports
+	}
+
+	/// Looks up a library, alias or executable by name.
+	/// Private libraries are only found if `include_private` is set, i.e. when looking up from within the package.
+	pub fn target_value<'v>(&self, name: &str, include_private: bool, heap: &'v Heap) -> Option<Value<'v>> {
+		let visible = |visibility: Visibility| include_private || visibility == Visibility::Public;
+		if let Some(lib) = self
+			.static_libraries
+			.iter()
+			.find(|lib| lib.name == name && visible(lib.visibility))
+		{
+			return Some(heap.alloc(StarStaticLibWrapper(lib.clone())));
+		}
+		if let Some(lib) = self
+			.object_libraries
+			.iter()
+			.find(|lib| lib.name == name && visible(lib.visibility))
+		{
+			return Some(heap.alloc(StarObjLibWrapper(lib.clone())));
+		}
+		if let Some(lib) = self
+			.interface_libraries
+			.iter()
+			.find(|lib| lib.name == name && visible(lib.visibility))
+		{
+			return Some(heap.alloc(StarIfaceLibWrapper(lib.clone())));
+		}
+		if let Some(alias) = self.aliases.iter().find(|alias| alias.name == name) {
+			// An alias is only as visible as the target it names
+			return match &alias.actual {
+				StarAliasTarget::Static(x) if visible(x.visibility) => {
+					Some(heap.alloc(StarStaticLibWrapper(x.clone())))
+				}
+				StarAliasTarget::Object(x) if visible(x.visibility) => Some(heap.alloc(StarObjLibWrapper(x.clone()))),
+				StarAliasTarget::Interface(x) if visible(x.visibility) => {
+					Some(heap.alloc(StarIfaceLibWrapper(x.clone())))
+				}
+				_ => None,
+			};
+		}
+		let exe = self.executables.iter().find(|exe| exe.name == name)?;
+		Some(heap.alloc(StarExecutableWrapper(exe.clone())))
+	}
+
+	pub fn new(
+		name: String,
+		version: Option<String>,
+		metadata: PackageMetadata,
+		api_version: u32,
+		path: PathBuf,
+		dependencies: Vec<Arc<StarProject>>,
+		package_options: HashMap<String, PkgOpt>,
+	) -> Self {
 		StarProject {
 			name,
+			version,
+			metadata,
+			api_version,
 			path,
 			dependencies,
 			executables: Vec::new(),
 			static_libraries: Vec::new(),
 			object_libraries: Vec::new(),
 			interface_libraries: Vec::new(),
+			docs: Vec::new(),
+			package_options,
+			aliases: Vec::new(),
+			source_groups: Vec::new(),
+			lint: None,
+			installs: Vec::new(),
+			recipe_files: Vec::new(),
+			registry_hash: None,
 
 			generator_names: HashMap::new(),
 		}
 	}
 
-	pub fn into_project(self) -> Result<Arc<Project>, String> {
+	/// Converts the evaluated recipes of this package and its dependencies, classifying sources with
+	/// `source_extensions`. `global_options` decides which paths in recipes are checked.
+	pub fn into_project(
+		self,
+		global_options: &GlobalOptions,
+		source_extensions: &SourceExtensions,
+	) -> Result<Arc<Project>, String> {
 		let mut cache = StarLinkTargetCache::new();
-		self.as_project_inner(&mut cache)
+		self.as_project_inner(&mut cache, global_options, source_extensions)
 	}
 
-	fn as_project_inner(&self, link_map: &mut StarLinkTargetCache) -> Result<Arc<Project>, String> {
+	/// Errors listing every source, and with `strict_include_dirs` every include directory, of a target that doesn't
+	/// exist, naming the target and the parameter it was given in. The sources of targets with `pre_build` steps aren't
+	/// checked, as the steps may generate them.
+	fn check_paths_exist(&self, strict_sources: bool, strict_include_dirs: bool) -> Result<(), String> {
+		let mut paths = Vec::<(&str, &str, &String)>::new();
+		for exe in &self.executables {
+			if strict_sources && exe.pre_build.is_empty() {
+				paths.extend(exe.sources.iter().map(|x| (exe.name.as_str(), "sources", x)));
+			}
+			if strict_include_dirs {
+				paths.extend(exe.include_dirs.iter().map(|x| (exe.name.as_str(), "include_dirs", x)));
+			}
+		}
+		let libraries = self
+			.static_libraries
+			.iter()
+			.map(|x| (&x.name, &x.sources, &x.include_dirs_public, &x.include_dirs_private, &x.pre_build))
+			.chain(
+				self.object_libraries
+					.iter()
+					.map(|x| (&x.name, &x.sources, &x.include_dirs_public, &x.include_dirs_private, &x.pre_build)),
+			);
+		for (name, sources, include_dirs_public, include_dirs_private, pre_build) in libraries {
+			if strict_sources && pre_build.is_empty() {
+				paths.extend(sources.iter().map(|x| (name.as_str(), "sources", x)));
+			}
+			if strict_include_dirs {
+				paths.extend(
+					include_dirs_public
+						.iter()
+						.map(|x| (name.as_str(), "include_dirs_public", x)),
+				);
+				paths.extend(
+					include_dirs_private
+						.iter()
+						.map(|x| (name.as_str(), "include_dirs_private", x)),
+				);
+			}
+		}
+		if strict_include_dirs {
+			for lib in &self.interface_libraries {
+				paths.extend(lib.include_dirs.iter().map(|x| (lib.name.as_str(), "include_dirs", x)));
+			}
+		}
+		let missing = paths
+			.into_iter()
+			.filter(|(_, _, path)| !self.path.join(path).exists())
+			.map(|(target, param, path)| format!("\n    {}::{} {}: \"{}\"", self.name, target, param, path))
+			.collect::<String>();
+		if missing.is_empty() {
+			Ok(())
+		} else {
+			Err(format!("Paths given to targets do not exist:{}", missing))
+		}
+	}
+
+	/// Errors on the first source, include directory or runtime file of a target that is outside the package's
+	/// directory, whether it's absolute or climbs out with `..`
+	fn check_hermetic(&self) -> Result<(), String> {
+		let package_dir = match path::absolute(&self.path) {
+			Ok(x) => misc::lexically_normal(&x),
+			Err(e) => return Err(format!("Error resolving path \"{}\": {}", self.path.display(), e)),
+		};
+		let mut paths = Vec::<(&str, &str, &String)>::new();
+		for exe in &self.executables {
+			paths.extend(exe.sources.iter().map(|x| (exe.name.as_str(), "Source", x)));
+			paths.extend(
+				exe.include_dirs
+					.iter()
+					.map(|x| (exe.name.as_str(), "Include directory", x)),
+			);
+			paths.extend(exe.runtime_files.iter().map(|x| (exe.name.as_str(), "Runtime file", x)));
+			paths.extend(exe.win32_manifest.iter().map(|x| (exe.name.as_str(), "Manifest", x)));
+			paths.extend(
+				exe.linker_script
+					.iter()
+					.map(|x| (exe.name.as_str(), "Linker script", x)),
+			);
+			paths.extend(
+				exe.version_script
+					.iter()
+					.map(|x| (exe.name.as_str(), "Version script", x)),
+			);
+		}
+		for lib in &self.static_libraries {
+			paths.extend(lib.sources.iter().map(|x| (lib.name.as_str(), "Source", x)));
+			let include_dirs = lib.include_dirs_public.iter().chain(&lib.include_dirs_private);
+			paths.extend(include_dirs.map(|x| (lib.name.as_str(), "Include directory", x)));
+		}
+		for lib in &self.object_libraries {
+			paths.extend(lib.sources.iter().map(|x| (lib.name.as_str(), "Source", x)));
+			let include_dirs = lib.include_dirs_public.iter().chain(&lib.include_dirs_private);
+			paths.extend(include_dirs.map(|x| (lib.name.as_str(), "Include directory", x)));
+		}
+		for lib in &self.interface_libraries {
+			paths.extend(
+				lib.include_dirs
+					.iter()
+					.map(|x| (lib.name.as_str(), "Include directory", x)),
+			);
+		}
+		for (target, kind, path) in paths {
+			if !misc::lexically_normal(&package_dir.join(path)).starts_with(&package_dir) {
+				return Err(format!(
+					"{} \"{}\" of \"{}::{}\" is outside the package directory \"{}\". Hermetic builds only use files within their packages.",
+					kind,
+					path,
+					self.name,
+					target,
+					package_dir.display()
+				));
+			}
+		}
+		Ok(())
+	}
+
+	fn as_project_inner(
+		&self,
+		link_map: &mut StarLinkTargetCache,
+		global_options: &GlobalOptions,
+		source_extensions: &SourceExtensions,
+	) -> Result<Arc<Project>, String> {
+		if global_options.hermetic {
+			self.check_hermetic()?;
+		}
+		self.check_paths_exist(global_options.strict_sources, global_options.strict_include_dirs)?;
 		let mut project = //Arc::<Project>::new_cyclic(|weak_parent| 
 		Project {
-			info: Arc::new(ProjectInfo { name: self.name.clone(), path: self.path.clone() }),
-			dependencies: self.dependencies.iter().map(|x| x.as_project_inner(link_map)).collect::<Result<_,_>>()?,
+			info: Arc::new(ProjectInfo {
+				name: self.name.clone(),
+				path: self.path.clone(),
+				version: self.version.clone(),
+				metadata: self.metadata.clone(),
+				api_version: self.api_version,
+				exports: self.exports(),
+				package_options: self.package_options.clone(),
+				source_groups: self.source_groups.clone(),
+				lint: self.lint.clone(),
+				installs: self.installs.clone(),
+				recipe_files: self.recipe_files.clone(),
+				registry_hash: self.registry_hash.clone(),
+			}),
+			dependencies: self.dependencies.iter().map(|x| x.as_project_inner(link_map, global_options, source_extensions)).collect::<Result<_,_>>()?,
 			executables: self
 				.executables
 				.iter()
 				.map(|x| -> Result<Arc<_>,String> {
-					let data = x.as_executable(Weak::new(), &self.path, link_map, &self.generator_names)?;
+					let data = x.as_executable(Weak::new(), &self.path, link_map, &self.generator_names, source_extensions)?;
 					Ok(Arc::new(
 						data
 					))
@@ -231,7 +520,7 @@ impl StarProject {
 					if let Some(lib) = link_map.get_static(&ptr) {
 						Ok(lib.clone())
 					} else {
-						let data = x.as_library(Weak::new(), &self.path, link_map, &self.generator_names)?;
+						let data = x.as_library(Weak::new(), &self.path, link_map, &self.generator_names, source_extensions)?;
 						let arc = Arc::new(data);
 						link_map.insert_static(ptr, arc.clone());
 						Ok(arc)
@@ -246,7 +535,7 @@ impl StarProject {
 					if let Some(lib) = link_map.get_object(&ptr) {
 						Ok(lib.clone())
 					} else {
-						let data = x.as_library(Weak::new(), &self.path, link_map, &self.generator_names)?;
+						let data = x.as_library(Weak::new(), &self.path, link_map, &self.generator_names, source_extensions)?;
 						let arc = Arc::new(data);
 						link_map.insert_object(ptr, arc.clone());
 						Ok(arc)
@@ -261,13 +550,14 @@ impl StarProject {
 					if let Some(lib) = link_map.get_interface(&ptr) {
 						Ok(lib.clone())
 					} else {
-						let data = x.as_library(Weak::new(), &self.path, link_map, &self.generator_names)?;
+						let data = x.as_library(Weak::new(), &self.path, link_map, &self.generator_names, source_extensions)?;
 						let arc = Arc::new(data);
 						link_map.insert_interface(ptr, arc.clone());
 						Ok(arc)
 					}
 				})
 				.collect::<Result<_,_>>()?,
+			docs: self.docs.iter().map(|x| Arc::new(x.as_docs(Weak::new(), &self.path))).collect(),
 		}; //);
 
 		let ret = Arc::<Project>::new_cyclic(move |weak_parent: &Weak<Project>| -> Project {
@@ -277,6 +567,9 @@ impl StarProject {
 			for exe in &mut project.executables {
 				Arc::get_mut(exe).unwrap().set_parent(weak_parent.clone());
 			}
+			for docs in &mut project.docs {
+				Arc::get_mut(docs).unwrap().set_parent(weak_parent.clone());
+			}
 			for lib in &mut project.static_libraries {
 				let lib_mut = unsafe { &mut (*Arc::as_ptr(lib).cast_mut()) };
 				lib_mut.set_parent(weak_parent.clone());
@@ -0,0 +1,113 @@
+use std::{fs, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+	dep_graph::DepSource,
+	listing::OptionSummary,
+	project::PackageMetadata,
+	toolchain::{
+		compiler::{Assembler, Compiler},
+		Toolchain,
+	},
+};
+
+/// A resolved dependency, with the hash recorded in the lockfile for registry packages
+#[derive(Debug, Serialize)]
+pub struct DependencySummary {
+	pub name: String,
+	#[serde(flatten)]
+	pub source: DepSource,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub hash: Option<String>,
+	#[serde(flatten)]
+	pub metadata: PackageMetadata,
+}
+
+/// What resolving the dependency graph produced, beyond the project itself
+#[derive(Debug, Default)]
+pub struct Resolution {
+	pub dependencies: Vec<DependencySummary>,
+	pub options: Vec<OptionSummary>,
+}
+
+#[derive(Serialize)]
+struct ToolSummary {
+	id: String,
+	version: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	target: Option<String>,
+}
+
+impl ToolSummary {
+	fn compiler(compiler: &dyn Compiler) -> Self {
+		ToolSummary {
+			id: compiler.id(),
+			version: compiler.version(),
+			// The placeholder compiler of the MSVC generator doesn't know its target
+			target: if compiler.id() == "MSVC" {
+				None
+			} else {
+				Some(compiler.target())
+			},
+		}
+	}
+
+	fn assembler(assembler: &dyn Assembler) -> Self {
+		ToolSummary {
+			id: assembler.id(),
+			version: assembler.version(),
+			target: None,
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct ToolchainSummary {
+	path: String,
+	c_compiler: Option<ToolSummary>,
+	cpp_compiler: Option<ToolSummary>,
+	nasm_assembler: Option<ToolSummary>,
+	profiles: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+	generator: &'a str,
+	toolchain: ToolchainSummary,
+	profile: Option<&'a str>,
+	dependencies: &'a [DependencySummary],
+	options: &'a [OptionSummary],
+}
+
+/// Writes the final configuration as JSON, so CI pipelines can archive and diff it
+pub fn write(
+	path: &Path,
+	generator: &str,
+	toolchain_path: &Path,
+	toolchain: &Toolchain,
+	profile: Option<&str>,
+	resolution: &Resolution,
+) -> Result<(), String> {
+	let summary = Summary {
+		generator,
+		toolchain: ToolchainSummary {
+			path: toolchain_path.to_string_lossy().to_string(),
+			c_compiler: toolchain.c_compiler.as_deref().map(ToolSummary::compiler),
+			cpp_compiler: toolchain.cpp_compiler.as_deref().map(ToolSummary::compiler),
+			nasm_assembler: toolchain.nasm_assembler.as_deref().map(ToolSummary::assembler),
+			profiles: toolchain.profile.keys().cloned().collect(),
+		},
+		profile,
+		dependencies: &resolution.dependencies,
+		options: &resolution.options,
+	};
+	let json = match serde_json::to_string_pretty(&summary) {
+		Ok(x) => x,
+		Err(e) => return Err(format!("Error serializing configure summary: {}", e)),
+	};
+	match fs::write(path, json + "\n") {
+		Ok(()) => Ok(()),
+		Err(e) => Err(format!("Error writing {}: {}", path.display(), e)),
+	}
+}
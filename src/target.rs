@@ -1,8 +1,4 @@
-use core::{
-	cmp, //
-	fmt,
-	hash,
-};
+use core::fmt;
 use std::{
 	path::PathBuf, //
 	sync::Arc,
@@ -32,21 +28,3 @@ pub trait LinkTarget: Target {
 	fn public_links(&self) -> Vec<LinkPtr>;
 	fn public_links_recursive(&self) -> Vec<LinkPtr>;
 }
-
-#[derive(Clone)]
-pub(super) struct LinkTargetPtr(pub Arc<dyn LinkTarget>);
-
-impl cmp::PartialEq for LinkTargetPtr {
-	fn eq(&self, other: &LinkTargetPtr) -> bool {
-		core::ptr::eq(Arc::as_ptr(&self.0) as *const (), Arc::as_ptr(&other.0) as *const ())
-	}
-}
-impl cmp::Eq for LinkTargetPtr {}
-impl hash::Hash for LinkTargetPtr {
-	fn hash<H>(&self, hasher: &mut H)
-	where
-		H: std::hash::Hasher,
-	{
-		(Arc::as_ptr(&self.0) as *const ()).hash(hasher)
-	}
-}
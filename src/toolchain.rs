@@ -1,9 +1,15 @@
 pub(crate) mod compiler;
+pub(crate) mod probe;
 
-use std::{collections::BTreeMap, fs, path::Path};
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+};
 
 use serde::Deserialize;
 
+use crate::misc::SourceExtensions;
 use compiler::{
 	identify_assembler, //
 	identify_compiler,
@@ -21,7 +27,17 @@ pub struct ToolchainFile {
 	nasm_assembler: Option<Vec<String>>,
 	static_linker: Option<Vec<String>>,
 	exe_linker: Option<Vec<String>>,
+	command_wrapper: Option<CommandWrapper>,
+	source_extensions: Option<SourceExtensions>,
+	/// Directories `find_program()` searches before `PATH`, relative to the toolchain file
+	tool_dirs: Option<Vec<PathBuf>>,
+	/// Run on each linked executable, with `$out` replaced by its path
+	sign_command: Option<Vec<String>>,
+	bare_metal: Option<BareMetal>,
 	profile: Option<BTreeMap<String, Profile>>,
+	/// Use the built-in Debug, Release and RelWithDebInfo profiles if the file defines none. Defaults to true.
+	default_profiles: Option<bool>,
+	host_toolchain: Option<Box<ToolchainFile>>,
 	// env: Option<HashMap<String, String>>
 }
 
@@ -33,7 +49,121 @@ pub struct Toolchain {
 	pub nasm_assembler: Option<Box<dyn Assembler>>,
 	pub static_linker: Option<Vec<String>>,
 	pub exe_linker: Option<Box<dyn ExeLinker>>,
+	pub command_wrapper: Option<CommandWrapper>,
+	pub source_extensions: SourceExtensions,
+	/// Directories `find_program()` searches before `PATH`
+	pub tool_dirs: Vec<PathBuf>,
+	/// Signs each linked executable, e.g. with signtool or codesign. `$out` is replaced by the executable's path.
+	pub sign_command: Option<Vec<String>>,
+	/// Settings for targets without an operating system, from `[bare_metal]`
+	pub bare_metal: Option<BareMetal>,
 	pub profile: BTreeMap<String, Profile>,
+	/// Builds the executables that run during the build when cross-compiling, from `[host_toolchain]`
+	pub host: Option<Box<Toolchain>>,
+}
+
+impl Toolchain {
+	/// The name of the platform being built for, e.g. "linux", or None if it can't be determined
+	pub(crate) fn platform(&self) -> Option<&'static str> {
+		let compiler = self.c_compiler.as_ref().or(self.cpp_compiler.as_ref())?;
+		// The MSVC generator only targets Windows
+		if compiler.id() == "MSVC" {
+			return Some("windows");
+		}
+		compiler::platform_name(&compiler.target())
+	}
+
+	/// The profile named `name`, or an error listing the profiles the toolchain provides
+	pub fn get_profile(&self, name: &str) -> Result<&Profile, String> {
+		self.profile.get(name).ok_or_else(|| {
+			if self.profile.is_empty() {
+				format!("Selected profile \"{}\" is not provided by toolchain, which has no profiles", name)
+			} else {
+				format!(
+					"Selected profile \"{}\" is not provided by toolchain. Available profiles: {}",
+					name,
+					self.profile.keys().cloned().collect::<Vec<_>>().join(", ")
+				)
+			}
+		})
+	}
+}
+
+/// The Ninja rules that run a compiler or assembler
+const COMPILE_RULES: [&str; 3] = ["compile_c_object", "compile_cpp_object", "assemble_nasm_object"];
+/// The Ninja rules that run a linker
+const LINK_RULES: [&str; 2] = ["link_static_lib", "link_exe"];
+
+/// A launcher that build commands are prefixed with, such as icecc, distcc or a remote execution client
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandWrapper {
+	pub command: Vec<String>,
+	/// Wrap the static library and executable link commands too, not only compile commands
+	#[serde(default)]
+	pub link: bool,
+	/// Rules that aren't wrapped, e.g. `assemble_nasm_object` for a wrapper that only distributes C and C++
+	#[serde(default)]
+	pub exclude: Vec<String>,
+}
+
+impl CommandWrapper {
+	/// Whether the commands of the Ninja rule `rule_name` are prefixed with the wrapper
+	pub(crate) fn wraps(&self, rule_name: &str) -> bool {
+		let wrappable = COMPILE_RULES.contains(&rule_name) || (self.link && LINK_RULES.contains(&rule_name));
+		wrappable && !self.exclude.iter().any(|x| x == rule_name)
+	}
+
+	fn validate(&self) -> Result<(), String> {
+		if self.command.is_empty() {
+			return Err("command_wrapper.command is empty".to_owned());
+		}
+		for rule_name in &self.exclude {
+			if !COMPILE_RULES.contains(&rule_name.as_str()) && !LINK_RULES.contains(&rule_name.as_str()) {
+				return Err(format!(
+					"Unknown rule \"{}\" in command_wrapper.exclude. Expected one of: {}",
+					rule_name,
+					COMPILE_RULES
+						.iter()
+						.chain(&LINK_RULES)
+						.copied()
+						.collect::<Vec<_>>()
+						.join(", ")
+				));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Settings for targets without an operating system, e.g. microcontrollers built with arm-none-eabi-gcc
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BareMetal {
+	/// Link executables without the system startup files and libraries
+	#[serde(default)]
+	pub nostdlib: bool,
+	/// gcc specs files, e.g. "nano.specs" for newlib-nano
+	#[serde(default)]
+	pub specs: Vec<String>,
+	/// Converts executables to the `binary_formats` they list. Defaults to the objcopy with the compiler's prefix.
+	pub objcopy: Option<Vec<String>>,
+}
+
+impl BareMetal {
+	/// Flags added to C and C++ compile commands
+	pub(crate) fn compile_flags(&self) -> Vec<String> {
+		self.specs.iter().map(|x| format!("--specs={}", x)).collect()
+	}
+
+	/// Flags added to the link of every executable
+	pub(crate) fn link_flags(&self) -> Vec<String> {
+		let mut ret = self.compile_flags();
+		if self.nostdlib {
+			ret.push("-nostdlib".to_owned());
+		}
+		ret
+	}
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -44,15 +174,91 @@ pub struct Profile {
 	pub cpp_compile_flags: Vec<String>,
 	#[serde(default)]
 	pub nasm_assemble_flags: Vec<String>,
+	/// Instrument code for coverage and generate a `coverage` target that reports it
+	#[serde(default)]
+	pub coverage: bool,
+	/// Generate the targets added with `add_benchmark()`
+	#[serde(default)]
+	pub benchmarks: bool,
+	/// Write a linker map file next to every executable
+	#[serde(default)]
+	pub map_files: bool,
+	/// System libraries linked into every executable, e.g. "pthread" or "ws2_32"
+	#[serde(default)]
+	pub link_libraries: Vec<String>,
+	/// Flags added to the link command of every executable
+	#[serde(default)]
+	pub link_flags: Vec<String>,
+	/// Platform name -> link libraries and flags added when building for that platform
+	#[serde(default)]
+	pub platform: BTreeMap<String, PlatformLinkOptions>,
 	pub vcxproj: Option<VcxprojProfile>,
 }
 
+impl Profile {
+	/// `link_libraries`, followed by those for `platform`
+	pub(crate) fn link_libraries(&self, platform: Option<&str>) -> Vec<String> {
+		let mut ret = self.link_libraries.clone();
+		if let Some(x) = platform.and_then(|x| self.platform.get(x)) {
+			ret.extend(x.link_libraries.iter().cloned());
+		}
+		ret
+	}
+
+	/// `link_flags`, followed by those for `platform`
+	pub(crate) fn link_flags(&self, platform: Option<&str>) -> Vec<String> {
+		let mut ret = self.link_flags.clone();
+		if let Some(x) = platform.and_then(|x| self.platform.get(x)) {
+			ret.extend(x.link_flags.iter().cloned());
+		}
+		ret
+	}
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformLinkOptions {
+	#[serde(default)]
+	pub link_libraries: Vec<String>,
+	#[serde(default)]
+	pub link_flags: Vec<String>,
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct VcxprojProfile {
 	pub preprocessor_definitions: Vec<String>,
 	pub property_group: BTreeMap<String, String>,
 	pub cl_compile: BTreeMap<String, String>,
 	pub link: BTreeMap<String, String>,
+	/// Replaces the `IntDir` of every target. `{package}` and `{target}` are replaced with the directory of the
+	/// target's package (see `--layout`) and the target's name. MSBuild macros such as `$(Platform)` are left to MSBuild.
+	pub int_dir: Option<String>,
+	/// Replaces the `OutDir` of every target, like `int_dir` but without `{target}`
+	pub out_dir: Option<String>,
+}
+
+/// `static_linker` with the options that leave timestamps, user and group IDs and file modes out of archives:
+/// the `D` modifier for `ar`-like archivers and `/Brepro` for `lib` and `llvm-lib`
+pub(crate) fn deterministic_static_linker(static_linker: &[String]) -> Vec<String> {
+	let mut ret = static_linker.to_owned();
+	let Some(exe) = static_linker.first() else {
+		return ret;
+	};
+	let stem = Path::new(exe)
+		.file_stem()
+		.map(|x| x.to_string_lossy().to_lowercase())
+		.unwrap_or_default();
+	if stem == "lib" || stem == "llvm-lib" {
+		ret.push("/Brepro".to_owned());
+	} else if stem == "ar" || stem.ends_with("-ar") || stem == "emar" {
+		// The first argument holds the operation and its modifiers, e.g. "qc" or "-rcs"
+		if let Some(ops) = ret.get_mut(1) {
+			if ops.trim_start_matches('-').chars().all(|c| c.is_ascii_alphabetic()) && !ops.contains('D') {
+				ops.push('D');
+			}
+		}
+	}
+	ret
 }
 
 pub fn get_toolchain(toolchain_path: &Path, for_msvc: bool) -> Result<Toolchain, String> {
@@ -66,6 +272,51 @@ pub fn get_toolchain(toolchain_path: &Path, for_msvc: bool) -> Result<Toolchain,
 		Err(e) => return Err(format!("Error reading toolchain file \"{}\": {}", toolchain_path.display(), e)),
 	};
 
+	let mut toolchain = toolchain_from_file(toolchain_file, toolchain_path, for_msvc)?;
+	if let Some(host_file) = toolchain.host_file.take() {
+		if host_file.host_toolchain.is_some() {
+			return Err(format!(
+				"Error reading toolchain file \"{}\": host_toolchain can't contain a host_toolchain",
+				toolchain_path.display()
+			));
+		}
+		if for_msvc {
+			log::warn!("Ignoring host_toolchain. It is only used by the Ninja generator");
+		} else {
+			let host = match toolchain_from_file(*host_file, toolchain_path, false) {
+				Ok(x) => x,
+				Err(e) => return Err(format!("Error in host_toolchain: {}", e)),
+			};
+			toolchain.toolchain.host = Some(Box::new(host.toolchain));
+		}
+	}
+	Ok(toolchain.toolchain)
+}
+
+/// A toolchain and the `[host_toolchain]` section of its file, which is identified separately
+struct ParsedToolchain {
+	toolchain: Toolchain,
+	host_file: Option<Box<ToolchainFile>>,
+}
+
+const DEFAULT_PROFILES_GNU: &str = include_str!("toolchain/default_profiles_gnu.toml");
+const DEFAULT_PROFILES_MSVC: &str = include_str!("toolchain/default_profiles_msvc.toml");
+
+/// The profiles used when a toolchain file defines none, with MSVC's flags or those of GCC and Clang
+fn default_profiles(msvc: bool) -> BTreeMap<String, Profile> {
+	let content = if msvc {
+		DEFAULT_PROFILES_MSVC
+	} else {
+		DEFAULT_PROFILES_GNU
+	};
+	toml::from_str(content).expect("built-in default profiles are valid")
+}
+
+fn toolchain_from_file(
+	toolchain_file: ToolchainFile,
+	toolchain_path: &Path,
+	for_msvc: bool,
+) -> Result<ParsedToolchain, String> {
 	let msvc_platforms = toolchain_file.msvc_platforms.unwrap_or_default();
 
 	let nasm_assembler = match toolchain_file.nasm_assembler {
@@ -107,7 +358,45 @@ pub fn get_toolchain(toolchain_path: &Path, for_msvc: bool) -> Result<Toolchain,
 		None => None,
 	};
 
-	let profile = toolchain_file.profile.unwrap_or_default();
+	let command_wrapper = toolchain_file.command_wrapper;
+	if let Some(wrapper) = &command_wrapper {
+		if let Err(e) = wrapper.validate() {
+			return Err(format!("Error reading toolchain file \"{}\": {}", toolchain_path.display(), e));
+		}
+	}
+
+	let mut profile = toolchain_file.profile.unwrap_or_default();
+	if profile.is_empty() && toolchain_file.default_profiles.unwrap_or(true) {
+		let msvc = for_msvc || c_compiler.iter().chain(&cpp_compiler).any(|x| x.id() == "MSVC");
+		profile = default_profiles(msvc);
+	}
+	for (name, prof) in &profile {
+		if let Some(platform) = prof
+			.platform
+			.keys()
+			.find(|x| !compiler::PLATFORMS.contains(&x.as_str()))
+		{
+			return Err(format!(
+				"Error reading toolchain file \"{}\": Unknown platform \"{}\" in profile \"{}\". Expected one of: {}",
+				toolchain_path.display(),
+				platform,
+				name,
+				compiler::PLATFORMS.join(", ")
+			));
+		}
+	}
+
+	let bare_metal = toolchain_file.bare_metal;
+	if let Some(bare_metal) = &bare_metal {
+		if for_msvc {
+			log::warn!("Ignoring bare_metal. It is only used by the Ninja generator");
+		} else if !bare_metal.specs.is_empty() && c_compiler.iter().chain(&cpp_compiler).any(|x| x.id() != "gcc") {
+			return Err(format!(
+				"Error reading toolchain file \"{}\": bare_metal.specs is only supported by gcc",
+				toolchain_path.display()
+			));
+		}
+	}
 
 	// Sanity checks
 	if let Some(ref c_compiler) = c_compiler {
@@ -128,8 +417,86 @@ pub fn get_toolchain(toolchain_path: &Path, for_msvc: bool) -> Result<Toolchain,
 		cpp_compiler,
 		static_linker,
 		exe_linker,
+		command_wrapper,
+		source_extensions: toolchain_file.source_extensions.unwrap_or_default(),
+		tool_dirs: toolchain_file
+			.tool_dirs
+			.unwrap_or_default()
+			.into_iter()
+			.map(|x| toolchain_path.parent().unwrap_or(Path::new("")).join(x))
+			.collect(),
+		sign_command: toolchain_file.sign_command,
+		bare_metal,
 		profile,
+		host: None,
 	};
 
-	Ok(toolchain)
+	Ok(ParsedToolchain { toolchain, host_file: toolchain_file.host_toolchain })
+}
+
+#[test]
+fn test_command_wrapper() {
+	let wrapper = toml::from_str::<CommandWrapper>(
+		r#"
+command = ["icecc"]
+exclude = ["assemble_nasm_object"]
+"#,
+	)
+	.unwrap();
+	assert!(wrapper.validate().is_ok());
+	assert!(wrapper.wraps("compile_cpp_object"));
+	assert!(!wrapper.wraps("assemble_nasm_object"));
+	assert!(!wrapper.wraps("link_exe"));
+	assert!(CommandWrapper { link: true, ..wrapper.clone() }.wraps("link_exe"));
+	assert!(CommandWrapper { exclude: vec!["link".to_owned()], ..wrapper }
+		.validate()
+		.is_err());
+}
+
+#[test]
+fn test_deterministic_static_linker() {
+	let linker = |x: &[&str]| deterministic_static_linker(&x.iter().map(|x| x.to_string()).collect::<Vec<_>>());
+	assert_eq!(linker(&["ar", "qc"]), ["ar", "qcD"]);
+	assert_eq!(linker(&["/usr/bin/llvm-ar", "-rcs"]), ["/usr/bin/llvm-ar", "-rcsD"]);
+	assert_eq!(linker(&["x86_64-w64-mingw32-ar", "qcD"]), ["x86_64-w64-mingw32-ar", "qcD"]);
+	assert_eq!(linker(&["llvm-lib.exe"]), ["llvm-lib.exe", "/Brepro"]);
+	assert_eq!(linker(&["libtool", "-static"]), ["libtool", "-static"]);
+}
+
+#[test]
+fn test_profile_link_libraries() {
+	let profile = toml::from_str::<Profile>(
+		r#"
+link_libraries = ["m"]
+link_flags = ["-Wl,--as-needed"]
+[platform.windows]
+link_libraries = ["ws2_32"]
+"#,
+	)
+	.unwrap();
+	assert_eq!(profile.link_libraries(Some("windows")), ["m", "ws2_32"]);
+	assert_eq!(profile.link_libraries(Some("linux")), ["m"]);
+	assert_eq!(profile.link_flags(None), ["-Wl,--as-needed"]);
+}
+
+#[test]
+fn test_bare_metal() {
+	let bare_metal = toml::from_str::<BareMetal>(
+		r#"
+nostdlib = true
+specs = ["nano.specs", "nosys.specs"]
+"#,
+	)
+	.unwrap();
+	assert_eq!(bare_metal.compile_flags(), ["--specs=nano.specs", "--specs=nosys.specs"]);
+	assert_eq!(bare_metal.link_flags(), ["--specs=nano.specs", "--specs=nosys.specs", "-nostdlib"]);
+}
+
+#[test]
+fn test_default_profiles() {
+	for msvc in [false, true] {
+		let profiles = default_profiles(msvc);
+		assert_eq!(profiles.keys().collect::<Vec<_>>(), ["Debug", "RelWithDebInfo", "Release"]);
+		assert_eq!(profiles.values().all(|x| x.vcxproj.is_some()), msvc);
+	}
 }
@@ -6,11 +6,14 @@ mod nasm;
 
 use std::process;
 
+const APPLE_CLANG_ID: &str = "Apple clang version ";
 const CLANG_ID: &str = "clang version ";
 const EMSCRIPTEN_ID: &str = "emcc ";
 const GCC_ID: &str = "gcc version ";
 const NASM_ID: &str = "NASM version ";
 const TARGET_PREFIX: &str = "Target: ";
+const TARGET_FLAG: &str = "--target=";
+const COLLECT_GCC_PREFIX: &str = "COLLECT_GCC=";
 
 pub trait Assembler {
 	fn id(&self) -> String;
@@ -33,15 +36,145 @@ pub trait Compiler {
 	fn cpp_std_flag(&self, std: &str) -> Result<String, String>;
 	fn position_independent_code_flag(&self) -> Option<String>;
 	fn position_independent_executable_flag(&self) -> Option<String>;
-}
-
-pub trait StaticLinker {
-	fn cmd(&self) -> Vec<String>;
+	/// Flags to instrument code for coverage, or None if the compiler doesn't support coverage
+	fn coverage_flags(&self) -> Option<Vec<String>> {
+		None
+	}
+	/// Flags that keep absolute paths and timestamps out of the output. Each `(from, to)` in `path_maps`
+	/// replaces the `from` prefix of paths embedded in debug info and macros such as `__FILE__` with `to`.
+	fn reproducible_flags(&self, _path_maps: &[(String, String)]) -> Vec<String> {
+		Vec::new()
+	}
+	/// Flags that compile and link code using threads, empty if nothing is needed
+	fn threads_flags(&self) -> Vec<String> {
+		Vec::new()
+	}
+	/// The compile flags and link flags of OpenMP code, or None if the compiler doesn't support OpenMP
+	fn openmp_flags(&self) -> Option<(Vec<String>, Vec<String>)> {
+		None
+	}
 }
 
 pub trait ExeLinker {
 	fn cmd(&self) -> Vec<String>;
 	fn position_independent_executable_flag(&self) -> Option<String>;
+	/// Flags to link an Apple framework, or None if the linker doesn't support frameworks
+	fn framework_flags(&self, _framework: &str) -> Option<Vec<String>> {
+		None
+	}
+	/// Flags to link coverage instrumented code, or None if the linker doesn't support coverage.
+	/// Linkers that need a location for the coverage data write it to `data_dir`.
+	fn coverage_flags(&self, _data_dir: &str) -> Option<Vec<String>> {
+		None
+	}
+	/// Flags that keep timestamps out of linked executables
+	fn reproducible_flags(&self) -> Vec<String> {
+		Vec::new()
+	}
+	/// Flags that add `dir` to the run-time search path for shared libraries. Empty if the target has no rpath.
+	fn rpath_flags(&self, _dir: &str) -> Vec<String> {
+		Vec::new()
+	}
+	/// Flags that link with the linker script `script`, or None if the linker doesn't take linker scripts
+	fn linker_script_flags(&self, _script: &str) -> Option<Vec<String>> {
+		None
+	}
+	/// Flags that apply the version script `script` to the exported symbols, or None if the linker doesn't take
+	/// version scripts
+	fn version_script_flags(&self, _script: &str) -> Option<Vec<String>> {
+		None
+	}
+	/// Flags that write a map of the linked sections and symbols to `path`, or None if the linker can't
+	fn map_file_flags(&self, _path: &str) -> Option<Vec<String>> {
+		None
+	}
+	/// Flags that link a Windows executable for `subsystem` ("console" or "windows") and that start it at
+	/// `entry_point`, or None if the linker doesn't link Windows executables
+	fn win32_subsystem_flags(&self, _subsystem: Option<&str>, _entry_point: Option<&str>) -> Option<Vec<String>> {
+		None
+	}
+	/// Flags that embed the application manifest `manifest` in a Windows executable and request `execution_level`,
+	/// or None if the linker can't embed manifests
+	fn win32_manifest_flags(&self, _manifest: Option<&str>, _execution_level: Option<&str>) -> Option<Vec<String>> {
+		None
+	}
+}
+
+/// Flags for GCC-compatible compilers that map path prefixes and allow `__DATE__` and `__TIME__` to be redefined
+fn gnu_reproducible_flags(path_maps: &[(String, String)]) -> Vec<String> {
+	let mut flags = path_maps
+		.iter()
+		.map(|(from, to)| format!("-ffile-prefix-map={}={}", from, to))
+		.collect::<Vec<_>>();
+	flags.push("-Wno-builtin-macro-redefined".to_owned());
+	flags
+}
+
+/// `ExeLinker::win32_subsystem_flags()` of MinGW linkers
+fn gnu_win32_subsystem_flags(subsystem: Option<&str>, entry_point: Option<&str>) -> Vec<String> {
+	let mut flags = Vec::new();
+	if let Some(subsystem) = subsystem {
+		flags.push(format!("-Wl,--subsystem,{}", subsystem));
+	}
+	if let Some(entry_point) = entry_point {
+		flags.push(format!("-Wl,--entry,{}", entry_point));
+	}
+	flags
+}
+
+/// Whether the target triple is for Windows, with either the MSVC or the GNU (MinGW) ABI
+pub(crate) fn is_windows_target(target: &str) -> bool {
+	target.contains("-windows") || target.contains("-mingw") || target.contains("-cygwin")
+}
+
+/// Platform names accepted by the `platforms` parameter of targets
+pub(crate) const PLATFORMS: [&str; 10] = [
+	"android",
+	"emscripten",
+	"freebsd",
+	"ios",
+	"linux",
+	"macos",
+	"netbsd",
+	"openbsd",
+	"wasi",
+	"windows",
+];
+
+/// The platform a target triple is for, as named in `PLATFORMS`
+pub(crate) fn platform_name(target: &str) -> Option<&'static str> {
+	if is_windows_target(target) {
+		Some("windows")
+	} else if target.contains("-android") {
+		Some("android")
+	} else if target.contains("-apple-ios") {
+		Some("ios")
+	} else if target.contains("-apple-darwin") || target.contains("-apple-macos") {
+		Some("macos")
+	} else if target.contains("-emscripten") {
+		Some("emscripten")
+	} else if target.contains("-wasi") {
+		Some("wasi")
+	} else if target.contains("-linux") {
+		Some("linux")
+	} else {
+		["freebsd", "netbsd", "openbsd"]
+			.into_iter()
+			.find(|x| target.contains(x))
+	}
+}
+
+/// Whether the target triple uses the MSVC ABI, and so MSVC-style objects, libraries and linker
+pub(crate) fn is_msvc_target(target: &str) -> bool {
+	is_windows_target(target) && !target.contains("-mingw") && !target.contains("-cygwin") && !target.ends_with("-gnu")
+}
+
+/// The command that prints a tool's version.
+/// The tool runs in the C locale so the output can be parsed regardless of the user's language settings.
+fn version_command(exe: &str) -> process::Command {
+	let mut command = process::Command::new(exe);
+	command.arg("-v").env("LC_ALL", "C").env("LANG", "C");
+	command
 }
 
 pub(super) fn identify_assembler(cmd: Vec<String>) -> Result<Box<dyn Assembler>, String> {
@@ -50,7 +183,7 @@ pub(super) fn identify_assembler(cmd: Vec<String>) -> Result<Box<dyn Assembler>,
 		Some(x) => x,
 		None => return Err("Assembler command is empty".to_owned()),
 	};
-	let version_output = match process::Command::new(exe).arg("-v").output() {
+	let version_output = match version_command(exe).output() {
 		Ok(x) => {
 			if !x.status.success() {
 				return Err(format!("Assembler command returned non-success exit code: \"{} -v\": {}", exe, x.status));
@@ -88,7 +221,7 @@ pub(super) fn identify_compiler(cmd: Vec<String>) -> Result<Box<dyn Compiler>, S
 	};
 	// The `-v` flag is a shorthand for '--verbose' or '--version --verbose'
 	// and outputs to stderr instead of stdout
-	let version_output = match process::Command::new(exe).arg("-v").output() {
+	let version_output = match version_command(exe).output() {
 		Ok(x) => {
 			if !x.status.success() {
 				return Err(format!("Compiler command returned non-success exit code: \"{} -v\": {}", exe, x.status));
@@ -126,7 +259,7 @@ pub(super) fn identify_linker(cmd: Vec<String>) -> Result<Box<dyn ExeLinker>, St
 	};
 	// The `-v` flag is a shorthand for '--verbose' or '--version --verbose'
 	// and outputs to stderr instead of stdout
-	let version_output = match process::Command::new(exe).arg("-v").output() {
+	let version_output = match version_command(exe).output() {
 		Ok(x) => {
 			if !x.status.success() {
 				return Err(format!("Linker command returned non-success exit code: \"{} -v\": {}", exe, x.status));
@@ -160,34 +293,45 @@ fn identify_clang(first_line: &str, lines: &[&str], cmd: &[String]) -> Result<Op
 	if !first_line.starts_with(CLANG_ID) && !first_line.contains(&(String::from(" ") + CLANG_ID)) {
 		return Ok(None);
 	}
-	log::info!("compiler: clang");
+	let apple = first_line.starts_with(APPLE_CLANG_ID);
+	log::info!("compiler: {}", if apple { "apple-clang" } else { "clang" });
 	let version = find_version(first_line, CLANG_ID);
 	log::info!("compiler version: {}", version);
 
-	let target = match lines.iter().find(|l| l.starts_with(TARGET_PREFIX)) {
-		None => return Err(format!("Could not find \"{}\" in compiler output", TARGET_PREFIX)),
-		Some(x) => x[TARGET_PREFIX.len()..].to_owned(),
-	};
+	let target = find_target(lines)?;
 	log::info!("compiler target: {}", target);
 
-	let target_windows = target.contains("-windows-");
-	Ok(Some(Box::new(clang::Clang { cmd: cmd.to_vec(), version, target, target_windows })))
+	let target_windows = is_windows_target(&target);
+	Ok(Some(Box::new(clang::Clang { cmd: cmd.to_vec(), version, target, target_windows, apple })))
 }
 
 fn identify_gcc(lines: &[&str], cmd: &[String]) -> Result<Option<Box<gcc::Gcc>>, String> {
-	if let Some(line) = lines.iter().find(|l| l.starts_with(GCC_ID)) {
+	let version = if let Some(line) = lines.iter().find(|l| l.starts_with(GCC_ID)) {
+		Some(find_version(line, GCC_ID))
+	} else if lines.iter().any(|l| l.starts_with(COLLECT_GCC_PREFIX)) {
+		// Localized output, e.g. "gcc-Version 11.4.0 (...)" if the C locale wasn't honoured
+		lines
+			.iter()
+			.rev()
+			.find(|l| l.starts_with("gcc"))
+			.and_then(|l| {
+				l.split_whitespace()
+					.find(|x| x.starts_with(|c: char| c.is_ascii_digit()) && x.contains('.'))
+			})
+			.map(str::to_owned)
+	} else {
+		None
+	};
+	if let Some(version) = version {
 		log::info!("compiler: gcc");
 
-		let version = find_version(line, GCC_ID);
 		log::info!("compiler version: {}", version);
 
-		let target = match lines.iter().find(|l| l.starts_with(TARGET_PREFIX)) {
-			None => return Err(format!("Could not find \"{}\" in compiler output", TARGET_PREFIX)),
-			Some(x) => x[TARGET_PREFIX.len()..].to_owned(),
-		};
+		let target = find_target(lines)?;
 		log::info!("compiler target: {}", target);
 
-		Ok(Some(Box::new(gcc::Gcc { cmd: cmd.to_vec(), version, target })))
+		let target_windows = is_windows_target(&target);
+		Ok(Some(Box::new(gcc::Gcc { cmd: cmd.to_vec(), version, target, target_windows })))
 	} else {
 		Ok(None)
 	}
@@ -219,15 +363,27 @@ fn identify_emscripten(
 	.to_owned();
 	log::info!("compiler version: {}", version);
 
-	let target = match lines.iter().find(|l| l.starts_with(TARGET_PREFIX)) {
-		None => return Err(format!("Could not find \"{}\" in compiler output", TARGET_PREFIX)),
-		Some(x) => x[TARGET_PREFIX.len()..].to_owned(),
-	};
+	let target = find_target(lines)?;
 	log::info!("compiler target: {}", target);
 
 	Ok(Some(Box::new(emscripten::Emscripten { cmd: cmd.to_vec(), version, target })))
 }
 
+fn find_target(lines: &[&str]) -> Result<String, String> {
+	if let Some(x) = lines.iter().find(|l| l.starts_with(TARGET_PREFIX)) {
+		return Ok(x[TARGET_PREFIX.len()..].to_owned());
+	}
+	// The "Configured with:" line of localized gcc output still contains the configure flags
+	let configured_target = lines
+		.iter()
+		.flat_map(|l| l.split_whitespace())
+		.find_map(|x| x.strip_prefix(TARGET_FLAG));
+	match configured_target {
+		Some(x) => Ok(x.to_owned()),
+		None => Err(format!("Could not find \"{}\" in compiler output", TARGET_PREFIX)),
+	}
+}
+
 fn find_version(line: &str, ver_str: &str) -> String {
 	let bgn_idx = line.find(ver_str).unwrap() + ver_str.len();
 	let version = match line[bgn_idx..].find(' ') {
@@ -272,3 +428,109 @@ pub(super) fn msvc_compiler() -> Box<dyn Compiler> {
 // Thread model: posix
 // Supported LTO compression algorithms: zlib zstd
 // gcc version 11.4.0 (Ubuntu 11.4.0-1ubuntu1~22.04)
+
+#[test]
+fn test_identify_gcc() {
+	let cmd = vec!["g++".to_owned()];
+	let english = [
+		"Using built-in specs.",
+		"COLLECT_GCC=g++",
+		"Target: x86_64-linux-gnu",
+		"Configured with: ../src/configure -v --prefix=/usr --build=x86_64-linux-gnu --host=x86_64-linux-gnu --target=x86_64-linux-gnu",
+		"Thread model: posix",
+		"gcc version 11.4.0 (Ubuntu 11.4.0-1ubuntu1~22.04)",
+	];
+	let gcc = identify_gcc(&english, &cmd).unwrap().unwrap();
+	assert_eq!(gcc.version(), "11.4.0");
+	assert_eq!(gcc.target(), "x86_64-linux-gnu");
+
+	let german = [
+		"Es werden eingebaute Spezifikationen verwendet.",
+		"COLLECT_GCC=g++",
+		"Ziel: x86_64-linux-gnu",
+		"Konfiguriert mit: ../src/configure -v --prefix=/usr --build=x86_64-linux-gnu --host=x86_64-linux-gnu --target=x86_64-linux-gnu",
+		"Thread-Modell: posix",
+		"gcc-Version 11.4.0 (Ubuntu 11.4.0-1ubuntu1~22.04)",
+	];
+	let gcc = identify_gcc(&german, &cmd).unwrap().unwrap();
+	assert_eq!(gcc.version(), "11.4.0");
+	assert_eq!(gcc.target(), "x86_64-linux-gnu");
+
+	let spanish = [
+		"Usando especificaciones internas.",
+		"COLLECT_GCC=gcc",
+		"Objetivo: aarch64-linux-gnu",
+		"Configurado con: ../src/configure -v --build=aarch64-linux-gnu --host=aarch64-linux-gnu --target=aarch64-linux-gnu",
+		"Modelo de hilos: posix",
+		"gcc versión 12.2.0 (Debian 12.2.0-14)",
+	];
+	let gcc = identify_gcc(&spanish, &cmd).unwrap().unwrap();
+	assert_eq!(gcc.version(), "12.2.0");
+	assert_eq!(gcc.target(), "aarch64-linux-gnu");
+
+	assert!(identify_gcc(&["clang version 16.0.1", "Target: x86_64-pc-windows-msvc"], &cmd)
+		.unwrap()
+		.is_none());
+}
+
+#[test]
+fn test_version_command_locale() {
+	let command = version_command("cc");
+	let envs = command.get_envs().collect::<Vec<_>>();
+	assert!(envs.contains(&("LC_ALL".as_ref(), Some("C".as_ref()))));
+	assert!(envs.contains(&("LANG".as_ref(), Some("C".as_ref()))));
+}
+
+#[test]
+fn test_identify_apple_clang_and_mingw() {
+	let cmd = vec!["clang++".to_owned()];
+	let apple = [
+		"Apple clang version 15.0.0 (clang-1500.1.0.2.5)",
+		"Target: arm64-apple-darwin23.2.0",
+		"Thread model: posix",
+		"InstalledDir: /Library/Developer/CommandLineTools/usr/bin",
+	];
+	let clang = identify_clang(apple[0], &apple, &cmd).unwrap().unwrap();
+	assert_eq!(Compiler::id(clang.as_ref()), "apple-clang");
+	assert_eq!(clang.version(), "15.0.0");
+	assert_eq!(ExeLinker::cmd(clang.as_ref()), cmd);
+	assert_eq!(clang.framework_flags("CoreFoundation").unwrap(), ["-framework", "CoreFoundation"]);
+	assert_eq!(clang.rpath_flags("@loader_path/../lib"), ["-Wl,-rpath,@loader_path/../lib"]);
+	let (openmp_compile_flags, openmp_link_flags) = clang.openmp_flags().unwrap();
+	assert_eq!(openmp_compile_flags, ["-Xpreprocessor", "-fopenmp"]);
+	assert_eq!(openmp_link_flags, ["-lomp"]);
+
+	let mingw = [
+		"Using built-in specs.",
+		"COLLECT_GCC=x86_64-w64-mingw32-g++",
+		"Target: x86_64-w64-mingw32",
+		"Thread model: posix",
+		"gcc version 12.2.0 (GCC)",
+	];
+	let gcc = identify_gcc(&mingw, &cmd).unwrap().unwrap();
+	assert_eq!(Compiler::id(gcc.as_ref()), "gcc");
+	assert_eq!(Compiler::position_independent_executable_flag(gcc.as_ref()), None);
+	assert_eq!(gcc.position_independent_code_flag(), None);
+	assert!(gcc.framework_flags("CoreFoundation").is_none());
+
+	assert!(is_msvc_target("x86_64-pc-windows-msvc"));
+	assert!(!is_msvc_target("x86_64-w64-windows-gnu"));
+	assert!(is_windows_target("x86_64-w64-windows-gnu"));
+	assert!(is_windows_target("x86_64-w64-mingw32"));
+	assert!(!is_windows_target("x86_64-linux-gnu"));
+	assert!(!is_windows_target("arm64-apple-darwin23.2.0"));
+}
+
+#[test]
+fn test_platform_name() {
+	assert_eq!(platform_name("x86_64-linux-gnu"), Some("linux"));
+	assert_eq!(platform_name("x86_64-pc-linux-gnu"), Some("linux"));
+	assert_eq!(platform_name("aarch64-linux-android21"), Some("android"));
+	assert_eq!(platform_name("arm64-apple-darwin23.2.0"), Some("macos"));
+	assert_eq!(platform_name("arm64-apple-ios17.0"), Some("ios"));
+	assert_eq!(platform_name("x86_64-w64-mingw32"), Some("windows"));
+	assert_eq!(platform_name("x86_64-pc-windows-msvc"), Some("windows"));
+	assert_eq!(platform_name("wasm32-unknown-emscripten"), Some("emscripten"));
+	assert_eq!(platform_name("x86_64-unknown-freebsd14.0"), Some("freebsd"));
+	assert_eq!(platform_name("arm-none-eabi"), None);
+}
@@ -1,15 +1,20 @@
-use super::{Compiler, ExeLinker};
+use super::{gnu_reproducible_flags, gnu_win32_subsystem_flags, is_msvc_target, Compiler, ExeLinker};
 
 pub(crate) struct Clang {
 	pub(super) cmd: Vec<String>,
 	pub(super) version: String,
 	pub(super) target: String,
 	pub(super) target_windows: bool,
+	/// Apple's fork of clang, which uses the system linker (ld64) and supports frameworks
+	pub(super) apple: bool,
 }
 
 impl Compiler for Clang {
 	fn id(&self) -> String {
-		"clang".to_owned()
+		match self.apple {
+			true => "apple-clang".to_owned(),
+			false => "clang".to_owned(),
+		}
 	}
 
 	fn version(&self) -> String {
@@ -64,18 +69,44 @@ impl Compiler for Clang {
 		}
 	}
 
+	fn threads_flags(&self) -> Vec<String> {
+		// The MSVC runtime's threads need no flags
+		match is_msvc_target(&self.target) {
+			true => Vec::new(),
+			false => vec!["-pthread".to_owned()],
+		}
+	}
+
+	fn openmp_flags(&self) -> Option<(Vec<String>, Vec<String>)> {
+		// Apple Clang rejects -fopenmp. Its preprocessor still handles the pragmas, with libomp (e.g. from Homebrew)
+		// providing omp.h and the runtime.
+		if self.apple {
+			return Some((vec!["-Xpreprocessor".to_owned(), "-fopenmp".to_owned()], vec!["-lomp".to_owned()]));
+		}
+		let flags = vec!["-fopenmp".to_owned()];
+		Some((flags.clone(), flags))
+	}
+
 	fn position_independent_executable_flag(&self) -> Option<String> {
 		match self.target_windows {
 			true => None,
 			false => Some("-fPIE".to_owned()),
 		}
 	}
+
+	fn coverage_flags(&self) -> Option<Vec<String>> {
+		Some(vec!["-fprofile-instr-generate".to_owned(), "-fcoverage-mapping".to_owned()])
+	}
+
+	fn reproducible_flags(&self, path_maps: &[(String, String)]) -> Vec<String> {
+		gnu_reproducible_flags(path_maps)
+	}
 }
 
 impl ExeLinker for Clang {
 	fn cmd(&self) -> Vec<String> {
 		let mut ret = self.cmd.clone();
-		if self.target_windows {
+		if is_msvc_target(&self.target) {
 			ret.push("-fuse-ld=lld-link".to_owned());
 		}
 		ret
@@ -87,4 +118,92 @@ impl ExeLinker for Clang {
 			false => Some("-pie".to_owned()),
 		}
 	}
+
+	fn framework_flags(&self, framework: &str) -> Option<Vec<String>> {
+		match self.apple {
+			true => Some(vec!["-framework".to_owned(), framework.to_owned()]),
+			false => None,
+		}
+	}
+
+	fn rpath_flags(&self, dir: &str) -> Vec<String> {
+		match self.target_windows {
+			true => Vec::new(),
+			false => vec![format!("-Wl,-rpath,{}", dir)],
+		}
+	}
+
+	fn map_file_flags(&self, path: &str) -> Option<Vec<String>> {
+		Some(vec![if self.apple {
+			format!("-Wl,-map,{}", path)
+		} else if is_msvc_target(&self.target) {
+			format!("-Wl,/MAP:{}", path)
+		} else {
+			format!("-Wl,-Map={}", path)
+		}])
+	}
+
+	fn linker_script_flags(&self, script: &str) -> Option<Vec<String>> {
+		// Apple's ld64 and lld-link don't take linker scripts
+		match self.apple || is_msvc_target(&self.target) {
+			true => None,
+			false => Some(vec!["-T".to_owned(), script.to_owned()]),
+		}
+	}
+
+	fn version_script_flags(&self, script: &str) -> Option<Vec<String>> {
+		match self.apple || self.target_windows {
+			true => None,
+			false => Some(vec![format!("-Wl,--version-script,{}", script)]),
+		}
+	}
+
+	fn coverage_flags(&self, data_dir: &str) -> Option<Vec<String>> {
+		// %p is replaced with the process ID so that concurrent test processes don't overwrite each other's profiles
+		Some(vec![format!("-fprofile-instr-generate={}/%p.profraw", data_dir)])
+	}
+
+	fn reproducible_flags(&self) -> Vec<String> {
+		// PE files contain a link timestamp
+		if is_msvc_target(&self.target) {
+			vec!["-Wl,/Brepro".to_owned()]
+		} else if self.target_windows {
+			vec!["-Wl,--no-insert-timestamp".to_owned()]
+		} else {
+			Vec::new()
+		}
+	}
+
+	fn win32_subsystem_flags(&self, subsystem: Option<&str>, entry_point: Option<&str>) -> Option<Vec<String>> {
+		if is_msvc_target(&self.target) {
+			let mut flags = Vec::new();
+			if let Some(subsystem) = subsystem {
+				flags.push(format!("-Wl,/SUBSYSTEM:{}", subsystem.to_ascii_uppercase()));
+			}
+			if let Some(entry_point) = entry_point {
+				flags.push(format!("-Wl,/ENTRY:{}", entry_point));
+			}
+			Some(flags)
+		} else if self.target_windows {
+			Some(gnu_win32_subsystem_flags(subsystem, entry_point))
+		} else {
+			None
+		}
+	}
+
+	fn win32_manifest_flags(&self, manifest: Option<&str>, execution_level: Option<&str>) -> Option<Vec<String>> {
+		// MinGW's linker has no manifest options. Manifests are compiled into a resource with windres instead.
+		if !is_msvc_target(&self.target) {
+			return None;
+		}
+		let mut flags = vec!["-Wl,/MANIFEST:EMBED".to_owned()];
+		if let Some(manifest) = manifest {
+			flags.push(format!("-Wl,/MANIFESTINPUT:{}", manifest));
+		}
+		if let Some(level) = execution_level {
+			// Quoted so that the single quotes lld-link expects survive the shell
+			flags.push(format!("\"-Wl,/MANIFESTUAC:level='{}'\"", level));
+		}
+		Some(flags)
+	}
 }
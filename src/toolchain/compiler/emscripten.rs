@@ -1,4 +1,4 @@
-use super::{Compiler, ExeLinker};
+use super::{gnu_reproducible_flags, Compiler, ExeLinker};
 
 pub(crate) struct Emscripten {
 	pub(super) cmd: Vec<String>,
@@ -63,6 +63,15 @@ impl Compiler for Emscripten {
 	fn position_independent_executable_flag(&self) -> Option<String> {
 		None
 	}
+
+	fn threads_flags(&self) -> Vec<String> {
+		// Builds with SharedArrayBuffer and Web Workers. Every object linked must be compiled with it.
+		vec!["-pthread".to_owned()]
+	}
+
+	fn reproducible_flags(&self, path_maps: &[(String, String)]) -> Vec<String> {
+		gnu_reproducible_flags(path_maps)
+	}
 }
 
 impl ExeLinker for Emscripten {
@@ -1,9 +1,11 @@
-use super::{Compiler, ExeLinker};
+use super::{gnu_reproducible_flags, gnu_win32_subsystem_flags, Compiler, ExeLinker};
 
 pub(crate) struct Gcc {
 	pub(super) cmd: Vec<String>,
 	pub(super) version: String,
 	pub(super) target: String,
+	/// MinGW, where all code is position independent
+	pub(super) target_windows: bool,
 }
 
 impl Compiler for Gcc {
@@ -57,11 +59,34 @@ impl Compiler for Gcc {
 	}
 
 	fn position_independent_code_flag(&self) -> Option<String> {
-		Some("-fPIC".to_owned())
+		match self.target_windows {
+			true => None,
+			false => Some("-fPIC".to_owned()),
+		}
+	}
+
+	fn threads_flags(&self) -> Vec<String> {
+		vec!["-pthread".to_owned()]
+	}
+
+	fn openmp_flags(&self) -> Option<(Vec<String>, Vec<String>)> {
+		let flags = vec!["-fopenmp".to_owned()];
+		Some((flags.clone(), flags))
 	}
 
 	fn position_independent_executable_flag(&self) -> Option<String> {
-		Some("-fPIE".to_owned())
+		match self.target_windows {
+			true => None,
+			false => Some("-fPIE".to_owned()),
+		}
+	}
+
+	fn coverage_flags(&self) -> Option<Vec<String>> {
+		Some(vec!["--coverage".to_owned()])
+	}
+
+	fn reproducible_flags(&self, path_maps: &[(String, String)]) -> Vec<String> {
+		gnu_reproducible_flags(path_maps)
 	}
 }
 
@@ -71,6 +96,52 @@ impl ExeLinker for Gcc {
 	}
 
 	fn position_independent_executable_flag(&self) -> Option<String> {
-		Some("-pie".to_owned())
+		match self.target_windows {
+			true => None,
+			false => Some("-pie".to_owned()),
+		}
+	}
+
+	fn coverage_flags(&self, _data_dir: &str) -> Option<Vec<String>> {
+		// The coverage data is written next to the object files
+		Some(vec!["--coverage".to_owned()])
+	}
+
+	fn reproducible_flags(&self) -> Vec<String> {
+		match self.target_windows {
+			// PE files contain a link timestamp
+			true => vec!["-Wl,--no-insert-timestamp".to_owned()],
+			false => Vec::new(),
+		}
+	}
+
+	fn rpath_flags(&self, dir: &str) -> Vec<String> {
+		match self.target_windows {
+			true => Vec::new(),
+			false => vec![format!("-Wl,-rpath,{}", dir)],
+		}
+	}
+
+	fn map_file_flags(&self, path: &str) -> Option<Vec<String>> {
+		Some(vec![format!("-Wl,-Map={}", path)])
+	}
+
+	fn linker_script_flags(&self, script: &str) -> Option<Vec<String>> {
+		Some(vec!["-T".to_owned(), script.to_owned()])
+	}
+
+	fn version_script_flags(&self, script: &str) -> Option<Vec<String>> {
+		// Symbol versions are an ELF feature
+		match self.target_windows {
+			true => None,
+			false => Some(vec![format!("-Wl,--version-script,{}", script)]),
+		}
+	}
+
+	fn win32_subsystem_flags(&self, subsystem: Option<&str>, entry_point: Option<&str>) -> Option<Vec<String>> {
+		match self.target_windows {
+			true => Some(gnu_win32_subsystem_flags(subsystem, entry_point)),
+			false => None,
+		}
 	}
 }
@@ -33,12 +33,26 @@ impl Compiler for Msvc {
 		unimplemented!()
 	}
 
-	fn c_std_flag(&self, _std: &str) -> Result<String, String> {
-		unimplemented!()
+	fn c_std_flag(&self, std: &str) -> Result<String, String> {
+		match std {
+			"11" => Ok("/std:c11".to_owned()),
+			"17" => Ok("/std:c17".to_owned()),
+			_ => Err(format!(
+				"Unrecognized value for option for \"c_standard\": \"{std}\". Accepted values are \"17\", \"11\""
+			)),
+		}
 	}
 
-	fn cpp_std_flag(&self, _std: &str) -> Result<String, String> {
-		unimplemented!()
+	fn cpp_std_flag(&self, std: &str) -> Result<String, String> {
+		match std {
+			"11" => Ok("/std:c++11".to_owned()),
+			"14" => Ok("/std:c++14".to_owned()),
+			"17" => Ok("/std:c++17".to_owned()),
+			"20" => Ok("/std:c++20".to_owned()),
+			_ => Err(format!(
+				"Unrecognized value for option for \"cpp_standard\": \"{std}\". Accepted values are \"20\", \"17\", \"14\", \"11\""
+			)),
+		}
 	}
 
 	fn position_independent_code_flag(&self) -> Option<String> {
@@ -48,4 +62,15 @@ impl Compiler for Msvc {
 	fn position_independent_executable_flag(&self) -> Option<String> {
 		None
 	}
+
+	fn openmp_flags(&self) -> Option<(Vec<String>, Vec<String>)> {
+		// The linker picks up the runtime from the objects
+		Some((vec!["/openmp".to_owned()], Vec::new()))
+	}
+
+	fn reproducible_flags(&self, path_maps: &[(String, String)]) -> Vec<String> {
+		let mut flags = vec!["/experimental:deterministic".to_owned()];
+		flags.extend(path_maps.iter().map(|(from, to)| format!("/pathmap:{}={}", from, to)));
+		flags
+	}
 }
@@ -0,0 +1,350 @@
+use std::{collections::BTreeMap, env, fs, path::PathBuf, process, sync::Mutex};
+
+use super::compiler::{is_msvc_target, is_windows_target, Compiler};
+
+/// Results of previous probes, keyed by the full command and source, so repeated checks across packages are only run once
+static PROBE_CACHE: Mutex<BTreeMap<String, bool>> = Mutex::new(BTreeMap::new());
+/// The library search directories of each compiler, keyed by its command and flags
+static LIBRARY_DIRS_CACHE: Mutex<BTreeMap<String, Vec<PathBuf>>> = Mutex::new(BTreeMap::new());
+/// Results of previous library searches, keyed by the file names and directories searched
+static FIND_LIBRARY_CACHE: Mutex<BTreeMap<String, Option<PathBuf>>> = Mutex::new(BTreeMap::new());
+/// Results of previous program searches, keyed by the name, directories searched and version argument
+static FIND_PROGRAM_CACHE: Mutex<BTreeMap<String, Option<FoundProgram>>> = Mutex::new(BTreeMap::new());
+
+const LIBRARY_EXTENSIONS: [&str; 5] = [".a", ".so", ".lib", ".dylib", ".tbd"];
+
+/// Compiler output that indicates a flag was accepted with a warning rather than rejected outright
+const FLAG_FAILURE_PATTERNS: [&str; 5] = [
+	"unrecognized command line option",
+	"unrecognized command-line option",
+	"unknown argument",
+	"unknown warning option",
+	"optimization flag",
+];
+
+/// A compiler configured the same way as for the build (standard and profile flags) used to test what it accepts
+#[derive(Clone, Debug)]
+pub(crate) struct ProbeCompiler {
+	cmd: Vec<String>,
+	out_flag: String,
+	flags: Vec<String>,
+}
+
+impl ProbeCompiler {
+	/// Returns `None` if the compiler can't be run at configure time (e.g. the placeholder used by the MSVC generator)
+	pub(crate) fn new(compiler: &dyn Compiler, flags: Vec<String>) -> Option<ProbeCompiler> {
+		if compiler.id() == "MSVC" {
+			return None;
+		}
+		Some(ProbeCompiler { cmd: compiler.cmd(), out_flag: compiler.out_flag(), flags })
+	}
+
+	/// Compiles `source` in an empty temporary directory. If `reject_flag_warnings` is set, warnings about unknown flags count as failure.
+	pub(crate) fn compiles(
+		&self,
+		source: &str,
+		ext: &str,
+		extra_flags: &[String],
+		reject_flag_warnings: bool,
+	) -> Result<bool, String> {
+		let mut args = self.flags.clone();
+		args.extend(extra_flags.iter().cloned());
+		let cache_key =
+			format!("{}\0{}\0{}\0{}\0{}", self.cmd.join(" "), args.join(" "), ext, reject_flag_warnings, source);
+		if let Some(result) = PROBE_CACHE.lock().map_err(|e| e.to_string())?.get(&cache_key) {
+			return Ok(*result);
+		}
+
+		let probe_dir = std::env::temp_dir().join(format!("catapult-probe-{}", uuid::Uuid::new_v4()));
+		if let Err(e) = fs::create_dir_all(&probe_dir) {
+			return Err(format!("Error creating directory {}: {}", probe_dir.display(), e));
+		}
+		let src_name = "probe".to_owned() + ext;
+		let result = match fs::write(probe_dir.join(&src_name), source) {
+			Ok(()) => self.run(&probe_dir, &src_name, &args, reject_flag_warnings),
+			Err(e) => Err(format!("Error writing probe source: {}", e)),
+		};
+		let _ = fs::remove_dir_all(&probe_dir);
+		let result = result?;
+
+		PROBE_CACHE.lock().map_err(|e| e.to_string())?.insert(cache_key, result);
+		Ok(result)
+	}
+
+	/// The directories the compiler's linker searches for libraries, including those of its sysroot, from
+	/// `-print-search-dirs`
+	pub(crate) fn library_dirs(&self) -> Result<Vec<PathBuf>, String> {
+		let cache_key = format!("{}\0{}", self.cmd.join(" "), self.flags.join(" "));
+		if let Some(dirs) = LIBRARY_DIRS_CACHE.lock().map_err(|e| e.to_string())?.get(&cache_key) {
+			return Ok(dirs.clone());
+		}
+		let (exe, cmd_args) = match self.cmd.split_first() {
+			Some(x) => x,
+			None => return Err("Compiler command is empty".to_owned()),
+		};
+		let output = match process::Command::new(exe)
+			.args(cmd_args)
+			.args(&self.flags)
+			.arg("-print-search-dirs")
+			.output()
+		{
+			Ok(x) => x,
+			Err(e) => return Err(format!("Error executing compiler command \"{}\": {}", exe, e)),
+		};
+		if !output.status.success() {
+			log::debug!("-print-search-dirs failed:\n{}", String::from_utf8_lossy(&output.stderr));
+		}
+		let mut dirs = Vec::new();
+		for line in String::from_utf8_lossy(&output.stdout).lines() {
+			if let Some(paths) = line.strip_prefix("libraries: ") {
+				for dir in env::split_paths(paths.trim_start_matches('=')) {
+					let dir = fs::canonicalize(&dir).unwrap_or(dir);
+					if dir.is_dir() && !dirs.contains(&dir) {
+						dirs.push(dir);
+					}
+				}
+			}
+		}
+		LIBRARY_DIRS_CACHE
+			.lock()
+			.map_err(|e| e.to_string())?
+			.insert(cache_key, dirs.clone());
+		Ok(dirs)
+	}
+
+	fn run(
+		&self,
+		probe_dir: &std::path::Path,
+		src_name: &str,
+		args: &[String],
+		reject_flag_warnings: bool,
+	) -> Result<bool, String> {
+		let (exe, cmd_args) = match self.cmd.split_first() {
+			Some(x) => x,
+			None => return Err("Compiler command is empty".to_owned()),
+		};
+		let output = match process::Command::new(exe)
+			.args(cmd_args)
+			.args(args)
+			.arg("-c")
+			.arg(src_name)
+			.arg(&self.out_flag)
+			.arg("probe.o")
+			.current_dir(probe_dir)
+			.output()
+		{
+			Ok(x) => x,
+			Err(e) => return Err(format!("Error executing compiler command \"{}\": {}", exe, e)),
+		};
+		if !output.status.success() {
+			log::debug!("Probe failed:\n{}", String::from_utf8_lossy(&output.stderr));
+			return Ok(false);
+		}
+		if reject_flag_warnings {
+			let diagnostics = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+			if FLAG_FAILURE_PATTERNS.iter().any(|x| diagnostics.contains(x)) {
+				log::debug!("Probe flag rejected:\n{}", diagnostics);
+				return Ok(false);
+			}
+		}
+		Ok(true)
+	}
+}
+
+/// The file names library `name` may have when linking for `target`, most preferred first. A name that already has a
+/// library extension, e.g. `libz.a` to prefer a static library, is only looked for as it is.
+pub(crate) fn library_file_names(name: &str, target: &str) -> Vec<String> {
+	if LIBRARY_EXTENSIONS.iter().any(|x| name.ends_with(x)) {
+		return vec![name.to_owned()];
+	}
+	if is_msvc_target(target) {
+		vec![format!("{}.lib", name)]
+	} else if is_windows_target(target) {
+		vec![
+			format!("lib{}.dll.a", name),
+			format!("lib{}.a", name),
+			format!("{}.lib", name),
+		]
+	} else if target.contains("-apple-") {
+		vec![
+			format!("lib{}.tbd", name),
+			format!("lib{}.dylib", name),
+			format!("lib{}.a", name),
+		]
+	} else {
+		vec![format!("lib{}.so", name), format!("lib{}.a", name)]
+	}
+}
+
+/// Forgets the results of earlier searches, which are only reused within one configure, so that a project that's
+/// configured again, e.g. by `catapult watch`, finds libraries and programs installed or removed since
+pub(crate) fn clear_search_caches() {
+	FIND_LIBRARY_CACHE.lock().unwrap_or_else(|e| e.into_inner()).clear();
+	FIND_PROGRAM_CACHE.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// The first of `file_names` in the first directory of `dirs` that has one
+pub(crate) fn find_library(file_names: &[String], dirs: &[PathBuf]) -> Result<Option<PathBuf>, String> {
+	let cache_key = format!(
+		"{}\0{}",
+		file_names.join("\0"),
+		dirs.iter().map(|x| x.to_string_lossy()).collect::<Vec<_>>().join("\0")
+	);
+	if let Some(result) = FIND_LIBRARY_CACHE.lock().map_err(|e| e.to_string())?.get(&cache_key) {
+		return Ok(result.clone());
+	}
+	let result = dirs
+		.iter()
+		.flat_map(|dir| file_names.iter().map(move |name| dir.join(name)))
+		.find(|x| x.is_file())
+		.map(|x| fs::canonicalize(&x).unwrap_or(x));
+	FIND_LIBRARY_CACHE
+		.lock()
+		.map_err(|e| e.to_string())?
+		.insert(cache_key, result.clone());
+	Ok(result)
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct FoundProgram {
+	pub path: PathBuf,
+	/// The first version number in the output of running the program with the version argument
+	pub version: Option<String>,
+}
+
+/// Looks for the program `name` in `dirs`, then in `PATH`. On Windows, the extensions in `PATHEXT` are tried too. If
+/// `version_arg` is given, the program is run with it to find its version.
+pub(crate) fn find_program(
+	name: &str,
+	dirs: &[PathBuf],
+	version_arg: Option<&str>,
+) -> Result<Option<FoundProgram>, String> {
+	let dirs = dirs
+		.iter()
+		.cloned()
+		.chain(
+			env::var_os("PATH")
+				.map(|x| env::split_paths(&x).collect::<Vec<_>>())
+				.unwrap_or_default(),
+		)
+		.collect::<Vec<_>>();
+	let cache_key = format!(
+		"{}\0{}\0{}",
+		name,
+		version_arg.unwrap_or_default(),
+		dirs.iter().map(|x| x.to_string_lossy()).collect::<Vec<_>>().join("\0")
+	);
+	if let Some(result) = FIND_PROGRAM_CACHE.lock().map_err(|e| e.to_string())?.get(&cache_key) {
+		return Ok(result.clone());
+	}
+	let mut file_names = vec![name.to_owned()];
+	if cfg!(windows) {
+		let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_owned());
+		file_names.extend(
+			extensions
+				.split(';')
+				.filter(|x| !x.is_empty())
+				.map(|x| name.to_owned() + x),
+		);
+	}
+	let path = dirs
+		.iter()
+		.flat_map(|dir| file_names.iter().map(move |name| dir.join(name)))
+		.find(|x| is_executable(x))
+		.map(|x| fs::canonicalize(&x).unwrap_or(x));
+	let result = match path {
+		Some(path) => {
+			let version = match version_arg {
+				Some(arg) => match process::Command::new(&path).arg(arg).output() {
+					Ok(output) => {
+						let printed = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+						parse_version(&printed)
+					}
+					Err(e) => return Err(format!("Error running \"{}\": {}", path.display(), e)),
+				},
+				None => None,
+			};
+			Some(FoundProgram { path, version })
+		}
+		None => None,
+	};
+	FIND_PROGRAM_CACHE
+		.lock()
+		.map_err(|e| e.to_string())?
+		.insert(cache_key, result.clone());
+	Ok(result)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &std::path::Path) -> bool {
+	use std::os::unix::fs::PermissionsExt;
+	fs::metadata(path).is_ok_and(|x| x.is_file() && x.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &std::path::Path) -> bool {
+	path.is_file()
+}
+
+/// The first dotted number in `text`, e.g. "3.21.12" from "libprotoc 3.21.12"
+fn parse_version(text: &str) -> Option<String> {
+	text.split(|c: char| !c.is_ascii_digit() && c != '.')
+		.map(|x| x.trim_matches('.'))
+		.find(|x| x.contains('.') && x.split('.').all(|part| !part.is_empty()))
+		.map(str::to_owned)
+}
+
+#[test]
+fn test_parse_version() {
+	assert_eq!(parse_version("libprotoc 3.21.12\n").as_deref(), Some("3.21.12"));
+	assert_eq!(parse_version("GNU Make 4.3\nBuilt for x86_64-pc-linux-gnu").as_deref(), Some("4.3"));
+	assert_eq!(parse_version("nasm version 2.16.01 compiled on Jan  1 2023").as_deref(), Some("2.16.01"));
+	assert_eq!(parse_version("no version here"), None);
+}
+
+#[test]
+fn test_find_library() {
+	assert_eq!(library_file_names("z", "x86_64-linux-gnu"), ["libz.so", "libz.a"]);
+	assert_eq!(library_file_names("z", "x86_64-pc-windows-msvc"), ["z.lib"]);
+	assert_eq!(library_file_names("libz.a", "x86_64-linux-gnu"), ["libz.a"]);
+
+	let dir = env::temp_dir().join(format!("catapult-find-library-{}", uuid::Uuid::new_v4()));
+	fs::create_dir_all(dir.join("b")).unwrap();
+	fs::write(dir.join("b").join("libz.a"), "").unwrap();
+	let names = library_file_names("z", "x86_64-linux-gnu");
+	let found = find_library(&names, &[dir.join("a"), dir.join("b")]).unwrap();
+	assert_eq!(found.as_deref(), Some(dir.join("b").join("libz.a").as_path()));
+	assert!(find_library(&names, &[dir.join("a")]).unwrap().is_none());
+
+	// A library installed since is only found once the results of the last configure are forgotten
+	fs::create_dir_all(dir.join("a")).unwrap();
+	fs::write(dir.join("a").join("libz.so"), "").unwrap();
+	assert!(find_library(&names, &[dir.join("a")]).unwrap().is_none());
+	clear_search_caches();
+	let found = find_library(&names, &[dir.join("a")]).unwrap();
+	assert_eq!(found.as_deref(), Some(dir.join("a").join("libz.so").as_path()));
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_find_program() {
+	let dir = env::temp_dir().join(format!("catapult-find-program-{}", uuid::Uuid::new_v4()));
+	fs::create_dir_all(&dir).unwrap();
+	let dirs = [dir.clone()];
+	let name = format!("tool-{}", uuid::Uuid::new_v4());
+	assert!(find_program(&name, &dirs, None).unwrap().is_none());
+
+	// A program installed since is only found once the results of the last configure are forgotten
+	let path = dir.join(&name);
+	fs::write(&path, "").unwrap();
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+	}
+	assert!(find_program(&name, &dirs, None).unwrap().is_none());
+	clear_search_caches();
+	let found = find_program(&name, &dirs, None).unwrap().unwrap();
+	assert_eq!(found.path, fs::canonicalize(&path).unwrap());
+	let _ = fs::remove_dir_all(&dir);
+}
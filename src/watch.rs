@@ -0,0 +1,174 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+	thread,
+	time::{Duration, SystemTime},
+};
+
+use crate::{misc::SourceExtensions, project::Project, BUILD_CATAPULT, CATAPULT_TOML};
+
+/// Files in package directories that can affect the build, besides the targets' listed sources
+const WATCHED_NAMES: [&str; 2] = [CATAPULT_TOML, BUILD_CATAPULT];
+
+/// Detects changes to a set of files by polling their modification times
+pub struct Watcher {
+	mtimes: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+	fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+impl Watcher {
+	pub fn new(files: Vec<PathBuf>) -> Watcher {
+		Watcher {
+			mtimes: files.into_iter().map(|x| (x.clone(), mtime(&x))).collect(),
+		}
+	}
+
+	/// The number of files watched
+	pub fn file_count(&self) -> usize {
+		self.mtimes.len()
+	}
+
+	/// Watches `files` instead of the current files. Files that were already watched keep the modification time they
+	/// had when they were first watched, so changes made since then are still reported.
+	pub fn set_files(&mut self, files: Vec<PathBuf>) {
+		let mut mtimes = BTreeMap::new();
+		for path in files {
+			let last = self.mtimes.remove(&path).unwrap_or_else(|| mtime(&path));
+			mtimes.insert(path, last);
+		}
+		self.mtimes = mtimes;
+	}
+
+	/// The files that were added, removed or modified since the last call
+	fn poll(&mut self) -> Vec<PathBuf> {
+		let mut changed = Vec::new();
+		for (path, last) in self.mtimes.iter_mut() {
+			let current = mtime(path);
+			if current != *last {
+				*last = current;
+				changed.push(path.clone());
+			}
+		}
+		changed
+	}
+
+	/// Blocks until a watched file changes, then waits until no file has changed for `debounce`
+	/// so that a burst of saves results in a single rebuild. Returns the changed files.
+	pub fn wait(&mut self, interval: Duration, debounce: Duration) -> Vec<PathBuf> {
+		let mut changed = Vec::new();
+		while changed.is_empty() {
+			thread::sleep(interval);
+			changed = self.poll();
+		}
+		loop {
+			thread::sleep(debounce);
+			let more = self.poll();
+			if more.is_empty() {
+				break;
+			}
+			for path in more {
+				if !changed.contains(&path) {
+					changed.push(path);
+				}
+			}
+		}
+		changed
+	}
+}
+
+/// The manifests, recipes, sources and headers of the project and its dependencies, and the `extra` files.
+/// Hidden directories and `build_dir` are skipped.
+pub fn watched_files(project: &Project, extra: &[PathBuf], build_dir: &Path) -> Vec<PathBuf> {
+	let mut files = extra.to_vec();
+	let mut visited = Vec::new();
+	let build_dir = fs::canonicalize(build_dir).unwrap_or(build_dir.to_owned());
+	collect_project_files(project, &build_dir, &mut visited, &mut files);
+	// Sources are absolute while walked paths are relative to the package, so normalize them to avoid duplicates
+	let mut files = files
+		.into_iter()
+		.map(|x| fs::canonicalize(&x).unwrap_or(x))
+		.collect::<Vec<_>>();
+	files.sort();
+	files.dedup();
+	files
+}
+
+fn collect_project_files(project: &Project, build_dir: &Path, visited: &mut Vec<String>, files: &mut Vec<PathBuf>) {
+	if visited.contains(&project.info.name) {
+		return;
+	}
+	visited.push(project.info.name.clone());
+	for dep in &project.dependencies {
+		collect_project_files(dep, build_dir, visited, files);
+	}
+	walk_dir(&project.info.path, build_dir, files);
+	for exe in &project.executables {
+		files.extend(exe.sources.iter().map(|x| x.full.clone()));
+	}
+	for lib in &project.static_libraries {
+		files.extend(lib.sources.iter().map(|x| x.full.clone()));
+	}
+	for lib in &project.object_libraries {
+		files.extend(lib.sources.iter().map(|x| x.full.clone()));
+	}
+}
+
+fn walk_dir(dir: &Path, build_dir: &Path, files: &mut Vec<PathBuf>) {
+	let entries = match fs::read_dir(dir) {
+		Ok(x) => x,
+		Err(e) => {
+			log::warn!("Could not watch directory {}: {}", dir.display(), e);
+			return;
+		}
+	};
+	for entry in entries.flatten() {
+		let path = entry.path();
+		let name = entry.file_name().to_string_lossy().to_string();
+		if path.is_dir() {
+			let is_build_dir = fs::canonicalize(&path).is_ok_and(|x| x == build_dir);
+			if !name.starts_with('.') && !is_build_dir {
+				walk_dir(&path, build_dir, files);
+			}
+		} else if WATCHED_NAMES.contains(&name.as_str())
+			|| name.ends_with(".star")
+			|| SourceExtensions::default().classify(&name).is_some()
+		{
+			files.push(path);
+		}
+	}
+}
+
+#[test]
+fn test_watcher_poll() {
+	let dir = std::env::temp_dir().join(format!("catapult_watch_test_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("main.c");
+	let mut watcher = Watcher::new(vec![file.clone()]);
+	assert!(watcher.poll().is_empty());
+	fs::write(&file, "int main() { return 0; }").unwrap();
+	assert_eq!(watcher.poll(), vec![file.clone()]);
+	assert!(watcher.poll().is_empty());
+	fs::remove_file(&file).unwrap();
+	assert_eq!(watcher.poll(), vec![file]);
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_watcher_set_files() {
+	let dir = std::env::temp_dir().join(format!("catapult_watch_set_files_test_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let (manifest, source, unwatched) = (dir.join("catapult.toml"), dir.join("main.c"), dir.join("old.c"));
+	let mut watcher = Watcher::new(vec![manifest.clone(), unwatched.clone()]);
+	// Changed while the build that discovers the sources runs
+	fs::write(&manifest, "[package]").unwrap();
+	watcher.set_files(vec![manifest.clone(), source.clone()]);
+	fs::write(&unwatched, "").unwrap();
+	assert_eq!(watcher.poll(), vec![manifest]);
+	fs::write(&source, "int main() { return 0; }").unwrap();
+	assert_eq!(watcher.poll(), vec![source]);
+	fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,6 @@
+#include "calc.h"
+#include "util.h"
+
+int calc_add(int a, int b) {
+	return util_abs(a) + util_abs(b);
+}
@@ -0,0 +1,3 @@
+#pragma once
+
+int calc_add(int a, int b);
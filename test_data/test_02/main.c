@@ -0,0 +1,5 @@
+#include "calc.h"
+
+int main(void) {
+	return calc_add(1, -1) == 2 ? 0 : 1;
+}
@@ -0,0 +1,5 @@
+#include "util.h"
+
+int util_abs(int x) {
+	return x < 0 ? -x : x;
+}
@@ -0,0 +1,408 @@
+use std::{
+	collections::BTreeMap,
+	env, fs,
+	path::{Path, PathBuf},
+	process,
+	sync::Mutex,
+};
+
+use catapult::{config::BuildDirSettings, generator::Generator, toolchain, Session};
+
+/// Sessions change the current directory while they run, so tests that use them take turns
+static CWD: Mutex<()> = Mutex::new(());
+
+const GCC_TOOLCHAIN: &str = r#"c_compiler = ["gcc"]
+cpp_compiler = ["g++"]
+static_linker = ["ar", "qc"]
+exe_linker = ["gcc"]
+"#;
+/// `GCC_TOOLCHAIN` with a profile that instruments for coverage and links a system library
+const GCC_COVERAGE_TOOLCHAIN: &str = r#"c_compiler = ["gcc"]
+cpp_compiler = ["g++"]
+static_linker = ["ar", "qc"]
+exe_linker = ["gcc"]
+
+[profile.Coverage]
+c_compile_flags = ["-O0"]
+link_libraries = ["pthread"]
+coverage = true
+"#;
+const MSVC_TOOLCHAIN: &str = r#"msvc_platforms = ["x64", "Win32"]
+"#;
+
+/// An empty directory for a test, with a toolchain file
+fn test_dir(name: &str, toolchain: &str) -> PathBuf {
+	let dir = env::temp_dir().join(format!("catapult-{}-{}", name, process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("toolchain.toml"), toolchain).unwrap();
+	dir
+}
+
+/// Copies `from` into `to`, recursively
+fn copy_dir(from: &Path, to: &Path) {
+	fs::create_dir_all(to).unwrap();
+	for entry in fs::read_dir(from).unwrap() {
+		let path = entry.unwrap().path();
+		let dest = to.join(path.file_name().unwrap());
+		if path.is_dir() {
+			copy_dir(&path, &dest);
+		} else {
+			fs::copy(&path, &dest).unwrap();
+		}
+	}
+}
+
+/// The contents of every file in `dir`, recursively
+fn read_files(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+	let mut files = BTreeMap::new();
+	for entry in fs::read_dir(dir).unwrap() {
+		let path = entry.unwrap().path();
+		if path.is_dir() {
+			files.extend(read_files(&path));
+		} else {
+			files.insert(path.clone(), fs::read(&path).unwrap());
+		}
+	}
+	files
+}
+
+#[test]
+fn test_generate_is_deterministic() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	for (generator, toolchain, build_file) in [
+		(Generator::Ninja, GCC_TOOLCHAIN, "build.ninja"),
+		(Generator::Msvc, MSVC_TOOLCHAIN, "test_two.sln"),
+	] {
+		let dir = test_dir("deterministic", toolchain);
+		let session = Session::new("test_data/test_02", dir.join("build"), dir.join("toolchain.toml"), generator);
+		session.generate().unwrap();
+		let first = read_files(&dir.join("build"));
+		assert!(first.contains_key(&dir.join("build").join(build_file)));
+		session.generate().unwrap();
+		assert_eq!(read_files(&dir.join("build")), first);
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
+
+#[test]
+fn test_generate_saves_settings() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("settings", GCC_TOOLCHAIN);
+	let build_dir = dir.join("build");
+	let session = Session::new("test_data/test_02", &build_dir, dir.join("toolchain.toml"), Generator::Ninja)
+		.package_option("test_two", "extra", "1")
+		.allow_unknown_options(true);
+	session.generate().unwrap();
+
+	let settings = BuildDirSettings::read(&build_dir).unwrap().unwrap();
+	assert_eq!(settings, session.settings().unwrap());
+	assert_eq!(settings.generator, "Ninja");
+	assert_eq!(settings.toolchain, dir.join("toolchain.toml"));
+	assert_eq!(settings.package_options["test_two"]["extra"], "1");
+
+	// The build files configure the build directory again with the same settings
+	let build_ninja = fs::read_to_string(build_dir.join("build.ninja")).unwrap();
+	let command = build_ninja
+		.lines()
+		.find(|x| x.contains(" configure "))
+		.expect("No regenerate command");
+	assert!(command.contains(&format!("--build-dir={}", build_dir.display())));
+	assert!(command.contains(&format!("--toolchain={}", settings.toolchain.display())));
+	assert!(command.contains("--package-option=test_two:extra=1"));
+	assert!(command.contains("--allow-unknown-options"));
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_targets_have_generated_flags() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("targets", GCC_COVERAGE_TOOLCHAIN);
+	let session = Session::new("test_data/test_02", dir.join("build"), dir.join("toolchain.toml"), Generator::Ninja)
+		.profile("Coverage");
+	let configured = session.parse().unwrap();
+	assert!(!dir.join("build").exists());
+	let targets = session.targets(&configured).unwrap();
+	let target = |name: &str| targets.iter().find(|x| x.name == name).unwrap();
+
+	// test_02 sets `c_standard` and `position_independent_code`
+	let calc = target("calc");
+	for flag in ["-O0", "--coverage", "-std=c11", "-fPIC"] {
+		assert!(calc.c_compile_flags.iter().any(|x| x == flag), "calc: {:?}", calc.c_compile_flags);
+	}
+	let app = target("app");
+	for flag in ["--coverage", "-std=c11", "-fPIE"] {
+		assert!(app.c_compile_flags.iter().any(|x| x == flag), "app: {:?}", app.c_compile_flags);
+	}
+	assert!(app.defines.iter().any(|x| x == "APP"));
+	for flag in ["--coverage", "-pie", "-lpthread"] {
+		assert!(app.link_flags.iter().any(|x| x == flag), "app: {:?}", app.link_flags);
+	}
+	assert_eq!(app.link_flags.last().map(String::as_str), Some("-lpthread"));
+
+	// The same flags the build files compile with
+	session.generate().unwrap();
+	let build_ninja = fs::read_to_string(dir.join("build").join("build.ninja")).unwrap();
+	assert!(build_ninja.contains(&format!("  FLAGS = {}\n", app.c_compile_flags.join(" "))));
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_targets_use_config_profile() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("config-profile", GCC_COVERAGE_TOOLCHAIN);
+	let src_dir = dir.join("src");
+	copy_dir(Path::new("test_data/test_02"), &src_dir);
+	fs::create_dir_all(src_dir.join(".catapult")).unwrap();
+	fs::write(src_dir.join(".catapult").join("config.toml"), "profile = \"Coverage\"\n").unwrap();
+	let session = Session::new(&src_dir, dir.join("build"), dir.join("toolchain.toml"), Generator::Ninja);
+	let configured = session.parse().unwrap();
+	assert_eq!(session.settings().unwrap().profile.as_deref(), Some("Coverage"));
+
+	let targets = session.targets(&configured).unwrap();
+	let app = targets.iter().find(|x| x.name == "app").unwrap();
+	assert!(app.c_compile_flags.iter().any(|x| x == "-O0"), "app: {:?}", app.c_compile_flags);
+	assert_eq!(app.link_flags.last().map(String::as_str), Some("-lpthread"));
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_check_reports_issues() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("check", GCC_TOOLCHAIN);
+	let util = env::current_dir().unwrap().join("test_data/test_02/util");
+	fs::write(
+		dir.join("catapult.toml"),
+		format!(
+			"[package]\nname = \"checked\"\n\n[dependencies]\nutil = {{ path = {:?} }}\n",
+			util.display().to_string()
+		),
+	)
+	.unwrap();
+	fs::write(
+		dir.join("build.catapult"),
+		r#"helpers = add_static_library(name = "helpers", sources = ["main.c"], visibility = "private")
+add_static_library(name = "unused", sources = ["main.c"], visibility = "private")
+add_executable(name = "main", sources = ["main.c"], link = [helpers])
+"#,
+	)
+	.unwrap();
+	fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+	let toolchain = toolchain::get_toolchain(&dir.join("toolchain.toml"), false).unwrap();
+
+	let original_dir = env::current_dir().unwrap();
+	env::set_current_dir(&dir).unwrap();
+	let issues = catapult::check_project(&toolchain, None, BTreeMap::new(), false);
+	env::set_current_dir(original_dir).unwrap();
+	let issues = issues
+		.unwrap()
+		.into_iter()
+		.map(|x| (x.code, x.package, x.target))
+		.collect::<Vec<_>>();
+	assert_eq!(
+		issues,
+		[
+			("unused-dependency", "checked".to_owned(), None),
+			("unused-target", "checked".to_owned(), Some("unused".to_owned())),
+		]
+	);
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_private_link_flags_reach_executable() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("private-link-flags", GCC_TOOLCHAIN);
+	let src_dir = dir.join("src");
+	fs::create_dir_all(&src_dir).unwrap();
+	fs::write(src_dir.join("catapult.toml"), "[package]\nname = \"linked\"\n").unwrap();
+	fs::write(
+		src_dir.join("build.catapult"),
+		r#"b = add_static_library(name = "b", sources = ["b.c"], link_flags_private = ["-lpthread"])
+a = add_static_library(name = "a", sources = ["a.c"], link_private = [b])
+add_executable(name = "app", sources = ["main.c"], link = [a])
+"#,
+	)
+	.unwrap();
+	for file in ["a.c", "b.c", "main.c"] {
+		fs::write(src_dir.join(file), "int main(void) { return 0; }\n").unwrap();
+	}
+	let session = Session::new(&src_dir, dir.join("build"), dir.join("toolchain.toml"), Generator::Ninja);
+	let configured = session.generate().unwrap();
+	let targets = session.targets(&configured).unwrap();
+	let target = |name: &str| targets.iter().find(|x| x.name == name).unwrap();
+
+	assert!(target("app").link_flags.iter().any(|x| x == "-lpthread"));
+	// They're not part of the interface of the libraries that link b
+	assert!(target("a").link_flags.is_empty());
+	let build_ninja = fs::read_to_string(dir.join("build").join("build.ninja")).unwrap();
+	assert!(build_ninja
+		.lines()
+		.any(|x| x.starts_with("  LINK_FLAGS = ") && x.contains("-lpthread")));
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_artifact_cache_skips_path_dependencies() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("artifact-cache", GCC_TOOLCHAIN);
+	let cache_dir = dir.join("cache");
+	let session = Session::new("test_data/test_02", dir.join("build"), dir.join("toolchain.toml"), Generator::Ninja)
+		.artifact_cache(&cache_dir);
+	session.generate().unwrap();
+
+	// util is a path dependency, so it could change without the build noticing a stale entry
+	let build_ninja = fs::read_to_string(dir.join("build").join("build.ninja")).unwrap();
+	let cache_dir = cache_dir.display().to_string();
+	assert!(build_ninja
+		.lines()
+		.filter(|x| !x.contains(" configure "))
+		.all(|x| !x.contains(&cache_dir)));
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_options_for_unknown_package() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("unknown-package", GCC_TOOLCHAIN);
+	let session = Session::new("test_data/test_02", dir.join("build"), dir.join("toolchain.toml"), Generator::Ninja)
+		.package_option("utli", "legacy", "true");
+	let error = match session.parse() {
+		Ok(_) => panic!("Options for an unknown package were accepted"),
+		Err(e) => e.to_string(),
+	};
+	assert!(error.contains("Options for \"utli\" were not used"), "{}", error);
+	assert!(error.contains("did you mean package 'util'?"), "{}", error);
+
+	// Like overrides of options a package doesn't declare
+	session.allow_unknown_options(true).parse().unwrap();
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_pre_build_sources_may_not_exist() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("pre-build-sources", GCC_TOOLCHAIN);
+	let src_dir = dir.join("src");
+	fs::create_dir_all(&src_dir).unwrap();
+	fs::write(src_dir.join("catapult.toml"), "[package]\nname = \"generated\"\n").unwrap();
+	fs::write(src_dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+	let session = Session::new(&src_dir, dir.join("build"), dir.join("toolchain.toml"), Generator::Ninja);
+
+	// The step generates the source when building
+	fs::write(
+		src_dir.join("build.catapult"),
+		r#"msg = add_static_library(name = "msg", sources = ["msg.c"], pre_build = ["echo 'int msg;' > msg.c"])
+add_executable(name = "main", sources = ["main.c"], link = [msg])
+"#,
+	)
+	.unwrap();
+	session.parse().unwrap();
+
+	fs::write(
+		src_dir.join("build.catapult"),
+		r#"msg = add_static_library(name = "msg", sources = ["msg.c"])
+add_executable(name = "main", sources = ["main.c"], link = [msg])
+"#,
+	)
+	.unwrap();
+	let error = match session.parse() {
+		Ok(_) => panic!("A missing source was accepted"),
+		Err(e) => e.to_string(),
+	};
+	assert!(error.contains("generated::msg sources: \"msg.c\""), "{}", error);
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_hermetic_rejects_scripts_outside_package() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("hermetic-scripts", GCC_TOOLCHAIN);
+	let src_dir = dir.join("src");
+	fs::create_dir_all(&src_dir).unwrap();
+	fs::write(src_dir.join("catapult.toml"), "[package]\nname = \"firmware\"\n\n[options]\nhermetic = true\n").unwrap();
+	fs::write(src_dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+	fs::write(dir.join("board.ld"), "").unwrap();
+	let session = Session::new(&src_dir, dir.join("build"), dir.join("toolchain.toml"), Generator::Ninja);
+	for param in ["linker_script", "version_script"] {
+		fs::write(
+			src_dir.join("build.catapult"),
+			format!("add_executable(name = \"main\", sources = [\"main.c\"], {} = \"../board.ld\")\n", param),
+		)
+		.unwrap();
+		let error = match session.parse() {
+			Ok(_) => panic!("{} outside the package was accepted", param),
+			Err(e) => e.to_string(),
+		};
+		assert!(error.contains("\"../board.ld\" of \"firmware::main\" is outside the package"), "{}", error);
+	}
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_targets_lists_tests() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("list-tests", GCC_TOOLCHAIN);
+	fs::write(dir.join("catapult.toml"), "[package]\nname = \"tested\"\n").unwrap();
+	fs::write(
+		dir.join("build.catapult"),
+		r#"add_executable(name = "main", sources = ["main.c"])
+add_test(name = "main_test", sources = ["main.c"])
+"#,
+	)
+	.unwrap();
+	fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+	let toolchain = toolchain::get_toolchain(&dir.join("toolchain.toml"), false).unwrap();
+
+	let original_dir = env::current_dir().unwrap();
+	env::set_current_dir(&dir).unwrap();
+	let targets = catapult::list_targets(&toolchain, None, BTreeMap::new(), false);
+	env::set_current_dir(original_dir).unwrap();
+	let targets = targets
+		.unwrap()
+		.into_iter()
+		.map(|x| (x.name, x.kind))
+		.collect::<Vec<_>>();
+	assert_eq!(targets, [("main".to_owned(), "executable"), ("main_test".to_owned(), "test")]);
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_config_header_from_checks() {
+	let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+	let dir = test_dir("config-header", GCC_TOOLCHAIN);
+	let src_dir = dir.join("src");
+	fs::create_dir_all(&src_dir).unwrap();
+	fs::write(src_dir.join("catapult.toml"), "[package]\nname = \"probed\"\n").unwrap();
+	fs::write(
+		src_dir.join("build.catapult"),
+		r#"config = generate_config_header(name = "probed_config", defines = {
+    "HAVE_STDIO_H": check_include_file("stdio.h", lang = "c"),
+    "HAVE_NO_SUCH_HEADER_H": check_include_file("no_such_header.h", lang = "c"),
+    "HAVE_PRINTF": check_symbol_exists("printf", headers = ["stdio.h"], lang = "c"),
+    "HAVE_NO_SUCH_FUNCTION": check_symbol_exists("no_such_function", headers = ["stdio.h"], lang = "c"),
+    "MAX_EVENTS": 64,
+})
+add_executable(name = "main", sources = ["main.c"], link = [config])
+"#,
+	)
+	.unwrap();
+	fs::write(src_dir.join("main.c"), "#include <probed_config.h>\nint main(void) { return 0; }\n").unwrap();
+	let session = Session::new(&src_dir, dir.join("build"), dir.join("toolchain.toml"), Generator::Ninja);
+	session.generate().unwrap();
+
+	let include_dir = dir.join("build").join("probed").join("include");
+	let header = fs::read_to_string(include_dir.join("probed_config.h")).unwrap();
+	for line in [
+		"#define HAVE_STDIO_H 1\n",
+		"/* #undef HAVE_NO_SUCH_HEADER_H */\n",
+		"#define HAVE_PRINTF 1\n",
+		"/* #undef HAVE_NO_SUCH_FUNCTION */\n",
+		"#define MAX_EVENTS 64\n",
+	] {
+		assert!(header.contains(line), "{}", header);
+	}
+	let build_ninja = fs::read_to_string(dir.join("build").join("build.ninja")).unwrap();
+	assert!(build_ninja.contains(&format!("-I{}", include_dir.display())));
+	let _ = fs::remove_dir_all(&dir);
+}
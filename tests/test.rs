@@ -13,7 +13,7 @@ fn test_01() {
 
 	let toolchain = Toolchain::default();
 	let (project, global_options) =
-		catapult::parse_project(&toolchain, BTreeMap::new()).expect("Could not parse project");
+		catapult::parse_project(&toolchain, None, BTreeMap::new(), false).expect("Could not parse project");
 	assert_eq!(project.dependencies.len(), 4);
 
 	assert_eq!(global_options.c_standard, Some("17".to_owned()));